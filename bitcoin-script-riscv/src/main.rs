@@ -21,7 +21,10 @@ fn execute_example() {
         0,
         opcode,
     );
-    verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+    verify_execution(
+        &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+    )
+    .unwrap();
     stack.op_true();
     println!("addi length: {}", stack.get_script().len());
     interactive(&stack);
@@ -0,0 +1,301 @@
+use riscv_decode::types::{IType, RType, ShiftType};
+
+// Zba / Zbb support
+//
+// The bit manipulation extensions are not decoded by riscv_decode, so they are decoded here
+// before falling back to the base instruction set. The operand types are reused from
+// riscv_decode. The unary instructions (clz, ctz, cpop, sext, zext, orc.b and rev8) have
+// the whole upper 12 bits fixed and use the IType to access rs1 and rd.
+
+const OPCODE_OP: u32 = 0x33;
+const OPCODE_OP_IMM: u32 = 0x13;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitmanipInstruction {
+    // Zba
+    Sh1add(RType),
+    Sh2add(RType),
+    Sh3add(RType),
+    // Zbb
+    Andn(RType),
+    Orn(RType),
+    Xnor(RType),
+    Max(RType),
+    Maxu(RType),
+    Min(RType),
+    Minu(RType),
+    Rol(RType),
+    Ror(RType),
+    Rori(ShiftType),
+    Clz(IType),
+    Ctz(IType),
+    Cpop(IType),
+    SextB(IType),
+    SextH(IType),
+    ZextH(IType),
+    OrcB(IType),
+    Rev8(IType),
+}
+
+use BitmanipInstruction::*;
+
+impl BitmanipInstruction {
+    pub fn decode(opcode: u32) -> Option<BitmanipInstruction> {
+        let func3 = (opcode >> 12) & 0x7;
+        let func7 = opcode >> 25;
+        let imm = opcode >> 20;
+
+        let instruction = match (opcode & 0x7f, func3, func7) {
+            (OPCODE_OP, 2, 0x10) => Sh1add(RType(opcode)),
+            (OPCODE_OP, 4, 0x10) => Sh2add(RType(opcode)),
+            (OPCODE_OP, 6, 0x10) => Sh3add(RType(opcode)),
+            (OPCODE_OP, 7, 0x20) => Andn(RType(opcode)),
+            (OPCODE_OP, 6, 0x20) => Orn(RType(opcode)),
+            (OPCODE_OP, 4, 0x20) => Xnor(RType(opcode)),
+            (OPCODE_OP, 6, 0x05) => Max(RType(opcode)),
+            (OPCODE_OP, 7, 0x05) => Maxu(RType(opcode)),
+            (OPCODE_OP, 4, 0x05) => Min(RType(opcode)),
+            (OPCODE_OP, 5, 0x05) => Minu(RType(opcode)),
+            (OPCODE_OP, 1, 0x30) => Rol(RType(opcode)),
+            (OPCODE_OP, 5, 0x30) => Ror(RType(opcode)),
+            (OPCODE_OP, 4, 0x04) if imm == 0x080 => ZextH(IType(opcode)),
+            (OPCODE_OP_IMM, 5, 0x30) => Rori(ShiftType(opcode)),
+            (OPCODE_OP_IMM, 1, _) => match imm {
+                0x600 => Clz(IType(opcode)),
+                0x601 => Ctz(IType(opcode)),
+                0x602 => Cpop(IType(opcode)),
+                0x604 => SextB(IType(opcode)),
+                0x605 => SextH(IType(opcode)),
+                _ => return None,
+            },
+            (OPCODE_OP_IMM, 5, _) => match imm {
+                0x287 => OrcB(IType(opcode)),
+                0x698 => Rev8(IType(opcode)),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sh1add(_) => "sh1add",
+            Sh2add(_) => "sh2add",
+            Sh3add(_) => "sh3add",
+            Andn(_) => "andn",
+            Orn(_) => "orn",
+            Xnor(_) => "xnor",
+            Max(_) => "max",
+            Maxu(_) => "maxu",
+            Min(_) => "min",
+            Minu(_) => "minu",
+            Rol(_) => "rol",
+            Ror(_) => "ror",
+            Rori(_) => "rori",
+            Clz(_) => "clz",
+            Ctz(_) => "ctz",
+            Cpop(_) => "cpop",
+            SextB(_) => "sext_b",
+            SextH(_) => "sext_h",
+            ZextH(_) => "zext_h",
+            OrcB(_) => "orc_b",
+            Rev8(_) => "rev8",
+        }
+    }
+
+    pub fn rd(&self) -> u32 {
+        match self {
+            Sh1add(x) | Sh2add(x) | Sh3add(x) | Andn(x) | Orn(x) | Xnor(x) | Max(x) | Maxu(x)
+            | Min(x) | Minu(x) | Rol(x) | Ror(x) => x.rd(),
+            Rori(x) => x.rd(),
+            Clz(x) | Ctz(x) | Cpop(x) | SextB(x) | SextH(x) | ZextH(x) | OrcB(x) | Rev8(x) => {
+                x.rd()
+            }
+        }
+    }
+
+    pub fn rs1(&self) -> u32 {
+        match self {
+            Sh1add(x) | Sh2add(x) | Sh3add(x) | Andn(x) | Orn(x) | Xnor(x) | Max(x) | Maxu(x)
+            | Min(x) | Minu(x) | Rol(x) | Ror(x) => x.rs1(),
+            Rori(x) => x.rs1(),
+            Clz(x) | Ctz(x) | Cpop(x) | SextB(x) | SextH(x) | ZextH(x) | OrcB(x) | Rev8(x) => {
+                x.rs1()
+            }
+        }
+    }
+
+    // None for the instructions that only read rs1
+    pub fn rs2(&self) -> Option<u32> {
+        match self {
+            Sh1add(x) | Sh2add(x) | Sh3add(x) | Andn(x) | Orn(x) | Xnor(x) | Max(x) | Maxu(x)
+            | Min(x) | Minu(x) | Rol(x) | Ror(x) => Some(x.rs2()),
+            _ => None,
+        }
+    }
+
+    // opcode, func3 and the fixed upper bits (func7 or the whole immediate for the unary ones)
+    pub fn encoding(&self) -> (u32, u32, u32) {
+        match self {
+            Sh1add(_) => (OPCODE_OP, 2, 0x10),
+            Sh2add(_) => (OPCODE_OP, 4, 0x10),
+            Sh3add(_) => (OPCODE_OP, 6, 0x10),
+            Andn(_) => (OPCODE_OP, 7, 0x20),
+            Orn(_) => (OPCODE_OP, 6, 0x20),
+            Xnor(_) => (OPCODE_OP, 4, 0x20),
+            Max(_) => (OPCODE_OP, 6, 0x05),
+            Maxu(_) => (OPCODE_OP, 7, 0x05),
+            Min(_) => (OPCODE_OP, 4, 0x05),
+            Minu(_) => (OPCODE_OP, 5, 0x05),
+            Rol(_) => (OPCODE_OP, 1, 0x30),
+            Ror(_) => (OPCODE_OP, 5, 0x30),
+            Rori(_) => (OPCODE_OP_IMM, 5, 0x30),
+            Clz(_) => (OPCODE_OP_IMM, 1, 0x600),
+            Ctz(_) => (OPCODE_OP_IMM, 1, 0x601),
+            Cpop(_) => (OPCODE_OP_IMM, 1, 0x602),
+            SextB(_) => (OPCODE_OP_IMM, 1, 0x604),
+            SextH(_) => (OPCODE_OP_IMM, 1, 0x605),
+            ZextH(_) => (OPCODE_OP, 4, 0x080),
+            OrcB(_) => (OPCODE_OP_IMM, 5, 0x287),
+            Rev8(_) => (OPCODE_OP_IMM, 5, 0x698),
+        }
+    }
+
+    pub fn execute(&self, value_1: u32, value_2: u32) -> u32 {
+        let shamt = value_2 & 0x1F;
+        match self {
+            Sh1add(_) => (value_1 << 1).wrapping_add(value_2),
+            Sh2add(_) => (value_1 << 2).wrapping_add(value_2),
+            Sh3add(_) => (value_1 << 3).wrapping_add(value_2),
+            Andn(_) => value_1 & !value_2,
+            Orn(_) => value_1 | !value_2,
+            Xnor(_) => !(value_1 ^ value_2),
+            Max(_) => (value_1 as i32).max(value_2 as i32) as u32,
+            Maxu(_) => value_1.max(value_2),
+            Min(_) => (value_1 as i32).min(value_2 as i32) as u32,
+            Minu(_) => value_1.min(value_2),
+            Rol(_) => value_1.rotate_left(shamt),
+            Ror(_) => value_1.rotate_right(shamt),
+            Rori(x) => value_1.rotate_right(x.shamt() & 0x1F),
+            Clz(_) => value_1.leading_zeros(),
+            Ctz(_) => value_1.trailing_zeros(),
+            Cpop(_) => value_1.count_ones(),
+            SextB(_) => value_1 as u8 as i8 as i32 as u32,
+            SextH(_) => value_1 as u16 as i16 as i32 as u32,
+            ZextH(_) => value_1 & 0xFFFF,
+            OrcB(_) => (0..4).fold(0, |acc, byte| match (value_1 >> (byte * 8)) & 0xFF {
+                0 => acc,
+                _ => acc | (0xFF << (byte * 8)),
+            }),
+            Rev8(_) => value_1.swap_bytes(),
+        }
+    }
+}
+
+/// Opcodes used to generate the verification scripts of every instruction (rd = a0)
+pub fn generate_bitmanip_sample_instructions() -> Vec<BitmanipInstruction> {
+    let opcodes: Vec<u32> = vec![
+        0x20c5_2533, // sh1add a0, a0, a2
+        0x20c5_4533, // sh2add a0, a0, a2
+        0x20c5_6533, // sh3add a0, a0, a2
+        0x40c5_7533, // andn a0, a0, a2
+        0x40c5_6533, // orn a0, a0, a2
+        0x40c5_4533, // xnor a0, a0, a2
+        0x0ac5_6533, // max a0, a0, a2
+        0x0ac5_7533, // maxu a0, a0, a2
+        0x0ac5_4533, // min a0, a0, a2
+        0x0ac5_5533, // minu a0, a0, a2
+        0x60c5_1533, // rol a0, a0, a2
+        0x60c5_5533, // ror a0, a0, a2
+        0x6075_5513, // rori a0, a0, 7
+        0x6005_1513, // clz a0, a0
+        0x6015_1513, // ctz a0, a0
+        0x6025_1513, // cpop a0, a0
+        0x6045_1513, // sext.b a0, a0
+        0x6055_1513, // sext.h a0, a0
+        0x0805_4533, // zext.h a0, a0
+        0x2875_5513, // orc.b a0, a0
+        0x6985_5513, // rev8 a0, a0
+    ];
+
+    opcodes
+        .into_iter()
+        .map(|opcode| BitmanipInstruction::decode(opcode).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let sample = generate_bitmanip_sample_instructions();
+        assert_eq!(sample.len(), 21);
+
+        for instruction in sample {
+            let (opcode, func3, fixed) = instruction.encoding();
+            assert_eq!(instruction.rd(), 10);
+            assert_eq!(instruction.rs1(), 10);
+            let encoded = match instruction {
+                Rori(x) => (fixed << 25) | (x.shamt() << 20),
+                _ if opcode == OPCODE_OP_IMM || instruction.rs2().is_none() => fixed << 20,
+                _ => (fixed << 25) | (12 << 20),
+            } | (10 << 15)
+                | (func3 << 12)
+                | (10 << 7)
+                | opcode;
+            assert_eq!(BitmanipInstruction::decode(encoded), Some(instruction));
+        }
+
+        // base instructions are not decoded as bitmanip
+        assert_eq!(BitmanipInstruction::decode(0x00b5_0533), None); // add a0, a0, a1
+        assert_eq!(BitmanipInstruction::decode(0x0025_1513), None); // slli a0, a0, 2
+        assert_eq!(BitmanipInstruction::decode(0x4025_5513), None); // srai a0, a0, 2
+        assert_eq!(BitmanipInstruction::decode(0x6035_1513), None); // reserved
+    }
+
+    #[test]
+    fn test_execute() {
+        let sample = generate_bitmanip_sample_instructions();
+        let expected = [
+            0x0000_0005, // sh1add 1, 3
+            0x0000_0007, // sh2add
+            0x0000_000b, // sh3add
+            0x0000_0000, // andn
+            0xffff_fffd, // orn
+            0xffff_fffd, // xnor
+            0x0000_0003, // max
+            0x0000_0003, // maxu
+            0x0000_0001, // min
+            0x0000_0001, // minu
+            0x0000_0008, // rol
+            0x2000_0000, // ror
+            0x0200_0000, // rori 7
+            0x0000_001f, // clz
+            0x0000_0000, // ctz
+            0x0000_0001, // cpop
+            0x0000_0001, // sext.b
+            0x0000_0001, // sext.h
+            0x0000_0001, // zext.h
+            0x0000_00ff, // orc.b
+            0x0100_0000, // rev8
+        ];
+
+        for (instruction, expected) in sample.iter().zip(expected) {
+            assert_eq!(instruction.execute(1, 3), expected, "{:?}", instruction);
+        }
+
+        let value = 0x8000_ff80;
+        assert_eq!(Max(RType(0)).execute(value, 1), 1);
+        assert_eq!(Maxu(RType(0)).execute(value, 1), value);
+        assert_eq!(Clz(IType(0)).execute(0, 0), 32);
+        assert_eq!(Ctz(IType(0)).execute(value, 0), 7);
+        assert_eq!(SextB(IType(0)).execute(value, 0), 0xffff_ff80);
+        assert_eq!(SextH(IType(0)).execute(value, 0), 0xffff_ff80);
+        assert_eq!(ZextH(IType(0)).execute(value, 0), 0xff80);
+        assert_eq!(OrcB(IType(0)).execute(value, 0), 0xff00_ffff);
+        assert_eq!(Rev8(IType(0)).execute(value, 0), 0x80ff_0080);
+    }
+}
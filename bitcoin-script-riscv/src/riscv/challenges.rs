@@ -8,11 +8,13 @@ use bitvmx_cpu_definitions::{
 };
 
 use crate::riscv::{
-    memory_alignment::{is_aligned, load_lower_half_nibble_table, load_upper_half_nibble_table},
+    memory_alignment::{
+        is_aligned, load_lower_half_nibble_table, load_lsb_table, load_upper_half_nibble_table,
+    },
     script_utils::{
         address_in_sections, address_not_in_sections, get_selected_vars, increment_var,
         is_lower_than, increment_decisions_in_altstack, var_to_decisions_in_altstack,
-        verify_wrong_chunk_value, witness_equals, StackTables,
+        verify_wrong_chunk_value, verify_wrong_opcode, witness_equals, StackTables,
     },
 };
 
@@ -28,7 +30,8 @@ use crate::riscv::{
 // One input value equivocation challenge
 // [WOTS_INPUT_DATA[address]|WOTS_PROVER_READ_ADD_1|WOTS_PROVER_READ_VALUE_1|WOTS_PROVER_LAST_STEP_1|WOTS_PROVER_READ_ADD_2|WOTS_PROVER_READ_VALUE_2|WOTS_PROVER_LAST_STEP_2]
 // If STEP_1 == INIT && ADD_1 == const_address && VALUE_1 != [WOTS_INPUT_DATA[address] || STEP_2 == INIT && ADD_2 == const_address && VALUE_2 != [WOTS_INPUT_DATA[address]  => verifier wins
-pub fn input_challenge(stack: &mut StackTracker, address: u32) {
+// The address has to be in the inputs of the owner whose commitment has the input data
+pub fn input_challenge(stack: &mut StackTracker, address: u32, owner_input: &SectionDefinition) {
     stack.clear_definitions();
 
     let input = stack.define(8, "prover_input");
@@ -65,6 +68,11 @@ pub fn input_challenge(stack: &mut StackTracker, address: u32) {
     //one of the two needs to be right
     stack.op_boolor();
     stack.op_verify();
+
+    let owner_address = stack.number_u32(address);
+    address_in_sections(stack, &owner_address, owner_input);
+    stack.op_verify();
+    stack.drop(owner_address);
 }
 
 // One rom value equivocation challenge
@@ -360,13 +368,14 @@ fn is_invalid_write(
 
 fn is_invalid_pc(
     stack: &mut StackTracker,
-    lower_half_nibble_table: &StackVariable,
+    lsb_table: &StackVariable,
     pc_address: StackVariable,
     code_sections: &SectionDefinition,
 ) {
     address_not_in_sections(stack, &pc_address, code_sections);
 
-    is_aligned(stack, pc_address, true, lower_half_nibble_table);
+    // instructions are 2-byte aligned when the compressed extension is used
+    is_aligned(stack, pc_address, true, lsb_table);
     stack.op_not();
 
     stack.op_boolor();
@@ -388,6 +397,7 @@ pub fn addresses_sections_challenge(
     let pc_address = stack.define(8, "pc_address");
     let upper_half_nibble_table = &load_upper_half_nibble_table(stack);
     let lower_half_nibble_table = &load_lower_half_nibble_table(stack);
+    let lsb_table = &load_lsb_table(stack);
 
     is_invalid_read(
         stack,
@@ -420,7 +430,7 @@ pub fn addresses_sections_challenge(
         register_sections,
     );
 
-    is_invalid_pc(stack, lower_half_nibble_table, pc_address, code_sections);
+    is_invalid_pc(stack, lsb_table, pc_address, code_sections);
 
     stack.op_boolor();
     stack.op_boolor();
@@ -428,6 +438,7 @@ pub fn addresses_sections_challenge(
 
     stack.op_verify();
 
+    stack.drop(*lsb_table);
     stack.drop(*lower_half_nibble_table);
     stack.drop(*upper_half_nibble_table);
     stack.drop(memory_witness);
@@ -440,7 +451,7 @@ pub fn opcode_challenge(stack: &mut StackTracker, chunk: &Chunk) {
     let opcode = stack.define(8, "prover_opcode");
     let tables = StackTables::new(stack, true, false, 2, 2, 0);
 
-    verify_wrong_chunk_value(stack, &tables, chunk, pc, opcode);
+    verify_wrong_opcode(stack, &tables, chunk, pc, opcode);
     tables.drop(stack);
 }
 
@@ -838,6 +849,8 @@ pub fn execute_challenge(challege_type: &ChallengeType) -> bool {
             prover_read_2,
             address,
             input_for_address,
+            owner: _,
+            owner_input,
         } => {
             stack.number_u32(*input_for_address); //TODO: this should make input_wots[address]
             stack.number_u32(prover_read_1.address);
@@ -848,7 +861,7 @@ pub fn execute_challenge(challege_type: &ChallengeType) -> bool {
             stack.number_u32(prover_read_2.value);
             stack.number_u64(prover_read_2.last_step);
 
-            input_challenge(&mut stack, *address);
+            input_challenge(&mut stack, *address, owner_input);
         }
         ChallengeType::InitializedData {
             prover_read_1,
@@ -901,6 +914,39 @@ pub fn execute_challenge(challege_type: &ChallengeType) -> bool {
             stack.number_u64(prover_read_2.last_step);
             rom_challenge(&mut stack, *address, *input_for_address);
         }
+        ChallengeType::OutputData {
+            prover_output,
+            prover_hash,
+            trace,
+            prover_next_hash,
+            prover_write_step_tk,
+            prover_last_step_tk,
+        } => {
+            // the output word is the only read of the step after the last one
+            stack.number_u32(prover_output.address);
+            stack.number_u32(prover_output.value);
+            stack.number_u64(prover_output.last_step);
+
+            stack.number_u32(0);
+            stack.number_u32(0);
+            stack.number_u64(0);
+
+            stack.number(1);
+
+            stack.hexstr_as_nibbles(prover_hash);
+
+            stack.number_u32(trace.get_write().address);
+            stack.number_u32(trace.get_write().value);
+            stack.number_u32(trace.get_pc().get_address());
+            stack.byte(trace.get_pc().get_micro());
+
+            stack.hexstr_as_nibbles(prover_next_hash);
+
+            stack.number_u64(*prover_write_step_tk);
+            stack.number_u64(*prover_last_step_tk);
+
+            read_value_challenge(&mut stack);
+        }
         ChallengeType::AddressesSections {
             prover_read_1,
             prover_read_2,
@@ -1474,6 +1520,20 @@ mod tests {
         read_2: &TraceRead,
         address: u32,
         input_for_address: u32,
+    ) -> bool {
+        // the address is in the input of the owner
+        let owner_input = SectionDefinition {
+            ranges: vec![(address & !3, address | 3)],
+        };
+        test_input_of_owner_aux(read_1, read_2, address, input_for_address, &owner_input)
+    }
+
+    fn test_input_of_owner_aux(
+        read_1: &TraceRead,
+        read_2: &TraceRead,
+        address: u32,
+        input_for_address: u32,
+        owner_input: &SectionDefinition,
     ) -> bool {
         let mut stack = StackTracker::new();
 
@@ -1485,7 +1545,7 @@ mod tests {
         stack.number_u32(read_2.value);
         stack.number_u64(read_2.last_step);
 
-        input_challenge(&mut stack, address);
+        input_challenge(&mut stack, address, owner_input);
 
         stack.op_true();
         stack.run().success
@@ -1522,6 +1582,57 @@ mod tests {
         let read_1 = TraceRead::new(0x0000_0005, 0x1234_0000, LAST_STEP_INIT);
         let read_2 = TraceRead::new(0x0000_0002, 0x1234_5678, LAST_STEP_INIT);
         assert!(test_input_aux(&read_1, &read_2, 0x0000_0002, 0x1234_0000));
+
+        //can't challenge with the commitment of an owner whose inputs don't have the address
+        let read_1 = TraceRead::new(0x0000_0002, 0x1234_5678, LAST_STEP_INIT);
+        let read_2 = TraceRead::new(0x0000_0002, 0x1234_5678, LAST_STEP_INIT);
+        let other_input = SectionDefinition {
+            ranges: vec![(0x0000_0004, 0x0000_0007), (0x0000_000c, 0x0000_000f)],
+        };
+        assert!(!test_input_of_owner_aux(
+            &read_1,
+            &read_2,
+            0x0000_0002,
+            0x1234_0000,
+            &other_input
+        ));
+    }
+
+    fn test_output_aux(prover_output: TraceRead, write_step: u64, last_step: u64) -> bool {
+        let write_pc = ProgramCounter::new(0x8000010c, 0x00);
+        let write = TraceWrite::new(0xf0000028, 1);
+
+        execute_challenge(&ChallengeType::OutputData {
+            prover_output,
+            prover_hash: "e2f115006467b4b1b2b27612bbfd40ed3bc8299b".to_string(),
+            trace: TraceStep::new(write, write_pc),
+            prover_next_hash: "345721506e79c53d2549fc63d02ba8fc3b17efa4".to_string(),
+            prover_write_step_tk: write_step - 1,
+            prover_last_step_tk: last_step,
+        })
+    }
+
+    #[test]
+    fn test_output() {
+        //can't challenge if the claimed output is the last write
+        let output = TraceRead::new(0xf0000028, 1, 100);
+        assert!(!test_output_aux(output, 100, 1000));
+
+        //challenge is valid if the claimed output differs from the last write
+        let output = TraceRead::new(0xf0000028, 2, 100);
+        assert!(test_output_aux(output.clone(), 100, 1000));
+
+        //challenge is valid if the output is written after the claimed write
+        let output = TraceRead::new(0xf0000028, 1, 50);
+        assert!(test_output_aux(output, 100, 1000));
+
+        //challenge is valid if the output is written at the last step
+        let output = TraceRead::new(0xf0000028, 2, 1000);
+        assert!(test_output_aux(output, 1000, 1000));
+
+        //can't challenge with a write after the last step
+        let output = TraceRead::new(0xf0000028, 2, 100);
+        assert!(!test_output_aux(output, 1001, 1000));
     }
 
     fn test_addresses_sections_aux(
@@ -1893,7 +2004,7 @@ mod tests {
             0x0000_f000,
             0x0000_f000,
             witness,
-            0x0000_f002,
+            0x0000_f001,
             &rw,
             &ro,
             &reg,
@@ -1945,10 +2056,15 @@ mod tests {
 
     #[test]
     fn test_opcode() {
-        let opcodes = &vec![1234, 5678];
+        // the last word is the first word of the next chunk
+        let opcodes = &vec![0x1111_2222, 0x3333_4444, 0x5555_6666];
         // can't challenge correct opcode
-        assert!(!test_opcode_aux(0xab00_0000, 1234, 0xab00_0000, opcodes));
-        assert!(!test_opcode_aux(0xab00_0004, 5678, 0xab00_0000, opcodes));
+        assert!(!test_opcode_aux(0xab00_0000, 0x1111_2222, 0xab00_0000, opcodes));
+        assert!(!test_opcode_aux(0xab00_0004, 0x3333_4444, 0xab00_0000, opcodes));
+
+        // can't challenge correct opcode of instructions aligned to 2 bytes
+        assert!(!test_opcode_aux(0xab00_0002, 0x4444_1111, 0xab00_0000, opcodes));
+        assert!(!test_opcode_aux(0xab00_0006, 0x6666_3333, 0xab00_0000, opcodes));
 
         // can't challenge address outside chunk
         assert!(!test_opcode_aux(0xab00_0008, 8888, 0xab00_0000, opcodes));
@@ -1956,6 +2072,8 @@ mod tests {
         // can challenge invalid opcodes
         assert!(test_opcode_aux(0xab00_0000, 8888, 0xab00_0000, opcodes));
         assert!(test_opcode_aux(0xab00_0004, 8888, 0xab00_0000, opcodes));
+        assert!(test_opcode_aux(0xab00_0002, 0x1111_2222, 0xab00_0000, opcodes));
+        assert!(test_opcode_aux(0xab00_0006, 0x3333_4444, 0xab00_0000, opcodes));
     }
     fn test_initialized_aux(
         read_1: &TraceRead,
@@ -2691,7 +2809,10 @@ mod tests {
             stack.number_u32(input.read_2.value);
             stack.number_u64(input.read_2.last_step);
 
-            input_challenge(&mut stack, input.address);
+            let owner_input = SectionDefinition {
+                ranges: vec![(input.address, input.address)],
+            };
+            input_challenge(&mut stack, input.address, &owner_input);
             stack.op_true();
 
             stack.run().success == input.expected_to_succeed
@@ -3372,14 +3493,14 @@ mod tests {
 
                         let chunk_base: u32 = rng.random();
                         let opcodes_chunk: Vec<u32> =
-                            (0..rng.random_range(1..50)).map(|_| rng.random()).collect();
+                            (0..rng.random_range(2..50)).map(|_| rng.random()).collect();
 
                         let pc: u32;
                         let mut opcode: u32;
 
                         if should_succeed {
                             // To succeed, the PC must be within the chunk, but the opcode must be wrong.
-                            let pc_index = rng.random_range(0..opcodes_chunk.len());
+                            let pc_index = rng.random_range(0..opcodes_chunk.len() - 1);
                             pc = chunk_base.wrapping_add((pc_index * 4) as u32);
                             let correct_opcode = opcodes_chunk[pc_index];
 
@@ -3398,13 +3519,13 @@ mod tests {
                                     pc = chunk_base.wrapping_sub(rng.random_range(1..100) * 4);
                                 } else {
                                     pc = chunk_base
-                                        .wrapping_add((opcodes_chunk.len() as u32) * 4)
+                                        .wrapping_add((opcodes_chunk.len() as u32 - 1) * 4)
                                         .wrapping_add(rng.random_range(0..100) * 4);
                                 }
                                 opcode = rng.random(); // Opcode can be anything
                             } else {
                                 // Scenario: PC is in bounds, but the opcode is correct.
-                                let pc_index = rng.random_range(0..opcodes_chunk.len());
+                                let pc_index = rng.random_range(0..opcodes_chunk.len() - 1);
                                 pc = chunk_base.wrapping_add((pc_index * 4) as u32);
                                 opcode = opcodes_chunk[pc_index];
                             }
@@ -4222,8 +4343,9 @@ mod tests {
                 expected_to_succeed: bool,
             }
 
-            let standard_chunk = vec![0x11, 0x22, 0x33, 0x44];
-            let single_item_chunk = vec![0xAA];
+            // the last word of each chunk is the first word of the next one
+            let standard_chunk = vec![0x11, 0x22, 0x33, 0x44, 0x55];
+            let single_item_chunk = vec![0xAA, 0x00];
 
             let test_cases = [
                 // --- Success Scenarios (Challenge should succeed) ---
@@ -4316,6 +4438,14 @@ mod tests {
                     opcodes_chunk: &standard_chunk,
                     expected_to_succeed: false,
                 },
+                TestCase {
+                    description: "Failure: Prover is honest with a 2-byte aligned PC at the end of the chunk.",
+                    prover_pc: 0x100E,
+                    prover_opcode: 0x0055_0000,
+                    chunk_base: 0x1000,
+                    opcodes_chunk: &standard_chunk,
+                    expected_to_succeed: false,
+                },
                 TestCase {
                     description: "Failure: Single item chunk, prover is honest.",
                     prover_pc: 0x2000,
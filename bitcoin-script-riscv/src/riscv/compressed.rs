@@ -0,0 +1,718 @@
+use bitcoin_script_stack::stack::{StackTracker, StackVariable};
+use riscv_decode::Instruction;
+
+use super::script_utils::nib_to_bin;
+
+// RV32C support
+//
+// A compressed instruction is identified by the two least significant bits of the
+// parcel being different from 0b11. Every compressed instruction is expanded into its
+// 32-bit equivalent, so the emulator and the verification scripts reuse the
+// implementation of the base instruction and only the program counter increment changes.
+//
+// The opcode committed in the trace is the 32-bit window fetched at the program counter.
+// For compressed instructions only the lower 16 bits are meaningful, the upper half
+// belongs to the next instruction.
+
+pub const COMPRESSED_INSTRUCTION_SIZE: u32 = 2;
+pub const INSTRUCTION_SIZE: u32 = 4;
+
+pub fn is_compressed(opcode: u32) -> bool {
+    opcode & 0b11 != 0b11
+}
+
+pub fn instruction_size(opcode: u32) -> u32 {
+    if is_compressed(opcode) {
+        COMPRESSED_INSTRUCTION_SIZE
+    } else {
+        INSTRUCTION_SIZE
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressedInstruction {
+    Addi4spn,
+    Lw,
+    Sw,
+    Addi,
+    Jal,
+    Li,
+    Addi16sp,
+    Lui,
+    Srli,
+    Srai,
+    Andi,
+    Sub,
+    Xor,
+    Or,
+    And,
+    J,
+    Beqz,
+    Bnez,
+    Slli,
+    Lwsp,
+    Jr,
+    Mv,
+    Ebreak,
+    Jalr,
+    Add,
+    Swsp,
+}
+
+use CompressedInstruction::*;
+
+// The order matters: the first entry whose fixed bits match is selected
+const COMPRESSED_INSTRUCTIONS: [CompressedInstruction; 26] = [
+    Addi4spn, Lw, Sw, Addi, Jal, Li, Addi16sp, Lui, Srli, Srai, Andi, Sub, Xor, Or, And, J, Beqz,
+    Bnez, Slli, Lwsp, Jr, Mv, Ebreak, Jalr, Add, Swsp,
+];
+
+/// Source of every bit of the expanded instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpandedBit {
+    Zero,
+    One,
+    Bit(u8),
+}
+
+use ExpandedBit::{Bit, One, Zero};
+
+enum Reg {
+    Full(u8),
+    Prime(u8),
+    Fixed(u8),
+}
+
+fn reg(reg: Reg) -> [ExpandedBit; 5] {
+    match reg {
+        Reg::Full(lsb) => [0, 1, 2, 3, 4].map(|i| Bit(lsb + i)),
+        // rd', rs1' and rs2' address the registers x8 to x15
+        Reg::Prime(lsb) => [Bit(lsb), Bit(lsb + 1), Bit(lsb + 2), One, Zero],
+        Reg::Fixed(n) => constant::<5>(n as u32),
+    }
+}
+
+fn constant<const N: usize>(value: u32) -> [ExpandedBit; N] {
+    let mut bits = [Zero; N];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        if (value >> i) & 1 == 1 {
+            *bit = One;
+        }
+    }
+    bits
+}
+
+// builds an immediate from pairs of (immediate bit, parcel bit)
+// if sign is set, the bits above the highest one are sign extended from that parcel bit
+fn imm(pairs: &[(u8, u8)], sign: Option<u8>) -> [ExpandedBit; 32] {
+    let mut bits = [Zero; 32];
+    for (imm_bit, parcel_bit) in pairs {
+        bits[*imm_bit as usize] = Bit(*parcel_bit);
+    }
+    if let Some(sign) = sign {
+        let highest = pairs.iter().map(|(imm_bit, _)| *imm_bit).max().unwrap() as usize;
+        for bit in bits.iter_mut().skip(highest + 1) {
+            *bit = Bit(sign);
+        }
+    }
+    bits
+}
+
+struct Layout([ExpandedBit; 32]);
+
+impl Layout {
+    fn new() -> Layout {
+        Layout([Zero; 32])
+    }
+
+    fn place(mut self, lsb: usize, bits: &[ExpandedBit]) -> Layout {
+        self.0[lsb..lsb + bits.len()].copy_from_slice(bits);
+        self
+    }
+
+    fn i_type(imm: [ExpandedBit; 32], rs1: Reg, func3: u32, rd: Reg, opcode: u32) -> Layout {
+        Layout::new()
+            .place(0, &constant::<7>(opcode))
+            .place(7, &reg(rd))
+            .place(12, &constant::<3>(func3))
+            .place(15, &reg(rs1))
+            .place(20, &imm[0..12])
+    }
+
+    fn s_type(imm: [ExpandedBit; 32], rs2: Reg, rs1: Reg, func3: u32, opcode: u32) -> Layout {
+        Layout::new()
+            .place(0, &constant::<7>(opcode))
+            .place(7, &imm[0..5])
+            .place(12, &constant::<3>(func3))
+            .place(15, &reg(rs1))
+            .place(20, &reg(rs2))
+            .place(25, &imm[5..12])
+    }
+
+    fn b_type(imm: [ExpandedBit; 32], rs2: Reg, rs1: Reg, func3: u32, opcode: u32) -> Layout {
+        Layout::new()
+            .place(0, &constant::<7>(opcode))
+            .place(7, &imm[11..12])
+            .place(8, &imm[1..5])
+            .place(12, &constant::<3>(func3))
+            .place(15, &reg(rs1))
+            .place(20, &reg(rs2))
+            .place(25, &imm[5..11])
+            .place(31, &imm[12..13])
+    }
+
+    fn j_type(imm: [ExpandedBit; 32], rd: Reg, opcode: u32) -> Layout {
+        Layout::new()
+            .place(0, &constant::<7>(opcode))
+            .place(7, &reg(rd))
+            .place(12, &imm[12..20])
+            .place(20, &imm[11..12])
+            .place(21, &imm[1..11])
+            .place(31, &imm[20..21])
+    }
+
+    fn u_type(imm: [ExpandedBit; 32], rd: Reg, opcode: u32) -> Layout {
+        Layout::new()
+            .place(0, &constant::<7>(opcode))
+            .place(7, &reg(rd))
+            .place(12, &imm[12..32])
+    }
+
+    fn r_type(func7: u32, rs2: Reg, rs1: Reg, func3: u32, rd: Reg, opcode: u32) -> Layout {
+        Layout::new()
+            .place(0, &constant::<7>(opcode))
+            .place(7, &reg(rd))
+            .place(12, &constant::<3>(func3))
+            .place(15, &reg(rs1))
+            .place(20, &reg(rs2))
+            .place(25, &constant::<7>(func7))
+    }
+}
+
+const CI_IMM: [(u8, u8); 6] = [(0, 2), (1, 3), (2, 4), (3, 5), (4, 6), (5, 12)];
+const CI_SHAMT: [(u8, u8); 5] = [(0, 2), (1, 3), (2, 4), (3, 5), (4, 6)];
+const CL_UIMM: [(u8, u8); 5] = [(2, 6), (3, 10), (4, 11), (5, 12), (6, 5)];
+const CJ_IMM: [(u8, u8); 11] = [
+    (1, 3),
+    (2, 4),
+    (3, 5),
+    (4, 11),
+    (5, 2),
+    (6, 7),
+    (7, 6),
+    (8, 9),
+    (9, 10),
+    (10, 8),
+    (11, 12),
+];
+const CB_IMM: [(u8, u8); 8] = [
+    (1, 3),
+    (2, 4),
+    (3, 10),
+    (4, 11),
+    (5, 2),
+    (6, 5),
+    (7, 6),
+    (8, 12),
+];
+
+impl CompressedInstruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Addi4spn => "addi4spn",
+            Lw => "lw",
+            Sw => "sw",
+            Addi => "addi",
+            Jal => "jal",
+            Li => "li",
+            Addi16sp => "addi16sp",
+            Lui => "lui",
+            Srli => "srli",
+            Srai => "srai",
+            Andi => "andi",
+            Sub => "sub",
+            Xor => "xor",
+            Or => "or",
+            And => "and",
+            J => "j",
+            Beqz => "beqz",
+            Bnez => "bnez",
+            Slli => "slli",
+            Lwsp => "lwsp",
+            Jr => "jr",
+            Mv => "mv",
+            Ebreak => "ebreak",
+            Jalr => "jalr",
+            Add => "add",
+            Swsp => "swsp",
+        }
+    }
+
+    /// Returns the (mask, value) of the bits of the parcel that identify the instruction
+    pub fn fixed_bits(&self) -> (u16, u16) {
+        let (func3, quadrant) = match self {
+            Addi4spn => (0, 0),
+            Lw => (2, 0),
+            Sw => (6, 0),
+            Addi => (0, 1),
+            Jal => (1, 1),
+            Li => (2, 1),
+            Addi16sp | Lui => (3, 1),
+            Srli | Srai | Andi | Sub | Xor | Or | And => (4, 1),
+            J => (5, 1),
+            Beqz => (6, 1),
+            Bnez => (7, 1),
+            Slli => (0, 2),
+            Lwsp => (2, 2),
+            Jr | Mv | Ebreak | Jalr | Add => (4, 2),
+            Swsp => (6, 2),
+        };
+
+        let (mask, value) = match self {
+            Addi16sp => (0x0F80, 0x0100),
+            // shamt[5] must be zero on RV32
+            Srli => (0x1C00, 0x0000),
+            Srai => (0x1C00, 0x0400),
+            Andi => (0x0C00, 0x0800),
+            Sub => (0x1C60, 0x0C00),
+            Xor => (0x1C60, 0x0C20),
+            Or => (0x1C60, 0x0C40),
+            And => (0x1C60, 0x0C60),
+            Slli => (0x1000, 0x0000),
+            Jr => (0x107C, 0x0000),
+            Mv => (0x1000, 0x0000),
+            Ebreak => (0x1FFC, 0x1000),
+            Jalr => (0x107C, 0x1000),
+            Add => (0x1000, 0x1000),
+            _ => (0, 0),
+        };
+
+        (0xE003 | mask, (func3 << 13) | quadrant | value)
+    }
+
+    pub fn expansion(&self) -> [ExpandedBit; 32] {
+        let layout = match self {
+            Addi4spn => Layout::i_type(
+                imm(
+                    &[
+                        (2, 6),
+                        (3, 5),
+                        (4, 11),
+                        (5, 12),
+                        (6, 7),
+                        (7, 8),
+                        (8, 9),
+                        (9, 10),
+                    ],
+                    None,
+                ),
+                Reg::Fixed(2),
+                0,
+                Reg::Prime(2),
+                0x13,
+            ),
+            Lw => Layout::i_type(imm(&CL_UIMM, None), Reg::Prime(7), 2, Reg::Prime(2), 0x03),
+            Sw => Layout::s_type(imm(&CL_UIMM, None), Reg::Prime(2), Reg::Prime(7), 2, 0x23),
+            Addi => Layout::i_type(imm(&CI_IMM, Some(12)), Reg::Full(7), 0, Reg::Full(7), 0x13),
+            Jal => Layout::j_type(imm(&CJ_IMM, Some(12)), Reg::Fixed(1), 0x6F),
+            Li => Layout::i_type(imm(&CI_IMM, Some(12)), Reg::Fixed(0), 0, Reg::Full(7), 0x13),
+            Addi16sp => Layout::i_type(
+                imm(&[(4, 6), (5, 2), (6, 5), (7, 3), (8, 4), (9, 12)], Some(12)),
+                Reg::Fixed(2),
+                0,
+                Reg::Fixed(2),
+                0x13,
+            ),
+            Lui => Layout::u_type(
+                imm(
+                    &[(12, 2), (13, 3), (14, 4), (15, 5), (16, 6), (17, 12)],
+                    Some(12),
+                ),
+                Reg::Full(7),
+                0x37,
+            ),
+            Srli => Layout::i_type(imm(&CI_SHAMT, None), Reg::Prime(7), 5, Reg::Prime(7), 0x13),
+            Srai => {
+                let mut shamt = imm(&CI_SHAMT, None);
+                shamt[10] = One;
+                Layout::i_type(shamt, Reg::Prime(7), 5, Reg::Prime(7), 0x13)
+            }
+            Andi => Layout::i_type(
+                imm(&CI_IMM, Some(12)),
+                Reg::Prime(7),
+                7,
+                Reg::Prime(7),
+                0x13,
+            ),
+            Sub => Layout::r_type(0x20, Reg::Prime(2), Reg::Prime(7), 0, Reg::Prime(7), 0x33),
+            Xor => Layout::r_type(0, Reg::Prime(2), Reg::Prime(7), 4, Reg::Prime(7), 0x33),
+            Or => Layout::r_type(0, Reg::Prime(2), Reg::Prime(7), 6, Reg::Prime(7), 0x33),
+            And => Layout::r_type(0, Reg::Prime(2), Reg::Prime(7), 7, Reg::Prime(7), 0x33),
+            J => Layout::j_type(imm(&CJ_IMM, Some(12)), Reg::Fixed(0), 0x6F),
+            Beqz => Layout::b_type(
+                imm(&CB_IMM, Some(12)),
+                Reg::Fixed(0),
+                Reg::Prime(7),
+                0,
+                0x63,
+            ),
+            Bnez => Layout::b_type(
+                imm(&CB_IMM, Some(12)),
+                Reg::Fixed(0),
+                Reg::Prime(7),
+                1,
+                0x63,
+            ),
+            Slli => Layout::i_type(imm(&CI_SHAMT, None), Reg::Full(7), 1, Reg::Full(7), 0x13),
+            Lwsp => Layout::i_type(
+                imm(&[(2, 4), (3, 5), (4, 6), (5, 12), (6, 2), (7, 3)], None),
+                Reg::Fixed(2),
+                2,
+                Reg::Full(7),
+                0x03,
+            ),
+            Jr => Layout::i_type(imm(&[], None), Reg::Full(7), 0, Reg::Fixed(0), 0x67),
+            Mv => Layout::r_type(0, Reg::Full(2), Reg::Fixed(0), 0, Reg::Full(7), 0x33),
+            Ebreak => Layout::i_type(constant::<32>(1), Reg::Fixed(0), 0, Reg::Fixed(0), 0x73),
+            Jalr => Layout::i_type(imm(&[], None), Reg::Full(7), 0, Reg::Fixed(1), 0x67),
+            Add => Layout::r_type(0, Reg::Full(2), Reg::Full(7), 0, Reg::Full(7), 0x33),
+            Swsp => Layout::s_type(
+                imm(&[(2, 9), (3, 10), (4, 11), (5, 12), (6, 7), (7, 8)], None),
+                Reg::Full(2),
+                Reg::Fixed(2),
+                2,
+                0x23,
+            ),
+        };
+        layout.0
+    }
+
+    pub fn expand(&self, parcel: u16) -> u32 {
+        self.expansion()
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, bit)| match bit {
+                Zero => acc,
+                One => acc | (1 << i),
+                Bit(src) => acc | ((((parcel >> src) & 1) as u32) << i),
+            })
+    }
+
+    /// Returns the mask of the bits of the parcel that can't be all zero.
+    /// The reserved encodings share the fixed bits with valid instructions and have them all zero
+    pub fn nonzero_bits(&self) -> u16 {
+        match self {
+            // nzuimm
+            Addi4spn => 0x1FE0,
+            // nzimm
+            Addi16sp | Lui => 0x107C,
+            // rd and rs1 (c.jalr with rs1 zero is c.ebreak)
+            Lwsp | Jr | Jalr => 0x0F80,
+            // rs2
+            Mv | Add => 0x007C,
+            _ => 0,
+        }
+    }
+
+    /// Returns the fields (mask and value) that the parcel can't have.
+    /// c.lui with rd 2 is c.addi16sp and with rd 0 is a hint
+    pub fn excluded_fields(&self) -> &'static [(u16, u16)] {
+        match self {
+            Lui => &[(0x0F80, 0x0000), (0x0F80, 0x0100)],
+            _ => &[],
+        }
+    }
+
+    fn is_reserved(&self, parcel: u16) -> bool {
+        let mask = self.nonzero_bits();
+        (mask != 0 && parcel & mask == 0)
+            || self
+                .excluded_fields()
+                .iter()
+                .any(|(mask, value)| parcel & mask == *value)
+    }
+
+    pub fn decode(parcel: u16) -> Option<CompressedInstruction> {
+        let compressed = COMPRESSED_INSTRUCTIONS.iter().find(|compressed| {
+            let (mask, value) = compressed.fixed_bits();
+            parcel & mask == value
+        })?;
+
+        if compressed.is_reserved(parcel) {
+            None
+        } else {
+            Some(*compressed)
+        }
+    }
+}
+
+/// Decodes the instruction on the fetch window, expanding it if it is compressed
+pub fn decode_instruction(opcode: u32) -> Option<(Instruction, Option<CompressedInstruction>)> {
+    if !is_compressed(opcode) {
+        return riscv_decode::decode(opcode)
+            .ok()
+            .map(|instruction| (instruction, None));
+    }
+
+    let parcel = opcode as u16;
+    let compressed = CompressedInstruction::decode(parcel)?;
+    riscv_decode::decode(compressed.expand(parcel))
+        .ok()
+        .map(|instruction| (instruction, Some(compressed)))
+}
+
+// position of the bit counting from the top of the stack after exploding the parcel
+fn bit_depth(bit: u8) -> u32 {
+    let nibble = (bit / 4) as u32;
+    let position = (3 - nibble) * 4 + (bit % 4) as u32;
+    15 - position
+}
+
+// Verifies the fixed bits of the compressed instruction and builds the expanded opcode.
+// Only the lower half of the fetch window is used, the upper half is dropped.
+pub fn expand_compressed_opcode(
+    stack: &mut StackTracker,
+    opcode: StackVariable,
+    compressed: CompressedInstruction,
+) -> StackVariable {
+    stack.set_breakpoint(&format!("expand_c_{}", compressed.name()));
+
+    stack.move_var(opcode);
+    stack.explode(opcode);
+    for _ in 0..4 {
+        nib_to_bin(stack);
+    }
+    stack.op_2drop();
+    stack.op_2drop();
+    for _ in 0..16 {
+        stack.from_altstack();
+    }
+
+    let (mask, value) = compressed.fixed_bits();
+    for bit in 0..16 {
+        if (mask >> bit) & 1 == 1 {
+            stack.number(bit_depth(bit));
+            stack.op_pick();
+            stack.number(((value >> bit) & 1) as u32);
+            stack.op_equalverify();
+        }
+    }
+
+    // rejects the reserved encodings as the decoder of the emulator does
+    let nonzero = compressed.nonzero_bits();
+    if nonzero != 0 {
+        stack.number(0);
+        for bit in 0..16 {
+            if (nonzero >> bit) & 1 == 1 {
+                stack.number(bit_depth(bit) + 1);
+                stack.op_pick();
+                stack.op_add();
+            }
+        }
+        stack.number(0);
+        stack.op_greaterthan();
+        stack.op_verify();
+    }
+
+    // counts the bits of the field that match the excluded value
+    for (mask, value) in compressed.excluded_fields() {
+        stack.number(0);
+        for bit in 0..16 {
+            if (mask >> bit) & 1 == 1 {
+                stack.number(bit_depth(bit) + 1);
+                stack.op_pick();
+                stack.number(((value >> bit) & 1) as u32);
+                stack.op_equal();
+                stack.op_add();
+            }
+        }
+        stack.number(mask.count_ones());
+        stack.op_equal();
+        stack.op_not();
+        stack.op_verify();
+    }
+
+    let expansion = compressed.expansion();
+    for nibble in 0..8 {
+        let bits = &expansion[nibble * 4..nibble * 4 + 4];
+        let constant = bits
+            .iter()
+            .enumerate()
+            .try_fold(0, |acc, (i, bit)| match bit {
+                Zero => Some(acc),
+                One => Some(acc | (1 << i)),
+                Bit(_) => None,
+            });
+
+        match constant {
+            Some(constant) => {
+                stack.number(constant);
+            }
+            None => {
+                stack.number(0);
+                for bit in bits.iter().rev() {
+                    stack.op_dup();
+                    stack.op_add();
+                    match bit {
+                        Zero => {}
+                        One => {
+                            stack.number(1);
+                            stack.op_add();
+                        }
+                        Bit(src) => {
+                            stack.number(bit_depth(*src) + 1);
+                            stack.op_pick();
+                            stack.op_add();
+                        }
+                    }
+                }
+            }
+        }
+        stack.to_altstack();
+    }
+
+    for _ in 0..8 {
+        stack.op_2drop();
+    }
+
+    stack.from_altstack_joined(8, "expanded_opcode")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_expansion_aux(parcel: u16, expected: CompressedInstruction, expanded: u32) {
+        let compressed = CompressedInstruction::decode(parcel).unwrap();
+        assert_eq!(compressed, expected);
+        assert_eq!(compressed.expand(parcel), expanded);
+
+        let mut stack = StackTracker::new();
+        // the upper half of the window belongs to the next instruction
+        let opcode = stack.number_u32(0xABCD_0000 | parcel as u32);
+        let result = expand_compressed_opcode(&mut stack, opcode, compressed);
+        let expected = stack.number_u32(expanded);
+        stack.equals(result, true, expected, true);
+        stack.op_true();
+        assert!(stack.run().success);
+    }
+
+    #[test]
+    fn test_expansion() {
+        // c.addi4spn x8, x2, 16
+        test_expansion_aux(0x0800, Addi4spn, 0x01010413);
+        // c.lw x10, 4(x11)
+        test_expansion_aux(0x41c8, Lw, 0x0045a503);
+        // c.sw x10, 4(x11)
+        test_expansion_aux(0xc1c8, Sw, 0x00a5a223);
+        // c.addi x10, -1
+        test_expansion_aux(0x157d, Addi, 0xfff50513);
+        // c.jal -2
+        test_expansion_aux(0x3ffd, Jal, 0xfffff0ef);
+        // c.li x10, 5
+        test_expansion_aux(0x4515, Li, 0x00500513);
+        // c.addi16sp -32
+        test_expansion_aux(0x713d, Addi16sp, 0xfe010113);
+        // c.lui x10, 0x1
+        test_expansion_aux(0x6505, Lui, 0x00001537);
+        // c.srli x10, 3
+        test_expansion_aux(0x810d, Srli, 0x00355513);
+        // c.srai x10, 3
+        test_expansion_aux(0x850d, Srai, 0x40355513);
+        // c.andi x10, 7
+        test_expansion_aux(0x891d, Andi, 0x00757513);
+        // c.sub x10, x11
+        test_expansion_aux(0x8d0d, Sub, 0x40b50533);
+        // c.xor x10, x11
+        test_expansion_aux(0x8d2d, Xor, 0x00b54533);
+        // c.or x10, x11
+        test_expansion_aux(0x8d4d, Or, 0x00b56533);
+        // c.and x10, x11
+        test_expansion_aux(0x8d6d, And, 0x00b57533);
+        // c.j 8
+        test_expansion_aux(0xa021, J, 0x0080006f);
+        // c.beqz x10, 8
+        test_expansion_aux(0xc501, Beqz, 0x00050463);
+        // c.bnez x10, -8
+        test_expansion_aux(0xfd65, Bnez, 0xfe051ce3);
+        // c.slli x10, 2
+        test_expansion_aux(0x050a, Slli, 0x00251513);
+        // c.lwsp x10, 12(x2)
+        test_expansion_aux(0x4532, Lwsp, 0x00c12503);
+        // c.jr x1
+        test_expansion_aux(0x8082, Jr, 0x00008067);
+        // c.mv x10, x11
+        test_expansion_aux(0x852e, Mv, 0x00b00533);
+        // c.ebreak
+        test_expansion_aux(0x9002, Ebreak, 0x00100073);
+        // c.jalr x10
+        test_expansion_aux(0x9502, Jalr, 0x000500e7);
+        // c.add x10, x11
+        test_expansion_aux(0x952e, Add, 0x00b50533);
+        // c.swsp x10, 12(x2)
+        test_expansion_aux(0xc62a, Swsp, 0x00a12623);
+    }
+
+    #[test]
+    fn test_reserved_and_invalid() {
+        // illegal instruction
+        assert!(CompressedInstruction::decode(0x0000).is_none());
+        // c.lwsp with rd zero
+        assert!(CompressedInstruction::decode(0x4002).is_none());
+        // c.jr with rs1 zero
+        assert!(CompressedInstruction::decode(0x8002).is_none());
+        // c.lui with rd zero
+        assert!(CompressedInstruction::decode(0x6005).is_none());
+        // c.srli with shamt[5] set is not valid on RV32
+        assert!(CompressedInstruction::decode(0x910d).is_none());
+        // c.flw is not supported
+        assert!(CompressedInstruction::decode(0x6188).is_none());
+        assert!(decode_instruction(0x0000).is_none());
+    }
+
+    #[test]
+    fn test_fixed_bits_are_verified() {
+        // c.add parcel verified as c.sub
+        let mut stack = StackTracker::new();
+        let opcode = stack.number_u32(0x952e);
+        let result = expand_compressed_opcode(&mut stack, opcode, Sub);
+        stack.drop(result);
+        stack.op_true();
+        assert!(!stack.run().success);
+    }
+
+    fn reserved_fails_verification(parcel: u16, compressed: CompressedInstruction) {
+        // the fixed bits match, only the reserved check rejects it
+        let (mask, value) = compressed.fixed_bits();
+        assert_eq!(parcel & mask, value);
+        assert_ne!(CompressedInstruction::decode(parcel), Some(compressed));
+
+        let mut stack = StackTracker::new();
+        let opcode = stack.number_u32(parcel as u32);
+        let result = expand_compressed_opcode(&mut stack, opcode, compressed);
+        stack.drop(result);
+        stack.op_true();
+        assert!(!stack.run().success);
+    }
+
+    #[test]
+    fn test_reserved_fails_verification() {
+        // c.addi4spn with nzuimm zero (the illegal instruction)
+        reserved_fails_verification(0x0000, Addi4spn);
+        // c.lwsp with rd zero
+        reserved_fails_verification(0x4002, Lwsp);
+        // c.jr with rs1 zero
+        reserved_fails_verification(0x8002, Jr);
+        // c.lui with nzimm zero
+        reserved_fails_verification(0x6501, Lui);
+        // c.ebreak verified as c.jalr
+        reserved_fails_verification(0x9002, Jalr);
+        // c.addi16sp (reserved and valid) verified as c.lui
+        reserved_fails_verification(0x6101, Lui);
+        reserved_fails_verification(0x713d, Lui);
+        // c.lui with rd zero
+        reserved_fails_verification(0x6005, Lui);
+        // c.jalr x10 verified as c.add
+        reserved_fails_verification(0x9502, Add);
+    }
+}
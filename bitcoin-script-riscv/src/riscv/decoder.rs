@@ -276,14 +276,11 @@ pub fn decode_r_type(
     stack.op_equalverify();
 
     // verify funct7
-    // possible options are [ 0x00 (0000 0000) | 0x20 (0010 0000)  | 0x01 (0000 0001) ]
+    // the base options are [ 0x00 (0000 0000) | 0x20 (0010 0000)  | 0x01 (0000 0001) ]
+    // and Zba/Zbb adds [ 0x04 (0000 0100) | 0x05 (0000 0101) | 0x10 (0001 0000) | 0x30 (0011 0000) ]
     stack.copy_var(op_nibbles[1]);
     stack.get_value_from_table(tables.rshift.shift_1, None);
-    if expected_funct7 == 0x01 {
-        stack.number(1);
-    } else {
-        stack.number(0);
-    }
+    stack.number((expected_funct7 & 0x7) as u32);
     stack.op_equalverify();
 
     stack.move_var(op_nibbles[0]);
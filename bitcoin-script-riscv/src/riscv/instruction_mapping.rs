@@ -8,10 +8,16 @@ use riscv_decode::{
 };
 
 use super::{
+    bitmanip::{generate_bitmanip_sample_instructions, BitmanipInstruction},
+    compressed::{decode_instruction, CompressedInstruction},
     instructions::ProgramSpec,
     trace::{STraceRead, STraceStep},
 };
 use bitcoin_script_stack::stack::StackTracker;
+use bitvmx_cpu_definitions::{
+    csr::{csr_name, CSRS},
+    syscall::SYSCALL_MICROS,
+};
 
 pub trait RdZero {
     fn is_rd_zero(&self) -> bool;
@@ -41,6 +47,26 @@ impl RdZero for ShiftType {
     }
 }
 
+impl RdZero for CsrType {
+    fn is_rd_zero(&self) -> bool {
+        self.rd() == 0
+    }
+}
+
+impl RdZero for CsrIType {
+    fn is_rd_zero(&self) -> bool {
+        self.rd() == 0
+    }
+}
+
+// every csr has its own script as the address of the counter is part of it
+fn csr_name_or_nop<X: RdZero>(x: &X, name: &str, csr: u32) -> String {
+    match csr_name(csr) {
+        Some(csr_name) => name_or_nop(x, &format!("{}_{}", name, csr_name)),
+        None => panic!("Csr not supported 0x{:03x}", csr),
+    }
+}
+
 fn name_or_nop<X: RdZero>(x: &X, name: &str) -> String {
     if x.is_rd_zero() {
         "nop".to_string()
@@ -64,6 +90,15 @@ pub fn requires_witness(instruction: &Instruction) -> bool {
     }
 }
 
+// the counters are computed from the conflict step committed by the prover
+pub fn requires_step(instruction: &Instruction) -> bool {
+    match instruction {
+        Csrrs(x) | Csrrc(x) => x.rd() != 0,
+        Csrrsi(x) | Csrrci(x) => x.rd() != 0,
+        _ => false,
+    }
+}
+
 pub fn get_key_from_instruction_and_micro(instruction: &Instruction, micro: u8) -> String {
     match instruction {
         Beq(_) => "beq".to_string(),
@@ -120,19 +155,61 @@ pub fn get_key_from_instruction_and_micro(instruction: &Instruction, micro: u8)
         Lui(x) => name_or_nop(x, "lui"),
         Auipc(x) => name_or_nop(x, "auipc"),
 
+        Csrrs(x) => csr_name_or_nop(x, "csrrs", x.csr()),
+        Csrrc(x) => csr_name_or_nop(x, "csrrc", x.csr()),
+        Csrrsi(x) => csr_name_or_nop(x, "csrrsi", x.csr()),
+        Csrrci(x) => csr_name_or_nop(x, "csrrci", x.csr()),
+
         Fence(_) => "nop".to_string(),
-        Ecall => "ecall".to_string(),
+        Ecall => match micro {
+            0 => "ecall".to_string(),
+            _ => format!("ecall_{}", micro),
+        },
         Ebreak => "nop".to_string(),
 
         _ => panic!("Instruction not supported {:?}", instruction),
     }
 }
 
+pub fn get_key_from_compressed_and_micro(
+    instruction: &Instruction,
+    compressed: CompressedInstruction,
+    micro: u8,
+) -> String {
+    if get_key_from_instruction_and_micro(instruction, micro) == "nop" {
+        return "c_nop".to_string();
+    }
+
+    match compressed {
+        CompressedInstruction::Lw
+        | CompressedInstruction::Sw
+        | CompressedInstruction::Lwsp
+        | CompressedInstruction::Swsp => format!("c_{}_{}", compressed.name(), micro),
+        _ => format!("c_{}", compressed.name()),
+    }
+}
+
+pub fn get_key_from_bitmanip(instruction: &BitmanipInstruction) -> String {
+    if instruction.rd() == 0 {
+        "nop".to_string()
+    } else {
+        instruction.name().to_string()
+    }
+}
+
 pub fn get_key_from_opcode(opcode: u32, micro: u8) -> Option<String> {
-    let instruction = riscv_decode::decode(opcode);
-    match instruction {
-        Ok(instruction) => Some(get_key_from_instruction_and_micro(&instruction, micro)),
-        Err(_) => None,
+    if let Some(bitmanip) = BitmanipInstruction::decode(opcode) {
+        return Some(get_key_from_bitmanip(&bitmanip));
+    }
+
+    match decode_instruction(opcode) {
+        Some((instruction, Some(compressed))) => Some(get_key_from_compressed_and_micro(
+            &instruction,
+            compressed,
+            micro,
+        )),
+        Some((instruction, None)) => Some(get_key_from_instruction_and_micro(&instruction, micro)),
+        None => None,
     }
 }
 
@@ -144,6 +221,7 @@ pub fn get_required_microinstruction(instruction: &Instruction) -> u8 {
         Sb(_) => 4,
         Sh(_) => 8,
         Sw(_) => 8,
+        Ecall => SYSCALL_MICROS,
         _ => 1,
     }
 }
@@ -198,6 +276,11 @@ pub fn generate_sample_instructions() -> Vec<(Instruction, u8)> {
         sample.push((Sw(SType(1124899)), i));
     }
 
+    // micro instructions of the syscalls
+    for i in 1..SYSCALL_MICROS {
+        sample.push((Ecall, i));
+    }
+
     for i in 0..4 {
         sample.push((Sb(SType(10846627)), i));
 
@@ -206,6 +289,55 @@ pub fn generate_sample_instructions() -> Vec<(Instruction, u8)> {
         sample.push((Lw(IType(21047043)), i));
     }
 
+    // read-only accesses to the counters writing to a0
+    for csr in CSRS {
+        let opcode = (csr << 20) | (10 << 7) | 0x73;
+        sample.push((Csrrs(CsrType(opcode | (2 << 12))), 0));
+        sample.push((Csrrc(CsrType(opcode | (3 << 12))), 0));
+        sample.push((Csrrsi(CsrIType(opcode | (6 << 12))), 0));
+        sample.push((Csrrci(CsrIType(opcode | (7 << 12))), 0));
+    }
+
+    sample
+}
+
+pub fn generate_compressed_sample_instructions() -> Vec<(Instruction, CompressedInstruction, u8)> {
+    let parcels: Vec<u16> = vec![
+        0x0800, // c.addi4spn
+        0x41c8, // c.lw
+        0xc1c8, // c.sw
+        0x157d, // c.addi
+        0x3ffd, // c.jal
+        0x4515, // c.li
+        0x713d, // c.addi16sp
+        0x6505, // c.lui
+        0x810d, // c.srli
+        0x850d, // c.srai
+        0x891d, // c.andi
+        0x8d0d, // c.sub
+        0x8d2d, // c.xor
+        0x8d4d, // c.or
+        0x8d6d, // c.and
+        0xa021, // c.j
+        0xc501, // c.beqz
+        0xfd65, // c.bnez
+        0x050a, // c.slli
+        0x4532, // c.lwsp
+        0x8082, // c.jr
+        0x852e, // c.mv
+        0x9502, // c.jalr
+        0x952e, // c.add
+        0xc62a, // c.swsp
+        0x0001, // c.nop
+    ];
+
+    let mut sample = Vec::new();
+    for parcel in parcels {
+        let (instruction, compressed) = decode_instruction(parcel as u32).unwrap();
+        for micro in 0..get_required_microinstruction(&instruction) {
+            sample.push((instruction, compressed.unwrap(), micro));
+        }
+    }
     sample
 }
 
@@ -214,6 +346,45 @@ pub fn generate_verification_script(
     micro: u8,
     base_register_address: u32,
     witness: bool,
+) -> Script {
+    generate_script(instruction, None, micro, base_register_address, witness)
+}
+
+pub fn generate_compressed_verification_script(
+    instruction: &Instruction,
+    compressed: CompressedInstruction,
+    micro: u8,
+    base_register_address: u32,
+    witness: bool,
+) -> Script {
+    generate_script(
+        instruction,
+        Some(compressed),
+        micro,
+        base_register_address,
+        witness,
+    )
+}
+
+pub fn generate_bitmanip_verification_script(
+    instruction: &BitmanipInstruction,
+    base_register_address: u32,
+) -> Script {
+    let mut stack = StackTracker::new();
+    let program = ProgramSpec::new(base_register_address);
+    let trace_step = STraceStep::define(&mut stack);
+    let trace_read = STraceRead::define(&mut stack);
+    let mut result = execute_bitmanip_step(&mut stack, &trace_read, instruction, program).unwrap();
+    compare_trace_step(&mut stack, &trace_step, &mut result);
+    stack.get_script()
+}
+
+fn generate_script(
+    instruction: &Instruction,
+    compressed: Option<CompressedInstruction>,
+    micro: u8,
+    base_register_address: u32,
+    witness: bool,
 ) -> Script {
     let mut stack = StackTracker::new();
     let program = ProgramSpec::new(base_register_address);
@@ -222,17 +393,34 @@ pub fn generate_verification_script(
         true => Some(stack.define(8, "witness")),
         false => None,
     };
+    let step = match requires_step(instruction) {
+        true => Some(stack.define(16, "prover_conflict_step_tk")),
+        false => None,
+    };
 
     let trace_read = STraceRead::define(&mut stack);
-    let mut result = execute_step(
-        &mut stack,
-        &trace_read,
-        &trace_step,
-        witness,
-        &instruction,
-        micro,
-        program,
-    )
+    let mut result = match compressed {
+        Some(compressed) => execute_compressed_step(
+            &mut stack,
+            &trace_read,
+            &trace_step,
+            witness,
+            instruction,
+            compressed,
+            micro,
+            program,
+        ),
+        None => execute_step(
+            &mut stack,
+            &trace_read,
+            &trace_step,
+            witness,
+            step,
+            instruction,
+            micro,
+            program,
+        ),
+    }
     .unwrap();
     compare_trace_step(&mut stack, &trace_step, &mut result);
     stack.get_script()
@@ -254,6 +442,24 @@ pub fn create_verification_script_mapping(base_register_address: u32) -> Instruc
         );
         mapping.insert(key, (script, requires_witness));
     }
+
+    for (instruction, compressed, micro) in generate_compressed_sample_instructions() {
+        let key = get_key_from_compressed_and_micro(&instruction, compressed, micro);
+        let script = generate_compressed_verification_script(
+            &instruction,
+            compressed,
+            micro,
+            base_register_address,
+            false,
+        );
+        mapping.insert(key, (script, false));
+    }
+
+    for instruction in generate_bitmanip_sample_instructions() {
+        let key = get_key_from_bitmanip(&instruction);
+        let script = generate_bitmanip_verification_script(&instruction, base_register_address);
+        mapping.insert(key, (script, false));
+    }
     mapping
 }
 
@@ -289,4 +495,88 @@ mod tests {
         }
         println!("Total instructions: {}", size);
     }
+
+    #[test]
+    fn test_get_key_from_compressed_and_micro() {
+        let sample = generate_compressed_sample_instructions();
+        for (instruction, compressed, micro) in sample {
+            let key = get_key_from_compressed_and_micro(&instruction, compressed, micro);
+            const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000;
+            let script = generate_compressed_verification_script(
+                &instruction,
+                compressed,
+                micro,
+                REGISTERS_BASE_ADDRESS,
+                false,
+            );
+            println!(
+                "Instruction: {:?}, Compressed: {:?}, Micro: {}, Key: {}, Size: {}",
+                instruction,
+                compressed,
+                micro,
+                key,
+                script.len()
+            );
+        }
+
+        assert_eq!(get_key_from_opcode(0x0001, 0), Some("c_nop".to_string()));
+        assert_eq!(get_key_from_opcode(0x41c8, 2), Some("c_lw_2".to_string()));
+        assert_eq!(get_key_from_opcode(0x952e, 0), Some("c_add".to_string()));
+        assert_eq!(get_key_from_opcode(0x00b50533, 0), Some("add".to_string()));
+    }
+
+    #[test]
+    fn test_get_key_from_bitmanip() {
+        const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000;
+        for instruction in generate_bitmanip_sample_instructions() {
+            let key = get_key_from_bitmanip(&instruction);
+            let script =
+                generate_bitmanip_verification_script(&instruction, REGISTERS_BASE_ADDRESS);
+            println!(
+                "Instruction: {:?}, Key: {}, Size: {}",
+                instruction,
+                key,
+                script.len()
+            );
+        }
+
+        // sh1add a0, a0, a2
+        assert_eq!(
+            get_key_from_opcode(0x20c52533, 0),
+            Some("sh1add".to_string())
+        );
+        // rev8 a0, a0
+        assert_eq!(get_key_from_opcode(0x69855513, 0), Some("rev8".to_string()));
+        // clz zero, a0
+        assert_eq!(get_key_from_opcode(0x60051013, 0), Some("nop".to_string()));
+    }
+
+    #[test]
+    fn test_get_key_from_csr() {
+        // rdcycle a0
+        assert_eq!(
+            get_key_from_opcode(0xc0002573, 0),
+            Some("csrrs_cycle".to_string())
+        );
+        // rdinstreth a1
+        assert_eq!(
+            get_key_from_opcode(0xc82025f3, 0),
+            Some("csrrs_instreth".to_string())
+        );
+        // rdtime zero
+        assert_eq!(get_key_from_opcode(0xc0102073, 0), Some("nop".to_string()));
+    }
+
+    #[test]
+    fn test_get_key_from_ecall() {
+        assert_eq!(
+            get_key_from_opcode(0x00000073, 0),
+            Some("ecall".to_string())
+        );
+        assert_eq!(
+            get_key_from_opcode(0x00000073, SYSCALL_MICROS - 1),
+            Some("ecall_12".to_string())
+        );
+        assert_eq!(get_required_microinstruction(&Ecall), SYSCALL_MICROS);
+    }
 }
@@ -1,5 +1,6 @@
 use bitcoin_script_stack::stack::{StackTracker, StackVariable};
 
+use bitvmx_cpu_definitions::csr::{CSR_CYCLEH, CSR_INSTRETH, CSR_TIMEH};
 use bitvmx_cpu_definitions::memory::MemoryAccessType;
 use bitvmx_cpu_definitions::memory::MemoryWitness;
 use bitvmx_cpu_definitions::trace::TraceRWStep;
@@ -7,14 +8,21 @@ use riscv_decode::Instruction;
 use riscv_decode::Instruction::*;
 
 use crate::riscv::memory_alignment::clear_least_significant_bit;
-use crate::riscv::memory_alignment::verify_alignment;
+use crate::riscv::memory_alignment::verify_instruction_alignment;
 use crate::ScriptValidation;
 
+use super::bitmanip::BitmanipInstruction;
+use super::compressed::{
+    decode_instruction, expand_compressed_opcode, CompressedInstruction,
+    COMPRESSED_INSTRUCTION_SIZE,
+};
 use super::decoder::*;
-use super::instruction_mapping::get_key_from_instruction_and_micro;
-use super::instruction_mapping::InstructionMapping;
+use super::instruction_mapping::{get_key_from_instruction_and_micro, get_key_from_opcode};
+use super::instruction_mapping::{requires_step, InstructionMapping};
+use super::instructions_bitmanip::op_bitmanip;
 use super::instructions_load::op_load;
 use super::instructions_store::op_store;
+use super::instructions_syscall::op_ecall;
 use super::operations;
 use super::operations::*;
 use super::script_utils::*;
@@ -70,7 +78,12 @@ pub fn op_nop(stack: &mut StackTracker, trace_read: &STraceRead) -> STraceStep {
 
     let tables = StackTables::new(stack, true, true, 0, 0, 0);
     stack.from_altstack();
-    let pc = pc_next(stack, &tables, trace_read.program_counter);
+    let pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     stack.to_altstack();
     tables.drop(stack);
     stack.from_altstack();
@@ -79,88 +92,6 @@ pub fn op_nop(stack: &mut StackTracker, trace_read: &STraceRead) -> STraceStep {
     STraceStep::new(write_add, write_value, pc, micro)
 }
 
-pub const REGISTER_A0: usize = 10;
-pub const REGISTER_A7_ECALL_ARG: usize = 17;
-
-pub fn op_ecall(
-    stack: &mut StackTracker,
-    trace_read: &STraceRead,
-    base_register_address: u32,
-) -> STraceStep {
-    //compare the instruction number with the halt constant
-    let constant = stack.number_u32(93);
-    is_equal_to(stack, &trace_read.read_1_value, &constant);
-    stack.to_altstack();
-    stack.drop(constant);
-    stack.from_altstack();
-
-    let (mut stack_halt, mut stack_if_false) = stack.open_if();
-
-    //any opcode other than halt (93) is treated as a nop
-    op_nop(&mut stack_if_false, trace_read);
-
-    //verify the memory witness
-    verify_memory_witness(
-        &mut stack_halt,
-        trace_read.mem_witness,
-        MemoryWitness::registers(),
-    );
-
-    //asserts opcode
-    let ecall = stack_halt.number_u32(0x00000073);
-    stack_halt.equals(trace_read.opcode, true, ecall, true);
-
-    //micro is not used
-    move_and_drop(&mut stack_halt, trace_read.micro);
-
-    //save the program counter
-    stack_halt.to_altstack();
-
-    //asserts that the return value is zero (success)
-    let success = stack_halt.number_u32(0x00000000);
-    stack_halt.equals(trace_read.read_2_value, false, success, true);
-    //save the second read value as the return value
-    stack_halt.to_altstack();
-
-    //assert reading from A0
-    let add_ra0 = stack_halt.number_u32(base_register_address + (REGISTER_A0 as u32) * 4);
-    stack_halt.equals(trace_read.read_2_add, true, add_ra0, true);
-
-    //we are already in the right halt branch
-    stack_halt.drop(trace_read.read_1_value);
-
-    //assert reading from A7
-    let add_ra7 = stack_halt.number_u32(base_register_address + (REGISTER_A7_ECALL_ARG as u32) * 4);
-    stack_halt.equals(trace_read.read_1_add, true, add_ra7, true);
-
-    //store write address
-    stack_halt.number_u32(base_register_address + (REGISTER_A0 as u32) * 4);
-
-    //restore the value as write_value
-    stack_halt.from_altstack();
-
-    //restore pc and keep it constant as it halted
-    stack_halt.from_altstack();
-
-    //add micro
-    stack_halt.number(0);
-
-    let ret = stack.end_if(
-        stack_halt,
-        stack_if_false,
-        8,
-        vec![
-            (8, "write_address".to_string()),
-            (8, "write_value".to_string()),
-            (8, "write_pc".to_string()),
-            (1, "write_micro".to_string()),
-        ],
-        0,
-    );
-
-    STraceStep::new(ret[0], ret[1], ret[2], ret[3])
-}
-
 pub fn op_conditional(
     instruction: &Instruction,
     stack: &mut StackTracker,
@@ -231,7 +162,12 @@ pub fn op_conditional(
     add_with_bit_extension(&mut stack_if_true, &tables, imm, pc, StackVariable::null());
 
     //if false, jump to the next instruction
-    pc_next(&mut stack_if_false, &tables, trace_read.program_counter);
+    pc_next(
+        &mut stack_if_false,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     move_and_drop(&mut stack_if_false, imm);
 
     let ret = stack.end_if(
@@ -242,7 +178,7 @@ pub fn op_conditional(
         0,
     );
     let write_pc = ret[0];
-    verify_alignment(stack, write_pc);
+    verify_instruction_alignment(stack, write_pc);
 
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
@@ -259,6 +195,7 @@ pub fn store_if_not_zero(
     tables: &StackTables,
     rd: StackVariable,
     program_counter: StackVariable,
+    instruction_size: u32,
     base_register_address: u32,
     mem_witness: StackVariable,
     expected_if_zero: MemoryWitness,
@@ -290,7 +227,12 @@ pub fn store_if_not_zero(
     stack_if_false.join(write_add);
     stack_if_false.rename(write_add, "write_add");
 
-    let write_value = pc_next(&mut stack_if_false, tables, program_counter);
+    let write_value = pc_next(
+        &mut stack_if_false,
+        tables,
+        program_counter,
+        instruction_size,
+    );
     stack_if_false.rename(write_value, "write_value");
 
     let ret = stack.end_if(
@@ -332,6 +274,7 @@ pub fn op_jal(
         &tables,
         rd,
         trace_read.program_counter,
+        trace_read.instruction_size,
         base_register_address,
         trace_read.mem_witness,
         MemoryWitness::default(),
@@ -344,7 +287,7 @@ pub fn op_jal(
 
     let write_pc = add_with_bit_extension(stack, &tables, pc, imm, StackVariable::null());
     stack.rename(write_pc, "write_pc");
-    verify_alignment(stack, write_pc);
+    verify_instruction_alignment(stack, write_pc);
 
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
@@ -385,6 +328,7 @@ pub fn op_jalr(
         &tables,
         rd,
         trace_read.program_counter,
+        trace_read.instruction_size,
         base_register_address,
         trace_read.mem_witness,
         MemoryWitness::new(
@@ -399,7 +343,7 @@ pub fn op_jalr(
         add_with_bit_extension(stack, &tables, trace_read.read_1_value, imm, bit_extension);
     let write_pc = clear_least_significant_bit(stack, write_pc);
     stack.rename(write_pc, "write_pc");
-    verify_alignment(stack, write_pc);
+    verify_instruction_alignment(stack, write_pc);
 
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
@@ -482,7 +426,12 @@ pub fn op_arithmetic_imm(
         _ => panic!("Unreachable"),
     };
 
-    let write_pc = pc_next(stack, &tables, trace_read.program_counter);
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
 
@@ -683,7 +632,12 @@ pub fn op_arithmetic(
         _ => panic!("Unreachable"),
     };
 
-    let write_pc = pc_next(stack, &tables, trace_read.program_counter);
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
 
@@ -748,7 +702,82 @@ pub fn op_upper(
         _ => panic!("Unreachable"),
     };
 
-    let write_pc = pc_next(stack, &tables, trace_read.program_counter);
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    let micro = stack.number(0);
+    stack.rename(micro, "write_micro");
+
+    let trace = STraceStep::new(write_addr, write_value, write_pc, micro);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+// Only the read-only accesses to the counters are supported (rs1 = x0 or zimm = 0).
+// The counters are not read from memory, the value written to rd is the conflict step committed
+// by the prover in the n-ary search, that is off by one of the step being verified.
+pub fn op_csr(
+    instruction: &Instruction,
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    conflict_step: StackVariable,
+    csr: u32,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 1, 4, 0);
+
+    stack.set_breakpoint(&format!("op_{:?}", instruction));
+
+    let func3 = match instruction {
+        Csrrs(_) => 2,
+        Csrrc(_) => 3,
+        Csrrsi(_) => 6,
+        Csrrci(_) => 7,
+        _ => panic!("Unreachable"),
+    };
+
+    // the csr, rs1 / zimm and func3 are verified as the upper part of the opcode
+    let (imm, rd) = decode_u_type(stack, &tables, trace_read.opcode, 0x73);
+    let expected = stack.number_u32((csr << 20) | (func3 << 12));
+    stack.equals(imm, true, expected, true);
+
+    // These value are not used
+    move_and_drop(stack, trace_read.micro);
+    move_and_drop(stack, trace_read.read_1_add);
+    move_and_drop(stack, trace_read.read_1_value);
+    move_and_drop(stack, trace_read.read_2_add);
+    move_and_drop(stack, trace_read.read_2_value);
+
+    verify_memory_witness(
+        stack,
+        trace_read.mem_witness,
+        MemoryWitness::new(
+            MemoryAccessType::Unused,
+            MemoryAccessType::Unused,
+            MemoryAccessType::Register,
+        ),
+    );
+
+    let write_addr = number_u32_partial(stack, base_register_address, 6);
+    stack.move_var(rd);
+    stack.join(write_addr);
+    stack.rename(write_addr, "write_addr");
+
+    let high = matches!(csr, CSR_CYCLEH | CSR_TIMEH | CSR_INSTRETH);
+    let write_value = csr_counter(stack, conflict_step, high);
+    stack.rename(write_value, "write_value");
+
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
 
@@ -759,6 +788,30 @@ pub fn op_upper(
     trace
 }
 
+// the counters hold the steps executed before the current one, which is the conflict step
+// returns the upper or the lower half of the 64 bits counter
+fn csr_counter(
+    stack: &mut StackTracker,
+    conflict_step: StackVariable,
+    high: bool,
+) -> StackVariable {
+    stack.move_var(conflict_step);
+    stack.explode(conflict_step);
+    for _ in 0..16 {
+        stack.to_altstack();
+    }
+
+    let upper = stack.from_altstack_joined(8, "counter_high");
+    let lower = stack.from_altstack_joined(8, "counter_low");
+    if high {
+        stack.drop(lower);
+        upper
+    } else {
+        move_and_drop(stack, upper);
+        lower
+    }
+}
+
 pub struct ProgramSpec {
     base_register_address: u32, // base address used for the registers
 }
@@ -777,11 +830,13 @@ impl ProgramSpec {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn execute_step(
     stack: &mut StackTracker,
     trace_read: &STraceRead,
     trace_step: &STraceStep,
     witness: Option<StackVariable>,
+    step: Option<StackVariable>,
     instruction: &Instruction,
     micro: u8,
     program: ProgramSpec,
@@ -789,7 +844,12 @@ pub fn execute_step(
     match instruction {
         Fence(_) | Ebreak => Ok(op_nop(stack, &trace_read)),
 
-        Ecall => Ok(op_ecall(stack, trace_read, program.base_register_address)),
+        Ecall => Ok(op_ecall(
+            stack,
+            trace_read,
+            micro,
+            program.base_register_address,
+        )),
 
         Beq(_) | Bne(_) | Blt(_) | Bge(_) | Bltu(_) | Bgeu(_) => Ok(op_conditional(
             instruction,
@@ -884,6 +944,34 @@ pub fn execute_step(
                 ))
             }
         }
+        Csrrs(x) | Csrrc(x) => {
+            if x.rd() == 0 {
+                Ok(op_nop(stack, trace_read))
+            } else {
+                Ok(op_csr(
+                    instruction,
+                    stack,
+                    trace_read,
+                    step.expect("The conflict step is required by the csr instructions"),
+                    x.csr(),
+                    program.base_register_address,
+                ))
+            }
+        }
+        Csrrsi(x) | Csrrci(x) => {
+            if x.rd() == 0 {
+                Ok(op_nop(stack, trace_read))
+            } else {
+                Ok(op_csr(
+                    instruction,
+                    stack,
+                    trace_read,
+                    step.expect("The conflict step is required by the csr instructions"),
+                    x.csr(),
+                    program.base_register_address,
+                ))
+            }
+        }
         _ => Err(ScriptValidation::InstructionNotImplemented(format!(
             "{:?}",
             instruction
@@ -891,6 +979,63 @@ pub fn execute_step(
     }
 }
 
+// The compressed instruction is expanded and verified as its 32-bit equivalent
+#[allow(clippy::too_many_arguments)]
+pub fn execute_compressed_step(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    trace_step: &STraceStep,
+    witness: Option<StackVariable>,
+    instruction: &Instruction,
+    compressed: CompressedInstruction,
+    micro: u8,
+    program: ProgramSpec,
+) -> Result<STraceStep, ScriptValidation> {
+    let trace_read = STraceRead {
+        instruction_size: COMPRESSED_INSTRUCTION_SIZE,
+        ..*trace_read
+    };
+
+    // nops don't decode the opcode, so all of them share the same script
+    if get_key_from_instruction_and_micro(instruction, micro) == "nop" {
+        return Ok(op_nop(stack, &trace_read));
+    }
+
+    let opcode = expand_compressed_opcode(stack, trace_read.opcode, compressed);
+    let trace_read = STraceRead {
+        opcode,
+        ..trace_read
+    };
+    execute_step(
+        stack,
+        &trace_read,
+        trace_step,
+        witness,
+        None,
+        instruction,
+        micro,
+        program,
+    )
+}
+
+pub fn execute_bitmanip_step(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    instruction: &BitmanipInstruction,
+    program: ProgramSpec,
+) -> Result<STraceStep, ScriptValidation> {
+    if instruction.rd() == 0 {
+        Ok(op_nop(stack, trace_read))
+    } else {
+        Ok(op_bitmanip(
+            instruction,
+            stack,
+            trace_read,
+            program.base_register_address,
+        ))
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn verify(
     instruction_mapping: &Option<InstructionMapping>,
@@ -911,19 +1056,29 @@ pub fn verify(
         None => None,
     };
 
-    let trace_read = STraceRead::from(&mut stack, trace);
     let opcode = trace.read_pc.opcode;
     let micro = trace.read_pc.pc.get_micro();
 
+    // the conflict step committed by the prover is the step before the one being verified
+    let step = match decode_instruction(opcode) {
+        Some((instruction, _)) if requires_step(&instruction) => {
+            let step = stack.number_u64(trace.step_number - 1);
+            stack.rename(step, "prover_conflict_step_tk");
+            consumes += 1;
+            Some(step)
+        }
+        _ => None,
+    };
+
+    let trace_read = STraceRead::from(&mut stack, trace);
+
     if let Some(mapping) = instruction_mapping {
-        let instruction = riscv_decode::decode(opcode).unwrap();
-        println!("instruction to verify: {:?}", instruction);
-        let key = get_key_from_instruction_and_micro(&instruction, micro);
+        let key = get_key_from_opcode(opcode, micro).unwrap();
         let (verification_script, _requires_witness) = mapping.get(&key).unwrap();
         stack.custom(verification_script.clone(), consumes, false, 0, "verify");
     } else {
         verify_execution(
-            &mut stack, trace_step, trace_read, witness, opcode, micro, program,
+            &mut stack, trace_step, trace_read, witness, step, opcode, micro, program,
         )?;
     }
 
@@ -936,27 +1091,48 @@ pub fn verify(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn verify_execution(
     stack: &mut StackTracker,
     trace_step: STraceStep,
     trace_read: STraceRead,
     witness: Option<StackVariable>,
+    step: Option<StackVariable>,
     opcode: u32,
     micro: u8,
     program: ProgramSpec,
 ) -> Result<(), ScriptValidation> {
-    let instruction = riscv_decode::decode(opcode).unwrap();
+    if let Some(bitmanip) = BitmanipInstruction::decode(opcode) {
+        let mut result_step = execute_bitmanip_step(stack, &trace_read, &bitmanip, program)?;
+        compare_trace_step(stack, &trace_step, &mut result_step);
+        return Ok(());
+    }
+
+    let (instruction, compressed) = decode_instruction(opcode).unwrap();
     println!("instruction to verify: {:?}", instruction);
     // println!("instruction to hex: {:2x}", instruction.into());
-    let mut result_step = execute_step(
-        stack,
-        &trace_read,
-        &trace_step,
-        witness,
-        &instruction,
-        micro,
-        program,
-    )?;
+    let mut result_step = match compressed {
+        Some(compressed) => execute_compressed_step(
+            stack,
+            &trace_read,
+            &trace_step,
+            witness,
+            &instruction,
+            compressed,
+            micro,
+            program,
+        )?,
+        None => execute_step(
+            stack,
+            &trace_read,
+            &trace_step,
+            witness,
+            step,
+            &instruction,
+            micro,
+            program,
+        )?,
+    };
     compare_trace_step(stack, &trace_step, &mut result_step);
 
     Ok(())
@@ -1097,7 +1273,10 @@ mod tests {
             0,
             opcode,
         );
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
         stack.op_true();
         assert!(stack.run().success);
     }
@@ -1126,7 +1305,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1168,7 +1350,10 @@ mod tests {
         );
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1206,7 +1391,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1230,7 +1418,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1268,7 +1459,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1292,7 +1486,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1338,7 +1535,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1384,7 +1584,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1430,7 +1633,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1469,7 +1675,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1510,7 +1719,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1551,7 +1763,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -1592,7 +1807,10 @@ mod tests {
         ); // pc, micro, opcode
 
         let program = ProgramSpec::new(BASE_REGISTER_ADDRESS);
-        verify_execution(&mut stack, trace_step, trace_read, None, opcode, 0, program).unwrap();
+        verify_execution(
+            &mut stack, trace_step, trace_read, None, None, opcode, 0, program,
+        )
+        .unwrap();
 
         stack.op_true();
 
@@ -0,0 +1,577 @@
+use bitcoin_script_stack::stack::{StackTracker, StackVariable};
+use bitvmx_cpu_definitions::memory::MemoryWitness;
+
+use crate::riscv::{
+    bitmanip::BitmanipInstruction::{self, *},
+    decoder::{decode_i_type, decode_r_type},
+    operations::*,
+    script_utils::*,
+};
+
+use super::{
+    instructions::{validate_register_address, verify_memory_witness, R_TYPE_OPCODE},
+    trace::{STraceRead, STraceStep},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CountOperation {
+    LeadingZeros,
+    TrailingZeros,
+    Ones,
+}
+
+// table with the result of the count operation for every nibble
+pub fn count_table(stack: &mut StackTracker, count: CountOperation) -> StackVariable {
+    let mut parts = Vec::new();
+    for i in (0..16u32).rev() {
+        let number = match count {
+            CountOperation::LeadingZeros => (i << 28).leading_zeros().min(4),
+            CountOperation::TrailingZeros => i.trailing_zeros().min(4),
+            CountOperation::Ones => i.count_ones(),
+        };
+        parts.push(stack.number(number));
+    }
+    stack.rename(parts[0], &format!("count_{:?}", count));
+    stack.join_count(parts[0], 15)
+}
+
+pub fn bitwise_not(stack: &mut StackTracker, value: StackVariable) -> StackVariable {
+    for _ in 0..8 {
+        stack.number(15);
+        stack.move_var_sub_n(value, 0);
+        stack.op_sub();
+    }
+    stack.join_in_stack(8, None, Some("not"))
+}
+
+// Counts the bits of the value using the count table (0..=32).
+// The leading and trailing zeros stop counting on the first nibble that is not zero.
+pub fn count_bits(
+    stack: &mut StackTracker,
+    value: StackVariable,
+    table: StackVariable,
+    modulo: StackVariable,
+    quotient: StackVariable,
+    count: CountOperation,
+) -> StackVariable {
+    stack.set_breakpoint(&format!("count_{:?}", count));
+
+    stack.move_var(value);
+    let nibbles = stack.explode(value);
+    let order: Vec<usize> = match count {
+        CountOperation::TrailingZeros => (0..8).rev().collect(),
+        _ => (0..8).collect(),
+    };
+
+    let mut acc = stack.number(0);
+    if count == CountOperation::Ones {
+        for i in order {
+            stack.move_var(nibbles[i]);
+            stack.get_value_from_table(table, None);
+            acc = stack.op_add();
+        }
+    } else {
+        // the flag is set while all the previous nibbles are zero
+        let mut flag = stack.number(1);
+        for i in order {
+            stack.move_var(nibbles[i]);
+            stack.op_dup();
+            stack.get_value_from_table(table, None);
+            stack.copy_var(flag);
+            stack.custom(
+                script! {
+                    OP_NOTIF
+                        OP_DROP
+                        0
+                    OP_ENDIF
+                }.compile(),
+                2,
+                true,
+                0,
+                "count_nibble",
+            );
+            stack.move_var(acc);
+            acc = stack.op_add();
+
+            stack.move_var(flag);
+            stack.move_var(nibbles[i]);
+            stack.op_not();
+            flag = stack.op_booland();
+        }
+        stack.drop(flag);
+    }
+    stack.rename(acc, "count");
+
+    // split the count in two nibbles and extend it to a word
+    stack.op_dup();
+    stack.get_value_from_table(modulo, None);
+    stack.to_altstack();
+    stack.get_value_from_table(quotient, None);
+    stack.to_altstack();
+
+    let result = stack.number(0);
+    for _ in 0..5 {
+        stack.number(0);
+    }
+    stack.from_altstack();
+    stack.from_altstack();
+    stack.join_count(result, 7)
+}
+
+// Amount to shift on the opposite direction to complete the rotation: (32 - amount) % 32
+pub fn rotation_complement(
+    stack: &mut StackTracker,
+    tables: &StackTables,
+    amount: StackVariable,
+) -> StackVariable {
+    let amount_copy = stack.copy_var(amount);
+    stack.explode(amount_copy);
+    u4_to_u8(stack);
+
+    stack.number(32);
+    stack.op_swap();
+    stack.op_sub();
+    stack.custom(
+        script! {
+            OP_DUP
+            32
+            OP_EQUAL
+            OP_IF
+                OP_DROP
+                0
+            OP_ENDIF
+        }.compile(),
+        1,
+        true,
+        0,
+        "mod_32",
+    );
+
+    stack.op_dup();
+    stack.get_value_from_table(tables.modulo, None);
+    stack.to_altstack();
+    let complement = stack.get_value_from_table(tables.quotient, None);
+    stack.from_altstack();
+    stack.join(complement);
+    stack.rename(complement, "rotation_complement");
+    complement
+}
+
+// Chooses the lower or the greater of the two values
+pub fn min_max(
+    stack: &mut StackTracker,
+    value: StackVariable,
+    than: StackVariable,
+    unsigned: bool,
+    max: bool,
+) -> StackVariable {
+    stack.move_var(value);
+    stack.move_var(than);
+    let value_copy = stack.copy_var(value);
+    let than_copy = stack.copy_var(than);
+    is_lower_than(stack, value_copy, than_copy, unsigned);
+
+    let (mut stack_if_true, mut stack_if_false) = stack.open_if();
+    if max {
+        stack_if_true.move_var(value);
+        stack_if_true.drop(value);
+        stack_if_false.drop(than);
+    } else {
+        stack_if_true.drop(than);
+        stack_if_false.move_var(value);
+        stack_if_false.drop(value);
+    }
+
+    stack.end_if(
+        stack_if_true,
+        stack_if_false,
+        2,
+        vec![(8, if max { "max" } else { "min" }.to_string())],
+        0,
+    )[0]
+}
+
+pub fn sign_extend(stack: &mut StackTracker, value: StackVariable, nibbles: u32) -> StackVariable {
+    stack.move_var(value);
+    stack.explode(value);
+    for _ in 0..nibbles {
+        stack.to_altstack();
+    }
+    for _ in 0..(8 - nibbles) / 2 {
+        stack.op_2drop();
+    }
+    let lower = stack.from_altstack_joined(nibbles, "lower");
+    bit_extend(stack, lower)
+}
+
+pub fn zero_extend_half(stack: &mut StackTracker, value: StackVariable) -> StackVariable {
+    stack.move_var(value);
+    stack.explode(value);
+    for _ in 0..4 {
+        stack.to_altstack();
+    }
+    stack.op_2drop();
+    stack.op_2drop();
+
+    let result = stack.number(0);
+    for _ in 0..3 {
+        stack.number(0);
+    }
+    for _ in 0..4 {
+        stack.from_altstack();
+    }
+    stack.join_count(result, 7)
+}
+
+pub fn or_combine_bytes(stack: &mut StackTracker, value: StackVariable) -> StackVariable {
+    stack.move_var(value);
+    stack.explode(value);
+    for _ in 0..4 {
+        stack.op_add();
+        if_greater(stack, 0, 0xF, 0);
+        stack.op_dup();
+        stack.to_altstack();
+        stack.to_altstack();
+    }
+    for _ in 0..8 {
+        stack.from_altstack();
+    }
+    stack.join_in_stack(8, None, Some("orc_b"))
+}
+
+pub fn reverse_bytes(stack: &mut StackTracker, value: StackVariable) -> StackVariable {
+    stack.move_var(value);
+    let nibbles = stack.explode(value);
+    for i in [6, 7, 4, 5, 2, 3, 0, 1] {
+        stack.move_var(nibbles[i]);
+    }
+    stack.join_in_stack(8, None, Some("rev8"))
+}
+
+pub fn op_bitmanip(
+    instruction: &BitmanipInstruction,
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let (mask, shifts) = match instruction {
+        Rol(_) | Ror(_) | Rori(_) => (LOGIC_MASK_AND | LOGIC_MASK_OR, true),
+        Andn(_) => (LOGIC_MASK_AND, false),
+        Orn(_) => (LOGIC_MASK_OR, false),
+        Xnor(_) => (LOGIC_MASK_XOR, false),
+        _ => (0, false),
+    };
+    let shift_mask = if shifts { 0xf } else { 7 };
+    let tables = StackTables::new(stack, true, true, shift_mask, shift_mask, mask);
+
+    let count = match instruction {
+        Clz(_) => Some(CountOperation::LeadingZeros),
+        Ctz(_) => Some(CountOperation::TrailingZeros),
+        Cpop(_) => Some(CountOperation::Ones),
+        _ => None,
+    };
+    // the count goes up to 32 so it needs bigger tables
+    let count_tables = count.map(|count| {
+        (
+            count_table(stack, count),
+            modulo_table(stack, 33),
+            quotient_table_ex(stack, 33),
+        )
+    });
+
+    stack.set_breakpoint(&format!("op_{}", instruction.name()));
+
+    let (opcode, func3, fixed) = instruction.encoding();
+
+    let (rd, amount) = match instruction {
+        Rori(_) => {
+            let (shamt, rs1, rd, _) = decode_i_type(
+                stack,
+                &tables,
+                trace_read.opcode,
+                func3 as u8,
+                opcode as u8,
+                Some(fixed as u8),
+            );
+
+            move_and_drop(stack, trace_read.read_2_add);
+            move_and_drop(stack, trace_read.read_2_value);
+            move_and_drop(stack, trace_read.micro);
+
+            verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::rur());
+            validate_register_address(stack, trace_read.read_1_add, rs1, base_register_address);
+            move_and_drop(stack, rs1);
+            move_and_drop(stack, trace_read.read_1_add);
+            (rd, shamt)
+        }
+        _ if instruction.rs2().is_none() => {
+            let (imm, rs1, rd, bit_extension) = decode_i_type(
+                stack,
+                &tables,
+                trace_read.opcode,
+                func3 as u8,
+                opcode as u8,
+                None,
+            );
+
+            // the whole immediate is fixed for the unary instructions
+            let expected = number_u32_partial(stack, fixed << 20, 3);
+            stack.equals(imm, true, expected, true);
+            move_and_drop(stack, bit_extension);
+
+            move_and_drop(stack, trace_read.read_2_add);
+            move_and_drop(stack, trace_read.read_2_value);
+            move_and_drop(stack, trace_read.micro);
+
+            verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::rur());
+            validate_register_address(stack, trace_read.read_1_add, rs1, base_register_address);
+            move_and_drop(stack, rs1);
+            move_and_drop(stack, trace_read.read_1_add);
+            (rd, StackVariable::null())
+        }
+        _ => {
+            let (rs1, rs2, rd) = decode_r_type(
+                stack,
+                &tables,
+                trace_read.opcode,
+                func3 as u8,
+                R_TYPE_OPCODE,
+                fixed as u8,
+            );
+
+            verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::registers());
+            move_and_drop(stack, trace_read.micro);
+
+            validate_register_address(stack, trace_read.read_1_add, rs1, base_register_address);
+            validate_register_address(stack, trace_read.read_2_add, rs2, base_register_address);
+            move_and_drop(stack, rs1);
+            move_and_drop(stack, trace_read.read_1_add);
+            move_and_drop(stack, rs2);
+            move_and_drop(stack, trace_read.read_2_add);
+
+            // the rotations use the 5 lsb of rs2 as the amount
+            let amount = match instruction {
+                Rol(_) | Ror(_) => {
+                    stack.move_var(trace_read.read_2_value);
+                    stack.explode(trace_read.read_2_value);
+                    stack.to_altstack();
+                    stack.get_value_from_table(tables.lshift.shift_3, None);
+                    stack.get_value_from_table(tables.rshift.shift_3, None);
+                    stack.to_altstack();
+                    stack.op_2drop();
+                    stack.op_2drop();
+                    stack.op_2drop();
+                    stack.from_altstack_joined(2, "masked_5lsb")
+                }
+                _ => StackVariable::null(),
+            };
+            (rd, amount)
+        }
+    };
+
+    let write_addr = number_u32_partial(stack, base_register_address, 6);
+    stack.move_var(rd);
+    stack.join(write_addr);
+    stack.rename(write_addr, "write_addr");
+
+    let value = trace_read.read_1_value;
+    let write_value = match instruction {
+        Sh1add(_) | Sh2add(_) | Sh3add(_) => {
+            let bits = match instruction {
+                Sh1add(_) => 1,
+                Sh2add(_) => 2,
+                _ => 3,
+            };
+            let amount = stack.byte(bits);
+            let shifted = shift_value_with_bits(stack, value, amount, false, false);
+            add_with_bit_extension(
+                stack,
+                &tables,
+                shifted,
+                trace_read.read_2_value,
+                StackVariable::null(),
+            )
+        }
+        Andn(_) | Orn(_) => {
+            let not = bitwise_not(stack, trace_read.read_2_value);
+            let logic = match instruction {
+                Andn(_) => LogicOperation::And,
+                _ => LogicOperation::Or,
+            };
+            logic_with_bit_extension(stack, &tables, value, not, StackVariable::null(), logic)
+        }
+        Xnor(_) => {
+            let xor = logic_with_bit_extension(
+                stack,
+                &tables,
+                value,
+                trace_read.read_2_value,
+                StackVariable::null(),
+                LogicOperation::Xor,
+            );
+            bitwise_not(stack, xor)
+        }
+        Max(_) | Maxu(_) | Min(_) | Minu(_) => min_max(
+            stack,
+            value,
+            trace_read.read_2_value,
+            matches!(instruction, Maxu(_) | Minu(_)),
+            matches!(instruction, Max(_) | Maxu(_)),
+        ),
+        Rol(_) | Ror(_) | Rori(_) => {
+            // the right shift uses the tables and the left shift the bits
+            let complement = rotation_complement(stack, &tables, amount);
+            let value_copy = stack.copy_var(value);
+            let (left, right) = match instruction {
+                Rol(_) => {
+                    let left = shift_value_with_bits(stack, value, amount, false, false);
+                    let right = shift_value_with_tables(
+                        stack, &tables, value_copy, complement, true, false,
+                    );
+                    (left, right)
+                }
+                _ => {
+                    let right = shift_value_with_tables(stack, &tables, value, amount, true, false);
+                    let left = shift_value_with_bits(stack, value_copy, complement, false, false);
+                    (left, right)
+                }
+            };
+            logic_with_bit_extension(
+                stack,
+                &tables,
+                left,
+                right,
+                StackVariable::null(),
+                LogicOperation::Or,
+            )
+        }
+        Clz(_) | Ctz(_) | Cpop(_) => {
+            let (table, modulo, quotient) = count_tables.unwrap();
+            count_bits(stack, value, table, modulo, quotient, count.unwrap())
+        }
+        SextB(_) => sign_extend(stack, value, 2),
+        SextH(_) => sign_extend(stack, value, 4),
+        ZextH(_) => zero_extend_half(stack, value),
+        OrcB(_) => or_combine_bytes(stack, value),
+        Rev8(_) => reverse_bytes(stack, value),
+    };
+    stack.rename(write_value, "write_value");
+
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    let micro = stack.number(0);
+    stack.rename(micro, "write_micro");
+
+    let trace = STraceStep::new(write_addr, write_value, write_pc, micro);
+    trace.to_altstack(stack);
+    if let Some((table, modulo, quotient)) = count_tables {
+        stack.drop(quotient);
+        stack.drop(modulo);
+        stack.drop(table);
+    }
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitwise_not() {
+        let mut stack = StackTracker::new();
+        let value = stack.number_u32(0x1234_abcd);
+        let result = bitwise_not(&mut stack, value);
+        let expected = stack.number_u32(!0x1234_abcd);
+        stack.equals(result, true, expected, true);
+        stack.op_true();
+        assert!(stack.run().success);
+    }
+
+    fn test_count_aux(value: u32, count: CountOperation, expected: u32) {
+        let mut stack = StackTracker::new();
+        let table = count_table(&mut stack, count);
+        let modulo = modulo_table(&mut stack, 33);
+        let quotient = quotient_table_ex(&mut stack, 33);
+
+        let value = stack.number_u32(value);
+        let result = count_bits(&mut stack, value, table, modulo, quotient, count);
+        let expected = stack.number_u32(expected);
+        stack.equals(result, true, expected, true);
+
+        stack.drop(quotient);
+        stack.drop(modulo);
+        stack.drop(table);
+        stack.op_true();
+        assert!(stack.run().success);
+    }
+
+    #[test]
+    fn test_count_bits() {
+        for value in [0, 1, 0x8000_0000, 0x0001_0f00, 0xffff_ffff, 0x0040_0000] {
+            test_count_aux(value, CountOperation::LeadingZeros, value.leading_zeros());
+            test_count_aux(value, CountOperation::TrailingZeros, value.trailing_zeros());
+            test_count_aux(value, CountOperation::Ones, value.count_ones());
+        }
+    }
+
+    fn test_rotation_aux(value: u32, amount: u32, right: bool) {
+        let expected = if right {
+            value.rotate_right(amount)
+        } else {
+            value.rotate_left(amount)
+        };
+
+        let mut stack = StackTracker::new();
+        let tables = StackTables::new(
+            &mut stack,
+            true,
+            true,
+            0xf,
+            0xf,
+            LOGIC_MASK_AND | LOGIC_MASK_OR,
+        );
+
+        let value = stack.number_u32(value);
+        let amount = stack.byte(amount as u8);
+        let complement = rotation_complement(&mut stack, &tables, amount);
+        let value_copy = stack.copy_var(value);
+        let (left, right) = if right {
+            let right = shift_value_with_tables(&mut stack, &tables, value, amount, true, false);
+            let left = shift_value_with_bits(&mut stack, value_copy, complement, false, false);
+            (left, right)
+        } else {
+            let left = shift_value_with_bits(&mut stack, value, amount, false, false);
+            let right =
+                shift_value_with_tables(&mut stack, &tables, value_copy, complement, true, false);
+            (left, right)
+        };
+        let result = logic_with_bit_extension(
+            &mut stack,
+            &tables,
+            left,
+            right,
+            StackVariable::null(),
+            LogicOperation::Or,
+        );
+
+        let expected = stack.number_u32(expected);
+        stack.equals(result, true, expected, true);
+        tables.drop(&mut stack);
+        stack.op_true();
+        assert!(stack.run().success);
+    }
+
+    #[test]
+    fn test_rotation() {
+        for amount in [0, 1, 4, 13, 31] {
+            test_rotation_aux(0x8123_4567, amount, true);
+            test_rotation_aux(0x8123_4567, amount, false);
+        }
+    }
+}
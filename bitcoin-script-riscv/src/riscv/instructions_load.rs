@@ -263,7 +263,12 @@ pub fn op_load_micro_0_aligned(
         0,
     );
 
-    let write_pc = pc_next(stack, tables, trace_read.program_counter);
+    let write_pc = pc_next(
+        stack,
+        tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
 
@@ -468,7 +473,12 @@ pub fn op_load_micro_3(
     let write_value = stack.move_var(trace_read.read_1_value);
     stack.rename(write_value, "write_value");
 
-    let write_pc = pc_next(stack, &tables, trace_read.program_counter);
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     stack.rename(write_pc, "write_pc");
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
@@ -196,7 +196,12 @@ fn op_store_sw_aligned(
     let write_value = stack.move_var(trace_read.read_2_value);
     stack.rename(write_value, "write_value");
 
-    pc_next(stack, tables, trace_read.program_counter);
+    pc_next(
+        stack,
+        tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     let micro = stack.number(0);
     stack.rename(micro, "write_micro");
 }
@@ -423,7 +428,12 @@ pub fn op_store_micro_3(
     stack_if_true.number(4);
 
     //if false, jump to the next instruction
-    pc_next(&mut stack_if_false, &tables, trace_read.program_counter);
+    pc_next(
+        &mut stack_if_false,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
     stack_if_false.number(0);
 
     let ret = stack.end_if(
@@ -0,0 +1,855 @@
+use bitcoin_script_stack::stack::{StackTracker, StackVariable};
+use bitvmx_cpu_definitions::{
+    memory::{MemoryAccessType, MemoryWitness},
+    syscall::*,
+};
+use riscv_decode::{types::SType, Instruction::Sb};
+
+use crate::riscv::{memory_alignment::*, operations::*, script_utils::*};
+
+use super::{
+    instructions::verify_memory_witness,
+    instructions_bitmanip::min_max,
+    trace::{STraceRead, STraceStep},
+};
+
+const ECALL_OPCODE: u32 = 0x0000_0073;
+
+// Every micro instruction of the ecall is described in the syscall module of the definitions
+pub fn op_ecall(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    micro: u8,
+    base_register_address: u32,
+) -> STraceStep {
+    match micro {
+        0 => op_ecall_dispatch(stack, trace_read, base_register_address),
+        MICRO_WRITE_LENGTH => op_write_length(stack, trace_read, base_register_address),
+        MICRO_WRITE_DATA => op_write_data(stack, trace_read, base_register_address),
+        MICRO_READ_AVAILABLE => op_read_available(stack, trace_read, base_register_address),
+        MICRO_READ_LENGTH => op_read_length(stack, trace_read, base_register_address),
+        MICRO_READ_INPUT => op_read_input(stack, trace_read, base_register_address),
+        MICRO_READ_SHIFT => op_read_shift(stack, trace_read, base_register_address),
+        MICRO_READ_MASK => op_read_mask(stack, trace_read, base_register_address),
+        MICRO_READ_MERGE => op_read_merge(stack, trace_read, base_register_address),
+        MICRO_READ_STORE => op_read_store(stack, trace_read, base_register_address),
+        MICRO_READ_ADVANCE => op_read_advance(stack, trace_read, base_register_address),
+        MICRO_BRK_LOWER => op_brk_lower(stack, trace_read, base_register_address),
+        MICRO_BRK_UPPER => op_brk_upper(stack, trace_read, base_register_address),
+        _ => panic!("Unreachable"),
+    }
+}
+
+fn register_address(base_register_address: u32, register: u32) -> u32 {
+    base_register_address + register * 4
+}
+
+fn verify_ecall(stack: &mut StackTracker, trace_read: &STraceRead, micro: u8) {
+    let ecall = stack.number_u32(ECALL_OPCODE);
+    stack.equals(trace_read.opcode, true, ecall, true);
+
+    let expected_micro = stack.number(micro as u32);
+    stack.equals(trace_read.micro, true, expected_micro, true);
+}
+
+fn verify_read_address(stack: &mut StackTracker, read_address: StackVariable, address: u32) {
+    let expected = stack.number_u32(address);
+    stack.equals(read_address, true, expected, true);
+}
+
+// leaves on top of the stack 1 if the var is equal to the constant
+fn equals_constant(stack: &mut StackTracker, var: StackVariable, value: u32) -> StackVariable {
+    let constant = stack.number_u32(value);
+    is_equal_to(stack, &var, &constant);
+    stack.to_altstack();
+    stack.drop(constant);
+    stack.from_altstack()
+}
+
+fn end_ecall_branch(
+    stack: &mut StackTracker,
+    stack_if_true: StackTracker,
+    stack_if_false: StackTracker,
+) -> Vec<StackVariable> {
+    stack.end_if(
+        stack_if_true,
+        stack_if_false,
+        4,
+        vec![
+            (8, "write_address".to_string()),
+            (8, "write_value".to_string()),
+            (8, "write_pc".to_string()),
+            (1, "write_micro".to_string()),
+        ],
+        0,
+    )
+}
+
+// micro 0: reads a7 and a0 and selects the outcome of the syscall
+fn op_ecall_dispatch(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 0, 0, 0);
+
+    verify_ecall(stack, trace_read, 0);
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_SYSCALL),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_2_add,
+        register_address(base_register_address, REGISTER_ARG_0),
+    );
+
+    //move vars that will be used and consumed on the branches
+    stack.move_var(trace_read.mem_witness);
+    stack.move_var(trace_read.read_1_value);
+    stack.move_var(trace_read.read_2_value);
+    stack.move_var(trace_read.program_counter);
+
+    let ret = dispatch_syscall(stack, &tables, trace_read, &SYSCALLS, base_register_address);
+
+    let trace = STraceStep::new(ret[0], ret[1], ret[2], ret[3]);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+fn dispatch_syscall(
+    stack: &mut StackTracker,
+    tables: &StackTables,
+    trace_read: &STraceRead,
+    syscalls: &[u32],
+    base_register_address: u32,
+) -> Vec<StackVariable> {
+    //any other syscall is not implemented
+    let (syscall, rest) = match syscalls.split_first() {
+        Some(split) => split,
+        None => {
+            let outcome = SyscallOutcome::Return(RETURN_ENOSYS);
+            return ecall_outcome(stack, tables, trace_read, outcome, base_register_address);
+        }
+    };
+
+    equals_constant(stack, trace_read.read_1_value, *syscall);
+    let (mut stack_if_true, mut stack_if_false) = stack.open_if();
+
+    match syscall_file_descriptors(*syscall) {
+        Some(file_descriptors) => dispatch_file_descriptor(
+            &mut stack_if_true,
+            tables,
+            trace_read,
+            *syscall,
+            file_descriptors,
+            base_register_address,
+        ),
+        None => ecall_outcome(
+            &mut stack_if_true,
+            tables,
+            trace_read,
+            syscall_outcome(*syscall, 0),
+            base_register_address,
+        ),
+    };
+
+    dispatch_syscall(
+        &mut stack_if_false,
+        tables,
+        trace_read,
+        rest,
+        base_register_address,
+    );
+
+    end_ecall_branch(stack, stack_if_true, stack_if_false)
+}
+
+fn dispatch_file_descriptor(
+    stack: &mut StackTracker,
+    tables: &StackTables,
+    trace_read: &STraceRead,
+    syscall: u32,
+    file_descriptors: &[u32],
+    base_register_address: u32,
+) -> Vec<StackVariable> {
+    //any other file descriptor is not valid for the syscall
+    let (file_descriptor, rest) = match file_descriptors.split_first() {
+        Some(split) => split,
+        None => {
+            let outcome = SyscallOutcome::Return(RETURN_EBADF);
+            return ecall_outcome(stack, tables, trace_read, outcome, base_register_address);
+        }
+    };
+
+    equals_constant(stack, trace_read.read_2_value, *file_descriptor);
+    let (mut stack_if_true, mut stack_if_false) = stack.open_if();
+
+    ecall_outcome(
+        &mut stack_if_true,
+        tables,
+        trace_read,
+        syscall_outcome(syscall, *file_descriptor),
+        base_register_address,
+    );
+    dispatch_file_descriptor(
+        &mut stack_if_false,
+        tables,
+        trace_read,
+        syscall,
+        rest,
+        base_register_address,
+    );
+
+    end_ecall_branch(stack, stack_if_true, stack_if_false)
+}
+
+// consumes the memory witness, a7, a0 and the program counter
+fn ecall_outcome(
+    stack: &mut StackTracker,
+    tables: &StackTables,
+    trace_read: &STraceRead,
+    outcome: SyscallOutcome,
+    base_register_address: u32,
+) -> Vec<StackVariable> {
+    let write_a0 = register_address(base_register_address, REGISTER_ARG_0);
+
+    let expected_witness = match outcome {
+        SyscallOutcome::Halt | SyscallOutcome::Return(_) => MemoryWitness::registers(),
+        SyscallOutcome::Nop | SyscallOutcome::Continue(_) => MemoryWitness::no_write(),
+    };
+    verify_memory_witness(stack, trace_read.mem_witness, expected_witness);
+    move_and_drop(stack, trace_read.read_1_value);
+
+    if outcome == SyscallOutcome::Halt {
+        //asserts that the return value is zero (success)
+        let success = stack.number_u32(0x00000000);
+        stack.equals(trace_read.read_2_value, false, success, true);
+    } else {
+        move_and_drop(stack, trace_read.read_2_value);
+    }
+
+    let (write_add, write_value) = match outcome {
+        SyscallOutcome::Halt => (
+            stack.number_u32(write_a0),
+            stack.move_var(trace_read.read_2_value),
+        ),
+        SyscallOutcome::Return(value) => (stack.number_u32(write_a0), stack.number_u32(value)),
+        SyscallOutcome::Nop | SyscallOutcome::Continue(_) => {
+            (stack.number_u32(0), stack.number_u32(0))
+        }
+    };
+
+    let (write_pc, write_micro) = match outcome {
+        //keep the program counter as it halted
+        SyscallOutcome::Halt => (stack.move_var(trace_read.program_counter), stack.number(0)),
+        SyscallOutcome::Continue(micro) => (
+            stack.move_var(trace_read.program_counter),
+            stack.number(micro as u32),
+        ),
+        SyscallOutcome::Nop | SyscallOutcome::Return(_) => (
+            pc_next(
+                stack,
+                tables,
+                trace_read.program_counter,
+                trace_read.instruction_size,
+            ),
+            stack.number(0),
+        ),
+    };
+
+    vec![write_add, write_value, write_pc, write_micro]
+}
+
+// micro 1: a0 = min(len, 4 - buf % 4)
+fn op_write_length(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 0, 0, 0);
+
+    verify_ecall(stack, trace_read, MICRO_WRITE_LENGTH);
+    verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::registers());
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_ARG_2),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_2_add,
+        register_address(base_register_address, REGISTER_ARG_1),
+    );
+
+    let write_add = stack.number_u32(register_address(base_register_address, REGISTER_ARG_0));
+    stack.rename(write_add, "write_add");
+
+    //available = 4 - buf % 4
+    stack.move_var(trace_read.read_2_value);
+    let (aligned, _alignment) = align_memory(stack, trace_read.read_2_value);
+    stack.to_altstack();
+    stack.drop(aligned);
+    let available = number_u32_partial(stack, 0, 7);
+    stack.number(4);
+    stack.from_altstack();
+    stack.op_sub();
+    stack.join(available);
+
+    let write_value = min_max(stack, trace_read.read_1_value, available, true, false);
+    stack.rename(write_value, "write_value");
+
+    stack.move_var(trace_read.program_counter);
+
+    //if there is nothing to write, jump to the next instruction
+    equals_constant(stack, write_value, 0);
+    let (mut stack_if_true, mut stack_if_false) = stack.open_if();
+
+    pc_next(
+        &mut stack_if_true,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    stack_if_true.number(0);
+
+    stack_if_false.number(MICRO_WRITE_DATA as u32);
+
+    let ret = stack.end_if(
+        stack_if_true,
+        stack_if_false,
+        1,
+        vec![(8, "write_pc".to_string()), (1, "write_micro".to_string())],
+        0,
+    );
+
+    let trace = STraceStep::new(write_add, write_value, ret[0], ret[1]);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+// micro 2: reads the word that contains buf, the bytes are captured by the emulator
+fn op_write_data(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 0, 0, 0);
+
+    verify_ecall(stack, trace_read, MICRO_WRITE_DATA);
+    verify_memory_witness(
+        stack,
+        trace_read.mem_witness,
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+            MemoryAccessType::Unused,
+        ),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_ARG_1),
+    );
+
+    //assert that the aligned buf is equal to read_2_address
+    stack.move_var(trace_read.read_1_value);
+    let (aligned, alignment) = align_memory(stack, trace_read.read_1_value);
+    stack.drop(alignment);
+    stack.equals(trace_read.read_2_add, true, aligned, true);
+    move_and_drop(stack, trace_read.read_2_value);
+
+    let write_add = stack.number_u32(0);
+    let write_value = stack.number_u32(0);
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    let write_micro = stack.number(0);
+
+    let trace = STraceStep::new(write_add, write_value, write_pc, write_micro);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+// micro 3: returns zero if input_pointer >= input_end
+fn op_read_available(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 0, 0, 0);
+
+    verify_ecall(stack, trace_read, MICRO_READ_AVAILABLE);
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        syscall_slot_address(base_register_address, SLOT_INPUT_POINTER),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_2_add,
+        syscall_slot_address(base_register_address, SLOT_INPUT_END),
+    );
+
+    //move vars that will be used and consumed on the branches
+    stack.move_var(trace_read.mem_witness);
+    stack.move_var(trace_read.program_counter);
+
+    stack.move_var(trace_read.read_1_value);
+    stack.move_var(trace_read.read_2_value);
+    is_lower_than(
+        stack,
+        trace_read.read_1_value,
+        trace_read.read_2_value,
+        true,
+    );
+
+    let (mut stack_if_true, mut stack_if_false) = stack.open_if();
+
+    //there is input available, continue with the read
+    verify_memory_witness(
+        &mut stack_if_true,
+        trace_read.mem_witness,
+        MemoryWitness::no_write(),
+    );
+    stack_if_true.number_u32(0);
+    stack_if_true.number_u32(0);
+    stack_if_true.move_var(trace_read.program_counter);
+    stack_if_true.number(MICRO_READ_LENGTH as u32);
+
+    //end of file
+    verify_memory_witness(
+        &mut stack_if_false,
+        trace_read.mem_witness,
+        MemoryWitness::registers(),
+    );
+    stack_if_false.number_u32(register_address(base_register_address, REGISTER_ARG_0));
+    stack_if_false.number_u32(0);
+    pc_next(
+        &mut stack_if_false,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    stack_if_false.number(0);
+
+    let ret = end_ecall_branch(stack, stack_if_true, stack_if_false);
+
+    let trace = STraceStep::new(ret[0], ret[1], ret[2], ret[3]);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+// micro 4: a0 = 1 if a byte is going to be read
+fn op_read_length(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 0, 0, 0);
+
+    verify_ecall(stack, trace_read, MICRO_READ_LENGTH);
+    verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::rur());
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_ARG_2),
+    );
+
+    //read_2 is not used
+    move_and_drop(stack, trace_read.read_2_add);
+    move_and_drop(stack, trace_read.read_2_value);
+
+    let write_add = stack.number_u32(register_address(base_register_address, REGISTER_ARG_0));
+    stack.rename(write_add, "write_add");
+
+    equals_constant(stack, trace_read.read_1_value, 0);
+    stack.to_altstack();
+    move_and_drop(stack, trace_read.read_1_value);
+
+    //write_value = len != 0
+    let write_value = number_u32_partial(stack, 0, 7);
+    stack.from_altstack();
+    stack.op_dup();
+    stack.to_altstack();
+    stack.op_not();
+    stack.join(write_value);
+    stack.rename(write_value, "write_value");
+
+    stack.move_var(trace_read.program_counter);
+
+    //if the length is zero, jump to the next instruction
+    stack.from_altstack();
+    let (mut stack_if_true, mut stack_if_false) = stack.open_if();
+
+    pc_next(
+        &mut stack_if_true,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    stack_if_true.number(0);
+
+    stack_if_false.number(MICRO_READ_INPUT as u32);
+
+    let ret = stack.end_if(
+        stack_if_true,
+        stack_if_false,
+        1,
+        vec![(8, "write_pc".to_string()), (1, "write_micro".to_string())],
+        0,
+    );
+
+    let trace = STraceStep::new(write_add, write_value, ret[0], ret[1]);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+// micro 5: aux_2 = byte at input_pointer
+fn op_read_input(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    verify_ecall(stack, trace_read, MICRO_READ_INPUT);
+    verify_memory_witness(
+        stack,
+        trace_read.mem_witness,
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+            MemoryAccessType::Register,
+        ),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        syscall_slot_address(base_register_address, SLOT_INPUT_POINTER),
+    );
+
+    let write_add = stack.number_u32(base_register_address + 0x84); //address of AUX2
+    stack.rename(write_add, "write_add");
+
+    //assert that the aligned input pointer is equal to read_2_address
+    stack.move_var(trace_read.read_1_value);
+    let (aligned, _alignment) = align_memory(stack, trace_read.read_1_value);
+    stack.to_altstack();
+    stack.equals(trace_read.read_2_add, true, aligned, true);
+
+    //choose the byte as lbu does
+    stack.move_var(trace_read.read_2_value);
+    let alignment = stack.from_altstack();
+    let write_value = choose_nibbles(stack, trace_read.read_2_value, alignment, 2, 0, 6, true, 0);
+    stack.rename(write_value, "write_value");
+
+    let write_pc = stack.move_var(trace_read.program_counter);
+    stack.rename(write_pc, "write_pc");
+    let write_micro = stack.number(MICRO_READ_SHIFT as u32);
+    stack.rename(write_micro, "write_micro");
+
+    STraceStep::new(write_add, write_value, write_pc, write_micro)
+}
+
+// micro 6: aux_2 = aux_2 << (buf % 4)
+fn op_read_shift(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    verify_ecall(stack, trace_read, MICRO_READ_SHIFT);
+    verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::registers());
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_ARG_1),
+    );
+    verify_read_address(stack, trace_read.read_2_add, base_register_address + 0x84);
+
+    let write_add = stack.number_u32(base_register_address + 0x84); //address of AUX2
+    stack.rename(write_add, "write_add");
+
+    stack.move_var(trace_read.read_1_value);
+    let (aligned, _alignment) = align_memory(stack, trace_read.read_1_value);
+    stack.to_altstack();
+    stack.drop(aligned);
+
+    //mask the byte and rotate it to its position in the word
+    stack.move_var(trace_read.read_2_value);
+    let mask = stack.number_u32(0x0000_0011);
+    let masked_value = mask_value(stack, trace_read.read_2_value, mask);
+    let alignment = stack.from_altstack();
+    let write_value = left_rotate(stack, masked_value, alignment);
+    stack.rename(write_value, "write_value");
+
+    let write_pc = stack.move_var(trace_read.program_counter);
+    stack.rename(write_pc, "write_pc");
+    let write_micro = stack.number(MICRO_READ_MASK as u32);
+    stack.rename(write_micro, "write_micro");
+
+    STraceStep::new(write_add, write_value, write_pc, write_micro)
+}
+
+// micro 7: aux_1 = mem[buf] with the byte at buf cleared
+fn op_read_mask(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    verify_ecall(stack, trace_read, MICRO_READ_MASK);
+    verify_memory_witness(
+        stack,
+        trace_read.mem_witness,
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+            MemoryAccessType::Register,
+        ),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_ARG_1),
+    );
+
+    //assert that the aligned buf is equal to read_2_address
+    stack.move_var(trace_read.read_1_value);
+    let (aligned, _alignment) = align_memory(stack, trace_read.read_1_value);
+    stack.to_altstack();
+    stack.equals(trace_read.read_2_add, true, aligned, true);
+
+    let write_add = stack.number_u32(base_register_address + 0x80); //address of AUX1
+    stack.rename(write_add, "write_add");
+
+    //clear the byte as sb does
+    stack.move_var(trace_read.read_2_value);
+    let mask_table = create_alignment_table(stack, &Sb(SType(0)), 1);
+    stack.from_altstack();
+    let mask = mask_table.peek(stack);
+    mask_table.drop(stack);
+
+    let write_value = mask_value(stack, trace_read.read_2_value, mask);
+    stack.rename(write_value, "write_value");
+
+    let write_pc = stack.move_var(trace_read.program_counter);
+    stack.rename(write_pc, "write_pc");
+    let write_micro = stack.number(MICRO_READ_MERGE as u32);
+    stack.rename(write_micro, "write_micro");
+
+    STraceStep::new(write_add, write_value, write_pc, write_micro)
+}
+
+// micro 8: aux_1 = aux_1 | aux_2
+fn op_read_merge(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    verify_ecall(stack, trace_read, MICRO_READ_MERGE);
+    verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::registers());
+    verify_read_address(stack, trace_read.read_1_add, base_register_address + 0x80);
+    verify_read_address(stack, trace_read.read_2_add, base_register_address + 0x84);
+
+    let write_add = stack.number_u32(base_register_address + 0x80); //address of AUX1
+    stack.rename(write_add, "write_add");
+
+    //as the read_1_value and read_2_value are masked, we are going to just add them instead of doing or
+    for _ in 0..8 {
+        stack.move_var_sub_n(trace_read.read_1_value, 0);
+        stack.move_var_sub_n(trace_read.read_2_value, 0);
+        stack.op_add();
+    }
+    let write_value = stack.join_in_stack(8, None, Some("write_value"));
+
+    let write_pc = stack.move_var(trace_read.program_counter);
+    stack.rename(write_pc, "write_pc");
+    let write_micro = stack.number(MICRO_READ_STORE as u32);
+    stack.rename(write_micro, "write_micro");
+
+    STraceStep::new(write_add, write_value, write_pc, write_micro)
+}
+
+// micro 9: mem[buf] = aux_1
+fn op_read_store(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    verify_ecall(stack, trace_read, MICRO_READ_STORE);
+    verify_memory_witness(
+        stack,
+        trace_read.mem_witness,
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+        ),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_ARG_1),
+    );
+    verify_read_address(stack, trace_read.read_2_add, base_register_address + 0x80);
+
+    //write_address = aligned(buf)
+    stack.move_var(trace_read.read_1_value);
+    let (write_add, alignment) = align_memory(stack, trace_read.read_1_value);
+    stack.drop(alignment);
+    stack.rename(write_add, "write_add");
+
+    let write_value = stack.move_var(trace_read.read_2_value);
+    stack.rename(write_value, "write_value");
+
+    let write_pc = stack.move_var(trace_read.program_counter);
+    stack.rename(write_pc, "write_pc");
+    let write_micro = stack.number(MICRO_READ_ADVANCE as u32);
+    stack.rename(write_micro, "write_micro");
+
+    STraceStep::new(write_add, write_value, write_pc, write_micro)
+}
+
+// micro 10: input_pointer = input_pointer + 1
+fn op_read_advance(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 0, 0, 0);
+
+    verify_ecall(stack, trace_read, MICRO_READ_ADVANCE);
+    verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::rur());
+
+    let input_pointer = syscall_slot_address(base_register_address, SLOT_INPUT_POINTER);
+    verify_read_address(stack, trace_read.read_1_add, input_pointer);
+
+    //read_2 is not used
+    move_and_drop(stack, trace_read.read_2_add);
+    move_and_drop(stack, trace_read.read_2_value);
+
+    let write_add = stack.number_u32(input_pointer);
+    stack.rename(write_add, "write_add");
+
+    let add_1 = stack.number(1);
+    let bit_extension = stack.number(0);
+    let write_value = add_with_bit_extension(
+        stack,
+        &tables,
+        trace_read.read_1_value,
+        add_1,
+        bit_extension,
+    );
+    stack.rename(write_value, "write_value");
+
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    let write_micro = stack.number(0);
+    stack.rename(write_micro, "write_micro");
+
+    let trace = STraceStep::new(write_add, write_value, write_pc, write_micro);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
+
+// micro 11: aux_1 = max(addr, heap_start)
+fn op_brk_lower(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    verify_ecall(stack, trace_read, MICRO_BRK_LOWER);
+    verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::registers());
+    verify_read_address(
+        stack,
+        trace_read.read_1_add,
+        register_address(base_register_address, REGISTER_ARG_0),
+    );
+    verify_read_address(
+        stack,
+        trace_read.read_2_add,
+        syscall_slot_address(base_register_address, SLOT_HEAP_START),
+    );
+
+    let write_add = stack.number_u32(base_register_address + 0x80); //address of AUX1
+    stack.rename(write_add, "write_add");
+
+    let write_value = min_max(
+        stack,
+        trace_read.read_1_value,
+        trace_read.read_2_value,
+        true,
+        true,
+    );
+    stack.rename(write_value, "write_value");
+
+    let write_pc = stack.move_var(trace_read.program_counter);
+    stack.rename(write_pc, "write_pc");
+    let write_micro = stack.number(MICRO_BRK_UPPER as u32);
+    stack.rename(write_micro, "write_micro");
+
+    STraceStep::new(write_add, write_value, write_pc, write_micro)
+}
+
+// micro 12: a0 = min(aux_1, heap_end)
+fn op_brk_upper(
+    stack: &mut StackTracker,
+    trace_read: &STraceRead,
+    base_register_address: u32,
+) -> STraceStep {
+    let tables = StackTables::new(stack, true, true, 0, 0, 0);
+
+    verify_ecall(stack, trace_read, MICRO_BRK_UPPER);
+    verify_memory_witness(stack, trace_read.mem_witness, MemoryWitness::registers());
+    verify_read_address(stack, trace_read.read_1_add, base_register_address + 0x80);
+    verify_read_address(
+        stack,
+        trace_read.read_2_add,
+        syscall_slot_address(base_register_address, SLOT_HEAP_END),
+    );
+
+    let write_add = stack.number_u32(register_address(base_register_address, REGISTER_ARG_0));
+    stack.rename(write_add, "write_add");
+
+    let write_value = min_max(
+        stack,
+        trace_read.read_1_value,
+        trace_read.read_2_value,
+        true,
+        false,
+    );
+    stack.rename(write_value, "write_value");
+
+    let write_pc = pc_next(
+        stack,
+        &tables,
+        trace_read.program_counter,
+        trace_read.instruction_size,
+    );
+    let write_micro = stack.number(0);
+    stack.rename(write_micro, "write_micro");
+
+    let trace = STraceStep::new(write_add, write_value, write_pc, write_micro);
+    trace.to_altstack(stack);
+    tables.drop(stack);
+    trace.from_altstack(stack);
+    trace
+}
@@ -27,6 +27,13 @@ pub fn load_lower_half_nibble_table(stack: &mut StackTracker) -> StackVariable {
     stack.join_in_stack(16, None, Some("lower_half_nibble_table"))
 }
 
+pub fn load_lsb_table(stack: &mut StackTracker) -> StackVariable {
+    for i in (0..16).rev() {
+        stack.number(i & 1);
+    }
+    stack.join_in_stack(16, None, Some("lsb_table"))
+}
+
 pub fn load_upper_half_nibble_table(stack: &mut StackTracker) -> StackVariable {
     for i in (0..16).rev() {
         stack.number(i >> 2);
@@ -61,6 +68,14 @@ pub fn verify_alignment(stack: &mut StackTracker, mem_address: StackVariable) {
     stack.drop(lower_half_nibble_table);
 }
 
+// instructions can be 2-byte aligned when the compressed extension is used
+pub fn verify_instruction_alignment(stack: &mut StackTracker, pc: StackVariable) {
+    let lsb_table = load_lsb_table(stack);
+    is_aligned(stack, pc, false, &lsb_table);
+    stack.op_verify();
+    stack.drop(lsb_table);
+}
+
 pub fn clear_least_significant_bit(
     stack: &mut StackTracker,
     mem_address: StackVariable,
@@ -284,6 +299,23 @@ mod tests {
         assert!(test_is_aligned_helper(0x0000_0004));
     }
 
+    fn test_instruction_alignment_helper(pc: u32) -> bool {
+        let mut stack = StackTracker::new();
+        let pc = stack.number_u32(pc);
+        verify_instruction_alignment(&mut stack, pc);
+        stack.drop(pc);
+        stack.op_true();
+        stack.run().success
+    }
+
+    #[test]
+    fn test_instruction_alignment() {
+        assert!(test_instruction_alignment_helper(0x0000_1000));
+        assert!(!test_instruction_alignment_helper(0x0000_1001));
+        assert!(test_instruction_alignment_helper(0x0000_1002));
+        assert!(!test_instruction_alignment_helper(0x0000_1003));
+    }
+
     #[test]
     fn test_align_memory() {
         let mut stack = StackTracker::new();
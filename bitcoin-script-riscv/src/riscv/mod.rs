@@ -1,9 +1,13 @@
+pub mod bitmanip;
 pub mod challenges;
+pub mod compressed;
 pub mod decoder;
 pub mod instruction_mapping;
 pub mod instructions;
+pub mod instructions_bitmanip;
 pub mod instructions_load;
 pub mod instructions_store;
+pub mod instructions_syscall;
 pub mod memory_alignment;
 pub mod operations;
 pub mod script_utils;
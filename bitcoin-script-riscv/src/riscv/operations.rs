@@ -10,11 +10,16 @@ pub fn sort_nibbles(stack: &mut StackTracker) {
     stack.from_altstack();
 }
 
-pub fn pc_next(stack: &mut StackTracker, tables: &StackTables, pc: StackVariable) -> StackVariable {
+pub fn pc_next(
+    stack: &mut StackTracker,
+    tables: &StackTables,
+    pc: StackVariable,
+    instruction_size: u32,
+) -> StackVariable {
     stack.set_breakpoint("pc_next");
     stack.move_var(pc);
     stack.explode(pc);
-    stack.number(4);
+    stack.number(instruction_size);
 
     let mut last = StackVariable::null();
     for i in 0..8 {
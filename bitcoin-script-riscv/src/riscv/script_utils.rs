@@ -798,9 +798,18 @@ impl WordTable {
 
     //assume index on top of the stack
     pub fn peek(&self, stack: &mut StackTracker) -> StackVariable {
+        self.peek_with_extra(stack, 0)
+    }
+
+    //assume index on top of the stack and `extra` elements between the index and the table
+    pub fn peek_with_extra(&self, stack: &mut StackTracker, extra: u32) -> StackVariable {
         stack.number(1);
         stack.op_add();
         multiply_by_8(stack);
+        if extra > 0 {
+            stack.number(extra);
+            stack.op_add();
+        }
 
         for i in 0..8 {
             if i > 0 {
@@ -1512,6 +1521,61 @@ pub fn verify_wrong_chunk_value(
     chunk_table.drop(stack);
 }
 
+// Code chunks carry the first word of the next chunk as the last element, so the fetch window
+// of an instruction that is not 4-byte aligned can be built for the whole chunk
+pub fn verify_wrong_opcode(
+    stack: &mut StackTracker,
+    tables: &StackTables,
+    chunk: &Chunk,
+    pc: StackVariable,
+    opcode: StackVariable,
+) {
+    let (start, end) = chunk.range();
+    address_in_range(stack, &(start, end - 4), &pc);
+    stack.op_verify();
+
+    // 1 if pc % 4 == 2
+    stack.copy_var_sub_n(pc, 7);
+    stack.get_value_from_table(tables.lshift.shift_2, None);
+    let upper_half = if_greater(stack, 7, 1, 0);
+
+    let chunk_table = WordTable::new(stack, chunk.data.clone());
+
+    let base_addr = stack.number_u32(chunk.base_addr);
+    let offset = sub(stack, tables, pc, base_addr);
+
+    let index = static_right_shift_2(stack, tables, offset);
+    let index = var_to_number(stack, index);
+
+    stack.copy_var(index);
+    stack.number(1);
+    stack.op_add();
+    let next_word = chunk_table.peek_with_extra(stack, 1);
+
+    stack.move_var(index);
+    let word = chunk_table.peek_with_extra(stack, 8);
+
+    // upper half of the word followed by the lower half of the next word
+    let word_copy = stack.copy_var(word);
+    stack.move_var(next_word);
+    let next_word_parts = stack.explode(next_word);
+    stack.move_var(word_copy);
+    let word_parts = stack.explode(word_copy);
+    for i in (4..8).rev() {
+        stack.drop(word_parts[i]);
+    }
+    for part in next_word_parts.iter().take(4) {
+        stack.move_var(*part);
+        stack.drop(*part);
+    }
+    let shifted_word = stack.join_count(next_word_parts[4], 7);
+
+    let [real_opcode] = get_selected_vars(stack, [shifted_word], [word], upper_half);
+
+    stack.equality(real_opcode, true, opcode, true, false, true);
+    chunk_table.drop(stack);
+}
+
 pub fn get_selected_vars<const N: usize>(
     stack: &mut StackTracker,
     vars_1: [StackVariable; N],
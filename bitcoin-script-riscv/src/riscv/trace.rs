@@ -11,6 +11,8 @@ pub struct STraceRead {
     pub program_counter: StackVariable,
     pub micro: StackVariable,
     pub opcode: StackVariable,
+    // not committed, it is given by the key of the instruction being verified
+    pub instruction_size: u32,
 }
 
 impl Default for STraceRead {
@@ -24,6 +26,7 @@ impl Default for STraceRead {
             program_counter: StackVariable::null(),
             micro: StackVariable::null(),
             opcode: StackVariable::null(),
+            instruction_size: 4,
         }
     }
 }
@@ -47,6 +50,7 @@ impl STraceRead {
             program_counter,
             micro,
             opcode,
+            instruction_size: 4,
         }
     }
 
@@ -87,6 +91,7 @@ impl STraceRead {
             program_counter,
             micro,
             opcode,
+            instruction_size: 4,
         }
     }
 
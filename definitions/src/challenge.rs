@@ -47,6 +47,10 @@ pub enum ChallengeType {
         prover_read_2: TraceRead,
         address: u32,
         input_for_address: u32,
+        // owner of the input containing the address, as defined in the program definition
+        owner: String,
+        // addresses of the inputs of the owner, the input word has to be in its commitment
+        owner_input: SectionDefinition,
     },
     InitializedData {
         prover_read_1: TraceRead,
@@ -67,6 +71,15 @@ pub enum ChallengeType {
         address: u32,
         input_for_address: u32,
     },
+    // the output word is read after the last step, so it is challenged as a ReadValue
+    OutputData {
+        prover_output: TraceRead,
+        prover_hash: String,
+        trace: TraceStep,
+        prover_next_hash: String,
+        prover_write_step_tk: u64,
+        prover_last_step_tk: u64,
+    },
     AddressesSections {
         prover_read_1: TraceRead,
         prover_read_2: TraceRead,
@@ -209,6 +222,8 @@ pub enum EmulatorResultType {
         last_step: u64,
         last_hash: String,
         halt: Option<(u32, u64)>, // (return value, step)
+        #[serde(default)]
+        output: Vec<TraceRead>, // words of the output section with their last write
     },
     VerifierCheckExecutionResult {
         step: Option<u64>, // step to challenge
@@ -253,13 +268,14 @@ impl EmulatorResultType {
 
     pub fn as_prover_execute(
         &self,
-    ) -> Result<(u64, String, Option<(u32, u64)>), EmulatorResultError> {
+    ) -> Result<(u64, String, Option<(u32, u64)>, Vec<TraceRead>), EmulatorResultError> {
         match self {
             EmulatorResultType::ProverExecuteResult {
                 last_step,
                 last_hash,
                 halt,
-            } => Ok((*last_step, last_hash.clone(), halt.clone())),
+                output,
+            } => Ok((*last_step, last_hash.clone(), halt.clone(), output.clone())),
             _ => Err(EmulatorResultError::GenericError(
                 "Expected ProverExecuteResult".to_string(),
             )),
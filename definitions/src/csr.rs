@@ -0,0 +1,58 @@
+// Deterministic CSR counters
+//
+// Only the user level counters are supported and all of them are read-only.
+// The counters are derived from the step being executed, so the cycle, time and
+// instret counters always hold the number of steps executed before the current one.
+// The counters are not kept in memory: the trace of a CSR access only writes rd and the script
+// takes the value from the conflict step committed by the prover.
+
+pub const CSR_CYCLE: u32 = 0xC00;
+pub const CSR_TIME: u32 = 0xC01;
+pub const CSR_INSTRET: u32 = 0xC02;
+pub const CSR_CYCLEH: u32 = 0xC80;
+pub const CSR_TIMEH: u32 = 0xC81;
+pub const CSR_INSTRETH: u32 = 0xC82;
+
+pub const CSRS: [u32; 6] = [
+    CSR_CYCLE,
+    CSR_TIME,
+    CSR_INSTRET,
+    CSR_CYCLEH,
+    CSR_TIMEH,
+    CSR_INSTRETH,
+];
+
+pub fn csr_name(csr: u32) -> Option<&'static str> {
+    match csr {
+        CSR_CYCLE => Some("cycle"),
+        CSR_TIME => Some("time"),
+        CSR_INSTRET => Some("instret"),
+        CSR_CYCLEH => Some("cycleh"),
+        CSR_TIMEH => Some("timeh"),
+        CSR_INSTRETH => Some("instreth"),
+        _ => None,
+    }
+}
+
+pub fn csr_value(csr: u32, step: u64) -> Option<u32> {
+    let counter = step - 1;
+    match csr {
+        CSR_CYCLE | CSR_TIME | CSR_INSTRET => Some(counter as u32),
+        CSR_CYCLEH | CSR_TIMEH | CSR_INSTRETH => Some((counter >> 32) as u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csr_value() {
+        assert_eq!(csr_value(CSR_CYCLE, 1), Some(0));
+        assert_eq!(csr_value(CSR_INSTRET, 11), Some(10));
+        assert_eq!(csr_value(CSR_CYCLEH, 0x1_0000_0002), Some(1));
+        assert_eq!(csr_value(CSR_TIME, 0x1_0000_0002), Some(1));
+        assert_eq!(csr_value(0x300, 1), None);
+    }
+}
@@ -1,5 +1,7 @@
 pub mod challenge;
+pub mod csr;
 pub mod memory;
+pub mod syscall;
 pub mod trace;
 
 pub mod constants {
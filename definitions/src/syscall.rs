@@ -0,0 +1,171 @@
+// Syscall table
+//
+// The ecall dispatches on a7 using the linux numbers, the arguments are taken from a0..a2
+// and the result is returned on a0 (negative errno on failure).
+// Every syscall is executed as a sequence of micro instructions of the ecall, so each step keeps
+// the usual shape of the trace (two reads and one write) and can be verified on its own.
+// The dispatch (micro 0) reads a7 and a0 and either completes the syscall or jumps to the
+// first micro instruction of the syscall. The transfers are short: `write` writes the bytes
+// of buf up to the end of its word and `read` reads a single byte, so the callers must loop
+// as they would do with any short transfer.
+
+pub const SYSCALL_READ: u32 = 63;
+pub const SYSCALL_WRITE: u32 = 64;
+pub const SYSCALL_EXIT: u32 = 93;
+// legacy: prints the char stored at 0xA000_1000 when the stdout is enabled
+pub const SYSCALL_PRINT_CHAR: u32 = 116;
+pub const SYSCALL_BRK: u32 = 214;
+
+pub const SYSCALLS: [u32; 5] = [
+    SYSCALL_READ,
+    SYSCALL_WRITE,
+    SYSCALL_EXIT,
+    SYSCALL_PRINT_CHAR,
+    SYSCALL_BRK,
+];
+
+pub const FD_STDIN: u32 = 0;
+pub const FD_STDOUT: u32 = 1;
+pub const FD_STDERR: u32 = 2;
+
+pub const RETURN_EBADF: u32 = -9i32 as u32;
+pub const RETURN_ENOSYS: u32 = -38i32 as u32;
+
+// registers used by the calling convention
+pub const REGISTER_SYSCALL: u32 = 17; // a7
+pub const REGISTER_ARG_0: u32 = 10; // a0, also used for the return value
+pub const REGISTER_ARG_1: u32 = 11; // a1
+pub const REGISTER_ARG_2: u32 = 12; // a2
+
+// write(fd, buf, len)
+//  1: a0 = min(len, 4 - buf % 4)
+//  2: reads the word that contains buf and captures a0 bytes from buf
+pub const MICRO_WRITE_LENGTH: u8 = 1;
+pub const MICRO_WRITE_DATA: u8 = 2;
+// read(fd, buf, len)
+//  3: returns 0 if input_pointer >= input_end
+//  4: a0 = len != 0
+//  5: aux_2 = byte at input_pointer
+//  6: aux_2 = aux_2 << (buf % 4)
+//  7: aux_1 = mem[buf] with the byte at buf cleared
+//  8: aux_1 = aux_1 | aux_2
+//  9: mem[buf] = aux_1
+// 10: input_pointer = input_pointer + 1
+pub const MICRO_READ_AVAILABLE: u8 = 3;
+pub const MICRO_READ_LENGTH: u8 = 4;
+pub const MICRO_READ_INPUT: u8 = 5;
+pub const MICRO_READ_SHIFT: u8 = 6;
+pub const MICRO_READ_MASK: u8 = 7;
+pub const MICRO_READ_MERGE: u8 = 8;
+pub const MICRO_READ_STORE: u8 = 9;
+pub const MICRO_READ_ADVANCE: u8 = 10;
+// brk(addr), the requested break is clamped to the heap section
+// 11: aux_1 = max(addr, heap_start)
+// 12: a0 = min(aux_1, heap_end)
+pub const MICRO_BRK_LOWER: u8 = 11;
+pub const MICRO_BRK_UPPER: u8 = 12;
+
+pub const SYSCALL_MICROS: u8 = 13;
+
+// The state of the syscalls is mapped after the aux registers so it can be read and written as a register.
+// The heap bounds and the end of the input are set when the program is loaded and never change.
+// The slots have their own section with the initial values, so they are committed with the
+// initialized data and a wrong initial read can be challenged as any other initialized word.
+pub const SLOT_HEAP_START: u32 = 0;
+pub const SLOT_HEAP_END: u32 = 1;
+pub const SLOT_INPUT_POINTER: u32 = 2;
+pub const SLOT_INPUT_END: u32 = 3;
+pub const SYSCALL_SLOTS: u32 = 4;
+
+// offset from the registers base address: 32 registers + 2 aux registers
+pub const SYSCALL_SLOTS_OFFSET: u32 = (32 + 2) * 4;
+
+pub fn syscall_slot_index(slot: u32) -> u32 {
+    SYSCALL_SLOTS_OFFSET / 4 + slot
+}
+
+pub fn syscall_slot_address(registers_base_address: u32, slot: u32) -> u32 {
+    registers_base_address + SYSCALL_SLOTS_OFFSET + slot * 4
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallOutcome {
+    // stops the execution, the pc remains in the ecall
+    Halt,
+    // continues with the next instruction
+    Nop,
+    // returns the value on a0 and continues with the next instruction
+    Return(u32),
+    // the syscall continues on the given micro instruction
+    Continue(u8),
+}
+
+// file descriptors accepted by the syscall, None if it doesn't use one
+pub fn syscall_file_descriptors(syscall: u32) -> Option<&'static [u32]> {
+    match syscall {
+        SYSCALL_READ => Some(&[FD_STDIN]),
+        SYSCALL_WRITE => Some(&[FD_STDOUT, FD_STDERR]),
+        _ => None,
+    }
+}
+
+// result of the dispatch for the syscall in a7 and the first argument in a0
+pub fn syscall_outcome(syscall: u32, arg_0: u32) -> SyscallOutcome {
+    if let Some(file_descriptors) = syscall_file_descriptors(syscall) {
+        if !file_descriptors.contains(&arg_0) {
+            return SyscallOutcome::Return(RETURN_EBADF);
+        }
+    }
+
+    match syscall {
+        SYSCALL_EXIT => SyscallOutcome::Halt,
+        SYSCALL_PRINT_CHAR => SyscallOutcome::Nop,
+        SYSCALL_WRITE => SyscallOutcome::Continue(MICRO_WRITE_LENGTH),
+        SYSCALL_READ => SyscallOutcome::Continue(MICRO_READ_AVAILABLE),
+        SYSCALL_BRK => SyscallOutcome::Continue(MICRO_BRK_LOWER),
+        _ => SyscallOutcome::Return(RETURN_ENOSYS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syscall_slot_address() {
+        assert_eq!(
+            syscall_slot_address(0xF000_0000, SLOT_HEAP_START),
+            0xF000_0088
+        );
+        assert_eq!(
+            syscall_slot_address(0xF000_0000, SLOT_INPUT_END),
+            0xF000_0094
+        );
+        assert_eq!(syscall_slot_index(SLOT_HEAP_START), 34);
+    }
+
+    #[test]
+    fn test_syscall_outcome() {
+        assert_eq!(syscall_outcome(SYSCALL_EXIT, 0), SyscallOutcome::Halt);
+        assert_eq!(
+            syscall_outcome(SYSCALL_WRITE, FD_STDERR),
+            SyscallOutcome::Continue(MICRO_WRITE_LENGTH)
+        );
+        assert_eq!(
+            syscall_outcome(SYSCALL_WRITE, FD_STDIN),
+            SyscallOutcome::Return(RETURN_EBADF)
+        );
+        assert_eq!(
+            syscall_outcome(SYSCALL_READ, FD_STDIN),
+            SyscallOutcome::Continue(MICRO_READ_AVAILABLE)
+        );
+        assert_eq!(
+            syscall_outcome(SYSCALL_BRK, 0x1234),
+            SyscallOutcome::Continue(MICRO_BRK_LOWER)
+        );
+        assert_eq!(
+            syscall_outcome(57, 3),
+            SyscallOutcome::Return(RETURN_ENOSYS)
+        );
+    }
+}
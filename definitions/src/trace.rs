@@ -4,6 +4,9 @@ use crate::memory::MemoryWitness;
 use blake3::Hasher;
 use serde::{Deserialize, Serialize};
 
+// size of a step and its hash encoded by TraceRWStep::to_binary
+pub const TRACE_STEP_BINARY_SIZE: usize = 88;
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ProgramCounter {
     address: u32,
@@ -28,16 +31,24 @@ impl ProgramCounter {
     }
 
     pub fn next_address(&mut self) {
-        self.address += 4;
+        self.next_address_with_size(4);
+    }
+
+    pub fn next_address_with_size(&mut self, instruction_size: u32) {
+        self.address += instruction_size;
         self.micro = 0;
     }
 
     pub fn next_micro(&mut self) {
         self.micro += 1;
     }
+
+    pub fn jump_micro(&mut self, micro: u8) {
+        self.micro = micro;
+    }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct TraceRead {
     pub address: u32,
     pub value: u32,
@@ -185,6 +196,95 @@ impl TraceRWStep {
             self.mem_witness.byte()
         )
     }
+
+    // fixed width (big-endian) encoding of the step followed by the hash up to it
+    pub fn to_binary(&self, hash: &[u8; 20]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(TRACE_STEP_BINARY_SIZE);
+        bytes.extend(self.step_number.to_be_bytes());
+        for read in [&self.read_1, &self.read_2] {
+            bytes.extend(read.address.to_be_bytes());
+            bytes.extend(read.value.to_be_bytes());
+            bytes.extend(read.last_step.to_be_bytes());
+        }
+        bytes.extend(self.read_pc.pc.get_address().to_be_bytes());
+        bytes.push(self.read_pc.pc.get_micro());
+        bytes.extend(self.read_pc.opcode.to_be_bytes());
+        bytes.extend(self.trace_step.to_bytes());
+        bytes.push(self.witness.is_some() as u8);
+        bytes.extend(self.witness.unwrap_or(0).to_be_bytes());
+        bytes.push(self.mem_witness.byte());
+        bytes.extend(hash);
+        bytes
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<(Self, [u8; 20]), String> {
+        if bytes.len() != TRACE_STEP_BINARY_SIZE {
+            return Err(format!(
+                "Invalid size of encoded step: {} expected: {}",
+                bytes.len(),
+                TRACE_STEP_BINARY_SIZE
+            ));
+        }
+
+        let mut reader = BinaryReader { bytes, position: 0 };
+        let step_number = reader.u64();
+        let read_1 = TraceRead::new(reader.u32(), reader.u32(), reader.u64());
+        let read_2 = TraceRead::new(reader.u32(), reader.u32(), reader.u64());
+        let read_pc =
+            TraceReadPC::new(ProgramCounter::new(reader.u32(), reader.u8()), reader.u32());
+        let trace_step = TraceStep::new(
+            TraceWrite::new(reader.u32(), reader.u32()),
+            ProgramCounter::new(reader.u32(), reader.u8()),
+        );
+        let witness = match (reader.u8(), reader.u32()) {
+            (0, _) => None,
+            (1, witness) => Some(witness),
+            (flag, _) => return Err(format!("Invalid witness flag: {}", flag)),
+        };
+        let mem_witness = MemoryWitness::from_byte(reader.u8());
+        let hash = reader.take::<20>();
+
+        Ok((
+            TraceRWStep::new(
+                step_number,
+                read_1,
+                read_2,
+                read_pc,
+                trace_step,
+                witness,
+                mem_witness,
+            ),
+            hash,
+        ))
+    }
+}
+
+// the size is checked before reading
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BinaryReader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let value = self.bytes[self.position..self.position + N]
+            .try_into()
+            .unwrap();
+        self.position += N;
+        value
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_be_bytes(self.take())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_be_bytes(self.take())
+    }
 }
 
 impl FromStr for TraceRWStep {
@@ -241,3 +341,43 @@ pub fn hash_to_string(hash: &[u8; 20]) -> String {
         .map(|byte| format!("{:02x}", byte))
         .collect::<String>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::LAST_STEP_INIT, memory::MemoryAccessType};
+
+    #[test]
+    fn test_binary_encoding() {
+        let trace = TraceRWStep::new(
+            0x0102_0304_0506_0708,
+            TraceRead::new(0xf000_0004, 0x1111_1111, 7),
+            TraceRead::new(0x0000_2000, 0x2222_2222, LAST_STEP_INIT),
+            TraceReadPC::new(ProgramCounter::new(0x0000_1000, 3), 0x00b5_2223),
+            TraceStep::new(
+                TraceWrite::new(0x0000_2004, 0x3333_3333),
+                ProgramCounter::new(0x0000_1004, 0),
+            ),
+            Some(0x4444_4444),
+            MemoryWitness::new(
+                MemoryAccessType::Register,
+                MemoryAccessType::Memory,
+                MemoryAccessType::Unused,
+            ),
+        );
+        let hash = [0xab; 20];
+
+        let bytes = trace.to_binary(&hash);
+        assert_eq!(bytes.len(), TRACE_STEP_BINARY_SIZE);
+        let (decoded, decoded_hash) = TraceRWStep::from_binary(&bytes).unwrap();
+        assert_eq!(decoded.to_csv(), trace.to_csv());
+        assert_eq!(decoded.witness, trace.witness);
+        assert_eq!(decoded_hash, hash);
+
+        let (decoded, _) =
+            TraceRWStep::from_binary(&TraceRWStep::default().to_binary(&hash)).unwrap();
+        assert_eq!(decoded.witness, None);
+
+        assert!(TraceRWStep::from_binary(&bytes[1..]).is_err());
+    }
+}
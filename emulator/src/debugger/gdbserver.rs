@@ -0,0 +1,417 @@
+// GDB remote serial protocol stub
+//
+// Serves a single gdb connection on a local port. It supports the packets needed to debug with
+// the ELF symbols: stop reason, register and memory reads, software breakpoints, single-step
+// and continue. Any other packet gets the empty reply, that gdb understands as unsupported.
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use tracing::{error, info};
+
+use super::{Debugger, StopReason, REGISTER_NAMES};
+use crate::{EmulatorError, ExecutionResult};
+
+const REGISTER_PC: u32 = 32;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// sent by gdb (ctrl-c) to interrupt the execution
+const INTERRUPT: u8 = 0x03;
+const PACKET_SIZE: usize = 0x4000;
+
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+enum Reply {
+    Packet(String),
+    // ends the session after sending the packet (if any)
+    Close(Option<String>),
+}
+
+pub struct GdbServer {
+    debugger: Debugger,
+    last_stop: StopReason,
+    no_ack: bool,
+}
+
+impl GdbServer {
+    pub fn new(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            last_stop: StopReason::Step,
+            no_ack: false,
+        }
+    }
+
+    pub fn serve(&mut self, port: u16) -> Result<(), EmulatorError> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(gdb_error)?;
+        info!("Waiting for gdb on port {}", port);
+        let (mut stream, address) = listener.accept().map_err(gdb_error)?;
+        info!("Gdb connected from {}", address);
+
+        while let Some(packet) = read_packet(&mut stream, self.no_ack)? {
+            let command = match packet {
+                Packet::Command(command) => command,
+                // the program is already stopped
+                Packet::Interrupt => continue,
+            };
+
+            match self.handle_command(&command, &mut || interrupt_requested(&mut stream)) {
+                Reply::Packet(reply) => send_packet(&mut stream, &reply)?,
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        send_packet(&mut stream, &reply)?;
+                    }
+                    break;
+                }
+            }
+        }
+
+        info!("Gdb disconnected");
+        Ok(())
+    }
+
+    fn handle_command(&mut self, command: &str, interrupted: &mut dyn FnMut() -> bool) -> Reply {
+        let args = command.get(1..).unwrap_or_default();
+        let reply = match command.chars().next() {
+            Some('?') => self.stop_reply(),
+            Some('g') => self.read_registers(),
+            Some('p') => self.read_register(args),
+            Some('m') => self.read_memory(args),
+            Some('Z') => self.set_breakpoint(args, true),
+            Some('z') => self.set_breakpoint(args, false),
+            Some('s') => {
+                self.jump(args);
+                self.last_stop = self.debugger.step_instruction();
+                self.stop_reply()
+            }
+            Some('c') => {
+                self.jump(args);
+                self.last_stop = self.debugger.resume(interrupted);
+                self.stop_reply()
+            }
+            Some('H') => "OK".to_string(),
+            Some('q') | Some('Q') => self.query(command),
+            Some('k') => return Reply::Close(None),
+            Some('D') => return Reply::Close(Some("OK".to_string())),
+            _ => String::new(),
+        };
+        Reply::Packet(reply)
+    }
+
+    fn query(&mut self, command: &str) -> String {
+        if command.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+",
+                PACKET_SIZE
+            );
+        }
+
+        if let Some(args) = command.strip_prefix("qXfer:features:read:target.xml:") {
+            return match parse_address_and_length(args) {
+                Some((offset, length)) => {
+                    let description = target_description();
+                    let start = (offset as usize).min(description.len());
+                    let end = (start + length).min(description.len());
+                    let prefix = if end == description.len() { "l" } else { "m" };
+                    format!("{}{}", prefix, &description[start..end])
+                }
+                None => "E01".to_string(),
+            };
+        }
+
+        match command {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn stop_reply(&self) -> String {
+        match &self.last_stop {
+            StopReason::Step => format!("S{:02x}", SIGTRAP),
+            StopReason::Breakpoint => format!("T{:02x}swbreak:;", SIGTRAP),
+            StopReason::Watchpoint(address) => format!("T{:02x}watch:{:08x};", SIGTRAP, address),
+            StopReason::Interrupted => format!("S{:02x}", SIGINT),
+            StopReason::Halt(exit_code) => format!("W{:02x}", exit_code & 0xFF),
+            StopReason::Error(err) => {
+                error!(
+                    "Execution stopped at step {}: {}",
+                    self.debugger.program.step, err
+                );
+                match err {
+                    ExecutionResult::InstructionNotImplemented(..) => format!("S{:02x}", SIGILL),
+                    _ => format!("S{:02x}", SIGSEGV),
+                }
+            }
+        }
+    }
+
+    fn register_value(&self, idx: u32) -> Option<u32> {
+        match idx {
+            0 => Some(0),
+            1..=31 => Some(self.debugger.program.registers.get(idx)),
+            REGISTER_PC => Some(self.debugger.program.pc.get_address()),
+            _ => None,
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..=REGISTER_PC)
+            .map(|idx| hex::encode(self.register_value(idx).unwrap().to_le_bytes()))
+            .collect()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match u32::from_str_radix(args, 16)
+            .ok()
+            .and_then(|idx| self.register_value(idx))
+        {
+            Some(value) => hex::encode(value.to_le_bytes()),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let (address, length) = match parse_address_and_length(args) {
+            Some(args) => args,
+            None => return "E01".to_string(),
+        };
+
+        let mut bytes = Vec::new();
+        for offset in 0..length.min(PACKET_SIZE / 2) as u32 {
+            match self.debugger.read_byte(address.wrapping_add(offset)) {
+                Ok(byte) => bytes.push(byte),
+                Err(_) => break,
+            }
+        }
+
+        // a partial read is valid as long as one byte could be read
+        if bytes.is_empty() && length > 0 {
+            return "E01".to_string();
+        }
+        hex::encode(bytes)
+    }
+
+    // Z0/z0 (software) and Z1/z1 (hardware) are handled the same way
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let mut parts = args.split(',');
+        let kind = parts.next();
+        let address = parts
+            .next()
+            .and_then(|arg| u32::from_str_radix(arg, 16).ok());
+
+        match (kind, address) {
+            (Some("0") | Some("1"), Some(address)) => {
+                if insert {
+                    self.debugger.breakpoints.insert(address);
+                } else {
+                    self.debugger.breakpoints.remove(&address);
+                }
+                "OK".to_string()
+            }
+            _ => String::new(),
+        }
+    }
+
+    // s and c can resume from a given address
+    fn jump(&mut self, args: &str) {
+        if let Ok(address) = u32::from_str_radix(args, 16) {
+            self.debugger.program.pc.jump(address);
+        }
+    }
+}
+
+fn target_description() -> String {
+    let mut registers = String::new();
+    for (idx, name) in REGISTER_NAMES.iter().enumerate() {
+        let register_type = match *name {
+            "ra" => "code_ptr",
+            "sp" | "gp" | "tp" | "fp" => "data_ptr",
+            _ => "int",
+        };
+        registers.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"32\" type=\"{}\" regnum=\"{}\"/>",
+            name, register_type, idx
+        ));
+    }
+    registers.push_str(&format!(
+        "<reg name=\"pc\" bitsize=\"32\" type=\"code_ptr\" regnum=\"{}\"/>",
+        REGISTER_PC
+    ));
+
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\"><architecture>riscv:rv32</architecture><feature name=\"org.gnu.gdb.riscv.cpu\">{}</feature></target>",
+        registers
+    )
+}
+
+// parses the "addr,length" arguments of the packets
+fn parse_address_and_length(args: &str) -> Option<(u32, usize)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        u32::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |acc, byte| acc.wrapping_add(byte))
+}
+
+fn encode_packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data))
+}
+
+fn gdb_error(err: std::io::Error) -> EmulatorError {
+    EmulatorError::DebuggerError(err.to_string())
+}
+
+fn read_byte(stream: &mut TcpStream) -> Result<Option<u8>, EmulatorError> {
+    let mut byte = [0; 1];
+    match stream.read(&mut byte).map_err(gdb_error)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// reads the next packet, None if the connection was closed
+fn read_packet(stream: &mut TcpStream, no_ack: bool) -> Result<Option<Packet>, EmulatorError> {
+    loop {
+        // skip the acks until the start of the packet
+        match read_byte(stream)? {
+            None => return Ok(None),
+            Some(INTERRUPT) => return Ok(Some(Packet::Interrupt)),
+            Some(b'$') => {}
+            Some(_) => continue,
+        }
+
+        let mut data = Vec::new();
+        loop {
+            match read_byte(stream)? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(byte) => data.push(byte),
+            }
+        }
+
+        let mut expected = [0; 2];
+        stream.read_exact(&mut expected).map_err(gdb_error)?;
+
+        let data = String::from_utf8_lossy(&data).to_string();
+        let valid = std::str::from_utf8(&expected)
+            .ok()
+            .and_then(|expected| u8::from_str_radix(expected, 16).ok())
+            == Some(checksum(&data));
+
+        if !no_ack {
+            let ack: &[u8] = if valid { b"+" } else { b"-" };
+            stream.write_all(ack).map_err(gdb_error)?;
+        }
+
+        if valid {
+            return Ok(Some(Packet::Command(data)));
+        }
+    }
+}
+
+fn send_packet(stream: &mut TcpStream, data: &str) -> Result<(), EmulatorError> {
+    stream
+        .write_all(encode_packet(data).as_bytes())
+        .map_err(gdb_error)
+}
+
+// checks without blocking if gdb asked to interrupt the execution
+fn interrupt_requested(stream: &mut TcpStream) -> bool {
+    let mut byte = [0; 1];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let requested = matches!(stream.read(&mut byte), Ok(1) if byte[0] == INTERRUPT);
+    let _ = stream.set_nonblocking(false);
+    requested
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::program_with_code;
+
+    fn get_server() -> GdbServer {
+        // a0 = 1; a0 = a0 + 1; a7 = 93; ecall
+        let program = program_with_code(&[0x0010_0513, 0x0015_0513, 0x05d0_0893, 0x0000_0073]);
+        GdbServer::new(Debugger::new(program, false, None))
+    }
+
+    fn reply(server: &mut GdbServer, command: &str) -> String {
+        match server.handle_command(command, &mut || false) {
+            Reply::Packet(reply) => reply,
+            Reply::Close(reply) => reply.unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_encode_packet() {
+        assert_eq!(encode_packet("OK"), "$OK#9a");
+        assert_eq!(encode_packet(""), "$#00");
+    }
+
+    #[test]
+    fn test_read_memory() {
+        let mut server = get_server();
+        assert_eq!(reply(&mut server, "m1000,4"), "13051000");
+        assert_eq!(reply(&mut server, "m1001,2"), "0510");
+        // partial read at the end of the section
+        assert_eq!(reply(&mut server, "m100e,4"), "0000");
+        assert_eq!(reply(&mut server, "m2000,4"), "E01");
+    }
+
+    #[test]
+    fn test_step_and_registers() {
+        let mut server = get_server();
+        assert_eq!(reply(&mut server, "?"), "S05");
+        assert_eq!(reply(&mut server, "s"), "S05");
+        assert_eq!(reply(&mut server, "pa"), "01000000");
+        assert_eq!(reply(&mut server, "p20"), "04100000");
+        assert_eq!(reply(&mut server, "g").len(), 33 * 8);
+    }
+
+    #[test]
+    fn test_breakpoint_and_continue() {
+        let mut server = get_server();
+        assert_eq!(reply(&mut server, "Z0,1008,4"), "OK");
+        assert_eq!(reply(&mut server, "c"), "T05swbreak:;");
+        assert_eq!(reply(&mut server, "p20"), "08100000");
+        assert_eq!(reply(&mut server, "z0,1008,4"), "OK");
+        assert_eq!(reply(&mut server, "c"), "W02");
+        // the program can't be resumed after the halt
+        assert_eq!(reply(&mut server, "s"), "W02");
+    }
+
+    #[test]
+    fn test_target_description() {
+        let mut server = get_server();
+        let description = target_description();
+        let first = reply(&mut server, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(first, format!("m{}", &description[..0x10]));
+        let last = reply(
+            &mut server,
+            &format!("qXfer:features:read:target.xml:10,{:x}", description.len()),
+        );
+        assert_eq!(last, format!("l{}", &description[0x10..]));
+    }
+}
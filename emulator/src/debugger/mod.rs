@@ -0,0 +1,191 @@
+pub mod gdbserver;
+pub mod repl;
+pub mod time_travel;
+
+use std::collections::HashSet;
+
+use bitvmx_cpu_definitions::trace::{compute_step_hash, TraceRWStep};
+
+use crate::{
+    executor::{fetcher::execute_step, utils::FailConfiguration},
+    loader::program::{Program, REGISTER_A0},
+    EmulatorError, ExecutionResult,
+};
+
+use self::time_travel::{TimeTravel, MAX_SNAPSHOTS, SNAPSHOT_INTERVAL};
+
+pub const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+// steps executed between the checks for an interruption
+const INTERRUPT_CHECK_INTERVAL: u64 = 0x10000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Step,
+    Breakpoint,
+    Watchpoint(u32),
+    Interrupted,
+    Halt(u32),
+    Error(ExecutionResult),
+}
+
+pub struct Debugger {
+    pub program: Program,
+    pub breakpoints: HashSet<u32>,
+    // aligned addresses of the memory words to stop on write
+    pub watchpoints: HashSet<u32>,
+    // trace of the last executed step
+    pub last_trace: Option<TraceRWStep>,
+    print_program_stdout: bool,
+    hasher: blake3::Hasher,
+    // snapshots used to go back to previous steps
+    time_travel: TimeTravel,
+    // the program can't be resumed once it halted or failed
+    finished: Option<StopReason>,
+}
+
+impl Debugger {
+    pub fn new(
+        program: Program,
+        print_program_stdout: bool,
+        checkpoint_path: Option<String>,
+    ) -> Self {
+        let time_travel =
+            TimeTravel::new(&program, checkpoint_path, SNAPSHOT_INTERVAL, MAX_SNAPSHOTS);
+        Self {
+            program,
+            breakpoints: HashSet::new(),
+            watchpoints: HashSet::new(),
+            last_trace: None,
+            print_program_stdout,
+            hasher: blake3::Hasher::new(),
+            time_travel,
+            finished: None,
+        }
+    }
+
+    pub fn finished(&self) -> Option<&StopReason> {
+        self.finished.as_ref()
+    }
+
+    // executes a single step (one micro instruction) and keeps the hash of the trace
+    pub fn step(&mut self) -> StopReason {
+        if let Some(reason) = &self.finished {
+            return reason.clone();
+        }
+
+        let trace = match execute_step(
+            &mut self.program,
+            self.print_program_stdout,
+            false,
+            FailConfiguration::default(),
+        ) {
+            Ok(trace) => trace,
+            Err(err) => {
+                let reason = StopReason::Error(err);
+                self.finished = Some(reason.clone());
+                return reason;
+            }
+        };
+
+        self.program.hash = compute_step_hash(
+            &mut self.hasher,
+            &self.program.hash,
+            &trace.trace_step.to_bytes(),
+        );
+        let write_address = trace.trace_step.get_write().address & !3;
+        self.last_trace = Some(trace);
+        self.time_travel.record(&self.program);
+
+        if self.program.halt {
+            let reason = StopReason::Halt(self.program.registers.get(REGISTER_A0 as u32));
+            self.finished = Some(reason.clone());
+            return reason;
+        }
+
+        if self.watchpoints.contains(&write_address) {
+            return StopReason::Watchpoint(write_address);
+        }
+
+        StopReason::Step
+    }
+
+    // executes all the micro instructions of the current instruction
+    pub fn step_instruction(&mut self) -> StopReason {
+        loop {
+            let reason = self.step();
+            if reason != StopReason::Step || self.program.pc.get_micro() == 0 {
+                return reason;
+            }
+        }
+    }
+
+    // runs until a breakpoint or watchpoint is reached, the program stops or interrupted returns true
+    pub fn resume(&mut self, interrupted: &mut dyn FnMut() -> bool) -> StopReason {
+        let mut steps: u64 = 0;
+        loop {
+            let reason = self.step();
+            if reason != StopReason::Step {
+                return reason;
+            }
+
+            if self.program.pc.get_micro() == 0
+                && self.breakpoints.contains(&self.program.pc.get_address())
+            {
+                return StopReason::Breakpoint;
+            }
+
+            steps += 1;
+            if steps % INTERRUPT_CHECK_INTERVAL == 0 && interrupted() {
+                return StopReason::Interrupted;
+            }
+        }
+    }
+
+    pub fn run_to(&mut self, address: u32, interrupted: &mut dyn FnMut() -> bool) -> StopReason {
+        let inserted = self.breakpoints.insert(address);
+        let reason = self.resume(interrupted);
+        if inserted {
+            self.breakpoints.remove(&address);
+        }
+        reason
+    }
+
+    // restores the state after the execution of the given step, it can be before the current one
+    pub fn go_to_step(&mut self, step: u64) -> Result<(), EmulatorError> {
+        let (program, last_trace) = self.time_travel.go_to_step(step)?;
+        self.finished = match program.halt {
+            true => Some(StopReason::Halt(program.registers.get(REGISTER_A0 as u32))),
+            false => None,
+        };
+        self.program = program;
+        self.last_trace = last_trace;
+        Ok(())
+    }
+
+    pub fn step_back(&mut self, steps: u64) -> Result<(), EmulatorError> {
+        self.go_to_step(self.program.step.saturating_sub(steps))
+    }
+
+    pub fn read_byte(&self, address: u32) -> Result<u8, ExecutionResult> {
+        let word = self.program.read_mem(address & !3, true)?;
+        Ok((word >> ((address & 3) * 8)) as u8)
+    }
+
+    // step of the last write to the register or memory word at the address
+    pub fn last_step(&self, address: u32) -> Result<u64, ExecutionResult> {
+        let registers = &self.program.registers;
+        if address >= registers.get_base_address()
+            && address <= registers.get_last_register_address()
+        {
+            return Ok(registers.get_last_step(registers.get_original_idx(address)));
+        }
+
+        self.program.find_section(address)?;
+        Ok(self.program.get_last_step(address & !3))
+    }
+}
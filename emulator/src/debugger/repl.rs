@@ -0,0 +1,350 @@
+// Interactive step debugger
+//
+// Reads one command per line and prints the result. Addresses are hexadecimal (with or without
+// the 0x prefix) and counts are decimal. A step is a single micro instruction, the same unit used
+// by the trace and the hashes.
+
+use std::io::{BufRead, Write};
+
+use bitvmx_cpu_definitions::{constants::LAST_STEP_INIT, trace::hash_to_string};
+
+use super::{Debugger, StopReason, REGISTER_NAMES};
+use crate::EmulatorError;
+
+const HELP: &str = "Commands:
+  step|s [n]            executes n steps (default 1)
+  back|bs [n]           goes back n steps (default 1)
+  goto <step>           goes to the given step, before or after the current one
+  continue|c            runs until a breakpoint, a watchpoint or the end of the program
+  run-to <pc>           runs until the pc is reached
+  break|b <pc>          stops when the pc is reached
+  delete|d <pc>         removes the breakpoint
+  watch|w <address>     stops when the memory word is written
+  unwatch <address>     removes the watchpoint
+  regs|r                prints the registers
+  mem|x <address> [n]   prints n memory words (default 1)
+  last-step <address>   prints the step of the last write to the register or memory word
+  trace|t               prints the trace and the hash of the current step
+  quit|q                exits the debugger";
+
+pub fn run_repl<R: BufRead, W: Write>(
+    debugger: &mut Debugger,
+    input: R,
+    output: &mut W,
+) -> Result<(), EmulatorError> {
+    write_line(output, HELP)?;
+    prompt(debugger, output)?;
+
+    for line in input.lines() {
+        let line = line.map_err(repl_error)?;
+        let args = line.split_whitespace().collect::<Vec<&str>>();
+        if let Some(&("quit" | "q")) = args.first() {
+            break;
+        }
+
+        let reply = match args.split_first() {
+            Some((command, args)) => execute_command(debugger, command, args),
+            None => String::new(),
+        };
+        if !reply.is_empty() {
+            write_line(output, &reply)?;
+        }
+        prompt(debugger, output)?;
+    }
+
+    Ok(())
+}
+
+fn execute_command(debugger: &mut Debugger, command: &str, args: &[&str]) -> String {
+    let address = args.first().and_then(|arg| parse_address(arg));
+
+    match (command, address) {
+        ("step" | "s", _) => {
+            let steps = match args.first() {
+                Some(steps) => match steps.parse::<u64>() {
+                    Ok(steps) => steps,
+                    Err(_) => return format!("Invalid number of steps: {}", steps),
+                },
+                None => 1,
+            };
+            let mut reason = StopReason::Step;
+            for _ in 0..steps {
+                reason = debugger.step();
+                if reason != StopReason::Step {
+                    break;
+                }
+            }
+            stop_message(debugger, &reason)
+        }
+        ("back" | "bs", _) => {
+            let steps = match args.first() {
+                Some(steps) => match steps.parse::<u64>() {
+                    Ok(steps) => steps,
+                    Err(_) => return format!("Invalid number of steps: {}", steps),
+                },
+                None => 1,
+            };
+            match debugger.step_back(steps) {
+                Ok(()) => stop_message(debugger, &StopReason::Step),
+                Err(err) => format!("Error: {}", err),
+            }
+        }
+        ("goto", _) => match args.first().map(|step| step.parse::<u64>()) {
+            Some(Ok(step)) => match debugger.go_to_step(step) {
+                Ok(()) => stop_message(debugger, &StopReason::Step),
+                Err(err) => format!("Error: {}", err),
+            },
+            Some(Err(_)) => format!("Invalid step: {}", args[0]),
+            None => "goto expects a step".to_string(),
+        },
+        ("continue" | "c", _) => {
+            let reason = debugger.resume(&mut || false);
+            stop_message(debugger, &reason)
+        }
+        ("run-to", Some(address)) => {
+            let reason = debugger.run_to(address, &mut || false);
+            stop_message(debugger, &reason)
+        }
+        ("break" | "b", Some(address)) => {
+            debugger.breakpoints.insert(address);
+            format!("Breakpoint at 0x{:08x}", address)
+        }
+        ("delete" | "d", Some(address)) => match debugger.breakpoints.remove(&address) {
+            true => format!("Breakpoint at 0x{:08x} removed", address),
+            false => format!("No breakpoint at 0x{:08x}", address),
+        },
+        ("watch" | "w", Some(address)) => {
+            debugger.watchpoints.insert(address & !3);
+            format!("Watchpoint at 0x{:08x}", address & !3)
+        }
+        ("unwatch", Some(address)) => match debugger.watchpoints.remove(&(address & !3)) {
+            true => format!("Watchpoint at 0x{:08x} removed", address & !3),
+            false => format!("No watchpoint at 0x{:08x}", address & !3),
+        },
+        ("regs" | "r", _) => registers(debugger),
+        ("mem" | "x", Some(address)) => {
+            let words = args
+                .get(1)
+                .and_then(|words| words.parse::<u32>().ok())
+                .unwrap_or(1);
+            memory(debugger, address, words)
+        }
+        ("last-step", Some(address)) => match debugger.last_step(address) {
+            Ok(step) => format!("0x{:08x} last step: {}", address, step_to_string(step)),
+            Err(err) => format!("Error: {}", err),
+        },
+        ("trace" | "t", _) => trace(debugger),
+        ("help" | "h", _) => HELP.to_string(),
+        (
+            "run-to" | "break" | "b" | "delete" | "d" | "watch" | "w" | "unwatch" | "mem" | "x"
+            | "last-step",
+            None,
+        ) => format!("{} expects an address", command),
+        _ => format!("Unknown command: {} (try help)", command),
+    }
+}
+
+fn stop_message(debugger: &Debugger, reason: &StopReason) -> String {
+    let position = format!(
+        "step: {} pc: 0x{:08x}:{}{}",
+        debugger.program.step,
+        debugger.program.pc.get_address(),
+        debugger.program.pc.get_micro(),
+        debugger
+            .program
+            .symbolizer
+            .annotate(debugger.program.pc.get_address())
+    );
+    match reason {
+        StopReason::Step => position,
+        StopReason::Breakpoint => format!("Breakpoint reached at {}", position),
+        StopReason::Watchpoint(address) => {
+            format!("Watchpoint 0x{:08x} written at {}", address, position)
+        }
+        StopReason::Interrupted => format!("Interrupted at {}", position),
+        StopReason::Halt(exit_code) => {
+            format!(
+                "Program halted with exit code {} at {}",
+                exit_code, position
+            )
+        }
+        StopReason::Error(err) => format!("Execution failed with: {} at {}", err, position),
+    }
+}
+
+fn registers(debugger: &Debugger) -> String {
+    let registers = &debugger.program.registers;
+    let mut lines = REGISTER_NAMES
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| {
+            format!(
+                "x{:<2} {:<4} 0x{:08x} last step: {}",
+                idx,
+                name,
+                registers.get(idx as u32),
+                step_to_string(registers.get_last_step(idx as u32))
+            )
+        })
+        .collect::<Vec<String>>();
+    lines.push(format!(
+        "pc       0x{:08x}:{}",
+        debugger.program.pc.get_address(),
+        debugger.program.pc.get_micro()
+    ));
+    lines.join("\n")
+}
+
+fn memory(debugger: &Debugger, address: u32, words: u32) -> String {
+    let address = address & !3;
+    (0..words)
+        .map(|idx| {
+            let address = address.wrapping_add(idx * 4);
+            match debugger.program.read_mem(address, true) {
+                Ok(value) => format!("0x{:08x}: 0x{:08x}", address, value),
+                Err(err) => format!("0x{:08x}: {}", address, err),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn trace(debugger: &Debugger) -> String {
+    let hash = hash_to_string(&debugger.program.hash);
+    match &debugger.last_trace {
+        Some(trace) => format!("{:?}\n{};{}", trace, trace.to_csv(), hash),
+        None => format!("No step executed yet\nhash: {}", hash),
+    }
+}
+
+fn step_to_string(step: u64) -> String {
+    if step == LAST_STEP_INIT {
+        return "init".to_string();
+    }
+    step.to_string()
+}
+
+fn parse_address(arg: &str) -> Option<u32> {
+    let arg = arg.strip_prefix("0x").unwrap_or(arg);
+    u32::from_str_radix(arg, 16).ok()
+}
+
+fn prompt<W: Write>(debugger: &Debugger, output: &mut W) -> Result<(), EmulatorError> {
+    write!(
+        output,
+        "[{} 0x{:08x}:{}]> ",
+        debugger.program.step,
+        debugger.program.pc.get_address(),
+        debugger.program.pc.get_micro()
+    )
+    .and_then(|_| output.flush())
+    .map_err(repl_error)
+}
+
+fn write_line<W: Write>(output: &mut W, line: &str) -> Result<(), EmulatorError> {
+    writeln!(output, "{}", line).map_err(repl_error)
+}
+
+fn repl_error(err: std::io::Error) -> EmulatorError {
+    EmulatorError::DebuggerError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::program_with_data;
+
+    fn get_debugger() -> Debugger {
+        // a0 = 0x2000; a1 = 5; sw a1, 4(a0); a7 = 93; a0 = 0; ecall
+        let program = program_with_data(&[
+            0x0000_2537,
+            0x0050_0593,
+            0x00b5_2223,
+            0x05d0_0893,
+            0x0000_0513,
+            0x0000_0073,
+        ]);
+        Debugger::new(program, false, None)
+    }
+
+    fn run(debugger: &mut Debugger, commands: &str) -> String {
+        let mut output = Vec::new();
+        run_repl(debugger, commands.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_step_and_registers() {
+        let mut debugger = get_debugger();
+        let output = run(&mut debugger, "step 2\nregs\n");
+        assert!(output.contains("step: 2 pc: 0x00001008:0"));
+        assert!(output.contains("x11 a1   0x00000005 last step: 2"));
+        assert!(output.contains("x12 a2   0x00000000 last step: init"));
+    }
+
+    #[test]
+    fn test_break_and_run_to() {
+        let mut debugger = get_debugger();
+        let output = run(&mut debugger, "break 0x100c\ncontinue\nrun-to 1014\n");
+        assert!(output.contains("Breakpoint reached at step: 3 pc: 0x0000100c:0"));
+        assert!(output.contains("step: 5 pc: 0x00001014:0"));
+        assert!(debugger.breakpoints.contains(&0x100c));
+        assert!(!debugger.breakpoints.contains(&0x1014));
+    }
+
+    #[test]
+    fn test_watch_and_memory() {
+        let mut debugger = get_debugger();
+        let output = run(
+            &mut debugger,
+            "watch 2004\nc\nmem 2000 2\nlast-step 2004\nlast-step 2008\nc\n",
+        );
+        assert!(output.contains("Watchpoint 0x00002004 written at step: 3"));
+        assert!(output.contains("0x00002004: 0x00000005"));
+        assert!(output.contains("0x00002004 last step: 3"));
+        assert!(output.contains("0x00002008 last step: init"));
+        assert!(output.contains("Program halted with exit code 0"));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut debugger = get_debugger();
+        let output = run(&mut debugger, "trace\ns\ntrace\nfoo\nquit\ns\n");
+        assert!(output.contains("No step executed yet"));
+        assert!(output.contains(&hash_to_string(&debugger.program.hash)));
+        assert!(output.contains("Unknown command: foo"));
+        // the commands after quit are not executed
+        assert_eq!(debugger.program.step, 1);
+    }
+
+    #[test]
+    fn test_back_and_goto() {
+        let mut debugger = get_debugger();
+        let output = run(
+            &mut debugger,
+            "c
+back 3
+mem 2004
+goto 2
+regs
+goto 9
+",
+        );
+        assert!(output.contains("Program halted with exit code 0 at step: 6"));
+        assert!(output.contains("step: 3 pc: 0x0000100c:0"));
+        assert!(output.contains("0x00002004: 0x00000005"));
+        assert!(output.contains("step: 2 pc: 0x00001008:0"));
+        assert!(output.contains("x11 a1   0x00000005 last step: 2"));
+        assert!(output.contains("Error: Debugger error The program halted at step 6"));
+
+        // the program can be resumed after going back from the end
+        let output = run(
+            &mut debugger,
+            "goto 4
+c
+",
+        );
+        assert!(output.contains("Program halted with exit code 0 at step: 6"));
+        assert_eq!(debugger.last_trace.as_ref().unwrap().step_number, 6);
+    }
+}
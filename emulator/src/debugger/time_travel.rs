@@ -0,0 +1,244 @@
+// Reverse execution
+//
+// The state at a previous step is rebuilt replaying forward from the closest known state: an
+// in-memory snapshot or the closest checkpoint file before the step. Every `snapshot_interval`
+// steps of the replay a snapshot is kept, so going back again inside the same checkpoint window
+// only replays from the closest snapshot.
+
+use std::collections::BTreeMap;
+
+use bitvmx_cpu_definitions::trace::{compute_step_hash, TraceRWStep};
+use tracing::warn;
+
+use crate::{
+    executor::{fetcher::execute_step, utils::FailConfiguration},
+    loader::{
+        checkpoint::{find_checkpoint, CheckpointOrigin},
+        program::Program,
+    },
+    EmulatorError,
+};
+
+pub const SNAPSHOT_INTERVAL: u64 = 1_000_000;
+pub const MAX_SNAPSHOTS: usize = 64;
+
+pub struct TimeTravel {
+    checkpoint_path: Option<String>,
+    // the checkpoints of other programs or inputs are not loaded
+    origin: CheckpointOrigin,
+    snapshots: BTreeMap<u64, Program>,
+    // the state the debugger started from is never evicted
+    initial_step: u64,
+    snapshot_interval: u64,
+    max_snapshots: usize,
+    hasher: blake3::Hasher,
+}
+
+impl TimeTravel {
+    pub fn new(
+        initial: &Program,
+        checkpoint_path: Option<String>,
+        snapshot_interval: u64,
+        max_snapshots: usize,
+    ) -> Self {
+        let mut snapshots = BTreeMap::new();
+        snapshots.insert(initial.step, initial.clone());
+        Self {
+            checkpoint_path,
+            origin: CheckpointOrigin::of(initial),
+            snapshots,
+            initial_step: initial.step,
+            snapshot_interval,
+            max_snapshots,
+            hasher: blake3::Hasher::new(),
+        }
+    }
+
+    // keeps a snapshot of the program if it is at the interval
+    pub fn record(&mut self, program: &Program) {
+        if program.step % self.snapshot_interval == 0 && !self.snapshots.contains_key(&program.step)
+        {
+            self.save_snapshot(program, program.step);
+        }
+    }
+
+    // returns the program after the execution of the given step and the trace of that step
+    pub fn go_to_step(
+        &mut self,
+        step: u64,
+    ) -> Result<(Program, Option<TraceRWStep>), EmulatorError> {
+        // starts at least one step before to obtain the trace of the step
+        let mut program = self.closest_program(step.saturating_sub(1).max(self.initial_step))?;
+        let mut last_trace = None;
+
+        while program.step < step {
+            if program.halt {
+                return Err(EmulatorError::DebuggerError(format!(
+                    "The program halted at step {} before reaching step {}",
+                    program.step, step
+                )));
+            }
+
+            let trace = execute_step(&mut program, false, false, FailConfiguration::default())?;
+            program.hash = compute_step_hash(
+                &mut self.hasher,
+                &program.hash,
+                &trace.trace_step.to_bytes(),
+            );
+            last_trace = Some(trace);
+
+            if program.step % self.snapshot_interval == 0 {
+                self.save_snapshot(&program, step);
+            }
+        }
+
+        Ok((program, last_trace))
+    }
+
+    fn closest_program(&mut self, step: u64) -> Result<Program, EmulatorError> {
+        let snapshot_step = self
+            .snapshots
+            .range(..=step)
+            .next_back()
+            .map(|(snapshot_step, _)| *snapshot_step);
+
+        // the checkpoint is only worth loading if it is closer than the snapshots
+        if let Some(path) = &self.checkpoint_path {
+            if let Some(checkpoint_step) = find_checkpoint(path, step) {
+                let checkpoint_is_closer = match snapshot_step {
+                    Some(snapshot_step) => snapshot_step < checkpoint_step,
+                    None => true,
+                };
+                if checkpoint_is_closer {
+                    match Program::deserialize_from_file(path, checkpoint_step, Some(&self.origin))
+                    {
+                        Ok(program) => {
+                            self.save_snapshot(&program, step);
+                            return Ok(program);
+                        }
+                        Err(err) => warn!("Checkpoint {} not loaded: {}", checkpoint_step, err),
+                    }
+                }
+            }
+        }
+
+        match snapshot_step {
+            Some(snapshot_step) => Ok(self.snapshots[&snapshot_step].clone()),
+            None => Err(EmulatorError::DebuggerError(format!(
+                "Step {} is before the first available step {}",
+                step, self.initial_step
+            ))),
+        }
+    }
+
+    // when the cache is full the snapshot farthest from the target step is evicted
+    fn save_snapshot(&mut self, program: &Program, target_step: u64) {
+        self.snapshots.insert(program.step, program.clone());
+        if self.snapshots.len() <= self.max_snapshots {
+            return;
+        }
+
+        let farthest = self
+            .snapshots
+            .keys()
+            .filter(|snapshot_step| **snapshot_step != self.initial_step)
+            .max_by_key(|snapshot_step| snapshot_step.abs_diff(target_step))
+            .copied();
+        if let Some(farthest) = farthest {
+            self.snapshots.remove(&farthest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loader::checkpoint::input_digest, test_utils::program_with_code};
+
+    // a0 = a0 + 1; j -4 (loops forever incrementing a0)
+    fn get_program() -> Program {
+        program_with_code(&[0x0015_0513, 0xffdf_f06f])
+    }
+
+    fn run_to(program: &mut Program, step: u64) {
+        let mut hasher = blake3::Hasher::new();
+        while program.step < step {
+            let trace = execute_step(program, false, false, FailConfiguration::default()).unwrap();
+            program.hash =
+                compute_step_hash(&mut hasher, &program.hash, &trace.trace_step.to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_go_to_step() {
+        let program = get_program();
+        let mut time_travel = TimeTravel::new(&program, None, 10, 4);
+
+        let mut expected = get_program();
+        run_to(&mut expected, 55);
+
+        let (program, trace) = time_travel.go_to_step(55).unwrap();
+        assert_eq!(program.step, 55);
+        assert_eq!(program.registers.get(10), expected.registers.get(10));
+        assert_eq!(program.hash, expected.hash);
+        assert_eq!(trace.unwrap().step_number, 55);
+
+        // the snapshots are bounded and the initial state is kept
+        assert_eq!(time_travel.snapshots.len(), 4);
+        assert!(time_travel.snapshots.contains_key(&0));
+        assert!(time_travel.snapshots.contains_key(&50));
+
+        // going back replays from the closest snapshot
+        let (program, _) = time_travel.go_to_step(41).unwrap();
+        let mut expected = get_program();
+        run_to(&mut expected, 41);
+        assert_eq!(program.registers.get(10), expected.registers.get(10));
+        assert_eq!(program.hash, expected.hash);
+
+        let (program, trace) = time_travel.go_to_step(0).unwrap();
+        assert_eq!(program.step, 0);
+        assert!(trace.is_none());
+    }
+
+    #[test]
+    fn test_checkpoint_of_other_input() {
+        let dir = std::env::temp_dir().join("time_travel_other_input");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap();
+
+        // a checkpoint of the same code executed with another input
+        let mut other = get_program();
+        other.input_digest = input_digest(&[1]);
+        run_to(&mut other, 20);
+        other.registers.set(10, 1234, 20);
+        other.serialize_to_file(path);
+
+        // it's not loaded, the step is replayed from the start
+        let mut time_travel = TimeTravel::new(&get_program(), Some(path.to_string()), 10, 4);
+        let (program, _) = time_travel.go_to_step(25).unwrap();
+        let mut expected = get_program();
+        run_to(&mut expected, 25);
+        assert_eq!(program.registers.get(10), expected.registers.get(10));
+
+        // but it is for the program executed with that input
+        let mut initial = get_program();
+        initial.input_digest = input_digest(&[1]);
+        let mut time_travel = TimeTravel::new(&initial, Some(path.to_string()), 10, 4);
+        let (program, _) = time_travel.go_to_step(25).unwrap();
+        let mut expected = other;
+        run_to(&mut expected, 25);
+        assert_eq!(program.registers.get(10), expected.registers.get(10));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_go_to_step_after_halt() {
+        // a7 = 93; ecall
+        let program = program_with_code(&[0x05d0_0893, 0x0000_0073]);
+
+        let mut time_travel = TimeTravel::new(&program, None, 10, 4);
+        assert!(time_travel.go_to_step(2).unwrap().0.halt);
+        assert!(time_travel.go_to_step(3).is_err());
+    }
+}
@@ -2,7 +2,9 @@ use bitvmx_cpu_definitions::{
     challenge::{ChallengeType, EquivocationKind, ProverFinalTraceType, ProverHashesAndStepType},
     constants::{CHUNK_SIZE, LAST_STEP_INIT},
     memory::Chunk,
-    trace::{generate_initial_step_hash, hashvec_to_string, validate_step_hash, TraceRWStep},
+    trace::{
+        generate_initial_step_hash, hashvec_to_string, validate_step_hash, TraceRWStep, TraceRead,
+    },
 };
 
 use serde::{Deserialize, Serialize};
@@ -44,7 +46,7 @@ pub fn prover_execute(
         fail_config,
         save_non_checkpoint_steps,
     )?;
-    if result != ExecutionResult::Halt(0, last_step) {
+    if !matches!(result, ExecutionResult::Halt(0, step, _) if step == last_step) {
         error!(
             "The execution of the program {} failed with error: {:?}. The claim should not be commited on-chain.",
             program_definition_file, result
@@ -125,6 +127,7 @@ pub fn verifier_check_execution(
     checkpoint_path: &str,
     claim_last_step: u64,
     claim_last_hash: &str,
+    claim_output: Vec<TraceRead>,
     force_condition: ForceCondition,
     fail_config: Option<FailConfiguration>,
     save_non_checkpoint_steps: bool,
@@ -137,11 +140,24 @@ pub fn verifier_check_execution(
         save_non_checkpoint_steps,
     )?;
 
-    let input_is_valid = result == ExecutionResult::Halt(0, last_step);
+    let (input_is_valid, output) = match &result {
+        ExecutionResult::Halt(0, step, output) => (*step == last_step, output.as_slice()),
+        _ => (false, [].as_slice()),
+    };
+    if input_is_valid && claim_output.len() != output.len() {
+        return Err(EmulatorError::ChallengeError(format!(
+            "The claimed output has {} words but the output section has {}",
+            claim_output.len(),
+            output.len()
+        )));
+    }
     let same_step_and_hash = last_step == claim_last_step && last_hash == claim_last_hash;
+    // the execution can be right while the claimed output is not
+    let same_output = find_output_conflict(&claim_output, output).is_none();
 
     let should_challenge = force_condition == ForceCondition::Always
         || !input_is_valid
+        || (same_step_and_hash && !same_output)
         || (force_condition == ForceCondition::ValidInputWrongStepOrHash && !same_step_and_hash)
         || (force_condition == ForceCondition::ValidInputStepAndHash && same_step_and_hash);
 
@@ -164,10 +180,11 @@ pub fn verifier_check_execution(
 
     // we use the minimum agreed step (that is one before the disagreement)
     let step_to_challenge = claim_last_step.min(last_step) - 1;
+    log_step_location(&program_def, checkpoint_path, &input, step_to_challenge);
 
     let challenge_log = VerifierChallengeLog::new(
         ExecutionLog::new(
-            ExecutionResult::Halt(0, claim_last_step),
+            ExecutionResult::Halt(0, claim_last_step, claim_output),
             claim_last_step,
             claim_last_hash.to_string(),
         ),
@@ -180,6 +197,28 @@ pub fn verifier_check_execution(
     Ok(Some(step_to_challenge))
 }
 
+// shows the function and source line of the step when the elf has symbols
+fn log_step_location(
+    program_def: &ProgramDefinition,
+    checkpoint_path: &str,
+    input: &[u8],
+    step: u64,
+) {
+    let symbolizer = match program_def.load_program() {
+        Ok(program) if !program.symbolizer.is_empty() => program.symbolizer,
+        _ => return,
+    };
+    if let Ok(trace) = program_def.get_trace_step(checkpoint_path, input.to_vec(), step, None) {
+        let pc = trace.read_pc.pc.get_address();
+        info!(
+            "Step to challenge: {} pc: 0x{:08x}{}",
+            step,
+            pc,
+            symbolizer.annotate(pc)
+        );
+    }
+}
+
 pub fn verifier_choose_segment(
     program_definition_file: &str,
     checkpoint_path: &str,
@@ -390,6 +429,7 @@ pub enum ForceChallenge {
     ProgramCounter,
     Opcode,
     InputData,
+    OutputData,
     InitializedData,
     UninitializedData,
     AddressesSections,
@@ -415,6 +455,22 @@ fn find_chunk_index(chunks: &[Chunk], address: u32) -> Option<usize> {
     })
 }
 
+// the first output word claimed with a different value or last write than the verifier's
+fn find_output_conflict(prover_output: &[TraceRead], output: &[TraceRead]) -> Option<usize> {
+    output
+        .iter()
+        .zip(prover_output)
+        .position(|(mine, prover)| mine.value != prover.value || mine.last_step != prover.last_step)
+}
+
+// code chunks carry the first word of the next chunk, which is not part of the chunk range
+fn find_code_chunk_index(chunks: &[Chunk], address: u32) -> Option<usize> {
+    chunks.iter().position(|Chunk { base_addr, data }| {
+        let chunk_size = data.len() - 1;
+        *base_addr <= address && address < *base_addr + chunk_size as u32 * 4
+    })
+}
+
 pub fn verifier_choose_challenge(
     program_definition_file: &str,
     checkpoint_path: &str,
@@ -533,6 +589,45 @@ pub fn verifier_choose_challenge(
         });
     }
 
+    // the output is read after the last step, so a wrong output word is challenged as a read
+    // bound to the last write the prover claims for it
+    let output_index = if step + 1 == prover_claim_last_step && force == ForceChallenge::No {
+        find_output_conflict(
+            verifier_log.prover_claim_execution.output(),
+            verifier_log.execution.output(),
+        )
+    } else if force == ForceChallenge::OutputData {
+        Some(0)
+    } else {
+        None
+    };
+    let output_reads = match output_index {
+        Some(idx) => {
+            let my_output_read = match verifier_log.execution.output().get(idx) {
+                Some(output_read) => output_read.clone(),
+                None => {
+                    return Err(EmulatorError::ChallengeError(
+                        "The program doesn't define an output section".to_string(),
+                    ))
+                }
+            };
+            let prover_output = verifier_log
+                .prover_claim_execution
+                .output()
+                .get(idx)
+                .cloned()
+                .unwrap_or_default();
+            // the address is the one of the word in the output section, not the claimed one
+            let output_read = TraceRead::new(
+                my_output_read.address,
+                prover_output.value,
+                prover_output.last_step,
+            );
+            Some((output_read, my_output_read))
+        }
+        None => None,
+    };
+
     let mut steps = vec![step, step + 1];
     let mut my_trace_idx = 1;
     if step > 0 {
@@ -574,7 +669,8 @@ pub fn verifier_choose_challenge(
         false,
     );
 
-    let is_valid_pc = program.address_in_sections(trace.read_pc.pc.get_address(), &code_sections);
+    let is_valid_pc =
+        program.instruction_in_sections(trace.read_pc.pc.get_address(), &code_sections);
 
     if (!(is_valid_read_1 && is_valid_read_2 && is_valid_write && is_valid_pc)
         && force == ForceChallenge::No)
@@ -629,7 +725,7 @@ pub fn verifier_choose_challenge(
         info!("Verifier choose to challenge invalid OPCODE");
         let pc = trace.read_pc.pc.get_address();
         let code_chunks = program.get_code_chunks(CHUNK_SIZE);
-        let chunk_index = find_chunk_index(&code_chunks, pc).unwrap() as u32;
+        let chunk_index = find_code_chunk_index(&code_chunks, pc).unwrap() as u32;
 
         return Ok(ChallengeType::Opcode {
             prover_pc_read: trace.read_pc,
@@ -638,11 +734,42 @@ pub fn verifier_choose_challenge(
         });
     }
 
+    let is_trace_read_future =
+        |read: &TraceRead| read.last_step > step && read.last_step != LAST_STEP_INIT;
+    let is_trace_read_conflict = is_trace_read_future(&prover_read_1)
+        || is_trace_read_future(&prover_read_2)
+        || prover_read_1.value != my_trace.read_1.value
+        || prover_read_2.value != my_trace.read_2.value;
+
+    // the reads of the last step are challenged before the output, that is read in the next one
+    let output_reads = match output_reads {
+        Some(reads) if !is_trace_read_conflict || force == ForceChallenge::OutputData => {
+            Some(reads)
+        }
+        _ => None,
+    };
+    let (prover_read_1, prover_read_2, my_read_1, my_read_2, read_step) = match &output_reads {
+        Some((output_read, my_output_read)) => (
+            output_read.clone(),
+            TraceRead::default(),
+            my_output_read.clone(),
+            TraceRead::default(),
+            step + 1,
+        ),
+        None => (
+            prover_read_1,
+            prover_read_2,
+            my_trace.read_1.clone(),
+            my_trace.read_2.clone(),
+            step,
+        ),
+    };
+
     let prover_read_step_1 = prover_read_1.last_step;
     let prover_read_step_2 = prover_read_2.last_step;
 
-    let is_read_1_future = prover_read_step_1 > step && prover_read_step_1 != LAST_STEP_INIT;
-    let is_read_2_future = prover_read_step_2 > step && prover_read_step_2 != LAST_STEP_INIT;
+    let is_read_1_future = prover_read_step_1 > read_step && prover_read_step_1 != LAST_STEP_INIT;
+    let is_read_2_future = prover_read_step_2 > read_step && prover_read_step_2 != LAST_STEP_INIT;
 
     if ((is_read_1_future || is_read_2_future) && force == ForceChallenge::No)
         || force == ForceChallenge::FutureRead
@@ -650,27 +777,28 @@ pub fn verifier_choose_challenge(
         let read_selector = if is_read_1_future { 1 } else { 2 };
 
         return Ok(ChallengeType::FutureRead {
-            prover_conflict_step_tk: step,
+            prover_conflict_step_tk: read_step,
             prover_read_step_1,
             prover_read_step_2,
             read_selector,
         });
     }
 
-    // check const read value
-    let is_read_1_conflict = prover_read_1.value != my_trace.read_1.value;
-    let is_read_2_conflict = prover_read_2.value != my_trace.read_2.value;
+    // check const read value, the output word also conflicts when only its last write differs
+    let is_read_1_conflict = prover_read_1.value != my_read_1.value || output_reads.is_some();
+    let is_read_2_conflict = prover_read_2.value != my_read_2.value;
 
     if ((is_read_1_conflict || is_read_2_conflict) && force == ForceChallenge::No)
         || force == ForceChallenge::InputData
         || force == ForceChallenge::InitializedData
         || force == ForceChallenge::UninitializedData
         || force == ForceChallenge::ReadValueNArySearch
+        || force == ForceChallenge::OutputData
     {
         let (conflict_read, my_conflict_read, read_selector) = if is_read_1_conflict {
-            (prover_read_1.clone(), my_trace.read_1.clone(), 1)
+            (prover_read_1.clone(), my_read_1.clone(), 1)
         } else {
-            (prover_read_2.clone(), my_trace.read_2.clone(), 2)
+            (prover_read_2.clone(), my_read_2.clone(), 2)
         };
 
         let conflict_address = conflict_read.address;
@@ -687,24 +815,34 @@ pub fn verifier_choose_challenge(
             || force == ForceChallenge::InitializedData
             || force == ForceChallenge::UninitializedData
         {
-            let input_size = program_def
-                .inputs
-                .iter()
-                .fold(0, |acc, input| acc + input.size);
+            let input_size = program_def.input_size();
+            // the input section can be part of a bigger section of the program
+            let input_start = match program.find_range_by_name(&program_def.input_section_name) {
+                Some((start, _)) => start,
+                None => section.start,
+            };
 
-            if (section.name == program_def.input_section_name
-                && conflict_address < section.start + input_size as u32
+            if (conflict_address >= input_start
+                && conflict_address < input_start + input_size as u32
                 && force == ForceChallenge::No)
                 || force == ForceChallenge::InputData
             {
                 info!("Verifier choose to challenge invalid INPUT DATA");
                 let value = program.read_mem(conflict_address, false)?;
+                // the word is checked against the commitment of the owner of its input
+                let owner = program_def
+                    .input_owner(conflict_address.wrapping_sub(input_start) as u64)
+                    .unwrap_or_default()
+                    .to_string();
+                let owner_input = program_def.owner_input_ranges(&owner, input_start);
 
                 return Ok(ChallengeType::InputData {
                     prover_read_1: prover_read_1,
                     prover_read_2: prover_read_2,
                     address: conflict_address,
                     input_for_address: value,
+                    owner,
+                    owner_input,
                 });
             } else if (section.initialized && force == ForceChallenge::No)
                 || force == ForceChallenge::InitializedData
@@ -761,6 +899,7 @@ pub fn verifier_choose_challenge(
 
             verifier_log.read_step = step_to_challenge - 1;
             verifier_log.read_selector = read_selector;
+            verifier_log.output_read = output_reads.map(|(output_read, _)| output_read);
             verifier_log.save(checkpoint_path)?;
 
             let fail_selection = fail_config.and_then(|fail_config| {
@@ -897,6 +1036,24 @@ pub fn verifier_choose_challenge_for_read_challenge(
     }
 
     let read_step = verifier_log.read_step;
+
+    // the output is read after the last step claimed by the prover
+    if (read_step == challenge_step
+        && verifier_log.output_read.is_some()
+        && force == ForceChallenge::No)
+        || force == ForceChallenge::OutputData
+    {
+        info!("Verifier choose to challenge invalid OUTPUT DATA");
+        return Ok(ChallengeType::OutputData {
+            prover_output: verifier_log.output_read.unwrap_or_default(),
+            prover_hash: prover_step_hash,
+            trace: my_trace.trace_step,
+            prover_next_hash,
+            prover_write_step_tk: challenge_step,
+            prover_last_step_tk: verifier_log.prover_claim_execution.last_step,
+        });
+    }
+
     if (read_step == challenge_step && force == ForceChallenge::No)
         || force == ForceChallenge::ReadValue
     {
@@ -1051,6 +1208,10 @@ mod tests {
         )
         .unwrap();
         info!("{:?}", result_1);
+        let claim_output = match &result_1.0 {
+            ExecutionResult::Halt(_, _, output) => output.clone(),
+            _ => vec![],
+        };
 
         // VERIFIER DECIDES TO CHALLENGE
         let result = verifier_check_execution(
@@ -1059,6 +1220,7 @@ mod tests {
             chk_verifier_path,
             result_1.1,
             &result_1.2,
+            claim_output,
             force_condition,
             fail_config_verifier.clone(),
             false,
@@ -2758,4 +2920,26 @@ mod tests {
             ForceChallenge::No,
         );
     }
+
+    #[test]
+    fn test_find_output_conflict() {
+        let output = vec![
+            TraceRead::new(0x2000, 1, 10),
+            TraceRead::new(0x2004, 2, LAST_STEP_INIT),
+        ];
+        assert_eq!(find_output_conflict(&output, &output), None);
+
+        // the claimed address is not part of the claim
+        let mut claim = output.clone();
+        claim[0].address = 0x3000;
+        assert_eq!(find_output_conflict(&claim, &output), None);
+
+        claim[1].value = 3;
+        assert_eq!(find_output_conflict(&claim, &output), Some(1));
+
+        // a right value written at another step can be challenged too
+        let mut claim = output.clone();
+        claim[0].last_step = 9;
+        assert_eq!(find_output_conflict(&claim, &output), Some(0));
+    }
 }
@@ -1,4 +1,4 @@
-use bitvmx_cpu_definitions::trace::TraceRWStep;
+use bitvmx_cpu_definitions::trace::{TraceRWStep, TraceRead};
 use serde::{Deserialize, Serialize};
 
 use crate::{decision::nary_search::NArySearchType, EmulatorError, ExecutionResult};
@@ -18,6 +18,14 @@ impl ExecutionLog {
             last_hash,
         }
     }
+
+    // the output of the halt, empty if the execution didn't halt
+    pub fn output(&self) -> &[TraceRead] {
+        match &self.result {
+            ExecutionResult::Halt(_, _, output) => output,
+            _ => &[],
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -90,6 +98,9 @@ pub struct VerifierChallengeLog {
     pub read_challenge_log: VerifierNAryLog,
     pub read_selector: u32,
     pub read_step: u64,
+    // the output word of the prover when the read challenged is the one of the output
+    #[serde(default)]
+    pub output_read: Option<TraceRead>,
 }
 
 impl VerifierChallengeLog {
@@ -107,6 +118,7 @@ impl VerifierChallengeLog {
             read_challenge_log: VerifierNAryLog::default(),
             read_selector: 0,
             read_step: 0,
+            output_read: None,
         }
     }
 
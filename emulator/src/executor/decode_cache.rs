@@ -0,0 +1,164 @@
+// Pre-decoded instructions
+//
+// The code sections can't be written (load_elf rejects writable code), so the instructions are
+// decoded once, walking every code section, and looked up by pc on every step instead of searching
+// the section and decoding the fetch window again. The addresses that aren't the start of an
+// instruction of the walk are not in the table and are fetched and decoded as before.
+
+use bitcoin_script_riscv::riscv::{
+    bitmanip::BitmanipInstruction,
+    compressed::{
+        decode_instruction, CompressedInstruction, COMPRESSED_INSTRUCTION_SIZE, INSTRUCTION_SIZE,
+    },
+    instruction_mapping::get_required_microinstruction,
+};
+use riscv_decode::Instruction;
+
+use crate::loader::program::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodedInstruction {
+    Base(Instruction, Option<CompressedInstruction>),
+    // Zba/Zbb are not decoded by riscv_decode
+    Bitmanip(BitmanipInstruction),
+}
+
+impl DecodedInstruction {
+    pub fn decode(opcode: u32) -> Option<Self> {
+        if let Some(bitmanip) = BitmanipInstruction::decode(opcode) {
+            return Some(DecodedInstruction::Bitmanip(bitmanip));
+        }
+        decode_instruction(opcode)
+            .map(|(instruction, compressed)| DecodedInstruction::Base(instruction, compressed))
+    }
+
+    pub fn size(&self) -> u32 {
+        match self {
+            DecodedInstruction::Base(_, Some(_)) => COMPRESSED_INSTRUCTION_SIZE,
+            _ => INSTRUCTION_SIZE,
+        }
+    }
+
+    pub fn micro_instructions(&self) -> u8 {
+        match self {
+            DecodedInstruction::Base(instruction, _) => get_required_microinstruction(instruction),
+            DecodedInstruction::Bitmanip(_) => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodedEntry {
+    // fetch window, kept for the trace
+    pub opcode: u32,
+    pub instruction: DecodedInstruction,
+    pub micro_instructions: u8,
+}
+
+// one entry every two bytes of the section
+#[derive(Clone)]
+struct CodeTable {
+    start: u32,
+    entries: Vec<Option<DecodedEntry>>,
+}
+
+#[derive(Clone, Default)]
+pub struct DecodeCache {
+    tables: Vec<CodeTable>,
+}
+
+impl DecodeCache {
+    pub fn new(program: &Program) -> Self {
+        let tables = program
+            .sections
+            .iter()
+            .filter(|section| section.is_code)
+            .map(|section| {
+                let mut entries = vec![None; section.size as usize / 2];
+                for (address, opcode) in section.instructions() {
+                    let idx = ((address - section.start) / 2) as usize;
+                    if idx >= entries.len() {
+                        continue;
+                    }
+                    entries[idx] =
+                        DecodedInstruction::decode(opcode).map(|instruction| DecodedEntry {
+                            opcode,
+                            instruction,
+                            micro_instructions: instruction.micro_instructions(),
+                        });
+                }
+                CodeTable {
+                    start: section.start,
+                    entries,
+                }
+            })
+            .collect();
+        Self { tables }
+    }
+
+    pub fn get(&self, address: u32) -> Option<&DecodedEntry> {
+        if address % 2 != 0 {
+            return None;
+        }
+        self.tables.iter().find_map(|table| {
+            let offset = address.checked_sub(table.start)?;
+            table.entries.get((offset / 2) as usize)?.as_ref()
+        })
+    }
+
+    // number of decoded instructions
+    pub fn len(&self) -> usize {
+        self.tables
+            .iter()
+            .map(|table| table.entries.iter().filter(|entry| entry.is_some()).count())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// the entries would flood the debug output of the program
+impl std::fmt::Debug for DecodeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodeCache")
+            .field("instructions", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::program_with_data;
+
+    // addi a0, a0, 1; c.addi a0, 1; sw a0, 0(t1); ecall
+    fn get_program() -> Program {
+        program_with_data(&[0x0015_0513, 0x2023_0505, 0x0073_00a3, 0x0000_0000])
+    }
+
+    #[test]
+    fn test_decode_cache() {
+        let program = get_program();
+        let cache = DecodeCache::new(&program);
+        assert_eq!(cache.len(), 4);
+
+        for address in [0x1000, 0x1004, 0x1006, 0x100a] {
+            let entry = cache.get(address).unwrap();
+            assert_eq!(entry.opcode, program.read_instruction(address).unwrap());
+            assert_eq!(
+                Some(entry.instruction),
+                DecodedInstruction::decode(entry.opcode)
+            );
+        }
+
+        assert_eq!(cache.get(0x1004).unwrap().instruction.size(), 2);
+        // sw
+        assert_eq!(cache.get(0x1006).unwrap().micro_instructions, 8);
+        // the middle of an instruction, an odd address and a data section
+        assert!(cache.get(0x1002).is_none());
+        assert!(cache.get(0x1001).is_none());
+        assert!(cache.get(0x2000).is_none());
+    }
+}
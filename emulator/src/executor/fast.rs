@@ -0,0 +1,263 @@
+// Fast execution
+//
+// Obtains only the result of an input (i.e. to check that it halts with 0 before a dispute). The
+// memory and the registers keep only the values, without the step of their last write, and the
+// steps are not hashed, printed or kept, nor checkpoints written. The result is the one of the full
+// execution with the same limit, but the hash and the last steps of the program are not valid
+// afterwards, so it can't be used to continue tracing.
+//
+// The instructions that take a single step are executed without building the trace. The ones
+// split in micro instructions (the unaligned loads and stores) go through execute_step, as every
+// micro instruction leaves the aux registers as the full execution, and so do the ecalls, the csr
+// reads and the instructions that are not in the decode cache.
+
+use riscv_decode::Instruction::*;
+
+use super::{
+    alignment_masks::get_mask_round_1_for_load,
+    decode_cache::DecodedInstruction,
+    fetcher::{
+        arithmetic_imm_result, arithmetic_result, branch_taken, execute_step,
+        get_type_and_read_from_instruction, load_result, shift_imm_result, shift_sl_result,
+        sl_imm_result, upper_result, wrapping_add_btype, wrapping_add_itype, wrapping_add_jtype,
+        wrapping_add_stype,
+    },
+    utils::FailConfiguration,
+};
+use crate::{
+    loader::program::{Program, Registers, REGISTER_ZERO},
+    ExecutionResult,
+};
+
+pub fn execute_fast(
+    program: &mut Program,
+    input: Vec<u8>,
+    input_section_name: &str,
+    little_endian: bool,
+    limit_step: Option<u64>,
+) -> ExecutionResult {
+    if let Err(err) = program.load_input(input, input_section_name, little_endian) {
+        return err;
+    }
+
+    program.drop_last_steps();
+    if program.decode_cache.is_none() {
+        program.build_decode_cache();
+    }
+
+    let fail_config = FailConfiguration::default();
+    loop {
+        if let Err(err) = fast_step(program, &fail_config) {
+            return err;
+        }
+
+        if program.halt {
+            return program.halt_result();
+        }
+
+        if limit_step == Some(program.step) {
+            return ExecutionResult::LimitStepReached(program.step);
+        }
+    }
+}
+
+fn fast_step(
+    program: &mut Program,
+    fail_config: &FailConfiguration,
+) -> Result<(), ExecutionResult> {
+    let pc = program.pc.get_address();
+    let cached = match (&program.decode_cache, program.pc.get_micro()) {
+        (Some(cache), 0) => cache.get(pc).copied(),
+        _ => None,
+    };
+    let decoded = match cached {
+        Some(entry) if single_step(&entry.instruction, &program.registers) => entry.instruction,
+        _ => return execute_step(program, false, false, fail_config.clone()).map(|_| ()),
+    };
+
+    program.step += 1;
+    program.instruction_size = decoded.size();
+
+    let instruction = match decoded {
+        DecodedInstruction::Base(instruction, _) => instruction,
+        DecodedInstruction::Bitmanip(bitmanip) => {
+            let value_1 = program.registers.get(bitmanip.rs1());
+            let value_2 = match bitmanip.rs2() {
+                Some(rs2) => program.registers.get(rs2),
+                None => 0,
+            };
+            set_register(program, bitmanip.rd(), bitmanip.execute(value_1, value_2));
+            program.advance_pc();
+            return Ok(());
+        }
+    };
+
+    let registers = &program.registers;
+    match instruction {
+        Ebreak | Fence(_) => program.advance_pc(),
+        Jal(x) => {
+            let next = pc + program.instruction_size;
+            set_register(program, x.rd(), next);
+            program.pc.jump(wrapping_add_jtype(pc, &x));
+        }
+        Jalr(x) => {
+            let dest = wrapping_add_itype(registers.get(x.rs1()), &x) & !1;
+            let next = pc + program.instruction_size;
+            set_register(program, x.rd(), next);
+            program.pc.jump(dest);
+        }
+        Mul(x) | Mulh(x) | Mulhsu(x) | Mulhu(x) | Div(x) | Divu(x) | Rem(x) | Remu(x) | Sub(x)
+        | Xor(x) | And(x) | Or(x) | Add(x) => {
+            let value =
+                arithmetic_result(&instruction, registers.get(x.rs1()), registers.get(x.rs2()));
+            set_register(program, x.rd(), value);
+            program.advance_pc();
+        }
+        Sll(x) | Srl(x) | Sra(x) | Slt(x) | Sltu(x) => {
+            let value =
+                shift_sl_result(&instruction, registers.get(x.rs1()), registers.get(x.rs2()));
+            set_register(program, x.rd(), value);
+            program.advance_pc();
+        }
+        Slli(x) | Srli(x) | Srai(x) => {
+            let value = shift_imm_result(&instruction, &x, registers.get(x.rs1()));
+            set_register(program, x.rd(), value);
+            program.advance_pc();
+        }
+        Slti(x) | Sltiu(x) => {
+            let value = sl_imm_result(&instruction, &x, registers.get(x.rs1()));
+            set_register(program, x.rd(), value);
+            program.advance_pc();
+        }
+        Addi(x) | Andi(x) | Ori(x) | Xori(x) => {
+            let value = arithmetic_imm_result(&instruction, &x, registers.get(x.rs1()));
+            set_register(program, x.rd(), value);
+            program.advance_pc();
+        }
+        Auipc(x) | Lui(x) => {
+            set_register(program, x.rd(), upper_result(&instruction, &x, pc));
+            program.advance_pc();
+        }
+        Beq(x) | Bne(x) | Blt(x) | Bge(x) | Bltu(x) | Bgeu(x) => {
+            match branch_taken(&instruction, registers.get(x.rs1()), registers.get(x.rs2())) {
+                true => program.pc.jump(wrapping_add_btype(pc, &x)),
+                false => program.advance_pc(),
+            }
+        }
+        Sw(x) => {
+            let address = wrapping_add_stype(registers.get(x.rs1()), &x);
+            let value = registers.get(x.rs2());
+            program.write_mem(address, value)?;
+            program.advance_pc();
+        }
+        Lbu(x) | Lb(x) | Lh(x) | Lhu(x) | Lw(x) => {
+            let address = wrapping_add_itype(registers.get(x.rs1()), &x);
+            let alignment = address % 4;
+            let value = program.read_mem(address - alignment, false)?;
+            let (mask, shift) = get_mask_round_1_for_load(&instruction, alignment);
+            set_register(
+                program,
+                x.rd(),
+                load_result(&instruction, mask, shift, value),
+            );
+            program.advance_pc();
+        }
+        _ => panic!("Unreachable"),
+    }
+
+    let new_pc = program.pc.get_address();
+    if new_pc % 2 != 0 {
+        return Err(ExecutionResult::UnalignedJump(new_pc));
+    }
+    Ok(())
+}
+
+// the writes to x0 are discarded as in the full execution
+fn set_register(program: &mut Program, rd: u32, value: u32) {
+    if rd != REGISTER_ZERO as u32 {
+        program.registers.set_value(rd, value);
+    }
+}
+
+// instructions executed in a single step that fast_step implements
+fn single_step(decoded: &DecodedInstruction, registers: &Registers) -> bool {
+    let instruction = match decoded {
+        DecodedInstruction::Base(instruction, _) => instruction,
+        DecodedInstruction::Bitmanip(_) => return true,
+    };
+    match instruction {
+        Ebreak | Fence(_) | Jal(_) | Jalr(_) | Mul(_) | Mulh(_) | Mulhsu(_) | Mulhu(_) | Div(_)
+        | Divu(_) | Rem(_) | Remu(_) | Sub(_) | Xor(_) | And(_) | Or(_) | Add(_) | Sll(_)
+        | Srl(_) | Sra(_) | Slt(_) | Sltu(_) | Slli(_) | Srli(_) | Srai(_) | Slti(_) | Sltiu(_)
+        | Addi(_) | Andi(_) | Ori(_) | Xori(_) | Auipc(_) | Lui(_) | Beq(_) | Bne(_) | Blt(_)
+        | Bge(_) | Bltu(_) | Bgeu(_) => true,
+        // only the aligned words are stored without micro instructions
+        Sw(x) => wrapping_add_stype(registers.get(x.rs1()), x) % 4 == 0,
+        Lbu(x) | Lb(x) | Lh(x) | Lhu(x) | Lw(x) => {
+            let alignment = wrapping_add_itype(registers.get(x.rs1()), x) % 4;
+            let (_word, _half, _byte, reads) =
+                get_type_and_read_from_instruction(instruction, alignment);
+            reads == 1
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::fetcher::{execute_program_with_options, ExecutionOptions},
+        loader::program::AUX_REGISTER_2,
+        test_utils::program_with_data,
+    };
+
+    // t1 = 0x2000; a1 = 25; loop: a0 = a0 + 1; sw a0, 0(t1); sb a0, 5(t1); lbu a2, 5(t1);
+    // bne a0, a1, loop; a7 = 93; a0 = 0; ecall
+    fn get_program() -> Program {
+        program_with_data(&[
+            0x0000_2337,
+            0x0190_0593,
+            0x0015_0513,
+            0x00a3_2023,
+            0x00a3_02a3,
+            0x0053_4603,
+            0xfeb5_1863,
+            0x05d0_0893,
+            0x0000_0513,
+            0x0000_0073,
+        ])
+    }
+
+    #[test]
+    fn test_fast_matches_full_execution() {
+        let mut full = get_program();
+        let (full_result, _) = execute_program_with_options(&mut full, ExecutionOptions::default());
+        assert!(matches!(full_result, ExecutionResult::Halt(0, _, _)));
+
+        // stopping at every step, also in the middle of the micro instructions of sb
+        for limit in (1..full.step).map(Some).chain([None]) {
+            let mut full = get_program();
+            let options = ExecutionOptions {
+                limit_step: limit,
+                ..Default::default()
+            };
+            let (full_result, _) = execute_program_with_options(&mut full, options);
+
+            let mut fast = get_program();
+            let result = execute_fast(&mut fast, vec![], ".input", false, limit);
+            assert_eq!(result, full_result);
+            assert_eq!(fast.step, full.step);
+            assert_eq!(fast.pc.get_address(), full.pc.get_address());
+            assert_eq!(fast.pc.get_micro(), full.pc.get_micro());
+            for idx in 0..=AUX_REGISTER_2 {
+                assert_eq!(fast.registers.get(idx), full.registers.get(idx));
+            }
+            let data = full.find_section_by_name("data").unwrap();
+            let fast_data = fast.find_section_by_name("data").unwrap();
+            for idx in 0..data.words() {
+                assert_eq!(fast_data.word(idx), data.word(idx));
+            }
+        }
+    }
+}
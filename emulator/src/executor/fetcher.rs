@@ -1,9 +1,22 @@
 use std::{cmp::Ordering, collections::HashSet};
 
-use super::{utils::FailConfiguration, verifier::verify_script};
-use crate::{executor::alignment_masks::*, loader::program::*, ExecutionResult};
-use bitcoin_script_riscv::riscv::instruction_mapping::create_verification_script_mapping;
+use super::{
+    decode_cache::DecodedInstruction, observer::ExecutionObserver, syscall::op_ecall,
+    trace_writer::TraceWriter, utils::FailConfiguration, verifier::verify_script,
+};
+use crate::{
+    executor::alignment_masks::*,
+    loader::{
+        checkpoint::{CheckpointFormat, CheckpointWriter},
+        program::*,
+    },
+    ExecutionResult,
+};
+use bitcoin_script_riscv::riscv::{
+    bitmanip::BitmanipInstruction, instruction_mapping::create_verification_script_mapping,
+};
 use bitvmx_cpu_definitions::{
+    csr::csr_value,
     memory::{MemoryAccessType, MemoryWitness},
     trace::*,
 };
@@ -11,11 +24,65 @@ use riscv_decode::{
     types::*,
     Instruction::{self, *},
 };
-use tracing::{error, info};
+use tracing::info;
 
 pub type TraceStepResult = (TraceRWStep, String);
 pub type FullTrace = Vec<TraceStepResult>;
 
+pub struct ExecutionOptions<'a> {
+    pub input: Vec<u8>,
+    pub input_section_name: String,
+    pub little_endian: bool,
+    pub checkpoint_path: Option<String>,
+    pub limit_step: Option<u64>,
+    pub print_trace: bool,
+    pub verify_on_chain: bool,
+    pub use_instruction_mapping: bool,
+    pub print_program_stdout: bool,
+    pub debug: bool,
+    pub no_hash: bool,
+    pub trace_list: Option<Vec<u64>>,
+    pub mem_dump: Option<u64>,
+    pub fail_config: FailConfiguration,
+    pub save_non_checkpoint_steps: bool,
+    // notified of every step and of the end of the execution
+    pub observers: Vec<&'a mut dyn ExecutionObserver>,
+    // receives the printed steps instead of the returned trace (that keeps only the last step)
+    pub trace_writer: Option<&'a mut TraceWriter>,
+    pub checkpoint_format: CheckpointFormat,
+    // steps between the checkpoints written to checkpoint_path
+    pub checkpoint_interval: u64,
+    // fetches and decodes every step instead of using the pre-decoded instructions
+    pub no_decode_cache: bool,
+}
+
+impl Default for ExecutionOptions<'_> {
+    fn default() -> Self {
+        Self {
+            input: Vec::new(),
+            input_section_name: ".input".to_string(),
+            little_endian: false,
+            checkpoint_path: None,
+            limit_step: None,
+            print_trace: false,
+            verify_on_chain: false,
+            use_instruction_mapping: false,
+            print_program_stdout: false,
+            debug: false,
+            no_hash: false,
+            trace_list: None,
+            mem_dump: None,
+            fail_config: FailConfiguration::default(),
+            save_non_checkpoint_steps: false,
+            observers: Vec::new(),
+            trace_writer: None,
+            checkpoint_format: CheckpointFormat::Json,
+            checkpoint_interval: CHECKPOINT_SIZE,
+            no_decode_cache: false,
+        }
+    }
+}
+
 pub fn execute_program(
     program: &mut Program,
     input: Vec<u8>,
@@ -34,13 +101,74 @@ pub fn execute_program(
     fail_config: FailConfiguration,
     save_non_checkpoint_steps: bool,
 ) -> (ExecutionResult, FullTrace) {
+    execute_program_with_options(
+        program,
+        ExecutionOptions {
+            input,
+            input_section_name: input_section_name.to_string(),
+            little_endian,
+            checkpoint_path: checkpoint_path.clone(),
+            limit_step,
+            print_trace,
+            verify_on_chain,
+            use_instruction_mapping,
+            print_program_stdout,
+            debug,
+            no_hash,
+            trace_list,
+            mem_dump,
+            fail_config,
+            save_non_checkpoint_steps,
+            observers: Vec::new(),
+            trace_writer: None,
+            checkpoint_format: CheckpointFormat::Json,
+            checkpoint_interval: CHECKPOINT_SIZE,
+            no_decode_cache: false,
+        },
+    )
+}
+
+pub fn execute_program_with_options(
+    program: &mut Program,
+    options: ExecutionOptions,
+) -> (ExecutionResult, FullTrace) {
+    let ExecutionOptions {
+        input,
+        input_section_name,
+        little_endian,
+        checkpoint_path,
+        limit_step,
+        print_trace,
+        verify_on_chain,
+        use_instruction_mapping,
+        print_program_stdout,
+        debug,
+        no_hash,
+        trace_list,
+        mem_dump,
+        fail_config,
+        save_non_checkpoint_steps,
+        mut observers,
+        mut trace_writer,
+        checkpoint_format,
+        checkpoint_interval,
+        no_decode_cache,
+    } = options;
     let trace_set: Option<HashSet<u64>> = trace_list.map(|vec| vec.into_iter().collect());
 
     let mut traces = Vec::new();
 
-    let load_input_result = program.load_input(input.clone(), input_section_name, little_endian);
-    if load_input_result.is_err() {
-        return (load_input_result.err().unwrap(), traces);
+    let load_input_result = program.load_input(input.clone(), &input_section_name, little_endian);
+    if let Err(err) = load_input_result {
+        notify_result(&mut observers, program, &err);
+        return (err, traces);
+    }
+
+    // the programs loaded from a checkpoint are decoded here
+    if no_decode_cache {
+        program.decode_cache = None;
+    } else if program.decode_cache.is_none() {
+        program.build_decode_cache();
     }
 
     let instruction_mapping = match verify_on_chain && use_instruction_mapping {
@@ -51,6 +179,7 @@ pub fn execute_program(
     };
 
     let mut hasher = blake3::Hasher::new();
+    let mut checkpoints = CheckpointWriter::new(checkpoint_format);
 
     let mut count = 0;
 
@@ -58,15 +187,23 @@ pub fn execute_program(
         //create path if it does not exist
         std::fs::create_dir_all(path).unwrap();
         if save_non_checkpoint_steps {
-            program.serialize_to_file(path);
+            checkpoints
+                .save(program, path)
+                .expect("Unable to write checkpoint");
+            for observer in observers.iter_mut() {
+                observer.on_checkpoint(program, path);
+            }
         }
     }
 
     if print_trace && (trace_set.is_none() || trace_set.as_ref().unwrap().contains(&program.step)) {
         let hash_hex = hash_to_string(&program.hash);
-        traces.push((TraceRWStep::default(), hash_hex.clone()));
         if debug {
-            info!("{};{}", traces[0].0.to_csv(), traces[0].1);
+            info!("{};{}", TraceRWStep::default().to_csv(), hash_hex);
+        }
+        match trace_writer.as_deref_mut() {
+            Some(writer) => writer.write_step(&TraceRWStep::default(), &hash_hex),
+            None => traces.push((TraceRWStep::default(), hash_hex)),
         }
     }
 
@@ -106,19 +243,15 @@ pub fn execute_program(
             }
 
             if debug && !input.is_empty() {
-                if let Some(input_section) = program.find_section_by_name(input_section_name) {
-                    for (idx, value) in input_section.data.iter().enumerate().take(10) {
-                        info!(
-                            "{:x}:  {:08x} ",
-                            (idx * 4) + input_section.start as usize,
-                            value.to_be()
-                        );
+                if let Some((start, size)) = program.find_range_by_name(&input_section_name) {
+                    for address in (start..start + size).step_by(4).take(10) {
+                        if let Ok(section) = program.find_section(address) {
+                            let idx = ((address - section.start) / 4) as usize;
+                            info!("{:x}:  {:08x} ", address, section.word(idx).to_be());
+                        }
                     }
                 } else {
-                    return (
-                        ExecutionResult::SectionNotFound(input_section_name.to_string()),
-                        traces,
-                    );
+                    break ExecutionResult::SectionNotFound(input_section_name.to_string());
                 }
             }
         }
@@ -148,6 +281,12 @@ pub fn execute_program(
             }
         }
 
+        if let Ok(trace) = &trace {
+            for observer in observers.iter_mut() {
+                observer.on_step(trace, &program.hash);
+            }
+        }
+
         if let Some(step) = mem_dump {
             if program.step == step {
                 info!("\n========== Dumping memory at step: {} ==========", step);
@@ -160,28 +299,40 @@ pub fn execute_program(
         if print_trace || trace.is_err() || program.halt || limit_step_reached {
             if trace_set.is_none() || trace_set.as_ref().unwrap().contains(&program.step) {
                 let hash_hex = hash_to_string(&program.hash);
-                traces.push((
-                    trace
-                        .as_ref()
-                        .unwrap_or(&TraceRWStep::from_step(program.step))
-                        .clone(),
-                    hash_hex.clone(),
-                ));
+                let failed_trace = TraceRWStep::from_step(program.step);
+                let step_trace = trace.as_ref().unwrap_or(&failed_trace);
+                // with a writer only the last step is kept in memory
+                let last_step = trace.is_err() || program.halt || limit_step_reached;
+                if let Some(writer) = trace_writer.as_deref_mut() {
+                    writer.write_step(step_trace, &hash_hex);
+                }
+                if trace_writer.is_none() || last_step {
+                    traces.push((step_trace.clone(), hash_hex.clone()));
+                }
                 if debug {
-                    info!(
-                        "{};{}",
-                        trace.as_ref().unwrap_or(&TraceRWStep::default()).to_csv(),
-                        hash_hex
-                    );
+                    let default_trace = TraceRWStep::default();
+                    let step_trace = trace.as_ref().unwrap_or(&default_trace);
+                    info!("{};{}", step_trace.to_csv(), hash_hex);
+                    if let Some(location) = program
+                        .symbolizer
+                        .symbolize(step_trace.read_pc.pc.get_address())
+                    {
+                        info!("at {}", location);
+                    }
                 }
             }
         }
 
         if let Some(path) = &checkpoint_path {
-            if program.step % CHECKPOINT_SIZE == 0
+            if program.step % checkpoint_interval.max(1) == 0
                 || ((trace.is_err() || program.halt) && save_non_checkpoint_steps)
             {
-                program.serialize_to_file(path);
+                checkpoints
+                    .save(program, path)
+                    .expect("Unable to write checkpoint");
+                for observer in observers.iter_mut() {
+                    observer.on_checkpoint(program, path);
+                }
             }
         }
 
@@ -202,7 +353,7 @@ pub fn execute_program(
         }
 
         if program.halt {
-            break ExecutionResult::Halt(program.registers.get(REGISTER_A0 as u32), program.step);
+            break program.halt_result();
         }
 
         if limit_step_reached {
@@ -218,9 +369,25 @@ pub fn execute_program(
         info!("Last hash: {}", hash_to_string(&program.hash));
     }
 
+    notify_result(&mut observers, program, &ret);
+
     (ret, traces)
 }
 
+fn notify_result(
+    observers: &mut [&mut dyn ExecutionObserver],
+    program: &Program,
+    result: &ExecutionResult,
+) {
+    for observer in observers.iter_mut() {
+        match result {
+            ExecutionResult::Halt(exit_code, _, _) => observer.on_halt(program, *exit_code),
+            ExecutionResult::LimitStepReached(_) => {}
+            err => observer.on_error(program, err),
+        }
+    }
+}
+
 pub fn wrapping_add(value: u32, x: u32, mask: u8) -> u32 {
     let offset = ((x as i32) << mask) >> mask;
     let value = value as i32;
@@ -252,27 +419,66 @@ pub fn execute_step(
     let pc = program.pc.clone();
     program.step += 1;
 
-    let opcode = match fail_config.fail_opcode {
-        Some(fo) if fo.step == program.step => fo.opcode,
-        _ => {
-            if fail_config.fail_memory_protection {
-                program.read_mem(pc.get_address(), true)?
-            } else {
-                program.read_instruction(pc.get_address())?
+    // the failures fetch something else than the code that was decoded
+    let failing_fetch = fail_config.fail_memory_protection
+        || matches!(&fail_config.fail_opcode, Some(fo) if fo.step == program.step);
+    let cached = match (&program.decode_cache, failing_fetch) {
+        (Some(cache), false) => cache.get(pc.get_address()).copied(),
+        _ => None,
+    };
+
+    let (opcode, decoded) = match cached {
+        Some(entry) => (entry.opcode, entry.instruction),
+        None => {
+            let opcode = match fail_config.fail_opcode {
+                Some(fo) if fo.step == program.step => fo.opcode,
+                _ => {
+                    if fail_config.fail_memory_protection {
+                        program.read_mem(pc.get_address(), true)?
+                    } else {
+                        program.read_instruction(pc.get_address())?
+                    }
+                }
+            };
+            // reserved and unknown encodings are not in the decode cache either
+            match DecodedInstruction::decode(opcode) {
+                Some(decoded) => (opcode, decoded),
+                None => {
+                    return Err(ExecutionResult::InstructionNotImplemented(
+                        opcode,
+                        "Unknown".to_string(),
+                    ))
+                }
             }
         }
     };
-
-    let instruction = riscv_decode::decode(opcode).unwrap();
+    program.instruction_size = decoded.size();
+
+    let instruction = match decoded {
+        DecodedInstruction::Base(instruction, _) => instruction,
+        DecodedInstruction::Bitmanip(bitmanip) => {
+            let (read_1, read_2, write_1, mem_witness) = op_bitmanip(&bitmanip, program);
+            return Ok(TraceRWStep::new(
+                program.step,
+                read_1,
+                read_2,
+                TraceReadPC::new(pc, opcode),
+                TraceStep::new(write_1, program.pc.clone()),
+                None,
+                mem_witness,
+            ));
+        }
+    };
 
     if debug && program.step % 100000000 < 10000 {
         info!(
-            "Step: {} PC: 0x{:08x}:{} Opcode: 0x{:08x} Instruction: {:?} ",
+            "Step: {} PC: 0x{:08x}:{} Opcode: 0x{:08x} Instruction: {:?}{}",
             program.step,
             pc.get_address(),
             pc.get_micro(),
             opcode,
-            instruction
+            instruction,
+            program.symbolizer.annotate(pc.get_address())
         );
     }
 
@@ -280,7 +486,7 @@ pub fn execute_step(
 
     let (read_1, read_2, write_1, mem_witness) = match instruction {
         Ebreak | Fence(_) => {
-            program.pc.next_address();
+            program.advance_pc();
             (
                 TraceRead::default(),
                 TraceRead::default(),
@@ -288,7 +494,7 @@ pub fn execute_step(
                 MemoryWitness::default(),
             )
         }
-        Ecall => op_ecall(program, print_program_stdout, debug),
+        Ecall => op_ecall(program, print_program_stdout, debug)?,
         Jal(x) => op_jal(&x, program),
         Jalr(x) => op_jalr(&x, program),
         Mul(x) | Mulh(x) | Mulhsu(x) | Mulhu(x) | Div(x) | Divu(x) | Rem(x) | Remu(x) | Sub(x)
@@ -312,6 +518,10 @@ pub fn execute_step(
             op_conditional(&instruction, &x, program)
         }
         Addi(x) | Andi(x) | Ori(x) | Xori(x) => op_arithmetic_imm(&instruction, &x, program),
+        Csrrw(x) | Csrrs(x) | Csrrc(x) => op_csr(&instruction, x.csr(), x.rs1(), x.rd(), program)?,
+        Csrrwi(x) | Csrrsi(x) | Csrrci(x) => {
+            op_csr(&instruction, x.csr(), x.zimm(), x.rd(), program)?
+        }
         _ => {
             return Err(ExecutionResult::InstructionNotImplemented(
                 opcode,
@@ -321,7 +531,7 @@ pub fn execute_step(
     };
 
     let new_pc = program.pc.get_address();
-    if !fail_config.fail_memory_protection && new_pc % 4 != 0 {
+    if !fail_config.fail_memory_protection && new_pc % 2 != 0 {
         return Err(ExecutionResult::UnalignedJump(new_pc));
     }
 
@@ -338,65 +548,101 @@ pub fn execute_step(
     Ok(trace)
 }
 
-pub fn op_ecall(
+// The counters are read-only, so only the accesses that don't write the csr are valid:
+// csrrs and csrrc with rs1 = x0, and csrrsi and csrrci with zimm = 0
+pub fn op_csr(
+    instruction: &Instruction,
+    csr: u32,
+    rs1_or_zimm: u32,
+    rd: u32,
+    program: &mut Program,
+) -> Result<(TraceRead, TraceRead, TraceWrite, MemoryWitness), ExecutionResult> {
+    let writes_csr = match instruction {
+        Csrrw(_) | Csrrwi(_) => true,
+        Csrrs(_) | Csrrc(_) | Csrrsi(_) | Csrrci(_) => rs1_or_zimm != 0,
+        _ => panic!("Unreachable"),
+    };
+
+    // the value is not read from memory as the script computes it from the step
+    let value = match csr_value(csr, program.step) {
+        Some(value) if !writes_csr => value,
+        _ => return Err(ExecutionResult::InvalidCsrAccess(csr)),
+    };
+
+    if rd == REGISTER_ZERO as u32 {
+        program.advance_pc();
+        return Ok((
+            TraceRead::default(),
+            TraceRead::default(),
+            TraceWrite::default(),
+            MemoryWitness::default(),
+        ));
+    }
+
+    program.registers.set(rd, value, program.step);
+    program.advance_pc();
+
+    Ok((
+        TraceRead::default(),
+        TraceRead::default(),
+        program.registers.to_trace_write(rd),
+        MemoryWitness::new(
+            MemoryAccessType::Unused,
+            MemoryAccessType::Unused,
+            MemoryAccessType::Register,
+        ),
+    ))
+}
+
+pub fn op_bitmanip(
+    instruction: &BitmanipInstruction,
     program: &mut Program,
-    print_program_stdout: bool,
-    debug: bool,
 ) -> (TraceRead, TraceRead, TraceWrite, MemoryWitness) {
-    let syscall = program.registers.get(REGISTER_A7_ECALL_ARG as u32);
-    let value_2 = program.registers.get(REGISTER_A0 as u32);
-    let read_1 = program
-        .registers
-        .to_trace_read(REGISTER_A7_ECALL_ARG as u32);
-    let read_2 = program.registers.to_trace_read(REGISTER_A0 as u32);
-
-    match syscall {
-        116 => {
-            if print_program_stdout {
-                let x = program.read_mem(0xA000_1000, false).unwrap_or(0) >> 24;
-                print!("{}", x as u8 as char);
-            }
-            program.pc.next_address();
-            //treat any syscall as a nop
-            (
-                TraceRead::default(),
-                TraceRead::default(),
-                TraceWrite::default(),
-                MemoryWitness::default(),
-            )
-        }
-        93 => {
-            //halt opcode
-            if debug {
-                info!("Exit code: 0x{:08x}", value_2);
-                for i in 0..32 {
-                    info!("Register {}: 0x{:08x}", i, program.registers.get(i));
-                }
-                info!("Total steps: {} 0x{:016x}", program.step, program.step);
-            }
+    let rd = instruction.rd();
+    if rd == REGISTER_ZERO as u32 {
+        program.advance_pc();
+        return (
+            TraceRead::default(),
+            TraceRead::default(),
+            TraceWrite::default(),
+            MemoryWitness::default(),
+        );
+    }
 
-            program.halt = true;
-            program
-                .registers
-                .set(REGISTER_A0 as u32, value_2, program.step);
-            (
-                read_1,
-                read_2,
-                program.registers.to_trace_write(REGISTER_A0 as u32),
-                MemoryWitness::registers(),
-            )
-            //Intenttionally PC is not modified and remains in this instruction
-        }
-        _ => {
-            error!("Unimplemented syscall: {}", syscall);
-            program.pc.next_address();
-            (
-                TraceRead::default(),
-                TraceRead::default(),
-                TraceWrite::default(),
-                MemoryWitness::default(),
-            )
-        }
+    let read_1 = program.registers.to_trace_read(instruction.rs1());
+    let value_1 = program.registers.get(instruction.rs1());
+
+    let (read_2, value_2, mem_witness) = match instruction.rs2() {
+        Some(rs2) => (
+            program.registers.to_trace_read(rs2),
+            program.registers.get(rs2),
+            MemoryWitness::registers(),
+        ),
+        None => (TraceRead::default(), 0, MemoryWitness::rur()),
+    };
+
+    let result = instruction.execute(value_1, value_2);
+
+    program.registers.set(rd, result, program.step);
+    program.advance_pc();
+
+    (
+        read_1,
+        read_2,
+        program.registers.to_trace_write(rd),
+        mem_witness,
+    )
+}
+
+pub fn branch_taken(instruction: &Instruction, value_1: u32, value_2: u32) -> bool {
+    match instruction {
+        Beq(_) => value_1 == value_2,
+        Bne(_) => value_1 != value_2,
+        Blt(_) => (value_1 as i32) < (value_2 as i32),
+        Bge(_) => (value_1 as i32) >= (value_2 as i32),
+        Bltu(_) => value_1 < value_2,
+        Bgeu(_) => value_1 >= value_2,
+        _ => panic!("Unreachable"),
     }
 }
 
@@ -412,20 +658,10 @@ pub fn op_conditional(
 
     let conditional_dest = wrapping_add_btype(program.pc.get_address(), x);
 
-    let condition_is_true = match instruction {
-        Beq(_) => value_1 == value_2,
-        Bne(_) => value_1 != value_2,
-        Blt(_) => (value_1 as i32) < (value_2 as i32),
-        Bge(_) => (value_1 as i32) >= (value_2 as i32),
-        Bltu(_) => value_1 < value_2,
-        Bgeu(_) => value_1 >= value_2,
-        _ => panic!("Unreachable"),
-    };
-
-    if condition_is_true {
+    if branch_taken(instruction, value_1, value_2) {
         program.pc.jump(conditional_dest);
     } else {
-        program.pc.next_address();
+        program.advance_pc();
     }
 
     (
@@ -448,9 +684,11 @@ pub fn op_jal(
         (TraceWrite::default(), MemoryWitness::default())
     } else {
         //state modification
-        program
-            .registers
-            .set(dest_register, pc.get_address() + 4, program.step);
+        program.registers.set(
+            dest_register,
+            pc.get_address() + program.instruction_size,
+            program.step,
+        );
         (
             program.registers.to_trace_write(dest_register),
             MemoryWitness::new(
@@ -492,9 +730,11 @@ pub fn op_jalr(
         )
     } else {
         //state modification
-        program
-            .registers
-            .set(dest_register, pc.get_address() + 4, program.step);
+        program.registers.set(
+            dest_register,
+            pc.get_address() + program.instruction_size,
+            program.step,
+        );
         (
             program.registers.to_trace_write(dest_register),
             MemoryWitness::rur(),
@@ -506,6 +746,57 @@ pub fn op_jalr(
     (read_1, TraceRead::default(), write_1, mem_witness)
 }
 
+pub fn arithmetic_result(instruction: &Instruction, value_1: u32, value_2: u32) -> u32 {
+    match instruction {
+        Mul(_) => {
+            let result: u64 = (value_1 as u64) * (value_2 as u64);
+            result as u32 // Low 32 bits
+        }
+        Mulh(_) => {
+            let result: i64 = (value_1 as i32 as i64) * (value_2 as i32 as i64);
+            (result >> 32) as u32 // High 32 bits
+        }
+        Mulhsu(_) => {
+            let result: i64 = (value_1 as i32 as i64) * (value_2 as u64 as i64);
+            (result >> 32) as u32 // High 32 bits
+        }
+        Mulhu(_) => {
+            let result: u64 = (value_1 as u64) * (value_2 as u64);
+            (result >> 32) as u32 // High 32 bits
+        }
+        Div(_) => match (value_1 as i32, value_2 as i32) {
+            (_, 0) => (-1 as i32) as u32,
+            (std::i32::MIN, -1) => std::i32::MIN as u32,
+            _ => (value_1 as i32 / value_2 as i32) as u32,
+        },
+        Divu(_) => {
+            if value_2 == 0 {
+                std::u32::MAX
+            } else {
+                value_1 / value_2
+            }
+        }
+        Rem(_) => match (value_1 as i32, value_2 as i32) {
+            (_, 0) => value_1,
+            (std::i32::MIN, -1) => 0,
+            _ => (value_1 as i32 % value_2 as i32) as u32,
+        },
+        Remu(_) => {
+            if value_2 == 0 {
+                value_1
+            } else {
+                value_1 % value_2
+            }
+        }
+        Sub(_) => value_1.wrapping_sub(value_2),
+        Xor(_) => value_1 ^ value_2,
+        And(_) => value_1 & value_2,
+        Or(_) => value_1 | value_2,
+        Add(_) => value_1.wrapping_add(value_2),
+        _ => panic!("Unreachable"),
+    }
+}
+
 pub fn op_arithmetic(
     instruction: &Instruction,
     x: &RType,
@@ -515,7 +806,7 @@ pub fn op_arithmetic(
     Option<u32>,
 ) {
     if x.rd() == REGISTER_ZERO as u32 {
-        program.pc.next_address();
+        program.advance_pc();
         return (
             (
                 TraceRead::default(),
@@ -560,57 +851,10 @@ pub fn op_arithmetic(
         _ => None,
     };
 
-    let result = match instruction {
-        Mul(_) => {
-            let result: u64 = (value_1 as u64) * (value_2 as u64);
-            result as u32 // Low 32 bits
-        }
-        Mulh(_) => {
-            let result: i64 = (value_1 as i32 as i64) * (value_2 as i32 as i64);
-            (result >> 32) as u32 // High 32 bits
-        }
-        Mulhsu(_) => {
-            let result: i64 = (value_1 as i32 as i64) * (value_2 as u64 as i64);
-            (result >> 32) as u32 // High 32 bits
-        }
-        Mulhu(_) => {
-            let result: u64 = (value_1 as u64) * (value_2 as u64);
-            (result >> 32) as u32 // High 32 bits
-        }
-        Div(_) => match (value_1 as i32, value_2 as i32) {
-            (_, 0) => (-1 as i32) as u32,
-            (std::i32::MIN, -1) => std::i32::MIN as u32,
-            _ => (value_1 as i32 / value_2 as i32) as u32,
-        },
-        Divu(_) => {
-            if value_2 == 0 {
-                std::u32::MAX
-            } else {
-                value_1 / value_2
-            }
-        }
-        Rem(_) => match (value_1 as i32, value_2 as i32) {
-            (_, 0) => value_1,
-            (std::i32::MIN, -1) => 0,
-            _ => (value_1 as i32 % value_2 as i32) as u32,
-        },
-        Remu(_) => {
-            if value_2 == 0 {
-                value_1
-            } else {
-                value_1 % value_2
-            }
-        }
-        Sub(_) => value_1.wrapping_sub(value_2),
-        Xor(_) => value_1 ^ value_2,
-        And(_) => value_1 & value_2,
-        Or(_) => value_1 | value_2,
-        Add(_) => value_1.wrapping_add(value_2),
-        _ => panic!("Unreachable"),
-    };
+    let result = arithmetic_result(instruction, value_1, value_2);
 
     program.registers.set(x.rd(), result, program.step);
-    program.pc.next_address();
+    program.advance_pc();
 
     (
         (
@@ -623,6 +867,20 @@ pub fn op_arithmetic(
     )
 }
 
+pub fn arithmetic_imm_result(instruction: &Instruction, x: &IType, rs1: u32) -> u32 {
+    let imm_value_signed = ((x.imm() as i32) << 20) >> 20;
+    match instruction {
+        // arithmetical operations
+        Addi(_) => wrapping_add_itype(rs1, x),
+        // logical operations
+        Andi(_) => (imm_value_signed & rs1 as i32) as u32,
+        Ori(_) => (imm_value_signed | rs1 as i32) as u32,
+        Xori(_) => (imm_value_signed ^ rs1 as i32) as u32,
+
+        _ => panic!("Unreachable"),
+    }
+}
+
 pub fn op_arithmetic_imm(
     instruction: &Instruction,
     x: &IType,
@@ -634,7 +892,7 @@ pub fn op_arithmetic_imm(
 
     if rd == REGISTER_ZERO as u32 {
         // nop is translated in: addi 0,0,0
-        program.pc.next_address();
+        program.advance_pc();
         return (
             TraceRead::default(),
             TraceRead::default(),
@@ -647,49 +905,18 @@ pub fn op_arithmetic_imm(
     let read_1 = program.registers.to_trace_read(x.rs1());
 
     let rs1 = program.registers.get(x.rs1());
-    let imm_value_signed = ((x.imm() as i32) << 20) >> 20;
-
-    // registers.get(x.rs1());
-    let result = match instruction {
-        // arithmetical operations
-        Addi(_) => wrapping_add_itype(rs1, x),
-        // logical operations
-        Andi(_) => (imm_value_signed & rs1 as i32) as u32,
-        Ori(_) => (imm_value_signed | rs1 as i32) as u32,
-        Xori(_) => (imm_value_signed ^ rs1 as i32) as u32,
-
-        _ => panic!("Unreachable"),
-    };
+    let result = arithmetic_imm_result(instruction, x, rs1);
 
     program.registers.set(rd, result, program.step);
-    program.pc.next_address();
+    program.advance_pc();
 
     let write = program.registers.to_trace_write(rd);
 
     (read_1, TraceRead::default(), write, MemoryWitness::rur())
 }
 
-pub fn op_shift_sl(
-    instruction: &Instruction,
-    x: &RType,
-    program: &mut Program,
-) -> (TraceRead, TraceRead, TraceWrite, MemoryWitness) {
-    let read_1 = program.registers.to_trace_read(x.rs1());
-    let read_2 = program.registers.to_trace_read(x.rs2());
-    let value_1 = program.registers.get(x.rs1());
-    let value_2 = program.registers.get(x.rs2());
-
-    if x.rd() == REGISTER_ZERO as u32 {
-        program.pc.next_address();
-        return (
-            TraceRead::default(),
-            TraceRead::default(),
-            TraceWrite::default(),
-            MemoryWitness::default(),
-        );
-    }
-
-    let result = match instruction {
+pub fn shift_sl_result(instruction: &Instruction, value_1: u32, value_2: u32) -> u32 {
+    match instruction {
         // Shift amount held in the lower 5 bits of register rs2
         Sll(_) => value_1 << (value_2 & 0x1F),
         Srl(_) => value_1 >> (value_2 & 0x1F),
@@ -709,10 +936,33 @@ pub fn op_shift_sl(
             }
         }
         _ => panic!("Unreachable"),
-    };
+    }
+}
+
+pub fn op_shift_sl(
+    instruction: &Instruction,
+    x: &RType,
+    program: &mut Program,
+) -> (TraceRead, TraceRead, TraceWrite, MemoryWitness) {
+    let read_1 = program.registers.to_trace_read(x.rs1());
+    let read_2 = program.registers.to_trace_read(x.rs2());
+    let value_1 = program.registers.get(x.rs1());
+    let value_2 = program.registers.get(x.rs2());
+
+    if x.rd() == REGISTER_ZERO as u32 {
+        program.advance_pc();
+        return (
+            TraceRead::default(),
+            TraceRead::default(),
+            TraceWrite::default(),
+            MemoryWitness::default(),
+        );
+    }
+
+    let result = shift_sl_result(instruction, value_1, value_2);
 
     program.registers.set(x.rd(), result, program.step);
-    program.pc.next_address();
+    program.advance_pc();
 
     (
         read_1,
@@ -722,13 +972,22 @@ pub fn op_shift_sl(
     )
 }
 
+pub fn shift_imm_result(instruction: &Instruction, x: &ShiftType, value: u32) -> u32 {
+    match instruction {
+        Slli(_) => value << x.shamt(),
+        Srli(_) => value >> x.shamt(),
+        Srai(_) => ((value as i32) >> x.shamt()) as u32,
+        _ => panic!("Unreachable"),
+    }
+}
+
 pub fn op_shift_imm(
     instruction: &Instruction,
     x: &ShiftType,
     program: &mut Program,
 ) -> (TraceRead, TraceRead, TraceWrite, MemoryWitness) {
     if x.rd() == REGISTER_ZERO as u32 {
-        program.pc.next_address();
+        program.advance_pc();
         return (
             TraceRead::default(),
             TraceRead::default(),
@@ -740,15 +999,10 @@ pub fn op_shift_imm(
     let read_1 = program.registers.to_trace_read(x.rs1());
     let value = program.registers.get(x.rs1());
 
-    let result = match instruction {
-        Slli(_) => value << x.shamt(),
-        Srli(_) => value >> x.shamt(),
-        Srai(_) => ((value as i32) >> x.shamt()) as u32,
-        _ => panic!("Unreachable"),
-    };
+    let result = shift_imm_result(instruction, x, value);
 
     program.registers.set(x.rd(), result, program.step);
-    program.pc.next_address();
+    program.advance_pc();
 
     (
         read_1,
@@ -758,13 +1012,34 @@ pub fn op_shift_imm(
     )
 }
 
+pub fn sl_imm_result(instruction: &Instruction, x: &IType, value: u32) -> u32 {
+    let imm_extended = ((x.imm() as i32) << 20) >> 20;
+    match instruction {
+        Slti(_) => {
+            if (value as i32) < imm_extended {
+                1
+            } else {
+                0
+            }
+        }
+        Sltiu(_) => {
+            if value < imm_extended as u32 {
+                1
+            } else {
+                0
+            }
+        }
+        _ => panic!("Unreachable"),
+    }
+}
+
 pub fn op_sl_imm(
     instruction: &Instruction,
     x: &IType,
     program: &mut Program,
 ) -> (TraceRead, TraceRead, TraceWrite, MemoryWitness) {
     if x.rd() == REGISTER_ZERO as u32 {
-        program.pc.next_address();
+        program.advance_pc();
         return (
             TraceRead::default(),
             TraceRead::default(),
@@ -775,29 +1050,10 @@ pub fn op_sl_imm(
 
     let read_1 = program.registers.to_trace_read(x.rs1());
     let value = program.registers.get(x.rs1());
-    let imm = x.imm();
-    let imm_extended = ((imm as i32) << 20) >> 20;
-
-    let result = match instruction {
-        Slti(_) => {
-            if (value as i32) < imm_extended {
-                1
-            } else {
-                0
-            }
-        }
-        Sltiu(_) => {
-            if value < imm_extended as u32 {
-                1
-            } else {
-                0
-            }
-        }
-        _ => panic!("Unreachable"),
-    };
+    let result = sl_imm_result(instruction, x, value);
 
     program.registers.set(x.rd(), result, program.step);
-    program.pc.next_address();
+    program.advance_pc();
 
     (
         read_1,
@@ -847,7 +1103,7 @@ pub fn op_store(
                 let read_2 = program.registers.to_trace_read(x.rs2());
                 let value = program.registers.get(x.rs2());
                 program.write_mem(dest_mem, value)?;
-                program.pc.next_address();
+                program.advance_pc();
 
                 return Ok((
                     read_1,
@@ -954,7 +1210,7 @@ pub fn op_store(
             let write_1 = TraceWrite::new(dest_mem, value);
 
             if reads == 1 || micro == 7 {
-                program.pc.next_address();
+                program.advance_pc();
             } else {
                 program.pc.next_micro();
             }
@@ -982,6 +1238,16 @@ pub fn get_src_mem(registers: &Registers, x: &IType) -> (TraceRead, u32, u32) {
     let alignment = src_mem % 4;
     (read_1, src_mem - alignment, alignment)
 }
+pub fn load_result(instruction: &Instruction, mask: u32, shift: i8, value: u32) -> u32 {
+    let masked = mask & value;
+    let shifted = match shift.cmp(&0) {
+        Ordering::Less => masked >> (-shift * 8),
+        Ordering::Greater => masked << (shift * 8),
+        Ordering::Equal => masked,
+    };
+    sign_extension(instruction, shifted)
+}
+
 pub fn op_load(
     instruction: &Instruction,
     x: &IType,
@@ -991,7 +1257,7 @@ pub fn op_load(
     let micro = program.pc.get_micro();
 
     if micro > 1 && x.rd() == REGISTER_ZERO as u32 {
-        program.pc.next_address();
+        program.advance_pc();
         return Ok((
             TraceRead::default(),
             TraceRead::default(),
@@ -1026,17 +1292,10 @@ pub fn op_load(
             } else {
                 get_mask_round_2_for_load(instruction, alignment)
             };
-            let masked = mask & value;
-            let shifted = match shift.cmp(&0) {
-                Ordering::Less => masked >> (-shift * 8),
-                Ordering::Greater => masked << (shift * 8),
-                Ordering::Equal => masked,
-            };
-
-            let shifted = sign_extension(instruction, shifted);
+            let shifted = load_result(instruction, mask, shift, value);
 
             let write_1 = if reads == 1 {
-                program.pc.next_address();
+                program.advance_pc();
                 if x.rd() == REGISTER_ZERO as u32 {
                     return Ok((
                         read_1,
@@ -1096,7 +1355,7 @@ pub fn op_load(
             let read_1 = program.registers.to_trace_read(AUX_REGISTER_1);
 
             program.registers.set(x.rd(), value, program.step);
-            program.pc.next_address();
+            program.advance_pc();
             let write_1 = program.registers.to_trace_write(x.rd());
 
             (read_1, TraceRead::default(), write_1, MemoryWitness::rur())
@@ -1107,6 +1366,14 @@ pub fn op_load(
     })
 }
 
+pub fn upper_result(instruction: &Instruction, x: &UType, pc: u32) -> u32 {
+    match instruction {
+        Auipc(_) => pc.wrapping_add(x.imm()),
+        Lui(_) => x.imm(),
+        _ => panic!("Unreachable"),
+    }
+}
+
 pub fn op_upper(
     instruction: &Instruction,
     x: &UType,
@@ -1114,7 +1381,7 @@ pub fn op_upper(
 ) -> (TraceRead, TraceRead, TraceWrite, MemoryWitness) {
     let dest_register = x.rd();
     if dest_register == REGISTER_ZERO as u32 {
-        program.pc.next_address();
+        program.advance_pc();
         return (
             TraceRead::default(),
             TraceRead::default(),
@@ -1123,15 +1390,11 @@ pub fn op_upper(
         );
     }
 
-    let value = match instruction {
-        Auipc(_) => program.pc.get_address().wrapping_add(x.imm()),
-        Lui(_) => x.imm(),
-        _ => panic!("Unreachable"),
-    };
+    let value = upper_result(instruction, x, program.pc.get_address());
 
     //state modification
     program.registers.set(dest_register, value, program.step);
-    program.pc.next_address();
+    program.advance_pc();
 
     (
         TraceRead::default(),
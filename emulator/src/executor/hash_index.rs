@@ -0,0 +1,190 @@
+// Sampled step hashes
+//
+// Keeps the hash of the steps multiple of any of the intervals (and of the last step) while the
+// program executes from step 0, so the hashes asked in the first n-ary search rounds are answered
+// without executing again. The rounds asking for steps between the samples still replay from the
+// closest checkpoint.
+
+use std::collections::BTreeMap;
+
+use bitvmx_cpu_definitions::trace::{generate_initial_step_hash, hash_to_string, TraceRWStep};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::observer::ExecutionObserver;
+use crate::{
+    loader::{checkpoint::input_digest, program::Program},
+    EmulatorError, ExecutionResult,
+};
+
+pub const HASH_INDEX_FILE: &str = "hash_index.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashIndex {
+    intervals: Vec<u64>,
+    // digest of the input of the execution
+    input_digest: String,
+    // the hashes are converted to hex only when a round asks for them
+    hashes: BTreeMap<u64, [u8; 20]>,
+    last_step: u64,
+    last_hash: [u8; 20],
+}
+
+impl HashIndex {
+    pub fn new(intervals: Vec<u64>, input: &[u8]) -> Self {
+        let initial_hash: [u8; 20] = generate_initial_step_hash()
+            .try_into()
+            .expect("Invalid hash size");
+        Self {
+            intervals: intervals
+                .into_iter()
+                .filter(|interval| *interval > 0)
+                .collect(),
+            input_digest: input_digest(input),
+            hashes: BTreeMap::from([(0, initial_hash)]),
+            last_step: 0,
+            last_hash: initial_hash,
+        }
+    }
+
+    pub fn record(&mut self, step: u64, hash: &[u8; 20]) {
+        if self.intervals.iter().any(|interval| step % interval == 0) {
+            self.hashes.insert(step, *hash);
+        }
+        self.last_step = step;
+        self.last_hash = *hash;
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    fn get(&self, step: u64) -> Option<&[u8; 20]> {
+        match step == self.last_step {
+            true => Some(&self.last_hash),
+            false => self.hashes.get(&step),
+        }
+    }
+
+    // hashes of the steps of a round as the execution from the base returns them: the steps after
+    // the last one repeat the hash of the previous step asked. None if a step wasn't sampled.
+    pub fn round_hashes(&self, base: u64, steps: &[u64]) -> Option<Vec<String>> {
+        if base > self.last_step {
+            return None;
+        }
+
+        let mut previous = self.get(base)?;
+        let mut hashes = Vec::with_capacity(steps.len());
+        for step in steps {
+            if *step <= self.last_step {
+                previous = self.get(*step)?;
+            }
+            hashes.push(hash_to_string(previous));
+        }
+        Some(hashes)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), EmulatorError> {
+        let fname = format!("{}/{}", path, HASH_INDEX_FILE);
+        let serialized = serde_json::to_string(self)
+            .map_err(|err| EmulatorError::HashIndexError(format!("{}: {}", fname, err)))?;
+        std::fs::write(&fname, serialized)
+            .map_err(|err| EmulatorError::HashIndexError(format!("{}: {}", fname, err)))
+    }
+
+    // the index of the path if it was written executing the same input
+    pub fn load(path: &str, input: &[u8]) -> Option<Self> {
+        let fname = format!("{}/{}", path, HASH_INDEX_FILE);
+        let serialized = std::fs::read(&fname).ok()?;
+        let index: HashIndex = match serde_json::from_slice(&serialized) {
+            Ok(index) => index,
+            Err(err) => {
+                warn!("Ignoring the invalid hash index {}: {}", fname, err);
+                return None;
+            }
+        };
+        match index.input_digest == input_digest(input) {
+            true => Some(index),
+            false => None,
+        }
+    }
+
+    pub fn remove(path: &str) {
+        let _ = std::fs::remove_file(format!("{}/{}", path, HASH_INDEX_FILE));
+    }
+}
+
+impl ExecutionObserver for HashIndex {
+    fn on_step(&mut self, trace: &TraceRWStep, hash: &[u8; 20]) {
+        self.record(trace.step_number, hash);
+    }
+
+    // the failed step is hashed too
+    fn on_error(&mut self, program: &Program, _error: &ExecutionResult) {
+        self.record(program.step, &program.hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_index() -> HashIndex {
+        let mut index = HashIndex::new(vec![4, 10], &[1, 2]);
+        for step in 1..=13 {
+            index.record(step, &[step as u8; 20]);
+        }
+        index
+    }
+
+    fn hash(step: u8) -> String {
+        hash_to_string(&[step; 20])
+    }
+
+    #[test]
+    fn test_round_hashes() {
+        let index = get_index();
+        // 0, 4, 8, 10, 12 and the last step
+        assert_eq!(index.len(), 5);
+
+        assert_eq!(
+            index.round_hashes(0, &[4, 8, 12]),
+            Some(vec![hash(4), hash(8), hash(12)])
+        );
+        // the last step is always known
+        assert_eq!(index.round_hashes(8, &[13]), Some(vec![hash(13)]));
+        // not sampled
+        assert_eq!(index.round_hashes(8, &[9, 10]), None);
+        // after the last step the previous hash is repeated
+        assert_eq!(
+            index.round_hashes(0, &[8, 16, 24]),
+            Some(vec![hash(8), hash(8), hash(8)])
+        );
+        assert_eq!(
+            index.round_hashes(12, &[14, 16]),
+            Some(vec![hash(12), hash(12)])
+        );
+        assert_eq!(index.round_hashes(16, &[20]), None);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join("hash_index");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap();
+
+        get_index().save(path).unwrap();
+        let index = HashIndex::load(path, &[1, 2]).unwrap();
+        assert_eq!(index.round_hashes(0, &[4]), Some(vec![hash(4)]));
+        // written for another input
+        assert!(HashIndex::load(path, &[1, 3]).is_none());
+
+        HashIndex::remove(path);
+        assert!(HashIndex::load(path, &[1, 2]).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,14 @@
 pub mod alignment_masks;
+pub mod decode_cache;
+pub mod fast;
 pub mod fetcher;
+pub mod hash_index;
+pub mod observer;
+pub mod parallel;
+pub mod profiler;
+pub mod steps;
+pub mod syscall;
+pub mod trace_file;
+pub mod trace_writer;
 pub mod utils;
 pub mod verifier;
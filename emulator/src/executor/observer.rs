@@ -0,0 +1,107 @@
+use bitvmx_cpu_definitions::trace::TraceRWStep;
+
+use crate::{loader::program::Program, ExecutionResult};
+
+// Hooks into the execution loop, every callback does nothing by default so an observer only
+// implements the ones it needs
+pub trait ExecutionObserver {
+    // called after every executed step with the hash up to that step
+    fn on_step(&mut self, _trace: &TraceRWStep, _hash: &[u8; 20]) {}
+
+    fn on_halt(&mut self, _program: &Program, _exit_code: u32) {}
+
+    // the execution failed, either executing a step or verifying it
+    fn on_error(&mut self, _program: &Program, _error: &ExecutionResult) {}
+
+    // called after the program is saved as a checkpoint in the path
+    fn on_checkpoint(&mut self, _program: &Program, _path: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::fetcher::{execute_program_with_options, ExecutionOptions},
+        test_utils::program_with_code,
+    };
+
+    #[derive(Default)]
+    struct Recorder {
+        steps: Vec<u64>,
+        last_hash: [u8; 20],
+        exit_code: Option<u32>,
+        error: Option<ExecutionResult>,
+    }
+
+    impl ExecutionObserver for Recorder {
+        fn on_step(&mut self, trace: &TraceRWStep, hash: &[u8; 20]) {
+            self.steps.push(trace.step_number);
+            self.last_hash = *hash;
+        }
+
+        fn on_halt(&mut self, _program: &Program, exit_code: u32) {
+            self.exit_code = Some(exit_code);
+        }
+
+        fn on_error(&mut self, _program: &Program, error: &ExecutionResult) {
+            self.error = Some(error.clone());
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        // a7 = 93; a0 = 7; ecall
+        let mut program = program_with_code(&[0x05d0_0893, 0x0070_0513, 0x0000_0073]);
+        let mut recorder = Recorder::default();
+        let options = ExecutionOptions {
+            observers: vec![&mut recorder],
+            ..Default::default()
+        };
+        let (result, _) = execute_program_with_options(&mut program, options);
+
+        assert_eq!(result, ExecutionResult::Halt(7, 3, vec![]));
+        assert_eq!(recorder.steps, vec![1, 2, 3]);
+        assert_eq!(recorder.last_hash, program.hash);
+        assert_eq!(recorder.exit_code, Some(7));
+        assert!(recorder.error.is_none());
+    }
+
+    #[test]
+    fn test_observer_error() {
+        // jumps out of the code section
+        let mut program = program_with_code(&[0x0080_006f]);
+        let mut recorder = Recorder::default();
+        let options = ExecutionOptions {
+            observers: vec![&mut recorder],
+            ..Default::default()
+        };
+        let (result, _) = execute_program_with_options(&mut program, options);
+
+        assert_eq!(recorder.steps, vec![1]);
+        assert_eq!(recorder.error, Some(result));
+        assert!(recorder.exit_code.is_none());
+    }
+
+    #[test]
+    fn test_observer_error_without_input_section() {
+        // resumed from a later step the input is not loaded, only the debug output of the failed
+        // step looks for its section
+        let mut program = program_with_code(&[0x0080_006f]);
+        program.step = 10;
+        let mut recorder = Recorder::default();
+        let options = ExecutionOptions {
+            input: vec![1, 2, 3, 4],
+            input_section_name: ".input".to_string(),
+            debug: true,
+            observers: vec![&mut recorder],
+            ..Default::default()
+        };
+        let (result, _) = execute_program_with_options(&mut program, options);
+
+        assert_eq!(
+            result,
+            ExecutionResult::SectionNotFound(".input".to_string())
+        );
+        assert_eq!(recorder.error, Some(result));
+    }
+}
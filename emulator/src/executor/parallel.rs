@@ -0,0 +1,243 @@
+// Parallel re-execution
+//
+// The steps between two checkpoints only depend on the first one, so the segments are executed
+// concurrently, each one seeded with the state and hash of its checkpoint. The hash chain is
+// stitched checking at every boundary that the state and hash obtained executing the previous
+// segment match the checkpoint the next one started from.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use tracing::info;
+
+use super::fetcher::{execute_program_with_options, ExecutionOptions};
+use crate::{
+    loader::{
+        checkpoint::{list_checkpoints, read_checkpoint, state_digest, CheckpointOrigin},
+        program::Program,
+    },
+    EmulatorError, ExecutionResult,
+};
+
+#[derive(Debug, Clone)]
+pub struct SegmentResult {
+    pub start_step: u64,
+    pub end_step: u64,
+    pub start_hash: [u8; 20],
+    pub end_hash: [u8; 20],
+    start_state: String,
+    end_state: String,
+    pub result: ExecutionResult,
+}
+
+impl SegmentResult {
+    // the next segment starts where this one ended
+    fn continues_into(&self, next: &SegmentResult) -> bool {
+        self.end_step == next.start_step
+            && self.end_hash == next.start_hash
+            && self.end_state == next.start_state
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParallelExecution {
+    pub result: ExecutionResult,
+    // hash of the last step
+    pub hash: [u8; 20],
+    pub segments: Vec<SegmentResult>,
+    // checkpoints that don't match the execution of the previous segment
+    pub mismatches: Vec<u64>,
+}
+
+// executes from the initial program (with the input already loaded) using the checkpoints after it
+pub fn execute_parallel(
+    initial: Program,
+    checkpoint_path: &str,
+    threads: usize,
+    limit_step: Option<u64>,
+) -> Result<ParallelExecution, EmulatorError> {
+    let mut starts = vec![initial.step];
+    starts.extend(
+        list_checkpoints(checkpoint_path)
+            .into_iter()
+            .filter(|step| *step > initial.step)
+            .filter(|step| match limit_step {
+                Some(limit) => *step < limit,
+                None => true,
+            }),
+    );
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|step| Some(*step))
+        .chain([limit_step])
+        .collect::<Vec<Option<u64>>>();
+    info!(
+        "Executing {} segments starting at steps: {:?}",
+        starts.len(),
+        starts
+    );
+
+    // every checkpoint has to be written from the same program and input
+    let origin = CheckpointOrigin::of(&initial);
+    let initial = Mutex::new(Some(initial));
+    let next = AtomicUsize::new(0);
+    let results = starts
+        .iter()
+        .map(|_| Mutex::new(None))
+        .collect::<Vec<Mutex<Option<Result<SegmentResult, EmulatorError>>>>>();
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, starts.len()) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                if idx >= starts.len() {
+                    break;
+                }
+                let program = match idx {
+                    0 => initial.lock().unwrap().take(),
+                    _ => None,
+                };
+                let result =
+                    execute_segment(checkpoint_path, &origin, program, starts[idx], ends[idx]);
+                *results[idx].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    let segments = results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect::<Result<Vec<SegmentResult>, EmulatorError>>()?;
+
+    // the execution ends at the first segment that halted or failed
+    let last = segments
+        .iter()
+        .position(|segment| !matches!(segment.result, ExecutionResult::LimitStepReached(_)))
+        .unwrap_or(segments.len() - 1);
+    let mismatches = segments[..=last]
+        .windows(2)
+        .filter(|pair| !pair[0].continues_into(&pair[1]))
+        .map(|pair| pair[1].start_step)
+        .collect();
+
+    Ok(ParallelExecution {
+        result: segments[last].result.clone(),
+        hash: segments[last].end_hash,
+        mismatches,
+        segments,
+    })
+}
+
+fn execute_segment(
+    checkpoint_path: &str,
+    origin: &CheckpointOrigin,
+    program: Option<Program>,
+    start_step: u64,
+    end_step: Option<u64>,
+) -> Result<SegmentResult, EmulatorError> {
+    let mut program = match program {
+        Some(program) => program,
+        None => read_checkpoint(checkpoint_path, start_step, Some(origin))?,
+    };
+    let start_hash = program.hash;
+    let start_state = state_digest(&program);
+
+    // a checkpoint of the halt step doesn't execute further
+    let result = match program.halt {
+        true => program.halt_result(),
+        false => {
+            let options = ExecutionOptions {
+                limit_step: end_step,
+                ..Default::default()
+            };
+            execute_program_with_options(&mut program, options).0
+        }
+    };
+
+    Ok(SegmentResult {
+        start_step,
+        end_step: program.step,
+        start_hash,
+        end_hash: program.hash,
+        start_state,
+        end_state: state_digest(&program),
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        loader::{checkpoint::write_json_checkpoint, program::REGISTER_A0},
+        test_utils::program_with_data,
+    };
+
+    // t1 = 0x2000; a1 = 25; loop: a0 = a0 + 1; sw a0, 0(t1); bne a0, a1, loop;
+    // a7 = 93; a0 = 0; ecall
+    fn get_program() -> Program {
+        program_with_data(&[
+            0x0000_2337,
+            0x0190_0593,
+            0x0015_0513,
+            0x00a3_2023,
+            0xfeb5_1ce3,
+            0x05d0_0893,
+            0x0000_0513,
+            0x0000_0073,
+        ])
+    }
+
+    fn write_checkpoints(path: &str) -> (ExecutionResult, [u8; 20]) {
+        let mut program = get_program();
+        let options = ExecutionOptions {
+            checkpoint_path: Some(path.to_string()),
+            checkpoint_interval: 16,
+            save_non_checkpoint_steps: true,
+            ..Default::default()
+        };
+        let (result, _) = execute_program_with_options(&mut program, options);
+        (result, program.hash)
+    }
+
+    #[test]
+    fn test_execute_parallel() {
+        let dir = std::env::temp_dir().join("parallel_execution");
+        let path = dir.to_str().unwrap();
+        let (result, hash) = write_checkpoints(path);
+        assert_eq!(result, ExecutionResult::Halt(0, 80, vec![]));
+
+        let execution = execute_parallel(get_program(), path, 3, None).unwrap();
+        assert_eq!(execution.result, result);
+        assert_eq!(execution.hash, hash);
+        // 0, 16, 32, 48, 64 and the halt step
+        assert_eq!(execution.segments.len(), 6);
+        assert!(execution.mismatches.is_empty());
+
+        // a checkpoint with a valid header but a state the previous segment doesn't reach
+        let mut tampered = read_checkpoint(path, 32, None).unwrap();
+        let a0 = tampered.registers.get(REGISTER_A0 as u32);
+        tampered.registers.set(REGISTER_A0 as u32, a0 - 1, 32);
+        write_json_checkpoint(&tampered, path).unwrap();
+
+        // the segment starting there doesn't reach the next checkpoint either
+        let execution = execute_parallel(get_program(), path, 2, None).unwrap();
+        assert_eq!(execution.mismatches, vec![32, 48]);
+        assert_eq!(execution.result, result);
+
+        // the checkpoints of another program are rejected
+        let mut other = get_program();
+        let code = other.find_section_by_name_mut("code").unwrap();
+        code.data[1] = 0x01a0_0593_u32.to_be();
+        let execution = execute_parallel(other, path, 2, None);
+        assert!(matches!(
+            execution,
+            Err(EmulatorError::InvalidCheckpoint(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
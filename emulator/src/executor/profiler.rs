@@ -0,0 +1,329 @@
+// Guest profiler
+//
+// Attributes every step to the function being executed. The calls and returns are detected with
+// the usual patterns of the RISC-V calling convention: jal/jalr linking ra (or t0) is a call and
+// jalr x0 jumping to ra (or t0) is a return. Tail calls are attributed to the caller.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bitcoin_script_riscv::riscv::{
+    bitmanip::BitmanipInstruction, compressed::decode_instruction,
+    instruction_mapping::get_key_from_opcode,
+};
+use bitvmx_cpu_definitions::trace::TraceRWStep;
+use riscv_decode::Instruction::{Jal, Jalr};
+
+use super::observer::ExecutionObserver;
+use crate::{loader::symbols::Symbolizer, EmulatorError};
+
+const REGISTER_RA: u32 = 1;
+const REGISTER_T0: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transfer {
+    Call,
+    Return,
+    None,
+}
+
+#[derive(Debug)]
+struct CallNode {
+    function: u32,
+    parent: usize,
+    children: HashMap<u32, usize>,
+    steps: u64,
+}
+
+impl CallNode {
+    fn new(function: u32, parent: usize) -> Self {
+        Self {
+            function,
+            parent,
+            children: HashMap::new(),
+            steps: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSteps {
+    pub name: String,
+    // steps executed by the function itself
+    pub self_steps: u64,
+    // steps including the functions it called
+    pub total_steps: u64,
+}
+
+#[derive(Debug)]
+pub struct Profiler {
+    // call tree, the children are always after their parents
+    nodes: Vec<CallNode>,
+    current: usize,
+    transfers: HashMap<u32, Transfer>,
+    // (pc, micro) => (opcode, steps)
+    instructions: HashMap<(u32, u8), (u32, u64)>,
+}
+
+impl Profiler {
+    pub fn new(entry_point: u32) -> Self {
+        Self {
+            nodes: vec![CallNode::new(entry_point, 0)],
+            current: 0,
+            transfers: HashMap::new(),
+            instructions: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, trace: &TraceRWStep) {
+        let pc = trace.read_pc.pc.get_address();
+        let micro = trace.read_pc.pc.get_micro();
+        let opcode = trace.read_pc.opcode;
+
+        self.nodes[self.current].steps += 1;
+        self.instructions
+            .entry((pc, micro))
+            .or_insert((opcode, 0))
+            .1 += 1;
+
+        let transfer = *self
+            .transfers
+            .entry(pc)
+            .or_insert_with(|| get_transfer(opcode));
+        match transfer {
+            Transfer::Call => self.call(trace.trace_step.get_pc().get_address()),
+            // returning from the entry point keeps the steps in the root
+            Transfer::Return => self.current = self.nodes[self.current].parent,
+            Transfer::None => {}
+        }
+    }
+
+    fn call(&mut self, function: u32) {
+        let next = self.nodes.len();
+        let child = *self.nodes[self.current]
+            .children
+            .entry(function)
+            .or_insert(next);
+        if child == next {
+            self.nodes.push(CallNode::new(function, self.current));
+        }
+        self.current = child;
+    }
+
+    fn stack(&self, mut node: usize) -> Vec<usize> {
+        let mut stack = vec![node];
+        while node != 0 {
+            node = self.nodes[node].parent;
+            stack.push(node);
+        }
+        stack.reverse();
+        stack
+    }
+
+    // lines of "caller;callee steps" as expected by flamegraph tools
+    pub fn folded_stacks(&self, symbolizer: &Symbolizer) -> Vec<String> {
+        let mut folded = BTreeMap::new();
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.steps == 0 {
+                continue;
+            }
+            let stack = self
+                .stack(idx)
+                .iter()
+                .map(|node| function_name(symbolizer, self.nodes[*node].function))
+                .collect::<Vec<String>>()
+                .join(";");
+            *folded.entry(stack).or_insert(0) += node.steps;
+        }
+
+        folded
+            .iter()
+            .map(|(stack, steps)| format!("{} {}", stack, steps))
+            .collect()
+    }
+
+    pub fn save_folded_stacks(
+        &self,
+        path: &str,
+        symbolizer: &Symbolizer,
+    ) -> Result<(), EmulatorError> {
+        let mut folded = self.folded_stacks(symbolizer).join("\n");
+        folded.push('\n');
+        std::fs::write(path, folded)
+            .map_err(|err| EmulatorError::ProfileError(format!("{}: {}", path, err)))
+    }
+
+    // sorted by the steps executed by the function itself
+    pub fn function_steps(&self, symbolizer: &Symbolizer) -> Vec<FunctionSteps> {
+        let mut subtree_steps = self
+            .nodes
+            .iter()
+            .map(|node| node.steps)
+            .collect::<Vec<u64>>();
+        for idx in (1..self.nodes.len()).rev() {
+            subtree_steps[self.nodes[idx].parent] += subtree_steps[idx];
+        }
+
+        let mut functions: HashMap<u32, (u64, u64)> = HashMap::new();
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let steps = functions.entry(node.function).or_insert((0, 0));
+            steps.0 += node.steps;
+            // recursive calls are already included in the outermost call
+            let stack = self.stack(idx);
+            let recursive = stack[..stack.len() - 1]
+                .iter()
+                .any(|ancestor| self.nodes[*ancestor].function == node.function);
+            if !recursive {
+                steps.1 += subtree_steps[idx];
+            }
+        }
+
+        let mut functions = functions
+            .into_iter()
+            .map(|(function, (self_steps, total_steps))| FunctionSteps {
+                name: function_name(symbolizer, function),
+                self_steps,
+                total_steps,
+            })
+            .collect::<Vec<FunctionSteps>>();
+        functions.sort_by(|a, b| b.self_steps.cmp(&a.self_steps).then(a.name.cmp(&b.name)));
+        functions
+    }
+
+    // steps by instruction key (as used in the instruction mapping), most used first
+    pub fn instruction_histogram(&self) -> Vec<(String, u64)> {
+        let mut histogram = HashMap::new();
+        for ((_, micro), (opcode, steps)) in &self.instructions {
+            let key = get_key_from_opcode(*opcode, *micro).unwrap_or("unknown".to_string());
+            *histogram.entry(key).or_insert(0) += steps;
+        }
+
+        let mut histogram = histogram.into_iter().collect::<Vec<(String, u64)>>();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        histogram
+    }
+}
+
+impl ExecutionObserver for Profiler {
+    fn on_step(&mut self, trace: &TraceRWStep, _hash: &[u8; 20]) {
+        self.record(trace);
+    }
+}
+
+fn is_link_register(register: u32) -> bool {
+    register == REGISTER_RA || register == REGISTER_T0
+}
+
+fn get_transfer(opcode: u32) -> Transfer {
+    if BitmanipInstruction::decode(opcode).is_some() {
+        return Transfer::None;
+    }
+
+    match decode_instruction(opcode) {
+        Some((Jal(x), _)) if is_link_register(x.rd()) => Transfer::Call,
+        Some((Jalr(x), _)) if is_link_register(x.rd()) => Transfer::Call,
+        Some((Jalr(x), _)) if x.rd() == 0 && is_link_register(x.rs1()) => Transfer::Return,
+        _ => Transfer::None,
+    }
+}
+
+fn function_name(symbolizer: &Symbolizer, address: u32) -> String {
+    match symbolizer.symbol(address) {
+        Some((name, 0)) => name.to_string(),
+        Some((name, offset)) => format!("{}+0x{:x}", name, offset),
+        None => format!("0x{:08x}", address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::{fetcher::execute_step, utils::FailConfiguration},
+        loader::symbols::Symbol,
+        test_utils::{program_with_code, CODE_BASE_ADDRESS},
+    };
+
+    fn profile(code: &[u32]) -> Profiler {
+        let mut program = program_with_code(code);
+
+        let mut profiler = Profiler::new(CODE_BASE_ADDRESS);
+        while !program.halt {
+            let trace =
+                execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+            profiler.record(&trace);
+        }
+        profiler
+    }
+
+    fn get_symbolizer() -> Symbolizer {
+        let symbol = |name: &str, address: u32, size: u32| Symbol {
+            name: name.to_string(),
+            address,
+            size,
+        };
+        Symbolizer::new(
+            vec![
+                symbol("_start", 0x1000, 0x10),
+                symbol("add_one", 0x1010, 0x8),
+                symbol("add_two", 0x1018, 0x10),
+            ],
+            vec![],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn test_profile() {
+        let profiler = profile(&[
+            0x0100_00ef, // jal add_one
+            0x0140_00ef, // jal add_two
+            0x05d0_0893, // a7 = 93
+            0x0000_0073, // ecall
+            0x0015_0513, // add_one: a0 = a0 + 1
+            0x0000_8067, // ret
+            0x0000_8293, // add_two: t0 = ra
+            0xff5f_f0ef, // jal add_one
+            0x0015_0513, // a0 = a0 + 1
+            0x0002_8067, // jr t0
+        ]);
+        let symbolizer = get_symbolizer();
+
+        assert_eq!(
+            profiler.folded_stacks(&symbolizer),
+            vec![
+                "_start 4",
+                "_start;add_one 2",
+                "_start;add_two 4",
+                "_start;add_two;add_one 2",
+            ]
+        );
+
+        let functions = profiler.function_steps(&symbolizer);
+        assert_eq!(
+            functions,
+            vec![
+                FunctionSteps {
+                    name: "_start".to_string(),
+                    self_steps: 4,
+                    total_steps: 12,
+                },
+                FunctionSteps {
+                    name: "add_one".to_string(),
+                    self_steps: 4,
+                    total_steps: 4,
+                },
+                FunctionSteps {
+                    name: "add_two".to_string(),
+                    self_steps: 4,
+                    total_steps: 6,
+                },
+            ]
+        );
+
+        let histogram = profiler.instruction_histogram();
+        assert_eq!(histogram[0], ("addi".to_string(), 5));
+        assert!(histogram.contains(&("jal".to_string(), 3)));
+        assert!(histogram.contains(&("jalr".to_string(), 3)));
+        assert!(histogram.contains(&("ecall".to_string(), 1)));
+    }
+}
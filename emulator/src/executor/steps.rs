@@ -0,0 +1,189 @@
+// Lazy stepping over a program
+//
+// Every step is executed and hashed only when it is requested, so long executions can be
+// consumed (or skipped) without keeping the trace in memory. The last item is the result of the
+// execution, after it the iterator is exhausted.
+
+use bitvmx_cpu_definitions::trace::{compute_step_hash, TraceRWStep};
+
+use super::{fetcher::execute_step, utils::FailConfiguration};
+use crate::{loader::program::Program, ExecutionResult};
+
+#[derive(Debug, Clone)]
+pub enum StepResult {
+    // trace of the step and the hash up to it
+    Step(TraceRWStep, [u8; 20]),
+    End(ExecutionResult),
+}
+
+pub struct Steps<'a> {
+    program: &'a mut Program,
+    hasher: blake3::Hasher,
+    // result to return once the execution ended
+    pending: Option<ExecutionResult>,
+    finished: bool,
+}
+
+impl<'a> Steps<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self {
+            program,
+            hasher: blake3::Hasher::new(),
+            pending: None,
+            finished: false,
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        self.program
+    }
+
+    // executes without yielding until the given step is reached or the execution ends
+    pub fn skip_to(&mut self, step: u64) -> &mut Self {
+        while !self.finished && self.pending.is_none() && self.program.step < step {
+            self.execute();
+        }
+        self
+    }
+
+    fn execute(&mut self) -> Option<TraceRWStep> {
+        if self.program.halt {
+            self.pending = Some(self.halt_result());
+            return None;
+        }
+
+        match execute_step(self.program, false, false, FailConfiguration::default()) {
+            Ok(trace) => {
+                self.program.hash = compute_step_hash(
+                    &mut self.hasher,
+                    &self.program.hash,
+                    &trace.trace_step.to_bytes(),
+                );
+                if self.program.halt {
+                    self.pending = Some(self.halt_result());
+                }
+                Some(trace)
+            }
+            Err(err) => {
+                // the failed step is hashed as execute_program does
+                self.program.hash = compute_step_hash(
+                    &mut self.hasher,
+                    &self.program.hash,
+                    &TraceRWStep::from_step(self.program.step)
+                        .trace_step
+                        .to_bytes(),
+                );
+                self.pending = Some(err);
+                None
+            }
+        }
+    }
+
+    fn halt_result(&self) -> ExecutionResult {
+        self.program.halt_result()
+    }
+}
+
+impl Iterator for Steps<'_> {
+    type Item = StepResult;
+
+    fn next(&mut self) -> Option<StepResult> {
+        if self.finished {
+            return None;
+        }
+
+        if self.pending.is_none() {
+            if let Some(trace) = self.execute() {
+                return Some(StepResult::Step(trace, self.program.hash));
+            }
+        }
+
+        self.finished = true;
+        self.pending.take().map(StepResult::End)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{executor::fetcher::execute_program, test_utils::program_with_code};
+    use bitvmx_cpu_definitions::trace::hash_to_string;
+
+    fn get_program() -> Program {
+        // a1 = 5; loop: a0 = a0 + 1; bne a0, a1, loop; a7 = 93; a0 = 0; ecall
+        program_with_code(&[
+            0x0050_0593,
+            0x0015_0513,
+            0xfeb5_1ee3,
+            0x05d0_0893,
+            0x0000_0513,
+            0x0000_0073,
+        ])
+    }
+
+    #[test]
+    fn test_steps_match_execute_program() {
+        let mut program = get_program();
+        let items = program.steps().collect::<Vec<StepResult>>();
+
+        let mut expected = get_program();
+        let (result, trace) = execute_program(
+            &mut expected,
+            vec![],
+            ".input",
+            false,
+            &None,
+            None,
+            true,
+            false,
+            false,
+            false,
+            false,
+            false,
+            None,
+            None,
+            FailConfiguration::default(),
+            false,
+        );
+
+        assert_eq!(result, ExecutionResult::Halt(0, 14, vec![]));
+        assert_eq!(items.len(), 15);
+        // the trace of execute_program includes the initial step
+        for (item, (expected_trace, expected_hash)) in items.iter().zip(trace.iter().skip(1)) {
+            match item {
+                StepResult::Step(trace, hash) => {
+                    assert_eq!(trace.step_number, expected_trace.step_number);
+                    assert_eq!(hash_to_string(hash), *expected_hash);
+                }
+                StepResult::End(_) => panic!("Unexpected end"),
+            }
+        }
+        assert!(matches!(items.last(), Some(StepResult::End(end)) if *end == result));
+        assert_eq!(program.hash, expected.hash);
+    }
+
+    #[test]
+    fn test_skip_to() {
+        let mut program = get_program();
+        let mut steps = program.steps();
+
+        match steps.skip_to(10).next() {
+            Some(StepResult::Step(trace, _)) => assert_eq!(trace.step_number, 11),
+            other => panic!("Unexpected item {:?}", other),
+        }
+        assert_eq!(steps.program().step, 11);
+
+        let remaining = steps
+            .take_while(|item| matches!(item, StepResult::Step(..)))
+            .count();
+        assert_eq!(remaining, 3);
+
+        let mut program = get_program();
+        let mut steps = program.steps();
+        assert!(matches!(
+            steps.skip_to(100).next(),
+            Some(StepResult::End(ExecutionResult::Halt(0, 14, vec![])))
+        ));
+        assert!(steps.next().is_none());
+    }
+}
@@ -0,0 +1,664 @@
+use std::io::Write;
+
+use bitvmx_cpu_definitions::{
+    memory::{MemoryAccessType, MemoryWitness},
+    syscall::*,
+    trace::{TraceRead, TraceWrite},
+};
+use tracing::{error, info};
+
+use crate::{loader::program::*, ExecutionResult};
+
+type EcallStep = (TraceRead, TraceRead, TraceWrite, MemoryWitness);
+
+// Every micro instruction of the ecall is described in the syscall module of the definitions
+pub fn op_ecall(
+    program: &mut Program,
+    print_program_stdout: bool,
+    debug: bool,
+) -> Result<EcallStep, ExecutionResult> {
+    Ok(match program.pc.get_micro() {
+        0 => op_ecall_dispatch(program, print_program_stdout, debug),
+        MICRO_WRITE_LENGTH => op_write_length(program),
+        MICRO_WRITE_DATA => op_write_data(program, print_program_stdout)?,
+        MICRO_READ_AVAILABLE => op_read_available(program),
+        MICRO_READ_LENGTH => op_read_length(program),
+        MICRO_READ_INPUT => op_read_input(program)?,
+        MICRO_READ_SHIFT => op_read_shift(program),
+        MICRO_READ_MASK => op_read_mask(program)?,
+        MICRO_READ_MERGE => op_read_merge(program),
+        MICRO_READ_STORE => op_read_store(program)?,
+        MICRO_READ_ADVANCE => op_read_advance(program),
+        MICRO_BRK_LOWER => op_brk_lower(program),
+        MICRO_BRK_UPPER => op_brk_upper(program),
+        _ => panic!("Unreachable"),
+    })
+}
+
+fn op_ecall_dispatch(program: &mut Program, print_program_stdout: bool, debug: bool) -> EcallStep {
+    let syscall = program.registers.get(REGISTER_SYSCALL);
+    let arg_0 = program.registers.get(REGISTER_ARG_0);
+    let read_1 = program.registers.to_trace_read(REGISTER_SYSCALL);
+    let read_2 = program.registers.to_trace_read(REGISTER_ARG_0);
+
+    match syscall_outcome(syscall, arg_0) {
+        SyscallOutcome::Halt => {
+            if debug {
+                info!("Exit code: 0x{:08x}", arg_0);
+                for i in 0..32 {
+                    info!("Register {}: 0x{:08x}", i, program.registers.get(i));
+                }
+                info!("Total steps: {} 0x{:016x}", program.step, program.step);
+            }
+
+            program.halt = true;
+            program.registers.set(REGISTER_ARG_0, arg_0, program.step);
+            //Intenttionally PC is not modified and remains in this instruction
+            (
+                read_1,
+                read_2,
+                program.registers.to_trace_write(REGISTER_ARG_0),
+                MemoryWitness::registers(),
+            )
+        }
+        SyscallOutcome::Nop => {
+            if print_program_stdout {
+                let x = program.read_mem(0xA000_1000, false).unwrap_or(0) >> 24;
+                print!("{}", x as u8 as char);
+            }
+            program.advance_pc();
+            (
+                read_1,
+                read_2,
+                TraceWrite::default(),
+                MemoryWitness::no_write(),
+            )
+        }
+        SyscallOutcome::Return(value) => {
+            if value == RETURN_ENOSYS {
+                error!("Unimplemented syscall: {}", syscall);
+            }
+            program.registers.set(REGISTER_ARG_0, value, program.step);
+            program.advance_pc();
+            (
+                read_1,
+                read_2,
+                program.registers.to_trace_write(REGISTER_ARG_0),
+                MemoryWitness::registers(),
+            )
+        }
+        SyscallOutcome::Continue(micro) => {
+            program.pc.jump_micro(micro);
+            (
+                read_1,
+                read_2,
+                TraceWrite::default(),
+                MemoryWitness::no_write(),
+            )
+        }
+    }
+}
+
+// a0 = min(len, 4 - buf % 4)
+fn op_write_length(program: &mut Program) -> EcallStep {
+    let len = program.registers.get(REGISTER_ARG_2);
+    let buf = program.registers.get(REGISTER_ARG_1);
+    let read_1 = program.registers.to_trace_read(REGISTER_ARG_2);
+    let read_2 = program.registers.to_trace_read(REGISTER_ARG_1);
+
+    let count = len.min(4 - buf % 4);
+    program.registers.set(REGISTER_ARG_0, count, program.step);
+
+    if count == 0 {
+        program.advance_pc();
+    } else {
+        program.pc.jump_micro(MICRO_WRITE_DATA);
+    }
+
+    (
+        read_1,
+        read_2,
+        program.registers.to_trace_write(REGISTER_ARG_0),
+        MemoryWitness::registers(),
+    )
+}
+
+// captures a0 bytes from buf, the state is not modified
+fn op_write_data(
+    program: &mut Program,
+    print_program_stdout: bool,
+) -> Result<EcallStep, ExecutionResult> {
+    let buf = program.registers.get(REGISTER_ARG_1);
+    let count = program.registers.get(REGISTER_ARG_0);
+    let read_1 = program.registers.to_trace_read(REGISTER_ARG_1);
+
+    let aligned = buf & !3;
+    let value = program.read_mem(aligned, false)?;
+    let read_2 = TraceRead::new(aligned, value, program.get_last_step(aligned));
+
+    let alignment = buf % 4;
+    let bytes: Vec<u8> = (alignment..alignment + count)
+        .map(|i| (value >> (i * 8)) as u8)
+        .collect();
+
+    if print_program_stdout {
+        let _ = std::io::stdout().write_all(&bytes);
+    }
+    program.captured_output.extend(bytes);
+    program.advance_pc();
+
+    Ok((
+        read_1,
+        read_2,
+        TraceWrite::default(),
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+            MemoryAccessType::Unused,
+        ),
+    ))
+}
+
+// returns zero (end of file) if there is no more input
+fn op_read_available(program: &mut Program) -> EcallStep {
+    let pointer = program
+        .registers
+        .get(syscall_slot_index(SLOT_INPUT_POINTER));
+    let end = program.registers.get(syscall_slot_index(SLOT_INPUT_END));
+    let read_1 = program
+        .registers
+        .to_trace_read(syscall_slot_index(SLOT_INPUT_POINTER));
+    let read_2 = program
+        .registers
+        .to_trace_read(syscall_slot_index(SLOT_INPUT_END));
+
+    if pointer < end {
+        program.pc.jump_micro(MICRO_READ_LENGTH);
+        return (
+            read_1,
+            read_2,
+            TraceWrite::default(),
+            MemoryWitness::no_write(),
+        );
+    }
+
+    program.registers.set(REGISTER_ARG_0, 0, program.step);
+    program.advance_pc();
+    (
+        read_1,
+        read_2,
+        program.registers.to_trace_write(REGISTER_ARG_0),
+        MemoryWitness::registers(),
+    )
+}
+
+// a0 = 1 if a byte is going to be read
+fn op_read_length(program: &mut Program) -> EcallStep {
+    let len = program.registers.get(REGISTER_ARG_2);
+    let read_1 = program.registers.to_trace_read(REGISTER_ARG_2);
+
+    program
+        .registers
+        .set(REGISTER_ARG_0, (len != 0) as u32, program.step);
+
+    if len == 0 {
+        program.advance_pc();
+    } else {
+        program.pc.next_micro();
+    }
+
+    (
+        read_1,
+        TraceRead::default(),
+        program.registers.to_trace_write(REGISTER_ARG_0),
+        MemoryWitness::rur(),
+    )
+}
+
+// aux_2 = byte at input_pointer
+fn op_read_input(program: &mut Program) -> Result<EcallStep, ExecutionResult> {
+    let pointer = program
+        .registers
+        .get(syscall_slot_index(SLOT_INPUT_POINTER));
+    let read_1 = program
+        .registers
+        .to_trace_read(syscall_slot_index(SLOT_INPUT_POINTER));
+
+    let aligned = pointer & !3;
+    let value = program.read_mem(aligned, false)?;
+    let read_2 = TraceRead::new(aligned, value, program.get_last_step(aligned));
+
+    let byte = (value >> ((pointer % 4) * 8)) & 0xFF;
+    program.registers.set(AUX_REGISTER_2, byte, program.step);
+    program.pc.next_micro();
+
+    Ok((
+        read_1,
+        read_2,
+        program.registers.to_trace_write(AUX_REGISTER_2),
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+            MemoryAccessType::Register,
+        ),
+    ))
+}
+
+// aux_2 = aux_2 << (buf % 4)
+fn op_read_shift(program: &mut Program) -> EcallStep {
+    let buf = program.registers.get(REGISTER_ARG_1);
+    let byte = program.registers.get(AUX_REGISTER_2);
+    let read_1 = program.registers.to_trace_read(REGISTER_ARG_1);
+    let read_2 = program.registers.to_trace_read(AUX_REGISTER_2);
+
+    let shifted = (byte & 0xFF) << ((buf % 4) * 8);
+    program.registers.set(AUX_REGISTER_2, shifted, program.step);
+    program.pc.next_micro();
+
+    (
+        read_1,
+        read_2,
+        program.registers.to_trace_write(AUX_REGISTER_2),
+        MemoryWitness::registers(),
+    )
+}
+
+// aux_1 = mem[buf] with the byte at buf cleared
+fn op_read_mask(program: &mut Program) -> Result<EcallStep, ExecutionResult> {
+    let buf = program.registers.get(REGISTER_ARG_1);
+    let read_1 = program.registers.to_trace_read(REGISTER_ARG_1);
+
+    let aligned = buf & !3;
+    let value = program.read_mem(aligned, false)?;
+    let read_2 = TraceRead::new(aligned, value, program.get_last_step(aligned));
+
+    let masked = value & !(0xFF << ((buf % 4) * 8));
+    program.registers.set(AUX_REGISTER_1, masked, program.step);
+    program.pc.next_micro();
+
+    Ok((
+        read_1,
+        read_2,
+        program.registers.to_trace_write(AUX_REGISTER_1),
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+            MemoryAccessType::Register,
+        ),
+    ))
+}
+
+// aux_1 = aux_1 | aux_2
+fn op_read_merge(program: &mut Program) -> EcallStep {
+    let value_1 = program.registers.get(AUX_REGISTER_1);
+    let value_2 = program.registers.get(AUX_REGISTER_2);
+    let read_1 = program.registers.to_trace_read(AUX_REGISTER_1);
+    let read_2 = program.registers.to_trace_read(AUX_REGISTER_2);
+
+    program
+        .registers
+        .set(AUX_REGISTER_1, value_1 | value_2, program.step);
+    program.pc.next_micro();
+
+    (
+        read_1,
+        read_2,
+        program.registers.to_trace_write(AUX_REGISTER_1),
+        MemoryWitness::registers(),
+    )
+}
+
+// mem[buf] = aux_1
+fn op_read_store(program: &mut Program) -> Result<EcallStep, ExecutionResult> {
+    let buf = program.registers.get(REGISTER_ARG_1);
+    let value = program.registers.get(AUX_REGISTER_1);
+    let read_1 = program.registers.to_trace_read(REGISTER_ARG_1);
+    let read_2 = program.registers.to_trace_read(AUX_REGISTER_1);
+
+    let aligned = buf & !3;
+    program.write_mem(aligned, value)?;
+    program.pc.next_micro();
+
+    Ok((
+        read_1,
+        read_2,
+        TraceWrite::new(aligned, value),
+        MemoryWitness::new(
+            MemoryAccessType::Register,
+            MemoryAccessType::Register,
+            MemoryAccessType::Memory,
+        ),
+    ))
+}
+
+// input_pointer = input_pointer + 1
+fn op_read_advance(program: &mut Program) -> EcallStep {
+    let index = syscall_slot_index(SLOT_INPUT_POINTER);
+    let pointer = program.registers.get(index);
+    let read_1 = program.registers.to_trace_read(index);
+
+    program.registers.set(index, pointer + 1, program.step);
+    program.advance_pc();
+
+    (
+        read_1,
+        TraceRead::default(),
+        program.registers.to_trace_write(index),
+        MemoryWitness::rur(),
+    )
+}
+
+// aux_1 = max(addr, heap_start)
+fn op_brk_lower(program: &mut Program) -> EcallStep {
+    let requested = program.registers.get(REGISTER_ARG_0);
+    let heap_start = program.registers.get(syscall_slot_index(SLOT_HEAP_START));
+    let read_1 = program.registers.to_trace_read(REGISTER_ARG_0);
+    let read_2 = program
+        .registers
+        .to_trace_read(syscall_slot_index(SLOT_HEAP_START));
+
+    program
+        .registers
+        .set(AUX_REGISTER_1, requested.max(heap_start), program.step);
+    program.pc.next_micro();
+
+    (
+        read_1,
+        read_2,
+        program.registers.to_trace_write(AUX_REGISTER_1),
+        MemoryWitness::registers(),
+    )
+}
+
+// a0 = min(aux_1, heap_end)
+fn op_brk_upper(program: &mut Program) -> EcallStep {
+    let lower = program.registers.get(AUX_REGISTER_1);
+    let heap_end = program.registers.get(syscall_slot_index(SLOT_HEAP_END));
+    let read_1 = program.registers.to_trace_read(AUX_REGISTER_1);
+    let read_2 = program
+        .registers
+        .to_trace_read(syscall_slot_index(SLOT_HEAP_END));
+
+    program
+        .registers
+        .set(REGISTER_ARG_0, lower.min(heap_end), program.step);
+    program.advance_pc();
+
+    (
+        read_1,
+        read_2,
+        program.registers.to_trace_write(REGISTER_ARG_0),
+        MemoryWitness::registers(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvmx_cpu_definitions::constants::LAST_STEP_INIT;
+
+    use super::*;
+
+    const CODE_ADDRESS: u32 = 0x1000;
+    const REGISTERS_ADDRESS: u32 = 0xF000_0000;
+    const DATA_ADDRESS: u32 = 0xA000_0000;
+    const INPUT_ADDRESS: u32 = 0xB000_0000;
+    const HEAP_ADDRESS: u32 = 0xC000_0000;
+
+    fn get_program(data: &[u8]) -> Program {
+        let mut program = Program::new(CODE_ADDRESS, REGISTERS_ADDRESS, 0xE000_0000);
+        program.add_section(Section::new_with_data(
+            "code",
+            vec![0x0000_0073u32.to_be()],
+            CODE_ADDRESS,
+            4,
+            true,
+            false,
+            true,
+        ));
+        program.add_section(Section::new_with_data(
+            "data",
+            vec_u8_to_vec_u32(data, false),
+            DATA_ADDRESS,
+            data.len() as u32,
+            false,
+            true,
+            true,
+        ));
+        program.add_section(Section::new(
+            ".input",
+            INPUT_ADDRESS,
+            0x10,
+            false,
+            true,
+            false,
+        ));
+        program.add_section(Section::new(
+            "heap",
+            HEAP_ADDRESS,
+            0x100,
+            false,
+            true,
+            false,
+        ));
+        program
+    }
+
+    fn set_syscall(program: &mut Program, syscall: u32, arguments: &[u32]) {
+        program.registers.set(REGISTER_SYSCALL, syscall, 0);
+        for (i, argument) in arguments.iter().enumerate() {
+            program
+                .registers
+                .set(REGISTER_ARG_0 + i as u32, *argument, 0);
+        }
+    }
+
+    fn step(program: &mut Program) -> EcallStep {
+        program.step += 1;
+        op_ecall(program, false, false).unwrap()
+    }
+
+    fn register(idx: u32) -> u32 {
+        REGISTERS_ADDRESS + idx * 4
+    }
+
+    fn slot(slot: u32) -> u32 {
+        syscall_slot_address(REGISTERS_ADDRESS, slot)
+    }
+
+    fn assert_micro(program: &Program, micro: u8) {
+        assert_eq!(program.pc.get_address(), CODE_ADDRESS);
+        assert_eq!(program.pc.get_micro(), micro);
+    }
+
+    fn assert_next_instruction(program: &Program) {
+        assert_eq!(program.pc.get_address(), CODE_ADDRESS + 4);
+        assert_eq!(program.pc.get_micro(), 0);
+    }
+
+    #[test]
+    fn test_write_micro_steps() {
+        let mut program = get_program(b"xhello!!");
+        set_syscall(
+            &mut program,
+            SYSCALL_WRITE,
+            &[FD_STDOUT, DATA_ADDRESS + 1, 5],
+        );
+
+        let (read_1, read_2, write, witness) = step(&mut program);
+        assert_eq!(read_1.address, register(REGISTER_SYSCALL));
+        assert_eq!(read_2.address, register(REGISTER_ARG_0));
+        assert_eq!(write.address, 0);
+        assert_eq!(witness.byte(), MemoryWitness::no_write().byte());
+        assert_micro(&program, MICRO_WRITE_LENGTH);
+
+        // only the bytes up to the end of the word are written
+        let (read_1, read_2, write, _) = step(&mut program);
+        assert_eq!(read_1.address, register(REGISTER_ARG_2));
+        assert_eq!(read_2.address, register(REGISTER_ARG_1));
+        assert_eq!((write.address, write.value), (register(REGISTER_ARG_0), 3));
+        assert_micro(&program, MICRO_WRITE_DATA);
+
+        let (read_1, read_2, write, witness) = step(&mut program);
+        assert_eq!(read_1.value, DATA_ADDRESS + 1);
+        assert_eq!(
+            (read_2.address, read_2.last_step),
+            (DATA_ADDRESS, LAST_STEP_INIT)
+        );
+        assert_eq!(write.address, 0);
+        assert_eq!(witness.write(), MemoryAccessType::Unused);
+        assert_eq!(program.captured_output, b"hel");
+        assert_next_instruction(&program);
+    }
+
+    #[test]
+    fn test_write_nothing() {
+        let mut program = get_program(b"xhello!!");
+        set_syscall(&mut program, SYSCALL_WRITE, &[FD_STDOUT, DATA_ADDRESS, 0]);
+        program.pc.jump_micro(MICRO_WRITE_LENGTH);
+
+        let (_, _, write, _) = step(&mut program);
+        assert_eq!(write.value, 0);
+        assert!(program.captured_output.is_empty());
+        assert_next_instruction(&program);
+    }
+
+    #[test]
+    fn test_read_micro_steps() {
+        let mut program = get_program(&[1, 2, 3, 4]);
+        program.load_input(b"ab".to_vec(), ".input", false).unwrap();
+        set_syscall(&mut program, SYSCALL_READ, &[FD_STDIN, DATA_ADDRESS + 2, 4]);
+
+        step(&mut program);
+        assert_micro(&program, MICRO_READ_AVAILABLE);
+
+        let (read_1, read_2, write, _) = step(&mut program);
+        assert_eq!(
+            (read_1.address, read_1.value),
+            (slot(SLOT_INPUT_POINTER), INPUT_ADDRESS)
+        );
+        assert_eq!(
+            (read_2.address, read_2.value),
+            (slot(SLOT_INPUT_END), INPUT_ADDRESS + 2)
+        );
+        assert_eq!(write.address, 0);
+        assert_micro(&program, MICRO_READ_LENGTH);
+
+        let (_, _, write, witness) = step(&mut program);
+        assert_eq!((write.address, write.value), (register(REGISTER_ARG_0), 1));
+        assert_eq!(witness.byte(), MemoryWitness::rur().byte());
+        assert_micro(&program, MICRO_READ_INPUT);
+
+        let (_, read_2, write, _) = step(&mut program);
+        assert_eq!(read_2.address, INPUT_ADDRESS);
+        assert_eq!(
+            (write.address, write.value),
+            (register(AUX_REGISTER_2), 0x61)
+        );
+        assert_micro(&program, MICRO_READ_SHIFT);
+
+        let (_, _, write, _) = step(&mut program);
+        assert_eq!(write.value, 0x0061_0000);
+        assert_micro(&program, MICRO_READ_MASK);
+
+        let (_, read_2, write, _) = step(&mut program);
+        assert_eq!((read_2.address, read_2.value), (DATA_ADDRESS, 0x0403_0201));
+        assert_eq!(
+            (write.address, write.value),
+            (register(AUX_REGISTER_1), 0x0400_0201)
+        );
+        assert_micro(&program, MICRO_READ_MERGE);
+
+        let (_, _, write, _) = step(&mut program);
+        assert_eq!(write.value, 0x0461_0201);
+        assert_micro(&program, MICRO_READ_STORE);
+
+        let (_, _, write, witness) = step(&mut program);
+        assert_eq!((write.address, write.value), (DATA_ADDRESS, 0x0461_0201));
+        assert_eq!(witness.write(), MemoryAccessType::Memory);
+        assert_eq!(program.read_mem(DATA_ADDRESS, false).unwrap(), 0x0461_0201);
+        assert_micro(&program, MICRO_READ_ADVANCE);
+
+        let (read_1, _, write, _) = step(&mut program);
+        assert_eq!(read_1.value, INPUT_ADDRESS);
+        assert_eq!(
+            (write.address, write.value),
+            (slot(SLOT_INPUT_POINTER), INPUT_ADDRESS + 1)
+        );
+        assert_next_instruction(&program);
+    }
+
+    #[test]
+    fn test_read_end_of_input() {
+        let mut program = get_program(&[1, 2, 3, 4]);
+        program.load_input(b"a".to_vec(), ".input", false).unwrap();
+        program
+            .registers
+            .set(syscall_slot_index(SLOT_INPUT_POINTER), INPUT_ADDRESS + 1, 0);
+        set_syscall(&mut program, SYSCALL_READ, &[FD_STDIN, DATA_ADDRESS, 4]);
+        program.pc.jump_micro(MICRO_READ_AVAILABLE);
+
+        let (_, _, write, _) = step(&mut program);
+        assert_eq!((write.address, write.value), (register(REGISTER_ARG_0), 0));
+        assert_next_instruction(&program);
+    }
+
+    #[test]
+    fn test_brk_micro_steps() {
+        let mut program = get_program(&[0; 4]);
+        program.declare_heap("heap").unwrap();
+        set_syscall(&mut program, SYSCALL_BRK, &[0xD000_0000]);
+
+        step(&mut program);
+        assert_micro(&program, MICRO_BRK_LOWER);
+
+        let (read_1, read_2, write, _) = step(&mut program);
+        assert_eq!(read_1.address, register(REGISTER_ARG_0));
+        assert_eq!(
+            (read_2.address, read_2.value),
+            (slot(SLOT_HEAP_START), HEAP_ADDRESS)
+        );
+        assert_eq!(
+            (write.address, write.value),
+            (register(AUX_REGISTER_1), 0xD000_0000)
+        );
+        assert_micro(&program, MICRO_BRK_UPPER);
+
+        // the break is clamped to the end of the heap
+        let (_, read_2, write, _) = step(&mut program);
+        assert_eq!(
+            (read_2.address, read_2.value),
+            (slot(SLOT_HEAP_END), HEAP_ADDRESS + 0x100)
+        );
+        assert_eq!(
+            (write.address, write.value),
+            (register(REGISTER_ARG_0), HEAP_ADDRESS + 0x100)
+        );
+        assert_next_instruction(&program);
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut program = get_program(&[0; 4]);
+        set_syscall(&mut program, SYSCALL_EXIT, &[3]);
+
+        let (_, read_2, write, witness) = step(&mut program);
+        assert_eq!(read_2.value, 3);
+        assert_eq!((write.address, write.value), (register(REGISTER_ARG_0), 3));
+        assert_eq!(witness.byte(), MemoryWitness::registers().byte());
+        assert!(program.halt);
+        assert_micro(&program, 0);
+    }
+
+    #[test]
+    fn test_print_char() {
+        let mut program = get_program(&[0; 4]);
+        set_syscall(&mut program, SYSCALL_PRINT_CHAR, &[0]);
+
+        let (read_1, _, write, witness) = step(&mut program);
+        assert_eq!(read_1.value, SYSCALL_PRINT_CHAR);
+        assert_eq!(write.address, 0);
+        assert_eq!(witness.byte(), MemoryWitness::no_write().byte());
+        assert!(!program.halt);
+        assert_next_instruction(&program);
+    }
+}
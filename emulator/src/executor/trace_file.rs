@@ -0,0 +1,361 @@
+// Binary trace file with a step index
+//
+// The steps are encoded with `TraceRWStep::to_binary` and grouped in blocks that can be compressed
+// independently. An index with the first step and the position of every block is written at the
+// end of the file, so a step (or a range of steps) is read decoding only the blocks containing it.
+//
+// header: magic | flags (u8) | steps per block (u32)
+// blocks: encoded steps (lz4 compressed when the flag is set)
+// index:  first step (u64) | steps (u32) | offset (u64) | length (u32) for every block
+// footer: index offset (u64) | blocks (u64) | magic
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use bitvmx_cpu_definitions::trace::{TraceRWStep, TRACE_STEP_BINARY_SIZE};
+
+use crate::EmulatorError;
+
+const MAGIC: &[u8; 8] = b"BVMXTRC1";
+const FLAG_COMPRESSED: u8 = 1;
+const HEADER_SIZE: usize = 13;
+const INDEX_ENTRY_SIZE: usize = 24;
+const FOOTER_SIZE: usize = 24;
+
+pub const DEFAULT_BLOCK_STEPS: u32 = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+struct BlockIndex {
+    first_step: u64,
+    steps: u32,
+    offset: u64,
+    length: u32,
+}
+
+impl BlockIndex {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(INDEX_ENTRY_SIZE);
+        bytes.extend(self.first_step.to_be_bytes());
+        bytes.extend(self.steps.to_be_bytes());
+        bytes.extend(self.offset.to_be_bytes());
+        bytes.extend(self.length.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            first_step: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            steps: u32::from_be_bytes(bytes[8..12].try_into().unwrap()),
+            offset: u64::from_be_bytes(bytes[12..20].try_into().unwrap()),
+            length: u32::from_be_bytes(bytes[20..24].try_into().unwrap()),
+        }
+    }
+}
+
+// builds the file on any writer, the steps are expected in increasing order
+pub struct TraceFileEncoder {
+    compressed: bool,
+    block_steps: u32,
+    block: Vec<u8>,
+    block_first_step: u64,
+    block_count: u32,
+    index: Vec<BlockIndex>,
+    offset: u64,
+}
+
+impl TraceFileEncoder {
+    pub fn new(compressed: bool, block_steps: u32) -> Self {
+        Self {
+            compressed,
+            block_steps: block_steps.max(1),
+            block: Vec::new(),
+            block_first_step: 0,
+            block_count: 0,
+            index: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    pub fn write_header(&mut self, out: &mut dyn Write) -> std::io::Result<()> {
+        let flags = match self.compressed {
+            true => FLAG_COMPRESSED,
+            false => 0,
+        };
+        out.write_all(MAGIC)?;
+        out.write_all(&[flags])?;
+        out.write_all(&self.block_steps.to_be_bytes())?;
+        self.offset = HEADER_SIZE as u64;
+        Ok(())
+    }
+
+    pub fn write_step(
+        &mut self,
+        out: &mut dyn Write,
+        trace: &TraceRWStep,
+        hash: &[u8; 20],
+    ) -> std::io::Result<()> {
+        if self.block_count == 0 {
+            self.block_first_step = trace.step_number;
+        }
+        self.block.extend(trace.to_binary(hash));
+        self.block_count += 1;
+        if self.block_count == self.block_steps {
+            self.write_block(out)?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, out: &mut dyn Write) -> std::io::Result<()> {
+        if self.block_count == 0 {
+            return Ok(());
+        }
+
+        let data = match self.compressed {
+            true => lz4_flex::compress_prepend_size(&self.block),
+            false => std::mem::take(&mut self.block),
+        };
+        out.write_all(&data)?;
+
+        self.index.push(BlockIndex {
+            first_step: self.block_first_step,
+            steps: self.block_count,
+            offset: self.offset,
+            length: data.len() as u32,
+        });
+        self.offset += data.len() as u64;
+        self.block.clear();
+        self.block_count = 0;
+        Ok(())
+    }
+
+    // writes the pending block and the index
+    pub fn finish(&mut self, out: &mut dyn Write) -> std::io::Result<()> {
+        self.write_block(out)?;
+        for block in &self.index {
+            out.write_all(&block.to_bytes())?;
+        }
+        out.write_all(&self.offset.to_be_bytes())?;
+        out.write_all(&(self.index.len() as u64).to_be_bytes())?;
+        out.write_all(MAGIC)
+    }
+}
+
+pub struct TraceFileReader {
+    file: File,
+    compressed: bool,
+    index: Vec<BlockIndex>,
+    // last decoded block
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+impl TraceFileReader {
+    pub fn open(path: &str) -> Result<Self, EmulatorError> {
+        let mut file = File::open(path).map_err(|err| file_error(path, err))?;
+
+        let mut header = [0u8; HEADER_SIZE];
+        file.read_exact(&mut header)
+            .map_err(|err| file_error(path, err))?;
+        if &header[0..8] != MAGIC {
+            return Err(EmulatorError::TraceFileError(format!(
+                "{}: not a binary trace file",
+                path
+            )));
+        }
+        let compressed = header[8] & FLAG_COMPRESSED != 0;
+
+        let mut footer = [0u8; FOOTER_SIZE];
+        file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))
+            .and_then(|_| file.read_exact(&mut footer))
+            .map_err(|err| file_error(path, err))?;
+        if &footer[16..24] != MAGIC {
+            return Err(EmulatorError::TraceFileError(format!(
+                "{}: the index is missing, the file is incomplete",
+                path
+            )));
+        }
+        let index_offset = u64::from_be_bytes(footer[0..8].try_into().unwrap());
+        let blocks = u64::from_be_bytes(footer[8..16].try_into().unwrap()) as usize;
+
+        let mut index = vec![0u8; blocks * INDEX_ENTRY_SIZE];
+        file.seek(SeekFrom::Start(index_offset))
+            .and_then(|_| file.read_exact(&mut index))
+            .map_err(|err| file_error(path, err))?;
+
+        Ok(Self {
+            file,
+            compressed,
+            index: index
+                .chunks(INDEX_ENTRY_SIZE)
+                .map(BlockIndex::from_bytes)
+                .collect(),
+            cached: None,
+        })
+    }
+
+    // number of steps in the file
+    pub fn steps(&self) -> u64 {
+        self.index.iter().map(|block| block.steps as u64).sum()
+    }
+
+    pub fn get_step(
+        &mut self,
+        step: u64,
+    ) -> Result<Option<(TraceRWStep, [u8; 20])>, EmulatorError> {
+        let block = match self.block_of(step) {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        let data = self.load_block(block)?;
+        let records = data.chunks(TRACE_STEP_BINARY_SIZE).collect::<Vec<&[u8]>>();
+        match records.binary_search_by_key(&step, |record| record_step(record)) {
+            Ok(idx) => decode(records[idx]).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // steps from `from` to `to` (both included) that are in the file
+    pub fn get_range(
+        &mut self,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<(TraceRWStep, [u8; 20])>, EmulatorError> {
+        let mut steps = Vec::new();
+        let mut block = self.block_of(from).unwrap_or(0);
+        while block < self.index.len() && self.index[block].first_step <= to {
+            for record in self.load_block(block)?.chunks(TRACE_STEP_BINARY_SIZE) {
+                let step = record_step(record);
+                if step >= from && step <= to {
+                    steps.push(decode(record)?);
+                }
+            }
+            block += 1;
+        }
+        Ok(steps)
+    }
+
+    // last block starting at or before the step
+    fn block_of(&self, step: u64) -> Option<usize> {
+        self.index
+            .partition_point(|block| block.first_step <= step)
+            .checked_sub(1)
+    }
+
+    fn load_block(&mut self, block: usize) -> Result<&[u8], EmulatorError> {
+        let cached = match &self.cached {
+            Some((cached, _)) => *cached == block,
+            None => false,
+        };
+
+        if !cached {
+            let entry = &self.index[block];
+            let mut data = vec![0u8; entry.length as usize];
+            self.file
+                .seek(SeekFrom::Start(entry.offset))
+                .and_then(|_| self.file.read_exact(&mut data))
+                .map_err(|err| file_error("read", err))?;
+
+            if self.compressed {
+                data = lz4_flex::decompress_size_prepended(&data).map_err(|err| {
+                    EmulatorError::TraceFileError(format!("block {}: {}", block, err))
+                })?;
+            }
+            if data.len() != entry.steps as usize * TRACE_STEP_BINARY_SIZE {
+                return Err(EmulatorError::TraceFileError(format!(
+                    "block {}: invalid size {}",
+                    block,
+                    data.len()
+                )));
+            }
+            self.cached = Some((block, data));
+        }
+
+        match &self.cached {
+            Some((_, data)) => Ok(data),
+            None => unreachable!(),
+        }
+    }
+}
+
+fn record_step(record: &[u8]) -> u64 {
+    u64::from_be_bytes(record[0..8].try_into().unwrap())
+}
+
+fn decode(record: &[u8]) -> Result<(TraceRWStep, [u8; 20]), EmulatorError> {
+    TraceRWStep::from_binary(record).map_err(EmulatorError::TraceFileError)
+}
+
+fn file_error(context: &str, err: std::io::Error) -> EmulatorError {
+    EmulatorError::TraceFileError(format!("{}: {}", context, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(path: &std::path::Path, compressed: bool, steps: std::ops::Range<u64>) {
+        let mut out = File::create(path).unwrap();
+        let mut encoder = TraceFileEncoder::new(compressed, 4);
+        encoder.write_header(&mut out).unwrap();
+        for step in steps {
+            let hash = [step as u8; 20];
+            encoder
+                .write_step(&mut out, &TraceRWStep::from_step(step), &hash)
+                .unwrap();
+        }
+        encoder.finish(&mut out).unwrap();
+    }
+
+    #[test]
+    fn test_read_steps() {
+        for compressed in [false, true] {
+            let path = std::env::temp_dir().join(format!("trace_file_{}.bin", compressed));
+            // the trace doesn't need to start at zero (i.e. executing from a checkpoint)
+            write_file(&path, compressed, 5..19);
+
+            let mut reader = TraceFileReader::open(path.to_str().unwrap()).unwrap();
+            assert_eq!(reader.steps(), 14);
+            assert_eq!(reader.index.len(), 4);
+
+            let (trace, hash) = reader.get_step(10).unwrap().unwrap();
+            assert_eq!(trace.step_number, 10);
+            assert_eq!(hash, [10; 20]);
+            assert_eq!(reader.get_step(18).unwrap().unwrap().0.step_number, 18);
+            assert!(reader.get_step(4).unwrap().is_none());
+            assert!(reader.get_step(19).unwrap().is_none());
+
+            let range = reader.get_range(7, 13).unwrap();
+            assert_eq!(
+                range
+                    .iter()
+                    .map(|(trace, _)| trace.step_number)
+                    .collect::<Vec<u64>>(),
+                (7..=13).collect::<Vec<u64>>()
+            );
+            assert_eq!(reader.get_range(0, 100).unwrap().len(), 14);
+            assert!(reader.get_range(20, 30).unwrap().is_empty());
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_incomplete_file() {
+        let path = std::env::temp_dir().join("trace_file_incomplete.bin");
+        let mut out = File::create(&path).unwrap();
+        let mut encoder = TraceFileEncoder::new(false, 4);
+        encoder.write_header(&mut out).unwrap();
+        encoder
+            .write_step(&mut out, &TraceRWStep::from_step(0), &[0; 20])
+            .unwrap();
+        drop(out);
+
+        assert!(matches!(
+            TraceFileReader::open(path.to_str().unwrap()),
+            Err(EmulatorError::TraceFileError(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+// Streaming trace output
+//
+// The steps are written as they are produced so the memory used doesn't depend on the length of
+// the execution. Writing errors are kept and reported by `finish` to not interrupt the execution.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use bitvmx_cpu_definitions::trace::TraceRWStep;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use super::trace_file::{TraceFileEncoder, DEFAULT_BLOCK_STEPS};
+use crate::EmulatorError;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum, Debug)]
+pub enum TraceFormat {
+    // TraceRWStep::to_csv followed by the hash, as printed by --trace
+    Csv,
+    // one json object with the trace and the hash per line
+    Json,
+    // fixed width steps with an index to read them back by step (see trace_file)
+    Binary,
+    // binary with the blocks of steps compressed
+    CompressedBinary,
+}
+
+#[derive(Serialize)]
+struct TraceLine<'a> {
+    trace: &'a TraceRWStep,
+    hash: &'a str,
+}
+
+pub struct TraceWriter {
+    writer: BufWriter<Box<dyn Write>>,
+    format: TraceFormat,
+    steps: u64,
+    error: Option<std::io::Error>,
+    // only used by the binary formats
+    encoder: Option<TraceFileEncoder>,
+}
+
+impl TraceWriter {
+    pub fn new(writer: Box<dyn Write>, format: TraceFormat) -> Self {
+        let mut writer = BufWriter::new(writer);
+        let mut encoder = match format {
+            TraceFormat::Binary => Some(TraceFileEncoder::new(false, DEFAULT_BLOCK_STEPS)),
+            TraceFormat::CompressedBinary => Some(TraceFileEncoder::new(true, DEFAULT_BLOCK_STEPS)),
+            TraceFormat::Csv | TraceFormat::Json => None,
+        };
+        let error = match &mut encoder {
+            Some(encoder) => encoder.write_header(&mut writer).err(),
+            None => None,
+        };
+
+        Self {
+            writer,
+            format,
+            steps: 0,
+            error,
+            encoder,
+        }
+    }
+
+    pub fn create(path: &str, format: TraceFormat) -> Result<Self, EmulatorError> {
+        let file = File::create(path).map_err(|err| trace_error(path, err))?;
+        Ok(Self::new(Box::new(file), format))
+    }
+
+    // number of steps written
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn write_step(&mut self, trace: &TraceRWStep, hash: &str) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = match self.format {
+            TraceFormat::Csv => writeln!(self.writer, "{};{}", trace.to_csv(), hash),
+            TraceFormat::Json => {
+                serde_json::to_writer(&mut self.writer, &TraceLine { trace, hash })
+                    .map_err(std::io::Error::from)
+                    .and_then(|_| writeln!(self.writer))
+            }
+            TraceFormat::Binary | TraceFormat::CompressedBinary => {
+                match (&mut self.encoder, decode_hash(hash)) {
+                    (Some(encoder), Some(hash)) => {
+                        encoder.write_step(&mut self.writer, trace, &hash)
+                    }
+                    _ => Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("invalid hash {}", hash),
+                    )),
+                }
+            }
+        };
+
+        match result {
+            Ok(()) => self.steps += 1,
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<u64, EmulatorError> {
+        if let Some(err) = self.error.take() {
+            return Err(trace_error("write", err));
+        }
+        if let Some(encoder) = &mut self.encoder {
+            encoder
+                .finish(&mut self.writer)
+                .map_err(|err| trace_error("index", err))?;
+        }
+        self.writer
+            .flush()
+            .map_err(|err| trace_error("flush", err))?;
+        Ok(self.steps)
+    }
+}
+
+fn decode_hash(hash: &str) -> Option<[u8; 20]> {
+    hex::decode(hash).ok()?.try_into().ok()
+}
+
+fn trace_error(context: &str, err: std::io::Error) -> EmulatorError {
+    EmulatorError::TraceWriterError(format!("{}: {}", context, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        executor::{
+            fetcher::{execute_program_with_options, ExecutionOptions},
+            trace_file::TraceFileReader,
+        },
+        test_utils::program_with_code,
+        ExecutionResult,
+    };
+
+    // keeps the written bytes after the writer is dropped
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn write(format: TraceFormat) -> String {
+        let buffer = SharedBuffer::default();
+        let mut writer = TraceWriter::new(Box::new(buffer.clone()), format);
+        writer.write_step(&TraceRWStep::from_step(1), "aa");
+        writer.write_step(&TraceRWStep::from_step(2), "bb");
+        assert_eq!(writer.finish().unwrap(), 2);
+        let bytes = buffer.0.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_csv() {
+        let lines = write(TraceFormat::Csv)
+            .lines()
+            .map(|line| line.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            format!("{};bb", TraceRWStep::from_step(2).to_csv())
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let output = write(TraceFormat::Json);
+        let lines = output.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 2);
+        let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line["hash"], "aa");
+        assert_eq!(line["trace"]["step_number"], 1);
+    }
+
+    #[test]
+    fn test_binary() {
+        let path = std::env::temp_dir().join("trace_writer.bin");
+        let path = path.to_str().unwrap();
+        let mut writer = TraceWriter::create(path, TraceFormat::CompressedBinary).unwrap();
+        writer.write_step(&TraceRWStep::from_step(1), &"aa".repeat(20));
+        writer.write_step(&TraceRWStep::from_step(2), &"bb".repeat(20));
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let mut reader = TraceFileReader::open(path).unwrap();
+        let (trace, hash) = reader.get_step(2).unwrap().unwrap();
+        assert_eq!(trace.step_number, 2);
+        assert_eq!(hash, [0xbb; 20]);
+        std::fs::remove_file(path).unwrap();
+
+        // the hash has to be a step hash to be encoded
+        let mut writer = TraceWriter::new(Box::new(SharedBuffer::default()), TraceFormat::Binary);
+        writer.write_step(&TraceRWStep::from_step(1), "aa");
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn test_execute_with_writer() {
+        // a7 = 93; a0 = 7; ecall
+        let mut program = program_with_code(&[0x05d0_0893, 0x0070_0513, 0x0000_0073]);
+
+        let buffer = SharedBuffer::default();
+        let mut writer = TraceWriter::new(Box::new(buffer.clone()), TraceFormat::Csv);
+        let options = ExecutionOptions {
+            print_trace: true,
+            trace_writer: Some(&mut writer),
+            ..Default::default()
+        };
+        let (result, trace) = execute_program_with_options(&mut program, options);
+
+        assert_eq!(result, ExecutionResult::Halt(7, 3, vec![]));
+        // the initial step and the three executed steps
+        assert_eq!(writer.finish().unwrap(), 4);
+        assert_eq!(buffer.0.borrow().iter().filter(|b| **b == b'\n').count(), 4);
+        // only the last step is kept in memory
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].0.step_number, 3);
+    }
+}
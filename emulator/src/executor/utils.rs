@@ -404,6 +404,7 @@ mod utils_tests {
             is_write: true,
             initialized: true,
             registers: false,
+            pages: None,
         });
         let fail_read_1_args = vec![
             "10".to_string(),
@@ -432,6 +433,7 @@ mod utils_tests {
             is_write: true,
             initialized: true,
             registers: false,
+            pages: None,
         });
         let fail_read_2_args = vec![
             "10".to_string(),
@@ -460,6 +462,7 @@ mod utils_tests {
             is_write: true,
             initialized: true,
             registers: false,
+            pages: None,
         });
         let fail_reads = FailReads::new(None, None);
         program.step = 10;
@@ -498,6 +501,7 @@ mod utils_tests {
             is_write: true,
             initialized: true,
             registers: false,
+            pages: None,
         });
         let fail_write_args = vec![
             "10".to_string(),
@@ -525,6 +529,7 @@ mod utils_tests {
             is_write: true,
             initialized: true,
             registers: false,
+            pages: None,
         });
         let fail_write = FailWrite::default();
         program.step = 10;
@@ -1,9 +1,13 @@
+pub mod debugger;
 pub mod decision;
 pub mod executor;
 pub mod loader;
 
+#[cfg(test)]
+mod test_utils;
+
 use bitcoin_script_riscv::ScriptValidation;
-use bitvmx_cpu_definitions::challenge::EmulatorResultError;
+use bitvmx_cpu_definitions::{challenge::EmulatorResultError, trace::TraceRead};
 use loader::program_definition::ProgramDefinitionError;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -33,12 +37,34 @@ pub enum EmulatorError {
 
     #[error("Invalid force configuration {0}")]
     InvalidForceConfiguration(String),
+
+    #[error("Debugger error {0}")]
+    DebuggerError(String),
+
+    #[error("Can't write the profile {0}")]
+    ProfileError(String),
+
+    #[error("Can't write the trace {0}")]
+    TraceWriterError(String),
+
+    #[error("Can't read the trace file {0}")]
+    TraceFileError(String),
+
+    #[error("Can't write the checkpoint {0}")]
+    CheckpointError(String),
+
+    #[error("Invalid checkpoint {0}")]
+    InvalidCheckpoint(String),
+
+    #[error("Can't write the hash index {0}")]
+    HashIndexError(String),
 }
 
 #[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ExecutionResult {
+    // the output holds the words of the output section with the step they were last written
     #[error("Program halted return value: {0} at step: {1}")]
-    Halt(u32, u64),
+    Halt(u32, u64, Vec<TraceRead>),
 
     #[error("Step limit reached: {0}")]
     LimitStepReached(u64),
@@ -72,11 +98,14 @@ pub enum ExecutionResult {
 
     #[error("Can't read from execute only section")]
     ReadFromExecuteOnlySection,
+
+    #[error("Invalid access to csr: 0x{0:03x}")]
+    InvalidCsrAccess(u32),
 }
 
 pub mod constants {
-    pub const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000; //CHECK: this can be parameterized
-    pub const STACK_BASE_ADDRESS: u32 = 0xE000_0000; //CHECK: this can be parameterized
+    pub const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000; // default, see MemoryLayout
+    pub const STACK_BASE_ADDRESS: u32 = 0xE000_0000; // default, see MemoryLayout
     pub const STACK_SIZE: u32 = 0x80_0000; //QEMU Default stack size
     pub const MAX_SECTIONS: usize = 900; // Having too many sections can break some challenges due to max script size
 }
@@ -0,0 +1,712 @@
+// Checkpoint files
+//
+// Every checkpoint has a header identifying the program (digest of the read-only sections), the
+// input and the state it was written from, so a stale checkpoint of another elf or input is
+// rejected instead of silently producing different hashes.
+//
+// The json checkpoints store the header and the whole program, which is slow to write and huge for
+// programs with large memory sections. The binary checkpoints store the header and the program
+// without the memory as json followed by the raw words of the sections, lz4 compressed. The delta checkpoints only store the
+// words written after the previous checkpoint and are rebuilt applying them to it. Every
+// `DELTA_CHAIN` deltas a full checkpoint is written to bound the files needed to load one.
+//
+// file:  magic | kind (u8) | lz4 payload
+// full:  metadata length (u32) | metadata (json header and program) | for every section: words (u32) | values | last steps
+//        (the paged sections: pages (u32) | for every page: number (u32) | values | last steps)
+// delta: base step (u64) | metadata length (u32) | metadata | words (u64) |
+//        for every word: section (u32) | index (u32) | value (u32) | last step (u64)
+
+use std::path::Path;
+
+use bitvmx_cpu_definitions::trace::hash_to_string;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    paged_memory::{Page, PagedMemory, PAGE_WORDS},
+    program::{Program, Section},
+};
+use crate::EmulatorError;
+
+const MAGIC: &[u8; 8] = b"BVMXCKP1";
+const KIND_FULL: u8 = 0;
+const KIND_DELTA: u8 = 1;
+
+pub const DELTA_CHAIN: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum, Debug, Default)]
+pub enum CheckpointFormat {
+    // the whole program as json (checkpoint.<step>.json)
+    #[default]
+    Json,
+    // compressed binary (checkpoint.<step>.bin)
+    Binary,
+    // binary with only the words written since the previous checkpoint
+    Delta,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointHeader {
+    pub rom_digest: String,
+    pub input_digest: String,
+    pub step: u64,
+    pub step_hash: String,
+    pub state_digest: String,
+}
+
+impl CheckpointHeader {
+    pub fn new(program: &Program) -> Self {
+        Self {
+            rom_digest: rom_digest(program),
+            input_digest: program.input_digest.clone(),
+            step: program.step,
+            step_hash: hash_to_string(&program.hash),
+            state_digest: state_digest(program),
+        }
+    }
+
+    fn verify(
+        &self,
+        fname: &str,
+        program: &Program,
+        step: u64,
+        origin: Option<&CheckpointOrigin>,
+    ) -> Result<(), EmulatorError> {
+        let check = |field: &str, found: &str, expected: &str| match found == expected {
+            true => Ok(()),
+            false => Err(EmulatorError::InvalidCheckpoint(format!(
+                "{}: {} is {} expected {}",
+                fname, field, found, expected
+            ))),
+        };
+
+        check("step", &self.step.to_string(), &step.to_string())?;
+        check(
+            "program step",
+            &program.step.to_string(),
+            &self.step.to_string(),
+        )?;
+        check("step hash", &hash_to_string(&program.hash), &self.step_hash)?;
+        check("state digest", &state_digest(program), &self.state_digest)?;
+        match origin {
+            Some(origin) => {
+                check("rom digest", &self.rom_digest, &origin.rom_digest)?;
+                check("input digest", &self.input_digest, &origin.input_digest)?;
+            }
+            // without the elf and the input a checkpoint of another program can't be detected
+            None => warn!(
+                "{}: the program and input are not verified (rom digest {} input digest {})",
+                fname, self.rom_digest, self.input_digest
+            ),
+        }
+        Ok(())
+    }
+}
+
+// program and input a checkpoint has to be written from
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointOrigin {
+    pub rom_digest: String,
+    pub input_digest: String,
+}
+
+impl CheckpointOrigin {
+    pub fn new(program: &Program, input: &[u8]) -> Self {
+        Self {
+            rom_digest: rom_digest(program),
+            input_digest: input_digest(input),
+        }
+    }
+
+    // the origin of the checkpoints written executing the program
+    pub fn of(program: &Program) -> Self {
+        Self {
+            rom_digest: rom_digest(program),
+            input_digest: program.input_digest.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    header: CheckpointHeader,
+    program: &'a Program,
+}
+
+#[derive(Deserialize)]
+struct CheckpointData {
+    header: CheckpointHeader,
+    program: Program,
+}
+
+pub fn input_digest(input: &[u8]) -> String {
+    blake3::hash(input).to_hex().to_string()
+}
+
+// the read-only sections (code and constants) can't change during the execution
+pub fn rom_digest(program: &Program) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for section in &program.sections {
+        if section.is_write || section.registers {
+            continue;
+        }
+        hasher.update(&section.start.to_be_bytes());
+        hasher.update(&section.size.to_be_bytes());
+        hasher.update(&words_to_bytes(&section.to_words()));
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+pub fn state_digest(program: &Program) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&serde_json::to_vec(&program.registers).unwrap_or_default());
+    hasher.update(&program.pc.get_address().to_be_bytes());
+    hasher.update(&[program.pc.get_micro(), program.halt as u8]);
+    hasher.update(&program.step.to_be_bytes());
+    hasher.update(&program.hash);
+    hasher.update(&program.captured_output);
+    for section in &program.sections {
+        hasher.update(&section.start.to_be_bytes());
+        match &section.pages {
+            Some(pages) => {
+                for (number, page) in pages.pages() {
+                    hasher.update(&number.to_be_bytes());
+                    hasher.update(&words_to_bytes(&page.data));
+                    hasher.update(&page_last_steps_to_bytes(page));
+                }
+            }
+            None => {
+                hasher.update(&words_to_bytes(&section.data));
+                hasher.update(&last_steps_to_bytes(&section.last_step));
+            }
+        }
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+// the pages without last steps are written as never written words
+fn page_last_steps_to_bytes(page: &Page) -> Vec<u8> {
+    (0..page.data.len())
+        .flat_map(|idx| page.word_last_step(idx).to_be_bytes())
+        .collect()
+}
+
+fn last_steps_to_bytes(last_steps: &[u64]) -> Vec<u8> {
+    last_steps
+        .iter()
+        .flat_map(|last_step| last_step.to_be_bytes())
+        .collect()
+}
+
+pub fn json_checkpoint_file(path: &str, step: u64) -> String {
+    format!("{}/checkpoint.{}.json", path, step)
+}
+
+pub fn checkpoint_file(path: &str, step: u64) -> String {
+    format!("{}/checkpoint.{}.bin", path, step)
+}
+
+// steps of the checkpoints (json or binary) in the path, sorted
+pub fn list_checkpoints(path: &str) -> Vec<u64> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut steps = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let name = name
+                .strip_suffix(".json")
+                .or_else(|| name.strip_suffix(".bin"))?;
+            name.strip_prefix("checkpoint.")?.parse::<u64>().ok()
+        })
+        .collect::<Vec<u64>>();
+    steps.sort();
+    steps.dedup();
+    steps
+}
+
+// closest checkpoint at or before the step in the path
+pub fn find_checkpoint(path: &str, step: u64) -> Option<u64> {
+    list_checkpoints(path)
+        .into_iter()
+        .filter(|checkpoint_step| *checkpoint_step <= step)
+        .max()
+}
+
+pub fn write_json_checkpoint(program: &Program, path: &str) -> Result<(), EmulatorError> {
+    let fname = json_checkpoint_file(path, program.step);
+    let checkpoint = CheckpointRef {
+        header: CheckpointHeader::new(program),
+        program,
+    };
+    let serialized = serde_json::to_string(&checkpoint)
+        .map_err(|err| EmulatorError::CheckpointError(format!("{}: {}", fname, err)))?;
+    std::fs::write(&fname, serialized)
+        .map_err(|err| EmulatorError::CheckpointError(format!("{}: {}", fname, err)))
+}
+
+// loads the json (or else the binary) checkpoint of the step and verifies its header
+pub fn read_checkpoint(
+    path: &str,
+    step: u64,
+    origin: Option<&CheckpointOrigin>,
+) -> Result<Program, EmulatorError> {
+    let json = json_checkpoint_file(path, step);
+    let (fname, (header, program)) =
+        match Path::new(&json).exists() || !Path::new(&checkpoint_file(path, step)).exists() {
+            true => (json.clone(), load_json(&json)?),
+            false => (checkpoint_file(path, step), load_binary(path, step)?),
+        };
+    header.verify(&fname, &program, step, origin)?;
+    Ok(program)
+}
+
+fn load_json(fname: &str) -> Result<(CheckpointHeader, Program), EmulatorError> {
+    let serialized = std::fs::read(fname).map_err(|_| load_error("Error loading file", fname))?;
+    let checkpoint: CheckpointData = serde_json::from_slice(&serialized)
+        .map_err(|_| load_error("Error deserializing file", fname))?;
+    Ok((checkpoint.header, checkpoint.program))
+}
+
+// keeps the previous checkpoint of the execution to write the deltas against it
+pub struct CheckpointWriter {
+    format: CheckpointFormat,
+    previous: Option<u64>,
+    deltas: usize,
+}
+
+impl CheckpointWriter {
+    pub fn new(format: CheckpointFormat) -> Self {
+        Self {
+            format,
+            previous: None,
+            deltas: 0,
+        }
+    }
+
+    pub fn save(&mut self, program: &Program, path: &str) -> Result<(), EmulatorError> {
+        if self.format == CheckpointFormat::Json {
+            return write_json_checkpoint(program, path);
+        }
+
+        let header = CheckpointHeader::new(program);
+        let (kind, payload) = match (self.format, self.previous) {
+            (CheckpointFormat::Delta, Some(base_step))
+                if base_step < program.step && self.deltas < DELTA_CHAIN =>
+            {
+                self.deltas += 1;
+                (KIND_DELTA, delta_payload(program, &header, base_step)?)
+            }
+            _ => {
+                self.deltas = 0;
+                (KIND_FULL, full_payload(program, &header)?)
+            }
+        };
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(kind);
+        bytes.extend(lz4_flex::compress_prepend_size(&payload));
+        let fname = checkpoint_file(path, program.step);
+        std::fs::write(&fname, bytes)
+            .map_err(|err| EmulatorError::CheckpointError(format!("{}: {}", fname, err)))?;
+
+        self.previous = Some(program.step);
+        Ok(())
+    }
+}
+
+// the header is verified by read_checkpoint once the deltas are applied
+fn load_binary(path: &str, step: u64) -> Result<(CheckpointHeader, Program), EmulatorError> {
+    let fname = checkpoint_file(path, step);
+    let bytes = std::fs::read(&fname).map_err(|_| load_error("Error loading file", &fname))?;
+    if bytes.len() <= MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(load_error("Not a binary checkpoint", &fname));
+    }
+    let payload = lz4_flex::decompress_size_prepended(&bytes[MAGIC.len() + 1..])
+        .map_err(|_| load_error("Error decompressing file", &fname))?;
+    let mut reader = PayloadReader {
+        bytes: &payload,
+        position: 0,
+        fname: &fname,
+    };
+
+    match bytes[MAGIC.len()] {
+        KIND_FULL => {
+            let (header, mut program) = reader.metadata()?;
+            for section in program.sections.iter_mut() {
+                match &mut section.pages {
+                    Some(pages) => {
+                        for _ in 0..reader.u32()? {
+                            let number = reader.u32()?;
+                            let page = reader.page(PAGE_WORDS)?;
+                            pages.insert_page(number, page);
+                        }
+                    }
+                    None => {
+                        let words = reader.u32()? as usize;
+                        let page = reader.page(words)?;
+                        section.data = page.data;
+                        section.last_step = page.last_step;
+                    }
+                }
+            }
+            Ok((header, program))
+        }
+        KIND_DELTA => {
+            let base_step = reader.u64()?;
+            if base_step >= step {
+                return Err(load_error("Invalid base checkpoint", &fname));
+            }
+            let (_, base) = load_binary(path, base_step)?;
+
+            let (header, mut program) = reader.metadata()?;
+            if program.sections.len() != base.sections.len() {
+                return Err(load_error(
+                    "Sections don't match the base checkpoint",
+                    &fname,
+                ));
+            }
+            for (section, base_section) in program.sections.iter_mut().zip(base.sections) {
+                section.data = base_section.data;
+                section.last_step = base_section.last_step;
+                section.pages = base_section.pages;
+            }
+
+            for _ in 0..reader.u64()? {
+                let section = reader.u32()? as usize;
+                let idx = reader.u32()? as usize;
+                let value = reader.u32()?;
+                let last_step = reader.u64()?;
+                match program.sections.get_mut(section) {
+                    Some(section) if idx < section.words() => {
+                        section.set_word(idx, value, last_step);
+                    }
+                    _ => return Err(load_error("Invalid word", &fname)),
+                }
+            }
+            Ok((header, program))
+        }
+        _ => Err(load_error("Unknown checkpoint kind", &fname)),
+    }
+}
+
+// the header and the program without the memory of the sections
+fn metadata(program: &Program, header: &CheckpointHeader) -> Result<Vec<u8>, EmulatorError> {
+    let program = Program {
+        sections: program
+            .sections
+            .iter()
+            .map(|section| Section {
+                name: section.name.clone(),
+                data: Vec::new(),
+                last_step: Vec::new(),
+                start: section.start,
+                size: section.size,
+                is_code: section.is_code,
+                is_write: section.is_write,
+                initialized: section.initialized,
+                registers: section.registers,
+                pages: section.pages.as_ref().map(|_| PagedMemory::default()),
+            })
+            .collect(),
+        registers: program.registers.clone(),
+        pc: program.pc.clone(),
+        step: program.step,
+        hash: program.hash,
+        halt: program.halt,
+        read_write_sections: program.read_write_sections.clone(),
+        read_only_sections: program.read_only_sections.clone(),
+        register_sections: program.register_sections.clone(),
+        code_sections: program.code_sections.clone(),
+        instruction_size: program.instruction_size,
+        captured_output: program.captured_output.clone(),
+        symbolizer: program.symbolizer.clone(),
+        input_digest: program.input_digest.clone(),
+        output_section: program.output_section.clone(),
+        elf_sections: program.elf_sections.clone(),
+        decode_cache: None,
+    };
+
+    let checkpoint = CheckpointRef {
+        header: header.clone(),
+        program: &program,
+    };
+    let metadata = serde_json::to_vec(&checkpoint)
+        .map_err(|err| EmulatorError::CheckpointError(err.to_string()))?;
+    let mut bytes = (metadata.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(metadata);
+    Ok(bytes)
+}
+
+fn full_payload(program: &Program, header: &CheckpointHeader) -> Result<Vec<u8>, EmulatorError> {
+    let mut bytes = metadata(program, header)?;
+    for section in &program.sections {
+        match &section.pages {
+            Some(pages) => {
+                bytes.extend((pages.len() as u32).to_be_bytes());
+                for (number, page) in pages.pages() {
+                    bytes.extend(number.to_be_bytes());
+                    bytes.extend(words_to_bytes(&page.data));
+                    bytes.extend(page_last_steps_to_bytes(page));
+                }
+            }
+            None => {
+                bytes.extend((section.data.len() as u32).to_be_bytes());
+                bytes.extend(words_to_bytes(&section.data));
+                bytes.extend(last_steps_to_bytes(&section.last_step));
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+// every write updates the last step of the word, so only the words with a last step after the base
+// can differ from it
+fn delta_payload(
+    program: &Program,
+    header: &CheckpointHeader,
+    base_step: u64,
+) -> Result<Vec<u8>, EmulatorError> {
+    let mut words = Vec::new();
+    let mut count: u64 = 0;
+    for (section_idx, section) in program.sections.iter().enumerate() {
+        for (idx, value, last_step) in section.words_written_after(base_step) {
+            words.extend((section_idx as u32).to_be_bytes());
+            words.extend((idx as u32).to_be_bytes());
+            words.extend(value.to_be_bytes());
+            words.extend(last_step.to_be_bytes());
+            count += 1;
+        }
+    }
+
+    let mut bytes = base_step.to_be_bytes().to_vec();
+    bytes.extend(metadata(program, header)?);
+    bytes.extend(count.to_be_bytes());
+    bytes.extend(words);
+    Ok(bytes)
+}
+
+fn load_error(message: &str, fname: &str) -> EmulatorError {
+    EmulatorError::CantLoadPorgram(format!("{}: {}", message, fname))
+}
+
+struct PayloadReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    fname: &'a str,
+}
+
+impl<'a> PayloadReader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], EmulatorError> {
+        let end = self.position + size;
+        if end > self.bytes.len() {
+            return Err(load_error("Truncated checkpoint", self.fname));
+        }
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, EmulatorError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, EmulatorError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // values followed by the last steps
+    fn page(&mut self, words: usize) -> Result<Page, EmulatorError> {
+        Ok(Page {
+            data: (0..words).map(|_| self.u32()).collect::<Result<_, _>>()?,
+            last_step: (0..words).map(|_| self.u64()).collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn metadata(&mut self) -> Result<(CheckpointHeader, Program), EmulatorError> {
+        let size = self.u32()? as usize;
+        let fname = self.fname;
+        let checkpoint: CheckpointData = serde_json::from_slice(self.take(size)?)
+            .map_err(|_| load_error("Error deserializing file", fname))?;
+        Ok((checkpoint.header, checkpoint.program))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::{fetcher::execute_step, utils::FailConfiguration},
+        test_utils::program_with_data,
+    };
+    use bitvmx_cpu_definitions::trace::compute_step_hash;
+
+    // t1 = 0x2000; loop: a0 = a0 + 1; sw a0, 0(t1); j loop
+    fn get_program() -> Program {
+        program_with_data(&[0x0000_2337, 0x0015_0513, 0x00a3_2023, 0xff9f_f06f])
+    }
+
+    fn assert_same_state(loaded: &Program, expected: &Program) {
+        assert_eq!(loaded.step, expected.step);
+        assert_eq!(loaded.hash, expected.hash);
+        assert_eq!(loaded.pc.get_address(), expected.pc.get_address());
+        assert_eq!(loaded.registers.get(10), expected.registers.get(10));
+        assert_eq!(loaded.sections, expected.sections);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        for format in [CheckpointFormat::Binary, CheckpointFormat::Delta] {
+            let dir = std::env::temp_dir().join(format!("checkpoints_{:?}", format));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.to_str().unwrap();
+
+            let mut program = get_program();
+            let mut writer = CheckpointWriter::new(format);
+            let mut hasher = blake3::Hasher::new();
+            let mut saved = Vec::new();
+            for step in [0, 5, 9, 20] {
+                while program.step < step {
+                    let trace =
+                        execute_step(&mut program, false, false, FailConfiguration::default())
+                            .unwrap();
+                    program.hash =
+                        compute_step_hash(&mut hasher, &program.hash, &trace.trace_step.to_bytes());
+                }
+                writer.save(&program, path).unwrap();
+                saved.push(program.clone());
+            }
+
+            for expected in &saved {
+                let loaded = read_checkpoint(path, expected.step, None).unwrap();
+                assert_same_state(&loaded, expected);
+                // also found by the program loader
+                let origin = CheckpointOrigin::of(expected);
+                let loaded =
+                    Program::deserialize_from_file(path, expected.step, Some(&origin)).unwrap();
+                assert_same_state(&loaded, expected);
+            }
+
+            let kind = std::fs::read(checkpoint_file(path, 9)).unwrap()[MAGIC.len()];
+            match format {
+                CheckpointFormat::Delta => assert_eq!(kind, KIND_DELTA),
+                _ => assert_eq!(kind, KIND_FULL),
+            }
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_missing_base() {
+        let dir = std::env::temp_dir().join("checkpoints_missing_base");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap();
+
+        let mut program = get_program();
+        let mut writer = CheckpointWriter::new(CheckpointFormat::Delta);
+        writer.save(&program, path).unwrap();
+        while program.step < 3 {
+            execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+        }
+        writer.save(&program, path).unwrap();
+        std::fs::remove_file(checkpoint_file(path, 0)).unwrap();
+
+        assert!(matches!(
+            read_checkpoint(path, 3, None),
+            Err(EmulatorError::CantLoadPorgram(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_header() {
+        let dir = std::env::temp_dir().join("checkpoints_header");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap();
+
+        let mut program = get_program();
+        program.input_digest = input_digest(&[1, 2, 3]);
+        while program.step < 3 {
+            execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+        }
+
+        for format in [CheckpointFormat::Binary, CheckpointFormat::Json] {
+            CheckpointWriter::new(format).save(&program, path).unwrap();
+
+            let origin = CheckpointOrigin::new(&get_program(), &[1, 2, 3]);
+            assert!(read_checkpoint(path, 3, Some(&origin)).is_ok());
+
+            // other input
+            let origin = CheckpointOrigin::new(&get_program(), &[1, 2, 4]);
+            assert!(matches!(
+                read_checkpoint(path, 3, Some(&origin)),
+                Err(EmulatorError::InvalidCheckpoint(_))
+            ));
+
+            // other program
+            let mut other = get_program();
+            other.sections[0].data[0] = 0;
+            let origin = CheckpointOrigin::new(&other, &[1, 2, 3]);
+            assert!(matches!(
+                read_checkpoint(path, 3, Some(&origin)),
+                Err(EmulatorError::InvalidCheckpoint(_))
+            ));
+        }
+
+        // a checkpoint of another step renamed
+        std::fs::rename(json_checkpoint_file(path, 3), json_checkpoint_file(path, 4)).unwrap();
+        assert!(matches!(
+            read_checkpoint(path, 4, None),
+            Err(EmulatorError::InvalidCheckpoint(_))
+        ));
+
+        // the state doesn't match the header
+        let mut tampered = program.clone();
+        tampered.registers.set(10, 1234, 3);
+        let checkpoint = serde_json::json!({
+            "header": CheckpointHeader::new(&program),
+            "program": tampered,
+        });
+        std::fs::write(json_checkpoint_file(path, 3), checkpoint.to_string()).unwrap();
+        assert!(matches!(
+            read_checkpoint(path, 3, None),
+            Err(EmulatorError::InvalidCheckpoint(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_checkpoint() {
+        let dir = std::env::temp_dir().join("checkpoints_find");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.to_str().unwrap();
+        for name in [
+            "checkpoint.0.json",
+            "checkpoint.300.bin",
+            "checkpoint.1000.json",
+            "checkpoint.700.txt",
+            "other.500.json",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert_eq!(find_checkpoint(path, 0), Some(0));
+        assert_eq!(find_checkpoint(path, 299), Some(0));
+        assert_eq!(find_checkpoint(path, 300), Some(300));
+        assert_eq!(find_checkpoint(path, 999), Some(300));
+        assert_eq!(find_checkpoint(path, 5000), Some(1000));
+        assert_eq!(find_checkpoint("/nonexistent/checkpoints", 5000), None);
+        assert_eq!(list_checkpoints(path), vec![0, 300, 1000]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
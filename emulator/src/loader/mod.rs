@@ -1,2 +1,5 @@
+pub mod checkpoint;
+pub mod paged_memory;
 pub mod program;
 pub mod program_definition;
+pub mod symbols;
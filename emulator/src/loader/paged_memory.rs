@@ -0,0 +1,197 @@
+// Paged memory
+//
+// The sections without initial data (the stack, the bss) are mostly untouched, so their words are
+// kept in pages allocated on the first write instead of vectors covering the whole section. The
+// words of the pages not allocated read as zero and as never written (LAST_STEP_INIT). The last
+// steps of a page are allocated on the first write at a step, so the pages only written while
+// loading (i.e. the input) keep just the values.
+
+use std::collections::BTreeMap;
+
+use bitvmx_cpu_definitions::constants::LAST_STEP_INIT;
+use serde::{Deserialize, Serialize};
+
+// words of a page (4 KiB)
+pub const PAGE_WORDS: usize = 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Page {
+    pub data: Vec<u32>,
+    // empty while no word of the page was written at a step
+    pub last_step: Vec<u64>,
+}
+
+impl Page {
+    fn new() -> Self {
+        Self {
+            data: vec![0; PAGE_WORDS],
+            last_step: Vec::new(),
+        }
+    }
+
+    pub fn word_last_step(&self, offset: usize) -> u64 {
+        self.last_step
+            .get(offset)
+            .copied()
+            .unwrap_or(LAST_STEP_INIT)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PagedMemory {
+    // page number => page
+    pages: BTreeMap<u32, Page>,
+    // the writes don't keep their step (see drop_last_steps)
+    #[serde(default)]
+    no_last_steps: bool,
+}
+
+impl PagedMemory {
+    fn locate(idx: usize) -> (u32, usize) {
+        ((idx / PAGE_WORDS) as u32, idx % PAGE_WORDS)
+    }
+
+    // word as it is stored, like the data of the sections
+    pub fn get(&self, idx: usize) -> u32 {
+        let (page, offset) = Self::locate(idx);
+        match self.pages.get(&page) {
+            Some(page) => page.data[offset],
+            None => 0,
+        }
+    }
+
+    pub fn get_last_step(&self, idx: usize) -> u64 {
+        let (page, offset) = Self::locate(idx);
+        match self.pages.get(&page) {
+            Some(page) => page.word_last_step(offset),
+            None => LAST_STEP_INIT,
+        }
+    }
+
+    pub fn set(&mut self, idx: usize, value: u32, step: u64) {
+        let (page, offset) = Self::locate(idx);
+        let page = self.pages.entry(page).or_insert_with(Page::new);
+        page.data[offset] = value;
+        if page.last_step.is_empty() && step != LAST_STEP_INIT && !self.no_last_steps {
+            page.last_step = vec![LAST_STEP_INIT; PAGE_WORDS];
+        }
+        if let Some(last_step) = page.last_step.get_mut(offset) {
+            *last_step = step;
+        }
+    }
+
+    pub fn pages(&self) -> impl Iterator<Item = (u32, &Page)> {
+        self.pages.iter().map(|(number, page)| (*number, page))
+    }
+
+    pub fn insert_page(&mut self, number: u32, mut page: Page) {
+        if page
+            .last_step
+            .iter()
+            .all(|last_step| *last_step == LAST_STEP_INIT)
+        {
+            page.last_step = Vec::new();
+        }
+        self.pages.insert(number, page);
+    }
+
+    // allocated pages
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    pub fn drop_last_steps(&mut self) {
+        self.no_last_steps = true;
+        for page in self.pages.values_mut() {
+            page.last_step = Vec::new();
+        }
+    }
+
+    // appends the words of the other memory after the first `offset` words
+    pub fn merge(&mut self, other: PagedMemory, offset: usize) {
+        for (number, page) in other.pages {
+            let first = number as usize * PAGE_WORDS + offset;
+            for (idx, value) in page.data.iter().enumerate() {
+                let last_step = page.word_last_step(idx);
+                if *value != 0 || last_step != LAST_STEP_INIT {
+                    self.set(first + idx, *value, last_step);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_on_write() {
+        let mut memory = PagedMemory::default();
+        assert_eq!(memory.get(5000), 0);
+        assert_eq!(memory.get_last_step(5000), LAST_STEP_INIT);
+        assert!(memory.is_empty());
+
+        memory.set(5000, 0x1234, 7);
+        assert_eq!(memory.get(5000), 0x1234);
+        assert_eq!(memory.get_last_step(5000), 7);
+        assert_eq!(memory.get(5001), 0);
+        assert_eq!(memory.get_last_step(5001), LAST_STEP_INIT);
+        assert_eq!(memory.len(), 1);
+
+        memory.set(0, 1, 8);
+        assert_eq!(memory.len(), 2);
+        assert_eq!(
+            memory
+                .pages()
+                .map(|(number, _)| number)
+                .collect::<Vec<u32>>(),
+            vec![0, 4]
+        );
+    }
+
+    #[test]
+    fn test_allocate_last_steps_on_step_write() {
+        let mut memory = PagedMemory::default();
+        memory.set(3, 0x1234, LAST_STEP_INIT);
+        assert_eq!(memory.get(3), 0x1234);
+        assert!(memory.pages().all(|(_, page)| page.last_step.is_empty()));
+
+        memory.set(4, 0x5678, 9);
+        assert_eq!(memory.get_last_step(3), LAST_STEP_INIT);
+        assert_eq!(memory.get_last_step(4), 9);
+        assert!(memory
+            .pages()
+            .all(|(_, page)| page.last_step.len() == PAGE_WORDS));
+
+        // without last steps the writes only keep the values
+        let mut memory = PagedMemory::default();
+        memory.drop_last_steps();
+        memory.set(4, 0x5678, 9);
+        assert_eq!(memory.get(4), 0x5678);
+        assert_eq!(memory.get_last_step(4), LAST_STEP_INIT);
+        assert!(memory.pages().all(|(_, page)| page.last_step.is_empty()));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut memory = PagedMemory::default();
+        memory.set(1, 1, 1);
+        let mut other = PagedMemory::default();
+        other.set(0, 2, 2);
+        other.set(PAGE_WORDS + 3, 3, 3);
+
+        // the other memory starts in the middle of a page
+        memory.merge(other, 10);
+        assert_eq!(memory.get(1), 1);
+        assert_eq!(memory.get(10), 2);
+        assert_eq!(memory.get_last_step(10), 2);
+        assert_eq!(memory.get(PAGE_WORDS + 13), 3);
+        assert_eq!(memory.get(11), 0);
+        assert_eq!(memory.len(), 2);
+    }
+}
@@ -1,23 +1,41 @@
-use std::cmp::Ordering;
-
-use bitcoin_script_riscv::riscv::instruction_mapping::{
-    get_key_from_instruction_and_micro, get_required_microinstruction,
+use std::{cmp::Ordering, sync::Arc};
+
+use bitcoin_script_riscv::riscv::{
+    bitmanip::BitmanipInstruction,
+    compressed::{decode_instruction, instruction_size, INSTRUCTION_SIZE},
+    instruction_mapping::{
+        get_key_from_bitmanip, get_key_from_compressed_and_micro,
+        get_key_from_instruction_and_micro, get_required_microinstruction,
+    },
 };
 use bitvmx_cpu_definitions::{
     constants::LAST_STEP_INIT,
     memory::{Chunk, MemoryAccessType, SectionDefinition},
+    syscall::{
+        syscall_slot_index, SLOT_HEAP_END, SLOT_HEAP_START, SLOT_INPUT_END, SLOT_INPUT_POINTER,
+        SYSCALL_SLOTS, SYSCALL_SLOTS_OFFSET,
+    },
     trace::{generate_initial_step_hash, ProgramCounter, TraceRead, TraceWrite},
 };
+use clap::ValueEnum;
 use elf::{abi::SHF_EXECINSTR, abi::SHF_WRITE, endian::LittleEndian, ElfBytes};
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 use tracing::{error, info};
 
 use crate::{
-    constants::*, loader::program_definition::ProgramDefinition, EmulatorError, ExecutionResult,
+    constants::*,
+    executor::{decode_cache::DecodeCache, steps::Steps},
+    loader::{
+        checkpoint::{input_digest, read_checkpoint, write_json_checkpoint, CheckpointOrigin},
+        paged_memory::{PagedMemory, PAGE_WORDS},
+        program_definition::ProgramDefinition,
+        symbols::Symbolizer,
+    },
+    EmulatorError, ExecutionResult,
 };
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Section {
     pub name: String,
     pub data: Vec<u32>,
@@ -28,6 +46,10 @@ pub struct Section {
     pub is_write: bool,
     pub initialized: bool,
     pub registers: bool, // special section for registers
+    // words of the sections without initial data, allocated by page on the first write (data and
+    // last_step are empty then)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<PagedMemory>,
 }
 
 impl Section {
@@ -41,14 +63,15 @@ impl Section {
     ) -> Section {
         Section {
             name: name.to_string(),
-            data: vec![0; size as usize / 4],
-            last_step: vec![LAST_STEP_INIT; size as usize / 4],
+            data: Vec::new(),
+            last_step: Vec::new(),
             start,
             size,
             is_code,
             is_write,
             initialized: false,
             registers,
+            pages: Some(PagedMemory::default()),
         }
     }
 
@@ -71,6 +94,75 @@ impl Section {
             is_write,
             initialized,
             registers: false,
+            pages: None,
+        }
+    }
+
+    pub fn words(&self) -> usize {
+        match &self.pages {
+            Some(_) => self.size as usize / 4,
+            None => self.data.len(),
+        }
+    }
+
+    // word as it is stored (big endian)
+    pub fn word(&self, idx: usize) -> u32 {
+        match &self.pages {
+            Some(pages) => pages.get(idx),
+            None => self.data[idx],
+        }
+    }
+
+    // the sections without steps (see Program::drop_last_steps) read as never written
+    pub fn word_last_step(&self, idx: usize) -> u64 {
+        match &self.pages {
+            Some(pages) => pages.get_last_step(idx),
+            None => self.last_step.get(idx).copied().unwrap_or(LAST_STEP_INIT),
+        }
+    }
+
+    pub fn set_word(&mut self, idx: usize, value: u32, step: u64) {
+        match &mut self.pages {
+            Some(pages) => pages.set(idx, value, step),
+            None => {
+                self.data[idx] = value;
+                if let Some(last_step) = self.last_step.get_mut(idx) {
+                    *last_step = step;
+                }
+            }
+        }
+    }
+
+    // every word as it is stored, including the ones of the pages not allocated
+    pub fn to_words(&self) -> Vec<u32> {
+        match &self.pages {
+            Some(pages) => (0..self.words()).map(|idx| pages.get(idx)).collect(),
+            None => self.data.clone(),
+        }
+    }
+
+    // index, value and last step of the words written after the step
+    pub fn words_written_after(&self, step: u64) -> Vec<(usize, u32, u64)> {
+        let written = move |last_step: &u64| *last_step != LAST_STEP_INIT && *last_step > step;
+        match &self.pages {
+            Some(pages) => pages
+                .pages()
+                .flat_map(|(number, page)| {
+                    let first = number as usize * PAGE_WORDS;
+                    page.last_step
+                        .iter()
+                        .enumerate()
+                        .filter(move |(_, last_step)| written(last_step))
+                        .map(move |(idx, last_step)| (first + idx, page.data[idx], *last_step))
+                })
+                .collect(),
+            None => self
+                .last_step
+                .iter()
+                .enumerate()
+                .filter(move |(_, last_step)| written(last_step))
+                .map(|(idx, last_step)| (idx, self.data[idx], *last_step))
+                .collect(),
         }
     }
 
@@ -78,11 +170,44 @@ impl Section {
         (self.start, self.start + self.size - 1)
     }
 
+    // returns the 32 bits fetched at the address. With compressed instructions the address
+    // can be 2-byte aligned and the window spans two words (zero after the end of the section)
+    pub fn fetch_window(&self, address: u32) -> u32 {
+        let offset = (address - self.start) as usize;
+        let word = u32::from_be(self.word(offset / 4));
+        if offset % 4 == 0 {
+            return word;
+        }
+        let next_word = match offset / 4 + 1 < self.words() {
+            true => u32::from_be(self.word(offset / 4 + 1)),
+            false => 0,
+        };
+        (word >> 16) | (next_word << 16)
+    }
+
+    // walks the section instruction by instruction returning the address and the fetch window
+    pub fn instructions(&self) -> Vec<(u32, u32)> {
+        let mut instructions = Vec::new();
+        let mut offset = 0;
+        while (offset / 4) < self.words() {
+            let address = self.start + offset as u32;
+            let window = self.fetch_window(address);
+            // a trailing zero parcel is the padding that aligns the section to 4 bytes
+            if offset % 4 == 2 && offset / 4 == self.words() - 1 && window & 0xFFFF == 0 {
+                break;
+            }
+            instructions.push((address, window));
+            offset += instruction_size(window) as usize;
+        }
+        instructions
+    }
+
     pub fn is_merge_compatible(&self, other: &Self) -> bool {
         self.is_code == other.is_code
             && self.is_write == other.is_write
             && self.initialized == other.initialized
             && self.registers == other.registers
+            && self.pages.is_some() == other.pages.is_some()
             && self.start + self.size == other.start
     }
 
@@ -94,8 +219,14 @@ impl Section {
             other
         );
 
-        self.data.extend(other.data);
-        self.last_step.extend(other.last_step);
+        let offset = self.words();
+        match (&mut self.pages, other.pages) {
+            (Some(pages), Some(other_pages)) => pages.merge(other_pages, offset),
+            _ => {
+                self.data.extend(other.data);
+                self.last_step.extend(other.last_step);
+            }
+        }
         self.size += other.size;
 
         self.name = format!(
@@ -110,6 +241,12 @@ pub const CHECKPOINT_SIZE: u64 = 50_000_000;
 pub const LIMIT_STEP: u64 = 10_000_000_000; //ten billion arbitrary limit
 const RISCV32_REGISTERS: usize = 32;
 const AUX_REGISTERS: usize = 2;
+// the registers and the aux registers
+const REGISTERS_SECTION_SIZE: u32 = SYSCALL_SLOTS_OFFSET;
+// the syscall slots follow in their own section, as their initial values are committed
+pub const SYSCALL_SLOTS_SECTION: &str = "syscall_slots";
+// the syscall slots are read and written as registers
+const REGISTERS_FILE_SIZE: usize = (SYSCALL_SLOTS_OFFSET + SYSCALL_SLOTS * 4) as usize / 4;
 pub const AUX_REGISTER_1: u32 = 32;
 pub const AUX_REGISTER_2: u32 = 33;
 pub const REGISTER_STACK_POINTER: usize = 2;
@@ -117,20 +254,20 @@ pub const REGISTER_ZERO: usize = 0;
 pub const REGISTER_A0: usize = 10;
 pub const REGISTER_A7_ECALL_ARG: usize = 17;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Registers {
     #[serde(with = "BigArray")]
-    value: [u32; RISCV32_REGISTERS + AUX_REGISTERS],
+    value: [u32; REGISTERS_FILE_SIZE],
     #[serde(with = "BigArray")]
-    last_step: [u64; RISCV32_REGISTERS + AUX_REGISTERS],
+    last_step: [u64; REGISTERS_FILE_SIZE],
     base_address: u32,
 }
 
 impl Registers {
     pub fn new(base_address: u32, sp_base_address: u32) -> Registers {
         let mut registers = Registers {
-            value: [0; RISCV32_REGISTERS + AUX_REGISTERS],
-            last_step: [LAST_STEP_INIT; RISCV32_REGISTERS + AUX_REGISTERS],
+            value: [0; REGISTERS_FILE_SIZE],
+            last_step: [LAST_STEP_INIT; REGISTERS_FILE_SIZE],
             base_address,
         };
         registers.value[REGISTER_STACK_POINTER] = sp_base_address; // Stack pointer
@@ -161,6 +298,14 @@ impl Registers {
         self.last_step[idx as usize] = step;
     }
 
+    // without the step of the write, for the executions that don't trace
+    pub fn set_value(&mut self, idx: u32, value: u32) {
+        if idx == REGISTER_ZERO as u32 {
+            panic!("Cannot set register zero. Value: {}", value);
+        }
+        self.value[idx as usize] = value;
+    }
+
     pub fn get_register_address(&self, idx: u32) -> u32 {
         self.base_address + (idx * 4)
     }
@@ -185,7 +330,7 @@ impl Registers {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Program {
     pub sections: Vec<Section>,
     pub registers: Registers,
@@ -197,6 +342,32 @@ pub struct Program {
     pub read_only_sections: SectionDefinition,
     pub register_sections: SectionDefinition,
     pub code_sections: SectionDefinition,
+    // size of the instruction being executed, 2 for compressed instructions
+    #[serde(skip, default = "default_instruction_size")]
+    pub instruction_size: u32,
+    // bytes written by the program to stdout and stderr
+    #[serde(default)]
+    pub captured_output: Vec<u8>,
+    // symbols and lines of the elf to describe the addresses
+    #[serde(default)]
+    pub symbolizer: Symbolizer,
+    // digest of the input loaded at step 0, kept in the checkpoints to validate them
+    #[serde(default)]
+    pub input_digest: String,
+    // section whose words are claimed as the output when the program halts
+    #[serde(default)]
+    pub output_section: Option<String>,
+    // allocated sections of the elf as name, start and size, so the input, heap and output are
+    // found by address also when they were loaded inside a segment or merged with other sections
+    #[serde(default)]
+    pub elf_sections: Vec<(String, u32, u32)>,
+    // instructions of the code sections decoded once, shared by the clones of the program
+    #[serde(skip)]
+    pub decode_cache: Option<Arc<DecodeCache>>,
+}
+
+fn default_instruction_size() -> u32 {
+    INSTRUCTION_SIZE
 }
 
 impl Program {
@@ -209,38 +380,137 @@ impl Program {
         // if the step is non-zero then we are running the program from a checkpoint
         // so we shouldn't rewrite the input. First, because it's already included in the checkpoint
         // and second, because the input section is writable and the value could've been changed
+        if self.step == 0 {
+            self.input_digest = input_digest(&input);
+        }
         if !input.is_empty() && self.step == 0 {
-            if let Some(section) = self.find_section_by_name_mut(input_section_name) {
-                let input_as_u32 = vec_u8_to_vec_u32(&input, little_endian);
-                for (i, byte) in input_as_u32.iter().enumerate() {
-                    section.data[i] = *byte;
+            let start = match self.find_range_by_name(input_section_name) {
+                Some((start, _)) => start,
+                None => {
+                    return Err(ExecutionResult::SectionNotFound(
+                        input_section_name.to_string(),
+                    ))
                 }
-            } else {
+            };
+            let input_as_u32 = vec_u8_to_vec_u32(&input, little_endian);
+            for (i, word) in input_as_u32.iter().enumerate() {
+                let address = start + i as u32 * 4;
+                let section = self.find_section_mut(address)?;
+                let idx = ((address - section.start) / 4) as usize;
+                section.set_word(idx, *word, LAST_STEP_INIT);
+            }
+            // the input is also available to the read syscall
+            self.declare_input(input_section_name, input.len() as u32)?;
+        }
+
+        Ok(())
+    }
+
+    // the read syscall consumes the input from the start of the input section
+    pub fn declare_input(
+        &mut self,
+        input_section_name: &str,
+        size: u32,
+    ) -> Result<(), ExecutionResult> {
+        let start = match self.find_range_by_name(input_section_name) {
+            Some((start, _)) => start,
+            None => {
                 return Err(ExecutionResult::SectionNotFound(
                     input_section_name.to_string(),
-                ));
+                ))
+            }
+        };
+
+        self.set_syscall_slot(SLOT_INPUT_POINTER, start);
+        self.set_syscall_slot(SLOT_INPUT_END, start + size);
+        Ok(())
+    }
+
+    // the brk syscall grows the program break up to the end of the heap section
+    pub fn declare_heap(&mut self, heap_section_name: &str) -> Result<(), ExecutionResult> {
+        let (start, size) = match self.find_range_by_name(heap_section_name) {
+            Some(range) => range,
+            None => {
+                return Err(ExecutionResult::SectionNotFound(
+                    heap_section_name.to_string(),
+                ))
             }
+        };
+
+        self.set_syscall_slot(SLOT_HEAP_START, start);
+        self.set_syscall_slot(SLOT_HEAP_END, start + size);
+        Ok(())
+    }
+
+    // the contents of the output section are claimed with the halt of the program
+    pub fn declare_output(&mut self, output_section_name: &str) -> Result<(), ExecutionResult> {
+        if self.find_range_by_name(output_section_name).is_none() {
+            return Err(ExecutionResult::SectionNotFound(
+                output_section_name.to_string(),
+            ));
         }
 
+        self.output_section = Some(output_section_name.to_string());
         Ok(())
     }
+
+    // words of the output section as they would be read at the current step
+    pub fn get_output(&self) -> Vec<TraceRead> {
+        let (start, size) = match &self.output_section {
+            Some(name) => match self.find_range_by_name(name) {
+                Some(range) => range,
+                None => return vec![],
+            },
+            None => return vec![],
+        };
+
+        (start..start + size)
+            .step_by(4)
+            .filter_map(|address| {
+                let section = self.find_section(address).ok()?;
+                let idx = ((address - section.start) / 4) as usize;
+                Some(TraceRead::new(
+                    address,
+                    u32::from_be(section.word(idx)),
+                    section.word_last_step(idx),
+                ))
+            })
+            .collect()
+    }
+
+    pub fn halt_result(&self) -> ExecutionResult {
+        ExecutionResult::Halt(
+            self.registers.get(REGISTER_A0 as u32),
+            self.step,
+            self.get_output(),
+        )
+    }
+
+    // the registers hold the current value of the slot and the section its initial value
+    fn set_syscall_slot(&mut self, slot: u32, value: u32) {
+        self.registers
+            .set(syscall_slot_index(slot), value, LAST_STEP_INIT);
+        if let Some(section) = self.find_section_by_name_mut(SYSCALL_SLOTS_SECTION) {
+            section.set_word(slot as usize, value.to_be(), LAST_STEP_INIT);
+        }
+    }
     pub fn serialize_to_file(&self, fpath: &str) {
-        let fname = format!("{}/checkpoint.{}.json", fpath, self.step);
-        let serialized = serde_json::to_string(self).unwrap();
-        std::fs::write(fname, serialized).expect("Unable to write file");
+        write_json_checkpoint(self, fpath).expect("Unable to write file");
     }
 
-    pub fn deserialize_from_file(fpath: &str, step: u64) -> Result<Program, EmulatorError> {
-        let fname = format!("{}/checkpoint.{}.json", fpath, step);
-        let serialized = std::fs::read(&fname).map_err(|_| {
-            EmulatorError::CantLoadPorgram(format!("Error loading file: {}", fname))
-        })?;
-        let serialized_str = std::str::from_utf8(&serialized).map_err(|_| {
-            EmulatorError::CantLoadPorgram(format!("Error parsing file: {}", fname))
-        })?;
-        serde_json::from_str(serialized_str).map_err(|_| {
-            EmulatorError::CantLoadPorgram(format!("Error deserializing file: {}", fname))
-        })
+    // loads the json checkpoint or the binary one written with the other checkpoint formats,
+    // checking that the state matches its header and that it was written from the origin
+    pub fn deserialize_from_file(
+        fpath: &str,
+        step: u64,
+        origin: Option<&CheckpointOrigin>,
+    ) -> Result<Program, EmulatorError> {
+        read_checkpoint(fpath, step, origin)
+    }
+
+    // lazily executes the program, yielding every step with its hash and then the result
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps::new(self)
     }
 
     pub fn new(entry_point: u32, registers_base_address: u32, sp_base_address: u32) -> Program {
@@ -257,9 +527,34 @@ impl Program {
             read_only_sections: SectionDefinition::default(),
             register_sections: SectionDefinition::default(),
             code_sections: SectionDefinition::default(),
+            instruction_size: INSTRUCTION_SIZE,
+            captured_output: Vec::new(),
+            symbolizer: Symbolizer::default(),
+            input_digest: input_digest(&[]),
+            output_section: None,
+            elf_sections: Vec::new(),
+            decode_cache: None,
         }
     }
 
+    pub fn build_decode_cache(&mut self) {
+        self.decode_cache = Some(Arc::new(DecodeCache::new(self)));
+    }
+
+    // keeps only the values of the memory, the steps of the last writes can't be traced afterwards
+    pub fn drop_last_steps(&mut self) {
+        for section in &mut self.sections {
+            section.last_step = Vec::new();
+            if let Some(pages) = &mut section.pages {
+                pages.drop_last_steps();
+            }
+        }
+    }
+
+    pub fn advance_pc(&mut self) {
+        self.pc.next_address_with_size(self.instruction_size);
+    }
+
     pub fn merge_sections(&mut self) {
         let sections = std::mem::take(&mut self.sections);
         let mut merged: Vec<Section> = Vec::with_capacity(sections.len());
@@ -377,25 +672,25 @@ impl Program {
     }
 
     pub fn check_sections_with_invalid_opcode(&self) -> Result<(), EmulatorError> {
-        let section_with_invalid_opcode = self.sections.iter().find(|section| {
-            section.is_code
-                && section
-                    .data
-                    .iter()
-                    .find(|opcode| riscv_decode::decode(u32::from_be(**opcode)).is_err())
-                    .is_some()
-        });
+        let invalid_opcode = self
+            .sections
+            .iter()
+            .filter(|section| section.is_code)
+            .find_map(|section| {
+                section
+                    .instructions()
+                    .into_iter()
+                    .find(|(_, opcode)| {
+                        decode_instruction(*opcode).is_none()
+                            && BitmanipInstruction::decode(*opcode).is_none()
+                    })
+                    .map(|(_, opcode)| (section, opcode))
+            });
 
-        if let Some(section_with_invalid_opcode) = section_with_invalid_opcode {
+        if let Some((section_with_invalid_opcode, opcode)) = invalid_opcode {
             return Err(EmulatorError::CantLoadPorgram(format!(
                 "Cannot load program: code section '{}' has invalid opcode '{}'",
-                section_with_invalid_opcode.name,
-                section_with_invalid_opcode
-                    .data
-                    .iter()
-                    .find(|opcode| riscv_decode::decode(u32::from_be(**opcode)).is_err())
-                    .map(|opcode| u32::from_be(*opcode))
-                    .unwrap()
+                section_with_invalid_opcode.name, opcode
             )));
         }
 
@@ -487,6 +782,19 @@ impl Program {
         self.sections.iter().find(|section| section.name == name)
     }
 
+    // start and size of the section with the name, looking it up in the sections of the elf when
+    // it was loaded inside a segment or merged with other sections
+    pub fn find_range_by_name(&self, name: &str) -> Option<(u32, u32)> {
+        match self.find_section_by_name(name) {
+            Some(section) => Some((section.start, section.size)),
+            None => self
+                .elf_sections
+                .iter()
+                .find(|(section_name, _, _)| section_name == name)
+                .map(|(_, start, size)| (*start, *size)),
+        }
+    }
+
     pub fn find_section_by_name_mut(&mut self, name: &str) -> Option<&mut Section> {
         self.sections
             .iter_mut()
@@ -497,13 +805,14 @@ impl Program {
         if cfg!(target_endian = "big") {
             panic!("Big endian machine not supported");
         }
+        if address % 2 != 0 {
+            return Err(ExecutionResult::UnalignedRead(address));
+        }
         let section = self.find_section(address)?;
         if !section.is_code {
             return Err(ExecutionResult::ExecuteFromNonCodeSection);
         }
-        Ok(u32::from_be(
-            section.data[(address - section.start) as usize / 4],
-        ))
+        Ok(section.fetch_window(address))
     }
 
     pub fn read_mem(
@@ -524,13 +833,13 @@ impl Program {
         };
 
         Ok(u32::from_be(
-            section.data[(address - section.start) as usize / 4],
+            section.word((address - section.start) as usize / 4),
         ))
     }
 
     pub fn get_last_step(&self, address: u32) -> u64 {
         let section = self.find_section(address).unwrap();
-        section.last_step[(address - section.start) as usize / 4]
+        section.word_last_step((address - section.start) as usize / 4)
     }
 
     pub fn write_mem(&mut self, address: u32, value: u32) -> Result<(), ExecutionResult> {
@@ -543,16 +852,15 @@ impl Program {
         if !section.is_write || section.is_code {
             return Err(ExecutionResult::WriteToReadOnlySection);
         }
-        section.data[(address - section.start) as usize / 4] = value.to_be();
-        section.last_step[(address - section.start) as usize / 4] = step;
+        section.set_word((address - section.start) as usize / 4, value.to_be(), step);
         Ok(())
     }
 
     pub fn dump_memory(&self) {
         info!(
             "\n------- Section: REGISTERS Start: 0x{:08x} Size: 0x{:08x} -------\n",
-            REGISTERS_BASE_ADDRESS,
-            (RISCV32_REGISTERS + AUX_REGISTERS) * std::mem::size_of::<u32>()
+            self.registers.get_base_address(),
+            REGISTERS_SECTION_SIZE
         );
 
         for (i, reg) in self.registers.value.iter().enumerate() {
@@ -571,15 +879,21 @@ impl Program {
         }
 
         for section in &self.sections {
-            if section.data.iter().any(|&word| word != 0) {
+            let words = section.to_words();
+            if words.iter().any(|&word| word != 0) {
                 info!(
                     "\n------- Section: {} Start: 0x{:08x} Size: 0x{:08x} -------\n",
                     section.name, section.start, section.size
                 );
-                for (i, word) in section.data.iter().enumerate() {
+                for (i, word) in words.iter().enumerate() {
                     let address = section.start + (i as u32 * 4);
                     if *word != 0 {
-                        info!("Address: 0x{:08x} Value: 0x{:08x}", address, word);
+                        info!(
+                            "Address: 0x{:08x} Value: 0x{:08x}{}",
+                            address,
+                            word,
+                            self.symbolizer.annotate(address)
+                        );
                     }
                 }
             } else {
@@ -597,6 +911,15 @@ impl Program {
             && address % 4 == 0
     }
 
+    // instructions are 2-byte aligned and at least 2 bytes long
+    pub fn instruction_in_sections(&self, address: u32, sections: &SectionDefinition) -> bool {
+        sections
+            .ranges
+            .iter()
+            .any(|&(start, end)| start <= address && address <= end - 1)
+            && address % 2 == 0
+    }
+
     pub fn is_valid_mem(
         &self,
         witness: MemoryAccessType,
@@ -625,7 +948,7 @@ impl Program {
             .filter(filter)
             .flat_map(|section| {
                 section
-                    .data
+                    .to_words()
                     .chunks(chunk_size as usize)
                     .enumerate()
                     .map(|(index, chunk)| {
@@ -636,6 +959,7 @@ impl Program {
                             data,
                         }
                     })
+                    .collect::<Vec<Chunk>>()
             })
             .collect()
     }
@@ -646,35 +970,52 @@ impl Program {
         })
     }
 
+    // every code chunk ends with the first word of the next one (zero at the end of the section),
+    // so the fetch window of an instruction that is not 4-byte aligned can be rebuilt
     pub fn get_code_chunks(&self, chunk_size: u32) -> Vec<Chunk> {
         self.get_chunks(chunk_size, |section| section.is_code)
+            .into_iter()
+            .map(|mut chunk| {
+                let section = self.find_section(chunk.base_addr).unwrap();
+                let next_word = (chunk.base_addr - section.start) as usize / 4 + chunk.data.len();
+                let lookahead = match next_word < section.words() {
+                    true => u32::from_be(section.word(next_word)),
+                    false => 0,
+                };
+                chunk.data.push(lookahead);
+                chunk
+            })
+            .collect()
     }
 
     pub fn get_uninitialized_ranges(
         &self,
         program_definition: &ProgramDefinition,
     ) -> SectionDefinition {
-        let mut uninitialized: Vec<(u32, u32)> = self
-            .sections
-            .iter()
-            .filter(|section| {
-                // we do inputs separately
-                !section.initialized && section.name != program_definition.input_section_name
-            })
-            .map(|section| section.range())
-            .collect();
-
-        let input_section = self
-            .find_section_by_name(&program_definition.input_section_name)
+        let (start, size) = self
+            .find_range_by_name(&program_definition.input_section_name)
             .expect("Input section not found");
+        let end = start + size - 1;
+
+        // we do inputs separately, the input section can be part of a bigger section
+        let mut uninitialized = Vec::new();
+        for section in self.sections.iter().filter(|section| !section.initialized) {
+            let (section_start, section_end) = section.range();
+            if end < section_start || start > section_end {
+                uninitialized.push((section_start, section_end));
+                continue;
+            }
+            if section_start < start {
+                uninitialized.push((section_start, start - 1));
+            }
+            if end < section_end {
+                uninitialized.push((end + 1, section_end));
+            }
+        }
 
         // input section is usually bigger than the actual input of the program, so the remaining space should be uninitialized
-        let input_size = program_definition
-            .inputs
-            .iter()
-            .fold(0, |acc, input| acc + input.size);
+        let input_size = program_definition.input_size();
 
-        let (start, end) = input_section.range();
         let uninit_start = start + input_size as u32;
 
         assert!(
@@ -717,7 +1058,80 @@ pub fn vec_u8_to_vec_u32(input: &[u8], little: bool) -> Vec<u32> {
         .collect()
 }
 
+// where the registers and the stack are placed, it has to match the linker script of the program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryLayout {
+    pub registers_base_address: u32,
+    pub stack_base_address: u32,
+    pub stack_size: u32,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self {
+            registers_base_address: REGISTERS_BASE_ADDRESS,
+            stack_base_address: STACK_BASE_ADDRESS,
+            stack_size: STACK_SIZE,
+        }
+    }
+}
+
+impl MemoryLayout {
+    // the values not given are the defaults
+    pub fn new(
+        registers_base_address: Option<u32>,
+        stack_base_address: Option<u32>,
+        stack_size: Option<u32>,
+    ) -> Self {
+        let default = Self::default();
+        Self {
+            registers_base_address: registers_base_address
+                .unwrap_or(default.registers_base_address),
+            stack_base_address: stack_base_address.unwrap_or(default.stack_base_address),
+            stack_size: stack_size.unwrap_or(default.stack_size),
+        }
+    }
+
+    // initial value of the stack pointer
+    pub fn stack_pointer(&self) -> Result<u32, EmulatorError> {
+        self.stack_base_address
+            .checked_add(self.stack_size)
+            .ok_or_else(|| {
+                EmulatorError::CantLoadPorgram(format!(
+                    "Stack base 0x{:08x} with size 0x{:08x} overflows the address space",
+                    self.stack_base_address, self.stack_size
+                ))
+            })
+    }
+}
+
+// how the memory of the program is built from the elf
+#[derive(Clone, Copy, PartialEq, ValueEnum, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElfLoadMode {
+    // from the allocated sections (SHF_ALLOC) of the section headers
+    #[default]
+    Sections,
+    // from the loadable segments (PT_LOAD) of the program headers, for stripped elfs or the ones
+    // of linkers that only lay out segments
+    Segments,
+}
+
 pub fn load_elf(fname: &str, show_sections: bool) -> Result<Program, EmulatorError> {
+    load_elf_with_options(
+        fname,
+        show_sections,
+        &MemoryLayout::default(),
+        ElfLoadMode::default(),
+    )
+}
+
+pub fn load_elf_with_options(
+    fname: &str,
+    show_sections: bool,
+    layout: &MemoryLayout,
+    mode: ElfLoadMode,
+) -> Result<Program, EmulatorError> {
     let path = std::path::PathBuf::from(fname);
     let file_data = std::fs::read(path)
         .map_err(|_| EmulatorError::CantLoadPorgram(format!("Error loading file: {}", fname)))?;
@@ -728,40 +1142,77 @@ pub fn load_elf(fname: &str, show_sections: bool) -> Result<Program, EmulatorErr
     let entry_point = u32::try_from(file.ehdr.e_entry).map_err(|_| {
         EmulatorError::CantLoadPorgram(format!("Invalid entrypoint for elf: {}", fname))
     })?;
-    let string_table = file
-        .section_headers_with_strtab()
-        .map_err(|_| EmulatorError::CantLoadPorgram(format!("Can't read headers for: {}", fname)))?
-        .1
-        .ok_or_else(|| {
-            EmulatorError::CantLoadPorgram(format!("Can't read string table for: {}", fname))
-        })?;
 
     let mut program = Program::new(
         entry_point,
-        REGISTERS_BASE_ADDRESS,
-        STACK_BASE_ADDRESS + STACK_SIZE,
+        layout.registers_base_address,
+        layout.stack_pointer()?,
     );
 
-    let sections = file.section_headers().ok_or_else(|| {
-        EmulatorError::CantLoadPorgram(format!("Can't read headers for: {}", fname))
-    })?;
-
     program.add_section(Section::new(
         "registers",
         program.registers.get_base_address(),
-        ((RISCV32_REGISTERS + AUX_REGISTERS) * 4) as u32,
+        REGISTERS_SECTION_SIZE,
         false,
         true,
         true,
     ));
     if show_sections {
-        info!("Loading section: {} Start: 0x{:08x} Size: 0x{:08x} Initialized: {} Flags: {:0b} Type: {:0b} ", "registers", REGISTERS_BASE_ADDRESS, ((RISCV32_REGISTERS + AUX_REGISTERS) * 4) as u32, false, 0, 0);
+        info!("Loading section: {} Start: 0x{:08x} Size: 0x{:08x} Initialized: {} Flags: {:0b} Type: {:0b} ", "registers", layout.registers_base_address, REGISTERS_SECTION_SIZE, false, 0, 0);
     }
 
-    sections.iter().for_each(|phdr| {
+    // the slots are read as registers, but their initial values are part of the initialized data
+    let mut syscall_slots = Section::new_with_data(
+        SYSCALL_SLOTS_SECTION,
+        vec![0; SYSCALL_SLOTS as usize],
+        program.registers.get_base_address() + SYSCALL_SLOTS_OFFSET,
+        SYSCALL_SLOTS * 4,
+        false,
+        true,
+        true,
+    );
+    syscall_slots.registers = true;
+    program.add_section(syscall_slots);
+
+    match mode {
+        ElfLoadMode::Sections => load_sections(&mut program, &file, slice, fname, show_sections)?,
+        ElfLoadMode::Segments => load_segments(&mut program, &file, slice, fname, show_sections)?,
+    }
+
+    program.symbolizer = Symbolizer::from_elf(&file);
+    program.elf_sections = elf_sections(&file);
+
+    program.sanity_check(Some(layout.stack_base_address))?;
+    program.merge_sections();
+    program.generate_sections_definitions();
+    program.build_decode_cache();
+
+    Ok(program)
+}
+
+fn load_sections(
+    program: &mut Program,
+    file: &ElfBytes<LittleEndian>,
+    slice: &[u8],
+    fname: &str,
+    show_sections: bool,
+) -> Result<(), EmulatorError> {
+    let string_table = file
+        .section_headers_with_strtab()
+        .map_err(|_| EmulatorError::CantLoadPorgram(format!("Can't read headers for: {}", fname)))?
+        .1
+        .ok_or_else(|| {
+            EmulatorError::CantLoadPorgram(format!("Can't read string table for: {}", fname))
+        })?;
+
+    let sections = file.section_headers().ok_or_else(|| {
+        EmulatorError::CantLoadPorgram(format!("Can't read headers for: {}", fname))
+    })?;
+
+    for phdr in sections.iter() {
 
         if phdr.sh_flags as u32 & elf::abi::SHF_ALLOC != elf::abi::SHF_ALLOC {
-            return;
+            continue;
         }
 
         let name = string_table.get_raw(phdr.sh_name as usize).map(|name| {
@@ -771,11 +1222,16 @@ pub fn load_elf(fname: &str, show_sections: bool) -> Result<Program, EmulatorErr
         let size = u32::try_from(phdr.sh_size);
         if start.is_err() || size.is_err() {
             error!("Invalid start or size for section: {} Start: 0x{:08x} Size: 0x{:08x}", name, start.unwrap_or(0), size.unwrap_or(0));
-            return;
+            continue;
         }
         let start = start.unwrap();
         let size = size.unwrap();
-        assert!(start % 4 == 0, "Start address must be a multiple of 4");
+        if start % 4 != 0 {
+            return Err(EmulatorError::CantLoadPorgram(format!(
+                "Section {} starts at 0x{:08x}, which is not a multiple of 4",
+                name, start
+            )));
+        }
         let padded_size = (size + 3) & !0b11;
 
         let initialized = phdr.sh_type == elf::abi::SHT_PROGBITS;
@@ -783,14 +1239,13 @@ pub fn load_elf(fname: &str, show_sections: bool) -> Result<Program, EmulatorErr
             if show_sections {
                 info!("Empty section: {} Start: 0x{:08x} Size: 0x{:08x} Initialized: {}", name, start, size, initialized);
             }
-            return;
+            continue;
         }
 
         let data = if initialized {
-            vec_u8_to_vec_u32(&slice[phdr.sh_offset as usize..(phdr.sh_offset + size as u64) as usize], false)
+            Some(vec_u8_to_vec_u32(&elf_data(slice, phdr.sh_offset, size as u64, &name)?, false))
         } else {
-            let num_u32 = padded_size / 4;
-            vec![0; num_u32 as usize]
+            None
         };
 
         if show_sections {
@@ -799,16 +1254,179 @@ pub fn load_elf(fname: &str, show_sections: bool) -> Result<Program, EmulatorErr
 
         let is_code = phdr.sh_flags as u32 & SHF_EXECINSTR == SHF_EXECINSTR;
         let is_write = phdr.sh_flags as u32 & SHF_WRITE == SHF_WRITE;
-        assert!(!(is_code && is_write), "We don't allow writable code sections");
+        if is_code && is_write {
+            return Err(EmulatorError::CantLoadPorgram(format!(
+                "Section {} is writable code, which is not allowed",
+                name
+            )));
+        }
 
-        program.add_section(Section::new_with_data(&name, data, start, padded_size, is_code, is_write, initialized));
-    });
+        // the sections without data are allocated by page as they are written
+        match data {
+            Some(data) => program.add_section(Section::new_with_data(&name, data, start, padded_size, is_code, is_write, initialized)),
+            None => program.add_section(Section::new(&name, start, padded_size, is_code, is_write, false)),
+        }
+    }
 
-    program.sanity_check(Some(STACK_BASE_ADDRESS))?;
-    program.merge_sections();
-    program.generate_sections_definitions();
+    Ok(())
+}
 
-    Ok(program)
+// allocated sections of the elf, empty when it has no section headers
+fn elf_sections(file: &ElfBytes<LittleEndian>) -> Vec<(String, u32, u32)> {
+    match file.section_headers_with_strtab() {
+        Ok((Some(sections), Some(string_table))) => sections
+            .iter()
+            .filter(|shdr| shdr.sh_flags as u32 & elf::abi::SHF_ALLOC == elf::abi::SHF_ALLOC)
+            .filter_map(|shdr| {
+                let name = string_table.get(shdr.sh_name as usize).ok()?;
+                let start = u32::try_from(shdr.sh_addr).ok()?;
+                let size = u32::try_from(shdr.sh_size).ok()?;
+                match size {
+                    0 => None,
+                    _ => Some((name.to_string(), start, (size + 3) & !0b11)),
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// the segments are named after the first allocated section they contain (when the elf still has
+// the section headers), the sections inside them are found by address with the elf sections
+fn segment_name(file: &ElfBytes<LittleEndian>, idx: usize, start: u32, end: u64) -> String {
+    let section_name = match file.section_headers_with_strtab() {
+        Ok((Some(sections), Some(string_table))) => sections
+            .iter()
+            .filter(|shdr| shdr.sh_flags as u32 & elf::abi::SHF_ALLOC == elf::abi::SHF_ALLOC)
+            .filter(|shdr| shdr.sh_size > 0 && shdr.sh_addr >= start as u64 && shdr.sh_addr < end)
+            .min_by_key(|shdr| shdr.sh_addr)
+            .and_then(|shdr| string_table.get(shdr.sh_name as usize).ok())
+            .map(|name| name.to_string()),
+        _ => None,
+    };
+    match section_name {
+        Some(name) if !name.is_empty() => name,
+        _ => format!("segment{}", idx),
+    }
+}
+
+fn load_segments(
+    program: &mut Program,
+    file: &ElfBytes<LittleEndian>,
+    slice: &[u8],
+    fname: &str,
+    show_sections: bool,
+) -> Result<(), EmulatorError> {
+    let segments = file.segments().ok_or_else(|| {
+        EmulatorError::CantLoadPorgram(format!("Can't read program headers for: {}", fname))
+    })?;
+
+    for (idx, phdr) in segments.iter().enumerate() {
+        if phdr.p_type != elf::abi::PT_LOAD || phdr.p_memsz == 0 {
+            continue;
+        }
+
+        let start = u32::try_from(phdr.p_vaddr).ok();
+        let mem_size = u32::try_from(phdr.p_memsz).ok();
+        let (start, mem_size) = match (start, mem_size) {
+            (Some(start), Some(mem_size)) if start.checked_add(mem_size).is_some() => {
+                (start, mem_size)
+            }
+            _ => {
+                return Err(EmulatorError::CantLoadPorgram(format!(
+                    "Segment {} at 0x{:x} with size 0x{:x} is outside the 32 bits address space",
+                    idx, phdr.p_vaddr, phdr.p_memsz
+                )))
+            }
+        };
+        let name = segment_name(file, idx, start, start as u64 + mem_size as u64);
+
+        if start % 4 != 0 {
+            return Err(EmulatorError::CantLoadPorgram(format!(
+                "Segment {} ({}) starts at 0x{:08x}, which is not a multiple of 4",
+                idx, name, start
+            )));
+        }
+        if phdr.p_filesz > phdr.p_memsz {
+            return Err(EmulatorError::CantLoadPorgram(format!(
+                "Segment {} ({}) has 0x{:x} bytes in the file but only 0x{:x} in memory",
+                idx, name, phdr.p_filesz, phdr.p_memsz
+            )));
+        }
+
+        let is_code = phdr.p_flags & elf::abi::PF_X == elf::abi::PF_X;
+        let is_write = phdr.p_flags & elf::abi::PF_W == elf::abi::PF_W;
+        if is_code && is_write {
+            return Err(EmulatorError::CantLoadPorgram(format!(
+                "Segment {} ({}) is writable code, which is not allowed",
+                idx, name
+            )));
+        }
+
+        if show_sections {
+            info!(
+                "Loading segment: {} ({}) Start: 0x{:08x} Size: 0x{:08x} File size: 0x{:08x} Flags: {:03b}",
+                idx, name, start, mem_size, phdr.p_filesz, phdr.p_flags
+            );
+        }
+
+        // the bytes in the file are the initialized part, padded to a word
+        let file_size = phdr.p_filesz as u32;
+        let initialized_size = (file_size + 3) & !0b11;
+        if file_size > 0 {
+            let data = elf_data(slice, phdr.p_offset, phdr.p_filesz, &name)?;
+            program.add_section(Section::new_with_data(
+                &name,
+                vec_u8_to_vec_u32(&data, false),
+                start,
+                initialized_size,
+                is_code,
+                is_write,
+                true,
+            ));
+        }
+
+        // the rest of the segment in memory is zero initialized
+        let padded_size = (mem_size + 3) & !0b11;
+        if padded_size > initialized_size {
+            if is_code {
+                return Err(EmulatorError::CantLoadPorgram(format!(
+                    "Code segment {} ({}) has 0x{:x} bytes not in the file",
+                    idx,
+                    name,
+                    padded_size - initialized_size
+                )));
+            }
+            let uninitialized_name = match file_size > 0 {
+                true => format!("{}.uninitialized", name),
+                false => name,
+            };
+            program.add_section(Section::new(
+                &uninitialized_name,
+                start + initialized_size,
+                padded_size - initialized_size,
+                false,
+                is_write,
+                false,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// bytes of the elf of a section or a segment
+fn elf_data(slice: &[u8], offset: u64, size: u64, name: &str) -> Result<Vec<u8>, EmulatorError> {
+    offset
+        .checked_add(size)
+        .and_then(|end| slice.get(offset as usize..end as usize))
+        .map(|data| data.to_vec())
+        .ok_or_else(|| {
+            EmulatorError::CantLoadPorgram(format!(
+                "The data of {} (0x{:x} bytes at 0x{:x}) is outside of the elf",
+                name, size, offset
+            ))
+        })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -837,17 +1455,34 @@ pub fn generate_rom_commitment(program: &Program) -> Result<RomCommitment, Emula
 
     for section in &program.sections {
         if section.is_code {
-            for i in 0..section.size / 4 {
-                let position = section.start + i * 4;
-                let data = program.read_mem(position, false)?;
+            for (position, data) in section.instructions() {
+                if let Some(bitmanip) = BitmanipInstruction::decode(data) {
+                    let key = get_key_from_bitmanip(&bitmanip);
+                    info!(
+                        "PC: 0x{:08x} Micro: 0 Opcode: 0x{:08x} Key: {}",
+                        position, data, key
+                    );
+                    rom_commitment.code.push(Code {
+                        address: position,
+                        micro: 0,
+                        opcode: data,
+                        key,
+                    });
+                    continue;
+                }
 
-                let instruction = riscv_decode::decode(data).expect(&format!(
+                let (instruction, compressed) = decode_instruction(data).expect(&format!(
                     "code section with undecodeable instruction: 0x{:08x} at position: 0x{:08x}",
                     data, position
                 ));
                 let micros = get_required_microinstruction(&instruction);
                 for micro in 0..micros {
-                    let key = get_key_from_instruction_and_micro(&instruction, micro);
+                    let key = match compressed {
+                        Some(compressed) => {
+                            get_key_from_compressed_and_micro(&instruction, compressed, micro)
+                        }
+                        None => get_key_from_instruction_and_micro(&instruction, micro),
+                    };
                     info!(
                         "PC: 0x{:08x} Micro: {} Opcode: 0x{:08x} Key: {}",
                         position, micro, data, key
@@ -892,6 +1527,8 @@ pub fn generate_rom_commitment(program: &Program) -> Result<RomCommitment, Emula
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::executor::fetcher::{execute_program_with_options, ExecutionOptions};
+    use elf::abi::{PF_R, PF_W, PF_X, PT_LOAD, SHF_ALLOC, SHT_NOBITS, SHT_PROGBITS, SHT_STRTAB};
 
     #[test]
     fn test_overlap_sections() {
@@ -920,6 +1557,230 @@ mod tests {
         assert!(program.check_sections_next_to_stack(Some(14)).is_err());
     }
 
+    #[test]
+    fn test_memory_layout() {
+        let layout = MemoryLayout::new(Some(0xA000_0000), None, Some(0x1000));
+        assert_eq!(layout.registers_base_address, 0xA000_0000);
+        assert_eq!(layout.stack_base_address, STACK_BASE_ADDRESS);
+        assert_eq!(layout.stack_pointer().unwrap(), STACK_BASE_ADDRESS + 0x1000);
+
+        let layout = MemoryLayout::new(None, Some(0xFFFF_0000), Some(0x1_0000));
+        assert!(layout.stack_pointer().is_err());
+    }
+
+    fn le_words(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn program_header(
+        offset: u32,
+        address: u32,
+        file_size: u32,
+        mem_size: u32,
+        flags: u32,
+    ) -> Vec<u8> {
+        le_words(&[
+            PT_LOAD, offset, address, address, file_size, mem_size, flags, 4,
+        ])
+    }
+
+    // elf without section headers with a code segment at 0x1000 and a data segment at 0x2000
+    // with 6 bytes in the file and 0x20 in memory
+    fn write_segments_elf(fname: &str, code_flags: u32) -> String {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend([2, 0, 0xf3, 0]); // executable, risc-v
+        elf.extend(le_words(&[1, 0x1000, 52, 0, 0])); // version, entry, phoff, shoff, flags
+        elf.extend([52, 0, 32, 0, 2, 0, 40, 0, 0, 0, 0, 0]); // sizes and counts of the headers
+        elf.extend(program_header(0x80, 0x1000, 8, 8, code_flags));
+        elf.extend(program_header(0x88, 0x2000, 6, 0x20, PF_R | PF_W));
+        elf.resize(0x80, 0);
+        elf.extend(le_words(&[0x0000_0513, 0x0000_006f])); // li a0, 0; j .
+        elf.extend([1, 2, 3, 4, 5, 6]);
+
+        let path = std::env::temp_dir().join(fname);
+        std::fs::write(&path, elf).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn section_header(
+        name: u32,
+        kind: u32,
+        flags: u32,
+        address: u32,
+        offset: u32,
+        size: u32,
+    ) -> Vec<u8> {
+        le_words(&[name, kind, flags, address, offset, size, 0, 0, 4, 0])
+    }
+
+    // elf with a code segment at 0x1000 that returns the first word of the input and a data
+    // segment at 0x2000 with the sections .data (6 bytes in the file), .input and .bss
+    fn write_segments_elf_with_sections(fname: &str) -> String {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend([2, 0, 0xf3, 0]); // executable, risc-v
+        elf.extend(le_words(&[1, 0x1000, 52, 0xb8, 0])); // version, entry, phoff, shoff, flags
+        elf.extend([52, 0, 32, 0, 2, 0, 40, 0, 5, 0, 4, 0]); // sizes and counts of the headers
+        elf.extend(program_header(0x80, 0x1000, 16, 16, PF_R | PF_X));
+        elf.extend(program_header(0x90, 0x2000, 6, 0x20, PF_R | PF_W));
+        elf.resize(0x80, 0);
+        // t1 = 0x2000; lw a0, 8(t1); a7 = 93; ecall
+        elf.extend(le_words(&[
+            0x0000_2337,
+            0x0083_2503,
+            0x05d0_0893,
+            0x0000_0073,
+        ]));
+        elf.extend([1, 2, 3, 4, 5, 6]);
+        elf.resize(0x98, 0);
+        elf.extend(b"\0.data\0.input\0.bss\0.shstrtab\0");
+        elf.resize(0xb8, 0);
+        elf.extend([0; 40]);
+        let flags = SHF_ALLOC | SHF_WRITE;
+        elf.extend(section_header(1, SHT_PROGBITS, flags, 0x2000, 0x90, 6));
+        elf.extend(section_header(7, SHT_NOBITS, flags, 0x2008, 0x96, 8));
+        elf.extend(section_header(14, SHT_NOBITS, flags, 0x2010, 0x96, 0x10));
+        elf.extend(section_header(19, SHT_STRTAB, 0, 0, 0x98, 29));
+
+        let path = std::env::temp_dir().join(fname);
+        std::fs::write(&path, elf).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_load_segments() {
+        let fname = write_segments_elf("load_segments.elf", PF_R | PF_X);
+        let program = load_elf_with_options(
+            &fname,
+            false,
+            &MemoryLayout::default(),
+            ElfLoadMode::Segments,
+        )
+        .unwrap();
+
+        let code = program.find_section(0x1004).unwrap();
+        assert_eq!((code.name.as_str(), code.is_code), ("segment0", true));
+        let data = program.find_section(0x2004).unwrap();
+        assert_eq!((data.name.as_str(), data.initialized), ("segment1", true));
+        assert_eq!(program.read_mem(0x2000, false).unwrap(), 0x0403_0201);
+        assert_eq!(program.read_mem(0x2004, false).unwrap(), 0x0000_0605);
+
+        // the memory not in the file is zero initialized
+        let uninitialized = program.find_section(0x2008).unwrap();
+        assert_eq!(uninitialized.name, "segment1.uninitialized");
+        assert!(uninitialized.is_write && !uninitialized.initialized);
+        assert_eq!(uninitialized.range(), (0x2008, 0x201f));
+        assert_eq!(program.read_mem(0x201c, false).unwrap(), 0);
+
+        // writable code is rejected instead of panicking
+        let fname = write_segments_elf("load_segments_wx.elf", PF_R | PF_W | PF_X);
+        let result = load_elf_with_options(
+            &fname,
+            false,
+            &MemoryLayout::default(),
+            ElfLoadMode::Segments,
+        );
+        assert!(matches!(result, Err(EmulatorError::CantLoadPorgram(_))));
+    }
+
+    #[test]
+    fn test_segments_with_input() {
+        let fname = write_segments_elf_with_sections("segments_with_input.elf");
+        let mut program = load_elf_with_options(
+            &fname,
+            false,
+            &MemoryLayout::default(),
+            ElfLoadMode::Segments,
+        )
+        .unwrap();
+
+        // the input and the heap are part of the tail of the data segment
+        let uninitialized = program.find_section(0x2008).unwrap();
+        assert_eq!(uninitialized.name, ".data.uninitialized");
+        assert_eq!(program.find_range_by_name(".input"), Some((0x2008, 8)));
+        program.declare_heap(".bss").unwrap();
+        program.declare_output(".input").unwrap();
+        assert_eq!(
+            program.registers.get(syscall_slot_index(SLOT_HEAP_END)),
+            0x2020
+        );
+
+        let options = ExecutionOptions {
+            input: vec![42, 0, 0, 0, 7, 0, 0, 0],
+            input_section_name: ".input".to_string(),
+            ..Default::default()
+        };
+        let (result, _) = execute_program_with_options(&mut program, options);
+        let output = vec![
+            TraceRead::new(0x2008, 42, LAST_STEP_INIT),
+            TraceRead::new(0x200c, 7, LAST_STEP_INIT),
+        ];
+        assert_eq!(result, ExecutionResult::Halt(42, 4, output));
+        assert_eq!(
+            program.registers.get(syscall_slot_index(SLOT_INPUT_END)),
+            0x2010
+        );
+    }
+
+    #[test]
+    fn test_syscall_slots_initialized() {
+        let fname = write_segments_elf("syscall_slots.elf", PF_R | PF_X);
+        let mut program = load_elf_with_options(
+            &fname,
+            false,
+            &MemoryLayout::default(),
+            ElfLoadMode::Segments,
+        )
+        .unwrap();
+        let heap = program.find_section(0x2008).unwrap().name.clone();
+        program.declare_heap(&heap).unwrap();
+        program.load_input(vec![1, 2, 3], "segment1", false).unwrap();
+
+        let slots = REGISTERS_BASE_ADDRESS + SYSCALL_SLOTS_OFFSET;
+        let initial = vec![0x2008, 0x2020, 0x2000, 0x2003];
+        let initialized_slots = |program: &Program| {
+            program
+                .get_initialized_chunks(8)
+                .into_iter()
+                .find(|chunk| chunk.base_addr == slots)
+                .unwrap()
+                .data
+        };
+        assert_eq!(initialized_slots(&program), initial);
+        assert!(program
+            .register_sections
+            .ranges
+            .contains(&(slots, slots + SYSCALL_SLOTS * 4 - 1)));
+
+        // the slots are updated as registers, the committed values don't change
+        let pointer = syscall_slot_index(SLOT_INPUT_POINTER);
+        program.registers.set(pointer, 0x2001, 1);
+        assert_eq!(program.registers.get(pointer), 0x2001);
+        assert_eq!(initialized_slots(&program), initial);
+    }
+
+    #[test]
+    fn test_output() {
+        let mut program = Program::new(0, 0, 0);
+        program.add_section(Section::new("output", 0x2000, 8, false, true, false));
+        assert!(program.declare_output("missing").is_err());
+        assert!(program.get_output().is_empty());
+
+        program.declare_output("output").unwrap();
+        program.step = 5;
+        program.write_mem(0x2004, 0x1234).unwrap();
+        program.registers.set(REGISTER_A0 as u32, 7, 5);
+
+        let expected = vec![
+            TraceRead::new(0x2000, 0, LAST_STEP_INIT),
+            TraceRead::new(0x2004, 0x1234, 5),
+        ];
+        assert_eq!(program.get_output(), expected);
+        assert_eq!(program.halt_result(), ExecutionResult::Halt(7, 5, expected));
+    }
+
     #[test]
     fn test_low_section() {
         let mut program = Program::new(0, 0, 0);
@@ -1080,6 +1941,33 @@ fn test_is_valid_mem() {
     assert!(!program.is_valid_mem(MemoryAccessType::Memory, 1000, true));
 }
 
+#[test]
+fn test_paged_section() {
+    let mut program = Program::new(0, 0, 0);
+    program.add_section(Section::new("stack", 0x1000, 0x80_0000, false, true, false));
+    let last = 0x1000 + 0x80_0000 - 4;
+
+    // untouched words
+    assert_eq!(program.read_mem(last, false).unwrap(), 0);
+    assert_eq!(program.get_last_step(last), LAST_STEP_INIT);
+
+    program.step = 5;
+    program.write_mem(last, 0x1234_5678).unwrap();
+    assert_eq!(program.read_mem(last, false).unwrap(), 0x1234_5678);
+    assert_eq!(program.get_last_step(last), 5);
+    assert_eq!(program.get_last_step(last - 4), LAST_STEP_INIT);
+
+    let section = &program.sections[0];
+    assert!(section.data.is_empty());
+    assert_eq!(section.pages.as_ref().unwrap().len(), 1);
+    let idx = (last - 0x1000) as usize / 4;
+    assert_eq!(
+        section.words_written_after(4),
+        vec![(idx, 0x1234_5678u32.to_be(), 5)]
+    );
+    assert!(section.words_written_after(5).is_empty());
+}
+
 #[test]
 fn test_vec_u8_to_vec_u32() {
     let input = vec![0x01, 0x02, 0x03, 0x04, 0x05];
@@ -1110,7 +1998,7 @@ fn test_serialize_deserialize() {
     original.serialize_to_file(path);
 
     // Deserialize
-    let deserialized = Program::deserialize_from_file(path, 0).unwrap();
+    let deserialized = Program::deserialize_from_file(path, 0, None).unwrap();
 
     // Compare
     assert_eq!(original.pc.get_address(), deserialized.pc.get_address());
@@ -1,4 +1,7 @@
-use bitvmx_cpu_definitions::trace::{hash_to_string, TraceRWStep};
+use bitvmx_cpu_definitions::{
+    memory::SectionDefinition,
+    trace::{hash_to_string, TraceRWStep},
+};
 use config::Config;
 use serde::Deserialize;
 use std::cmp::min;
@@ -9,13 +12,18 @@ use tracing::info;
 use crate::{
     decision::nary_search::NArySearchDefinition,
     executor::{
-        fetcher::{execute_program, FullTrace},
+        fetcher::{execute_program_with_options, ExecutionOptions, FullTrace},
+        hash_index::HashIndex,
+        observer::ExecutionObserver,
         utils::FailConfiguration,
     },
     EmulatorError, ExecutionResult,
 };
 
-use super::program::{load_elf, Program, CHECKPOINT_SIZE};
+use super::{
+    checkpoint::{find_checkpoint, read_checkpoint, CheckpointOrigin},
+    program::{load_elf_with_options, ElfLoadMode, MemoryLayout, Program, CHECKPOINT_SIZE},
+};
 
 #[derive(Error, Debug)]
 pub enum ProgramDefinitionError {
@@ -23,6 +31,8 @@ pub enum ProgramDefinitionError {
     BadConfig(String),
     #[error("while trying to build configuration")]
     ConfigFileError(#[from] config::ConfigError),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,12 +50,38 @@ pub struct ProgramDefinition {
     pub max_steps: u64,
     pub input_section_name: String,
     pub inputs: Vec<InputDefinition>,
+    // section used by the brk syscall, without it the program break can't grow
+    pub heap_section_name: Option<String>,
+    // section whose final contents are claimed as the result of the program
+    pub output_section_name: Option<String>,
+    // steps between checkpoints, CHECKPOINT_SIZE if not defined
+    #[serde(default)]
+    pub checkpoint_interval: Option<u64>,
+    // reduces the interval to the closest n-ary round interval so the checkpoints fall on the
+    // steps the rounds ask for
+    #[serde(default)]
+    pub align_checkpoints_to_rounds: bool,
+    // keeps the hashes of the steps multiple of the interval (and of the n-ary round intervals
+    // above it) while executing, to answer the rounds without executing again
+    #[serde(default)]
+    pub hash_index_interval: Option<u64>,
+    // memory layout of the program, the defaults of MemoryLayout if not defined
+    #[serde(default)]
+    pub registers_base_address: Option<u32>,
+    #[serde(default)]
+    pub stack_base_address: Option<u32>,
+    #[serde(default)]
+    pub stack_size: Option<u32>,
+    // builds the memory from the sections (default) or from the segments of the elf
+    #[serde(default)]
+    pub load_mode: ElfLoadMode,
 }
 
 impl ProgramDefinition {
     pub fn from_config(config: &str) -> Result<Self, ProgramDefinitionError> {
         let mut program: Self = parse_config(config)?;
         program.config_path = config.to_string();
+        program.check_input_sizes()?;
         Ok(program)
     }
 
@@ -53,17 +89,189 @@ impl ProgramDefinition {
         NArySearchDefinition::new(self.max_steps, self.nary_search)
     }
 
+    pub fn checkpoint_interval(&self) -> u64 {
+        let interval = self.checkpoint_interval.unwrap_or(CHECKPOINT_SIZE).max(1);
+        if !self.align_checkpoints_to_rounds {
+            return interval;
+        }
+
+        let nary_def = self.nary_def();
+        (1..=nary_def.full_rounds)
+            .map(|round| nary_def.max_steps / (nary_def.nary as u64).pow(round as u32))
+            .find(|round_interval| *round_interval <= interval)
+            .unwrap_or(interval)
+    }
+
+    fn hash_index_intervals(&self) -> Option<Vec<u64>> {
+        let interval = self.hash_index_interval?.max(1);
+        let nary_def = self.nary_def();
+        let mut intervals = vec![interval];
+        intervals.extend(
+            (1..=nary_def.full_rounds)
+                .map(|round| nary_def.max_steps / (nary_def.nary as u64).pow(round as u32))
+                .filter(|round_interval| {
+                    *round_interval >= interval && round_interval % interval != 0
+                }),
+        );
+        Some(intervals)
+    }
+
+    pub fn memory_layout(&self) -> MemoryLayout {
+        MemoryLayout::new(
+            self.registers_base_address,
+            self.stack_base_address,
+            self.stack_size,
+        )
+    }
+
+    // bytes of the input section taken by the inputs
+    pub fn input_size(&self) -> u64 {
+        self.inputs.iter().map(|input| input.size).sum()
+    }
+
+    // offset of every input from the start of the input section, in the order they are defined
+    pub fn input_offsets(&self) -> Vec<(u64, &InputDefinition)> {
+        let mut offset = 0;
+        self.inputs
+            .iter()
+            .map(|input| {
+                let input_offset = offset;
+                offset += input.size;
+                (input_offset, input)
+            })
+            .collect()
+    }
+
+    // owner of the input containing the byte at the offset
+    pub fn input_owner(&self, offset: u64) -> Option<&str> {
+        self.input_offsets()
+            .into_iter()
+            .find(|(start, input)| offset >= *start && offset < start + input.size)
+            .map(|(_, input)| input.owner.as_str())
+    }
+
+    // addresses of the inputs of the owner with the input section starting at the address
+    pub fn owner_input_ranges(&self, owner: &str, section_start: u32) -> SectionDefinition {
+        let ranges = self
+            .input_offsets()
+            .into_iter()
+            .filter(|(_, input)| input.owner == owner && input.size > 0)
+            .map(|(offset, input)| {
+                let start = section_start + offset as u32;
+                (start, start + input.size as u32 - 1)
+            })
+            .collect();
+        SectionDefinition { ranges }
+    }
+
+    // the sizes are multiples of 4 so every word of the input section belongs to a single owner
+    fn check_input_sizes(&self) -> Result<(), ProgramDefinitionError> {
+        match self.inputs.iter().find(|input| input.size % 4 != 0) {
+            Some(input) => Err(ProgramDefinitionError::BadConfig(format!(
+                "The input of {} has {} bytes, not a multiple of 4",
+                input.owner, input.size
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // checks that the whole input has the size of the inputs defined
+    pub fn check_input(&self, input: &[u8]) -> Result<(), ProgramDefinitionError> {
+        if input.len() as u64 != self.input_size() {
+            return Err(ProgramDefinitionError::InvalidInput(format!(
+                "The input has {} bytes but {} are defined",
+                input.len(),
+                self.input_size()
+            )));
+        }
+        Ok(())
+    }
+
+    // builds the input of the program placing the input given by every owner at its offset. An
+    // owner with more than one input gives them in the order they are defined
+    pub fn build_input(
+        &self,
+        owner_inputs: &[(String, Vec<u8>)],
+    ) -> Result<Vec<u8>, ProgramDefinitionError> {
+        for (owner, _) in owner_inputs {
+            let given = owner_inputs
+                .iter()
+                .filter(|(other, _)| other == owner)
+                .count();
+            let defined = self
+                .inputs
+                .iter()
+                .filter(|input| &input.owner == owner)
+                .count();
+            if given != defined {
+                return Err(ProgramDefinitionError::InvalidInput(format!(
+                    "{} gave {} inputs but owns {}",
+                    owner, given, defined
+                )));
+            }
+        }
+
+        let mut data = Vec::with_capacity(self.input_size() as usize);
+        for (idx, (offset, input)) in self.input_offsets().into_iter().enumerate() {
+            // inputs of the same owner before this one
+            let previous = self.inputs[..idx]
+                .iter()
+                .filter(|other| other.owner == input.owner)
+                .count();
+            let owner_input = match owner_inputs
+                .iter()
+                .filter(|(owner, _)| owner == &input.owner)
+                .nth(previous)
+            {
+                Some((_, owner_input)) => owner_input,
+                None => {
+                    return Err(ProgramDefinitionError::InvalidInput(format!(
+                        "Missing the input of {} at offset {}",
+                        input.owner, offset
+                    )))
+                }
+            };
+            if owner_input.len() as u64 != input.size {
+                return Err(ProgramDefinitionError::InvalidInput(format!(
+                    "The input of {} at offset {} has {} bytes but {} are defined",
+                    input.owner,
+                    offset,
+                    owner_input.len(),
+                    input.size
+                )));
+            }
+            data.extend_from_slice(owner_input);
+        }
+
+        Ok(data)
+    }
+
     pub fn load_program(&self) -> Result<Program, EmulatorError> {
         //extract the path from config path and concat with elf
         let elf_path = self.config_path.split("/").collect::<Vec<&str>>();
         let elf_file = format!("{}/{}", elf_path[..elf_path.len() - 1].join("/"), self.elf);
-        load_elf(&elf_file, false)
+        let mut program =
+            load_elf_with_options(&elf_file, false, &self.memory_layout(), self.load_mode)?;
+        if let Some(heap_section_name) = &self.heap_section_name {
+            program.declare_heap(heap_section_name)?;
+        }
+        if let Some(output_section_name) = &self.output_section_name {
+            program.declare_output(output_section_name)?;
+        }
+        // the input slots are committed with the declared size, the input has to match it
+        let input_size = self.input_size();
+        if input_size > 0 {
+            program.declare_input(&self.input_section_name, input_size as u32)?;
+        }
+        Ok(program)
     }
 
+    // the checkpoint has to be written by the execution of this program with the same input
     pub fn load_program_from_checkpoint(
         &self,
         checkpoint_path: &str,
         step: u64,
+        input_data: &[u8],
     ) -> Result<Program, EmulatorError> {
         let ndefs = self.nary_def();
         if step >= ndefs.max_steps {
@@ -73,27 +281,36 @@ impl ProgramDefinition {
             )));
         }
 
-        let checkpoint_step = (step / CHECKPOINT_SIZE) * CHECKPOINT_SIZE;
+        // a checkpoint has the state after its step, so it has to be before the step to trace it
+        let checkpoint_step = match step.checked_sub(1) {
+            Some(before) => find_checkpoint(checkpoint_path, before).unwrap_or(0),
+            None => 0,
+        };
 
         if checkpoint_step == 0 {
             self.load_program()
         } else {
-            Program::deserialize_from_file(checkpoint_path, checkpoint_step)
+            // the input section is part of the rom if it is read only
+            let mut program = self.load_program()?;
+            program.load_input(input_data.to_vec(), &self.input_section_name, false)?;
+            let origin = CheckpointOrigin::new(&program, input_data);
+            read_checkpoint(checkpoint_path, checkpoint_step, Some(&origin))
         }
     }
 
-    pub fn execute_helper(
+    fn run_program(
         &self,
         checkpoint_path: &str,
         input_data: Vec<u8>,
         steps: Option<Vec<u64>>,
         fail_config: Option<FailConfiguration>,
         save_non_checkpoint_steps: bool,
+        observers: Vec<&mut dyn ExecutionObserver>,
     ) -> Result<(ExecutionResult, FullTrace), EmulatorError> {
         let checkpoint_path_str = checkpoint_path.to_string();
         let (mut program, checkpoint_path, output_trace) = match &steps {
             Some(steps) => (
-                self.load_program_from_checkpoint(checkpoint_path, steps[0])?,
+                self.load_program_from_checkpoint(checkpoint_path, steps[0], &input_data)?,
                 None,
                 true,
             ),
@@ -101,24 +318,40 @@ impl ProgramDefinition {
             None => (self.load_program()?, Some(checkpoint_path_str), false),
         };
 
-        Ok(execute_program(
+        Ok(execute_program_with_options(
             &mut program,
+            ExecutionOptions {
+                input: input_data,
+                input_section_name: self.input_section_name.clone(),
+                checkpoint_path,
+                limit_step: Some(self.max_steps),
+                print_trace: output_trace,
+                trace_list: steps,
+                fail_config: fail_config.unwrap_or_default(),
+                save_non_checkpoint_steps,
+                checkpoint_interval: self.checkpoint_interval(),
+                observers,
+                ..Default::default()
+            },
+        ))
+    }
+
+    pub fn execute_helper(
+        &self,
+        checkpoint_path: &str,
+        input_data: Vec<u8>,
+        steps: Option<Vec<u64>>,
+        fail_config: Option<FailConfiguration>,
+        save_non_checkpoint_steps: bool,
+    ) -> Result<(ExecutionResult, FullTrace), EmulatorError> {
+        self.run_program(
+            checkpoint_path,
             input_data,
-            &self.input_section_name,
-            false,
-            &checkpoint_path,
-            Some(self.max_steps),
-            output_trace,
-            false,
-            false,
-            false,
-            false,
-            false,
             steps,
-            None,
-            fail_config.unwrap_or_default(),
+            fail_config,
             save_non_checkpoint_steps,
-        ))
+            Vec::new(),
+        )
     }
 
     pub fn get_execution_result(
@@ -128,14 +361,31 @@ impl ProgramDefinition {
         fail_config: Option<FailConfiguration>,
         save_non_checkpoint_steps: bool,
     ) -> Result<(ExecutionResult, u64, String), EmulatorError> {
-        let (result, trace) = self.execute_helper(
+        // the index is only valid for the hashes of the execution without failures
+        HashIndex::remove(checkpoint_path);
+        let mut hash_index = match (self.hash_index_intervals(), &fail_config) {
+            (Some(intervals), None) => Some(HashIndex::new(intervals, &input_data)),
+            _ => None,
+        };
+        let observers = hash_index
+            .iter_mut()
+            .map(|index| index as &mut dyn ExecutionObserver)
+            .collect();
+
+        let (result, trace) = self.run_program(
             checkpoint_path,
             input_data,
             None,
             fail_config,
             save_non_checkpoint_steps,
+            observers,
         )?;
 
+        if let Some(hash_index) = &hash_index {
+            hash_index.save(checkpoint_path)?;
+            info!("Hash index with {} steps saved", hash_index.len());
+        }
+
         if trace.len() == 0 {
             return Err(EmulatorError::CantObtainTrace);
         }
@@ -169,6 +419,16 @@ impl ProgramDefinition {
             round, steps
         );
         let required_hashes = steps.len();
+
+        if fail_config.is_none() {
+            let hashes = HashIndex::load(checkpoint_path, &input)
+                .and_then(|index| index.round_hashes(base, &steps));
+            if let Some(hashes) = hashes {
+                info!("Hashes for round: {} found in the hash index", round);
+                return Ok(hashes);
+            }
+        }
+
         steps.insert(0, base); //asks base step as it should be always obtainable
 
         let (_result, trace) = self.execute_helper(
@@ -246,3 +506,124 @@ fn parse_config<T: for<'a> Deserialize<'a>>(config: &str) -> Result<T, ProgramDe
         .try_deserialize::<T>()
         .map_err(ProgramDefinitionError::ConfigFileError)
 }
+
+#[cfg(test)]
+mod tests {
+    use config::FileFormat;
+
+    use super::*;
+
+    fn program_definition_with_inputs(inputs: &str) -> ProgramDefinition {
+        let config = format!(
+            "elf: test.elf
+nary_search: 8
+max_steps: 1024
+input_section_name: .input
+inputs:
+{}",
+            inputs
+        );
+        Config::builder()
+            .add_source(config::File::from_str(&config, FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    fn program_definition() -> ProgramDefinition {
+        program_definition_with_inputs(
+            "  - size: 8
+    owner: prover
+  - size: 4
+    owner: verifier
+",
+        )
+    }
+
+    #[test]
+    fn test_build_input() {
+        let program_def = program_definition();
+        assert_eq!(program_def.input_size(), 12);
+        assert_eq!(program_def.input_owner(4), Some("prover"));
+        assert_eq!(program_def.input_owner(8), Some("verifier"));
+        assert_eq!(program_def.input_owner(12), None);
+
+        // the order the owners give their inputs doesn't matter
+        let input = program_def
+            .build_input(&[
+                ("verifier".to_string(), vec![9, 10, 11, 12]),
+                ("prover".to_string(), vec![1, 2, 3, 4, 5, 6, 7, 8]),
+            ])
+            .unwrap();
+        assert_eq!(input, (1..=12).collect::<Vec<u8>>());
+
+        assert!(program_def
+            .build_input(&[("prover".to_string(), vec![1, 2, 3, 4, 5, 6, 7, 8])])
+            .is_err());
+        assert!(program_def
+            .build_input(&[
+                ("prover".to_string(), vec![1, 2, 3, 4]),
+                ("verifier".to_string(), vec![9, 10, 11, 12]),
+            ])
+            .is_err());
+        assert!(program_def
+            .build_input(&[
+                ("prover".to_string(), vec![1, 2, 3, 4, 5, 6, 7, 8]),
+                ("verifier".to_string(), vec![9, 10, 11, 12]),
+                ("other".to_string(), vec![]),
+            ])
+            .is_err());
+    }
+    #[test]
+    fn test_build_input_of_owner_with_many_inputs() {
+        let program_def = program_definition_with_inputs(
+            "  - size: 4
+    owner: prover
+  - size: 4
+    owner: verifier
+  - size: 8
+    owner: prover
+",
+        );
+
+        // the inputs of the prover are given in the order they are defined
+        let input = program_def
+            .build_input(&[
+                ("prover".to_string(), vec![1, 2, 3, 4]),
+                ("prover".to_string(), vec![9, 10, 11, 12, 13, 14, 15, 16]),
+                ("verifier".to_string(), vec![5, 6, 7, 8]),
+            ])
+            .unwrap();
+        assert_eq!(input, (1..=16).collect::<Vec<u8>>());
+        assert!(program_def.check_input(&input).is_ok());
+        assert!(program_def.check_input(&input[..12]).is_err());
+
+        assert!(program_def
+            .build_input(&[
+                ("prover".to_string(), vec![1, 2, 3, 4]),
+                ("verifier".to_string(), vec![5, 6, 7, 8]),
+            ])
+            .is_err());
+
+        assert_eq!(
+            program_def.owner_input_ranges("prover", 0x1000).ranges,
+            vec![(0x1000, 0x1003), (0x1008, 0x100f)]
+        );
+        assert_eq!(
+            program_def.owner_input_ranges("verifier", 0x1000).ranges,
+            vec![(0x1004, 0x1007)]
+        );
+    }
+
+    #[test]
+    fn test_unaligned_input_size() {
+        let program_def = program_definition_with_inputs(
+            "  - size: 6
+    owner: prover
+",
+        );
+        assert!(program_def.check_input_sizes().is_err());
+        assert!(program_definition().check_input_sizes().is_ok());
+    }
+}
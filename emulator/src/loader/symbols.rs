@@ -0,0 +1,267 @@
+// Symbol table and DWARF line information of the elf
+//
+// Both are optional: a stripped elf or one compiled without debug info just produces less
+// detailed locations.
+
+use std::collections::HashMap;
+
+use elf::{
+    abi::{STT_FUNC, STT_OBJECT},
+    endian::LittleEndian,
+    ElfBytes,
+};
+use gimli::EndianSlice;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LineRow {
+    pub address: u32,
+    // index in the file table, None marks the end of a sequence of rows
+    pub file: Option<u32>,
+    pub line: u32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Symbolizer {
+    // sorted by address
+    symbols: Vec<Symbol>,
+    files: Vec<String>,
+    // sorted by address
+    lines: Vec<LineRow>,
+}
+
+impl Symbolizer {
+    pub fn from_elf(file: &ElfBytes<LittleEndian>) -> Self {
+        let (files, lines) = match load_lines(file) {
+            Ok(lines) => lines,
+            Err(err) => {
+                warn!("Can't read the line information of the elf: {}", err);
+                (Vec::new(), Vec::new())
+            }
+        };
+
+        Self::new(load_symbols(file), files, lines)
+    }
+
+    pub fn new(mut symbols: Vec<Symbol>, files: Vec<String>, mut lines: Vec<LineRow>) -> Self {
+        // for aliases the symbol with the largest size is kept
+        symbols.sort_by_key(|symbol| (symbol.address, std::cmp::Reverse(symbol.size)));
+        symbols.dedup_by_key(|symbol| symbol.address);
+        // the end of a sequence goes before a sequence starting at the same address
+        lines.sort_by_key(|row| (row.address, row.file.is_some()));
+        Self {
+            symbols,
+            files,
+            lines,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty() && self.lines.is_empty()
+    }
+
+    // symbol containing the address and the offset of the address inside it
+    pub fn symbol(&self, address: u32) -> Option<(&str, u32)> {
+        let idx = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        let symbol = &self.symbols[idx.checked_sub(1)?];
+        let offset = address - symbol.address;
+        // symbols without size (usually labels of assembly code) extend to the next one
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+        Some((&symbol.name, offset))
+    }
+
+    pub fn line(&self, address: u32) -> Option<(&str, u32)> {
+        let idx = self.lines.partition_point(|row| row.address <= address);
+        let row = &self.lines[idx.checked_sub(1)?];
+        let file = self.files.get(row.file? as usize)?;
+        Some((file, row.line))
+    }
+
+    // function+offset (file:line)
+    pub fn symbolize(&self, address: u32) -> Option<String> {
+        let symbol = self
+            .symbol(address)
+            .map(|(name, offset)| format!("{}+0x{:x}", name, offset));
+        let line = self
+            .line(address)
+            .map(|(file, line)| format!("({}:{})", file, line));
+
+        match (symbol, line) {
+            (Some(symbol), Some(line)) => Some(format!("{} {}", symbol, line)),
+            (Some(location), None) | (None, Some(location)) => Some(location),
+            (None, None) => None,
+        }
+    }
+
+    // location to append to a line of output, empty when unknown
+    pub fn annotate(&self, address: u32) -> String {
+        match self.symbolize(address) {
+            Some(location) => format!(" <{}>", location),
+            None => String::new(),
+        }
+    }
+}
+
+fn load_symbols(file: &ElfBytes<LittleEndian>) -> Vec<Symbol> {
+    let (table, strings) = match file.symbol_table() {
+        Ok(Some(table)) => table,
+        _ => return Vec::new(),
+    };
+
+    table
+        .iter()
+        .filter(|symbol| symbol.st_symtype() == STT_FUNC || symbol.st_symtype() == STT_OBJECT)
+        .filter_map(|symbol| {
+            let name = strings.get(symbol.st_name as usize).ok()?;
+            if name.is_empty() {
+                return None;
+            }
+            Some(Symbol {
+                name: name.to_string(),
+                address: u32::try_from(symbol.st_value).ok()?,
+                size: u32::try_from(symbol.st_size).ok()?,
+            })
+        })
+        .collect()
+}
+
+fn load_lines<'data>(
+    file: &ElfBytes<'data, LittleEndian>,
+) -> Result<(Vec<String>, Vec<LineRow>), gimli::Error> {
+    // missing (or compressed) debug sections are loaded as empty
+    let dwarf = gimli::Dwarf::load(
+        |id| -> Result<EndianSlice<'data, gimli::LittleEndian>, gimli::Error> {
+            let data = match file.section_header_by_name(id.name()) {
+                Ok(Some(header)) => match file.section_data(&header) {
+                    Ok((data, None)) => data,
+                    _ => &[],
+                },
+                _ => &[],
+            };
+            Ok(EndianSlice::new(data, gimli::LittleEndian))
+        },
+    )?;
+
+    let mut files = Vec::new();
+    let mut file_indexes = HashMap::new();
+    let mut lines = Vec::new();
+
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            let address = row.address() as u32;
+            if row.end_sequence() {
+                lines.push(LineRow {
+                    address,
+                    file: None,
+                    line: 0,
+                });
+                continue;
+            }
+
+            let name = match row.file(header) {
+                Some(entry) => dwarf
+                    .attr_string(&unit, entry.path_name())?
+                    .to_string_lossy()
+                    .into_owned(),
+                None => continue,
+            };
+            let file = *file_indexes.entry(name.clone()).or_insert_with(|| {
+                files.push(name);
+                files.len() as u32 - 1
+            });
+
+            lines.push(LineRow {
+                address,
+                file: Some(file),
+                line: row.line().map(|line| line.get() as u32).unwrap_or(0),
+            });
+        }
+    }
+
+    Ok((files, lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_symbolizer() -> Symbolizer {
+        let symbols = vec![
+            Symbol {
+                name: "main".to_string(),
+                address: 0x1010,
+                size: 0x20,
+            },
+            Symbol {
+                name: "_start".to_string(),
+                address: 0x1000,
+                size: 0,
+            },
+            Symbol {
+                name: "buffer".to_string(),
+                address: 0x2000,
+                size: 0x10,
+            },
+        ];
+        let lines = vec![
+            LineRow {
+                address: 0x1010,
+                file: Some(0),
+                line: 3,
+            },
+            LineRow {
+                address: 0x1018,
+                file: Some(0),
+                line: 5,
+            },
+            LineRow {
+                address: 0x1030,
+                file: None,
+                line: 0,
+            },
+        ];
+        Symbolizer::new(symbols, vec!["main.c".to_string()], lines)
+    }
+
+    #[test]
+    fn test_symbolize() {
+        let symbolizer = get_symbolizer();
+        assert_eq!(symbolizer.symbolize(0x0fff), None);
+        assert_eq!(symbolizer.symbolize(0x1008), Some("_start+0x8".to_string()));
+        assert_eq!(
+            symbolizer.symbolize(0x1010),
+            Some("main+0x0 (main.c:3)".to_string())
+        );
+        assert_eq!(
+            symbolizer.symbolize(0x101c),
+            Some("main+0xc (main.c:5)".to_string())
+        );
+        // out of the function and the line sequence
+        assert_eq!(symbolizer.symbolize(0x1030), None);
+        assert_eq!(symbolizer.symbolize(0x200c), Some("buffer+0xc".to_string()));
+        assert_eq!(symbolizer.symbolize(0x2010), None);
+
+        assert_eq!(symbolizer.annotate(0x1010), " <main+0x0 (main.c:3)>");
+        assert_eq!(symbolizer.annotate(0x2010), "");
+    }
+}
@@ -1,8 +1,11 @@
 use bitcoin_script_riscv::riscv::instruction_mapping::create_verification_script_mapping;
-use bitvmx_cpu_definitions::{challenge::EmulatorResultType, trace::TraceRWStep};
+use bitvmx_cpu_definitions::{
+    challenge::EmulatorResultType,
+    trace::{hash_to_string, TraceRWStep},
+};
 use clap::{Parser, Subcommand};
 use emulator::{
-    constants::REGISTERS_BASE_ADDRESS,
+    debugger::{gdbserver::GdbServer, repl::run_repl, Debugger},
     decision::{
         challenge::{
             prover_execute, prover_final_trace, prover_get_hashes_and_step,
@@ -13,16 +16,32 @@ use emulator::{
         nary_search::NArySearchType,
     },
     executor::{
-        fetcher::execute_program,
+        fast::execute_fast,
+        fetcher::{execute_program_with_options, ExecutionOptions},
+        observer::ExecutionObserver,
+        parallel::execute_parallel,
+        profiler::Profiler,
+        trace_file::TraceFileReader,
+        trace_writer::{TraceFormat, TraceWriter},
         utils::{FailConfiguration, FailExecute, FailOpcode, FailReads, FailWrite},
     },
-    loader::program::{generate_rom_commitment, load_elf, Program},
+    loader::{
+        checkpoint::{CheckpointFormat, CheckpointOrigin},
+        program::{
+            generate_rom_commitment, load_elf, load_elf_with_options, ElfLoadMode, MemoryLayout,
+            Program, CHECKPOINT_SIZE,
+        },
+        program_definition::ProgramDefinition,
+    },
     EmulatorError, ExecutionResult,
 };
 use hex::FromHex;
-use std::io::Write;
+use std::{io::Write, time::Instant};
 use tracing::{error, info, Level};
 
+// functions shown in the summary of the profile
+const PROFILE_TOP_FUNCTIONS: usize = 20;
+
 /// BitVMX-CPU Emulator and Verifier
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -39,8 +58,17 @@ enum Commands {
         pdf: String,
 
         /// Input
-        #[arg(short, long, value_name = "INPUT (hex)")]
-        input: String,
+        #[arg(
+            short,
+            long,
+            value_name = "INPUT (hex)",
+            required_unless_present = "owner_input"
+        )]
+        input: Option<String>,
+
+        /// Input of an owner of the program definition, placed at its offset (repeat for every input, in the order they are defined)
+        #[arg(long, value_names = &["owner", "input (hex)"], num_args = 2, action = clap::ArgAction::Append, conflicts_with = "input")]
+        owner_input: Option<Vec<String>>,
 
         /// Checkpoint path
         #[arg(short, long, value_name = "CHECKPOINT_PROVER_PATH")]
@@ -69,8 +97,17 @@ enum Commands {
         pdf: String,
 
         /// Input
-        #[arg(short, long, value_name = "INPUT (hex)")]
-        input: String,
+        #[arg(
+            short,
+            long,
+            value_name = "INPUT (hex)",
+            required_unless_present = "owner_input"
+        )]
+        input: Option<String>,
+
+        /// Input of an owner of the program definition, placed at its offset (repeat for every input, in the order they are defined)
+        #[arg(long, value_names = &["owner", "input (hex)"], num_args = 2, action = clap::ArgAction::Append, conflicts_with = "input")]
+        owner_input: Option<Vec<String>>,
 
         /// Checkpoint path
         #[arg(short, long, value_name = "CHECKPOINT_VERIFIER_PATH")]
@@ -84,6 +121,10 @@ enum Commands {
         #[arg(short, long, value_name = "CLAIM_LAST_HASH")]
         claim_last_hash: String,
 
+        /// Claim output, the words of the output section with their last write (json)
+        #[arg(long, value_name = "CLAIM_OUTPUT (json)")]
+        claim_output: Option<String>,
+
         /// Force
         #[arg(short, long)]
         force: ForceCondition,
@@ -265,7 +306,11 @@ enum Commands {
     },
 
     ///Generate the instruction mapping
-    InstructionMapping,
+    InstructionMapping {
+        /// Base address of the registers (hex with 0x or decimal)
+        #[arg(long, value_name = "ADDRESS", value_parser = parse_address)]
+        registers_base: Option<u32>,
+    },
 
     ///Generate the ROM commitment
     GenerateRomCommitment {
@@ -278,10 +323,84 @@ enum Commands {
         sections: bool,
     },
 
+    ///Debug ELF file with gdb
+    Gdbserver {
+        /// ELF file to load
+        #[arg(short, long, value_name = "FILE")]
+        elf: String,
+
+        /// Port to listen for the gdb connection
+        #[arg(short, long, default_value = "1234")]
+        port: u16,
+
+        /// Input as hex
+        #[arg(short, long, value_name = "HEX")]
+        input: Option<String>,
+
+        /// Section name where the input will be loaded
+        #[arg(long, value_name = "SectionName")]
+        input_section: Option<String>,
+
+        /// Section name used as heap by the brk syscall
+        #[arg(long, value_name = "SectionName")]
+        heap_section: Option<String>,
+
+        /// Input as little endian
+        #[arg(long, default_value = "false")]
+        input_as_little: bool,
+
+        /// Print program stdout
+        #[arg(long)]
+        stdout: bool,
+
+        /// Show sections
+        #[arg(long)]
+        sections: bool,
+    },
+
+    ///Debug ELF file or checkpoint step by step
+    Debug {
+        /// ELF file to load, with a checkpoint step the checkpoint is verified against it and the input
+        #[arg(short, long, value_name = "FILE")]
+        elf: Option<String>,
+
+        /// Checkpoint step to load
+        #[arg(short, long, value_name = "Step")]
+        step: Option<u64>,
+
+        /// Checkpoint path
+        #[arg(short, long)]
+        checkpoint_path: Option<String>,
+
+        /// Input as hex
+        #[arg(short, long, value_name = "HEX")]
+        input: Option<String>,
+
+        /// Section name where the input will be loaded
+        #[arg(long, value_name = "SectionName")]
+        input_section: Option<String>,
+
+        /// Section name used as heap by the brk syscall
+        #[arg(long, value_name = "SectionName")]
+        heap_section: Option<String>,
+
+        /// Input as little endian
+        #[arg(long, default_value = "false")]
+        input_as_little: bool,
+
+        /// Print program stdout
+        #[arg(long)]
+        stdout: bool,
+
+        /// Show sections
+        #[arg(long)]
+        sections: bool,
+    },
+
     ///Execute ELF file
     Execute {
         /// Outputs the trace
-        /// ELF file to load
+        /// ELF file to load, with a checkpoint step the checkpoint is verified against it and the input
         #[arg(short, long, value_name = "FILE")]
         elf: Option<String>,
 
@@ -301,7 +420,11 @@ enum Commands {
         #[arg(long, value_name = "SectionName")]
         input_section: Option<String>,
 
-        /// Input as little endina
+        /// Section name used as heap by the brk syscall
+        #[arg(long, value_name = "SectionName")]
+        heap_section: Option<String>,
+
+        /// Input as little endian
         #[arg(long, default_value = "false")]
         input_as_little: bool,
 
@@ -394,6 +517,73 @@ enum Commands {
         /// Should we save steps that are not checkpoints (like first, error and halt steps)
         #[arg(short, long, action = clap::ArgAction::Set, default_value_t = true)]
         save_non_checkpoint_steps: bool,
+
+        /// Profile the steps by function, writing the folded stacks to the file
+        #[arg(long, value_name = "FILE")]
+        profile: Option<String>,
+
+        /// Writes the trace to the file while executing instead of keeping it in memory
+        #[arg(long, value_name = "FILE")]
+        trace_file: Option<String>,
+
+        /// Format of the trace file
+        #[arg(long, value_enum, default_value = "csv")]
+        trace_format: TraceFormat,
+
+        /// Format of the checkpoints
+        #[arg(long, value_enum, default_value = "json")]
+        checkpoint_format: CheckpointFormat,
+
+        /// Steps between checkpoints
+        #[arg(long, default_value_t = CHECKPOINT_SIZE)]
+        checkpoint_interval: u64,
+
+        /// Fetches and decodes the instruction on every step instead of using the pre-decoded ones
+        #[arg(long)]
+        no_decode_cache: bool,
+
+        /// Only obtains the result, without hashing, tracing or writing checkpoints
+        #[arg(long)]
+        fast: bool,
+
+        /// Re-executes the segments between the checkpoints of the path with the number of threads
+        #[arg(long, value_name = "THREADS")]
+        parallel: Option<usize>,
+
+        /// Base address of the registers (hex with 0x or decimal)
+        #[arg(long, value_name = "ADDRESS", value_parser = parse_address)]
+        registers_base: Option<u32>,
+
+        /// Base address of the stack (hex with 0x or decimal)
+        #[arg(long, value_name = "ADDRESS", value_parser = parse_address)]
+        stack_base: Option<u32>,
+
+        /// Size of the stack (hex with 0x or decimal)
+        #[arg(long, value_name = "SIZE", value_parser = parse_address)]
+        stack_size: Option<u32>,
+
+        /// Builds the memory from the sections or from the loadable segments of the elf
+        #[arg(long, value_enum, default_value = "sections")]
+        load_mode: ElfLoadMode,
+    },
+
+    ///Read steps of a binary trace file
+    ReadTrace {
+        /// Trace file written with --trace-format binary
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+
+        /// Step to read
+        #[arg(short, long)]
+        step: Option<u64>,
+
+        /// First step of the range to read
+        #[arg(long)]
+        from: Option<u64>,
+
+        /// Last step of the range to read
+        #[arg(long)]
+        to: Option<u64>,
     },
 }
 
@@ -406,8 +596,10 @@ fn main() -> Result<(), EmulatorError> {
 
     let cli = Cli::parse();
     match &cli.command {
-        Some(Commands::InstructionMapping) => {
-            let mapping = create_verification_script_mapping(REGISTERS_BASE_ADDRESS);
+        Some(Commands::InstructionMapping { registers_base }) => {
+            let mapping = create_verification_script_mapping(
+                registers_base.unwrap_or(MemoryLayout::default().registers_base_address),
+            );
             for (key, (script, requires_witness)) in mapping {
                 info!(
                     "Key: {}, Script: {:?}, Size: {}, Witness: {}",
@@ -422,12 +614,86 @@ fn main() -> Result<(), EmulatorError> {
             let program = load_elf(elf, *sections)?;
             generate_rom_commitment(&program)?;
         }
+        Some(Commands::Gdbserver {
+            elf,
+            port,
+            input,
+            input_section,
+            heap_section,
+            input_as_little,
+            stdout,
+            sections,
+        }) => {
+            let input = parse_hex_input(input)?;
+            let mut program = load_elf(elf, *sections)?;
+            program.load_input(
+                input,
+                &input_section.clone().unwrap_or(".input".to_string()),
+                *input_as_little,
+            )?;
+            if let Some(heap_section) = heap_section {
+                program.declare_heap(heap_section)?;
+            }
+            GdbServer::new(Debugger::new(program, *stdout, None)).serve(*port)?;
+        }
+        Some(Commands::Debug {
+            elf,
+            step,
+            checkpoint_path,
+            input,
+            input_section,
+            heap_section,
+            input_as_little,
+            stdout,
+            sections,
+        }) => {
+            let input = parse_hex_input(input)?;
+            let program = match (elf, step) {
+                (Some(elf), None) => {
+                    let mut program = load_elf(elf, *sections)?;
+                    program.load_input(
+                        input,
+                        &input_section.clone().unwrap_or(".input".to_string()),
+                        *input_as_little,
+                    )?;
+                    if let Some(heap_section) = heap_section {
+                        program.declare_heap(heap_section)?;
+                    }
+                    program
+                }
+                (elf, Some(step)) => {
+                    let path = checkpoint_path
+                        .as_ref()
+                        .expect("Checkpoint path is expected");
+                    let origin = match elf {
+                        Some(elf) => {
+                            let program = load_elf(elf, *sections)?;
+                            Some(CheckpointOrigin::new(&program, &input))
+                        }
+                        None => None,
+                    };
+                    Program::deserialize_from_file(path, *step, origin.as_ref())?
+                }
+                _ => {
+                    error!("To debug chose an elf file or a checkpoint step");
+                    return Err(EmulatorError::InvalidParameters);
+                }
+            };
+
+            let mut debugger = Debugger::new(program, *stdout, checkpoint_path.clone());
+            run_repl(
+                &mut debugger,
+                std::io::stdin().lock(),
+                &mut std::io::stdout(),
+            )?;
+        }
         Some(Commands::Execute {
             elf,
             step,
             limit,
             input,
             input_section,
+            heap_section,
             input_as_little,
             no_hash,
             trace,
@@ -451,34 +717,52 @@ fn main() -> Result<(), EmulatorError> {
             dump_mem,
             fail_pc,
             save_non_checkpoint_steps,
+            profile,
+            trace_file,
+            trace_format,
+            checkpoint_format,
+            checkpoint_interval,
+            no_decode_cache,
+            fast,
+            parallel,
+            registers_base,
+            stack_base,
+            stack_size,
+            load_mode,
         }) => {
             if elf.is_none() && step.is_none() {
                 error!("To execute an elf file or a checkpoint step is required");
                 return Err(EmulatorError::InvalidParameters);
             }
-            if elf.is_some() && step.is_some() {
-                error!("To execute chose an elf file or a checkpoint not both");
-                return Err(EmulatorError::InvalidParameters);
-            }
 
-            let (mut program, input) = match elf {
-                Some(elf) => {
-                    let input = input
-                        .clone()
-                        .map(|i| Vec::from_hex(i).unwrap())
-                        .unwrap_or(Vec::new());
-                    let program = load_elf(elf, *sections)?;
+            let input = parse_hex_input(input)?;
+            let layout = MemoryLayout::new(*registers_base, *stack_base, *stack_size);
+            let (mut program, input) = match (elf, step) {
+                (Some(elf), None) => {
+                    let mut program = load_elf_with_options(elf, *sections, &layout, *load_mode)?;
+                    if let Some(heap_section) = heap_section {
+                        program.declare_heap(heap_section)?;
+                    }
                     if *debug {
                         info!("Execute program {} with input: {:?}", elf, input);
                     }
                     (program, input)
                 }
-                None => {
+                (elf, step) => {
                     let step = step.expect("Step is expected");
                     let path = checkpoint_path
                         .as_ref()
                         .expect("Checkpoint path is expected");
-                    let program = Program::deserialize_from_file(path, step)?;
+                    // the checkpoint has to be written executing the elf with the input
+                    let origin = match elf {
+                        Some(elf) => {
+                            let program =
+                                load_elf_with_options(elf, *sections, &layout, *load_mode)?;
+                            Some(CheckpointOrigin::new(&program, &input))
+                        }
+                        None => None,
+                    };
+                    let program = Program::deserialize_from_file(path, step, origin.as_ref())?;
                     if *debug {
                         info!("Execute from checkpoint: {} up to: {:?}", step, limit);
                     }
@@ -486,6 +770,55 @@ fn main() -> Result<(), EmulatorError> {
                 }
             };
 
+            if *fast {
+                let result = execute_fast(
+                    &mut program,
+                    input,
+                    &input_section.clone().unwrap_or(".input".to_string()),
+                    *input_as_little,
+                    *limit,
+                );
+                info!("Execution result: {:?}", result);
+                return Ok(());
+            }
+
+            if let Some(threads) = parallel {
+                let path = match checkpoint_path {
+                    Some(path) => path,
+                    None => {
+                        error!("To execute in parallel the checkpoint path is required");
+                        return Err(EmulatorError::InvalidParameters);
+                    }
+                };
+                if elf.is_some() {
+                    program.load_input(
+                        input,
+                        &input_section.clone().unwrap_or(".input".to_string()),
+                        *input_as_little,
+                    )?;
+                }
+
+                let execution = execute_parallel(program, path, *threads, *limit)?;
+                for segment in &execution.segments {
+                    info!(
+                        "Segment {} to {}: {:?}",
+                        segment.start_step, segment.end_step, segment.result
+                    );
+                }
+                for step in &execution.mismatches {
+                    error!("The checkpoint {} doesn't match the previous segment", step);
+                }
+                info!("Execution result: {:?}", execution.result);
+                info!("Last hash: {}", hash_to_string(&execution.hash));
+                if !execution.mismatches.is_empty() {
+                    return Err(EmulatorError::InvalidCheckpoint(format!(
+                        "checkpoints {:?} don't match the previous segments",
+                        execution.mismatches
+                    )));
+                }
+                return Ok(());
+            }
+
             let numbers = match list {
                 Some(list) => {
                     let numbers: Result<Vec<u64>, _> =
@@ -526,37 +859,94 @@ fn main() -> Result<(), EmulatorError> {
                 fail_selection_bits: None,
                 fail_prover_challenge_step: false,
             };
-            let result = execute_program(
-                &mut program,
+            let mut profiler = Profiler::new(program.pc.get_address());
+            let mut observers: Vec<&mut dyn ExecutionObserver> = Vec::new();
+            if profile.is_some() {
+                observers.push(&mut profiler);
+            }
+            let mut trace_writer = match trace_file {
+                Some(path) => Some(TraceWriter::create(path, *trace_format)?),
+                None => None,
+            };
+            let options = ExecutionOptions {
                 input,
-                &input_section.clone().unwrap_or(".input".to_string()),
-                *input_as_little,
-                &checkpoint_path,
-                *limit,
-                *trace,
-                *verify,
-                !*no_mapping,
-                *stdout,
-                debugvar,
-                *no_hash,
-                numbers,
-                *dump_mem,
+                input_section_name: input_section.clone().unwrap_or(".input".to_string()),
+                little_endian: *input_as_little,
+                checkpoint_path: checkpoint_path.clone(),
+                limit_step: *limit,
+                print_trace: *trace || trace_file.is_some(),
+                verify_on_chain: *verify,
+                use_instruction_mapping: !*no_mapping,
+                print_program_stdout: *stdout,
+                debug: debugvar,
+                no_hash: *no_hash,
+                trace_list: numbers,
+                mem_dump: *dump_mem,
                 fail_config,
-                *save_non_checkpoint_steps,
-            )
-            .0;
+                save_non_checkpoint_steps: *save_non_checkpoint_steps,
+                observers,
+                trace_writer: trace_writer.as_mut(),
+                checkpoint_format: *checkpoint_format,
+                checkpoint_interval: *checkpoint_interval,
+                no_decode_cache: *no_decode_cache,
+            };
+            let start = Instant::now();
+            let result = execute_program_with_options(&mut program, options).0;
             info!("Execution result: {:?}", result);
+            info!("{} steps executed in {:?}", program.step, start.elapsed());
+
+            if let (Some(path), Some(trace_writer)) = (trace_file, trace_writer) {
+                let steps = trace_writer.finish()?;
+                info!("{} steps written to: {}", steps, path);
+            }
+
+            if let Some(path) = profile {
+                profiler.save_folded_stacks(path, &program.symbolizer)?;
+                info!("Folded stacks saved to: {}", path);
+                for function in profiler
+                    .function_steps(&program.symbolizer)
+                    .iter()
+                    .take(PROFILE_TOP_FUNCTIONS)
+                {
+                    info!(
+                        "Function: {} self steps: {} total steps: {}",
+                        function.name, function.self_steps, function.total_steps
+                    );
+                }
+                for (key, steps) in profiler.instruction_histogram() {
+                    info!("Instruction: {} steps: {}", key, steps);
+                }
+            }
+        }
+        Some(Commands::ReadTrace {
+            file,
+            step,
+            from,
+            to,
+        }) => {
+            let mut reader = TraceFileReader::open(file)?;
+            let steps = match step {
+                Some(step) => reader.get_step(*step)?.into_iter().collect(),
+                None => reader.get_range(from.unwrap_or(0), to.unwrap_or(u64::MAX))?,
+            };
+            if steps.is_empty() {
+                error!("No steps found in {} ({} steps)", file, reader.steps());
+            }
+            for (trace, hash) in steps {
+                info!("{};{}", trace.to_csv(), hash_to_string(&hash));
+            }
         }
         Some(Commands::ProverExecute {
             pdf,
             input,
+            owner_input,
             checkpoint_prover_path,
             force,
             fail_config_prover,
             command_file,
             save_non_checkpoint_steps,
         }) => {
-            let input_bytes = hex::decode(input).expect("Invalid hex string");
+            let input_bytes = read_input(pdf, input, owner_input)?;
             let result = prover_execute(
                 pdf,
                 input_bytes.clone(),
@@ -567,15 +957,16 @@ fn main() -> Result<(), EmulatorError> {
             )?;
             info!("Prover execute: {:?}", result);
 
-            let halt = match result.0 {
-                ExecutionResult::Halt(result, step) => Some((result, step)),
-                _ => None,
+            let (halt, output) = match result.0 {
+                ExecutionResult::Halt(result, step, output) => (Some((result, step)), output),
+                _ => (None, vec![]),
             };
 
             let result = EmulatorResultType::ProverExecuteResult {
                 last_step: result.1,
                 last_hash: result.2,
                 halt: halt,
+                output,
             }
             .to_value()?;
 
@@ -586,21 +977,30 @@ fn main() -> Result<(), EmulatorError> {
         Some(Commands::VerifierCheckExecution {
             pdf,
             input,
+            owner_input,
             checkpoint_verifier_path,
             claim_last_step,
             claim_last_hash,
+            claim_output,
             force,
             fail_config_verifier,
             command_file,
             save_non_checkpoint_steps,
         }) => {
-            let input_bytes = hex::decode(input).expect("Invalid hex string");
+            let input_bytes = read_input(pdf, input, owner_input)?;
+            let claim_output = match claim_output {
+                Some(claim_output) => {
+                    serde_json::from_str(claim_output).expect("Invalid claim output")
+                }
+                None => vec![],
+            };
             let result = verifier_check_execution(
                 pdf,
                 input_bytes.clone(),
                 checkpoint_verifier_path,
                 *claim_last_step,
                 claim_last_hash,
+                claim_output,
                 force.clone(),
                 fail_config_verifier.clone(),
                 *save_non_checkpoint_steps,
@@ -780,6 +1180,53 @@ fn main() -> Result<(), EmulatorError> {
     Ok(())
 }
 
+// the input given as hex or built from the input of every owner
+fn read_input(
+    pdf: &str,
+    input: &Option<String>,
+    owner_input: &Option<Vec<String>>,
+) -> Result<Vec<u8>, EmulatorError> {
+    match owner_input {
+        Some(owner_input) => {
+            let owner_inputs = owner_input
+                .chunks(2)
+                .map(|pair| parse_hex(&pair[1]).map(|data| (pair[0].clone(), data)))
+                .collect::<Result<Vec<(String, Vec<u8>)>, EmulatorError>>()?;
+            Ok(ProgramDefinition::from_config(pdf)?.build_input(&owner_inputs)?)
+        }
+        None => {
+            let input = parse_hex(input.as_ref().expect("Input is expected"))?;
+            ProgramDefinition::from_config(pdf)?.check_input(&input)?;
+            Ok(input)
+        }
+    }
+}
+
+// decodes an input given in hex, reporting the invalid ones instead of panicking
+fn parse_hex(value: &str) -> Result<Vec<u8>, EmulatorError> {
+    Vec::from_hex(value).map_err(|err| {
+        error!("Invalid hex input {}: {}", value, err);
+        EmulatorError::InvalidParameters
+    })
+}
+
+// the optional input of the subcommands, empty when it's not given
+fn parse_hex_input(input: &Option<String>) -> Result<Vec<u8>, EmulatorError> {
+    match input {
+        Some(input) => parse_hex(input),
+        None => Ok(Vec::new()),
+    }
+}
+
+// parses an address or a size given in hex (with 0x) or in decimal
+fn parse_address(value: &str) -> Result<u32, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(&hex.replace('_', ""), 16),
+        None => value.parse::<u32>(),
+    }
+    .map_err(|err| format!("Invalid value {}: {}", value, err))
+}
+
 fn create_or_open_file(file_path: &str) -> std::fs::File {
     std::fs::OpenOptions::new()
         .create(true) // create if it doesn't exist
@@ -0,0 +1,40 @@
+use crate::{
+    constants::{REGISTERS_BASE_ADDRESS, STACK_BASE_ADDRESS},
+    loader::program::{Program, Section},
+};
+
+pub const CODE_BASE_ADDRESS: u32 = 0x1000;
+pub const DATA_BASE_ADDRESS: u32 = 0x2000;
+
+// program that starts executing the code at CODE_BASE_ADDRESS
+pub fn program_with_code(code: &[u32]) -> Program {
+    let mut program = Program::new(
+        CODE_BASE_ADDRESS,
+        REGISTERS_BASE_ADDRESS,
+        STACK_BASE_ADDRESS,
+    );
+    program.add_section(Section::new_with_data(
+        "code",
+        code.iter().map(|opcode| opcode.to_be()).collect(),
+        CODE_BASE_ADDRESS,
+        code.len() as u32 * 4,
+        true,
+        false,
+        true,
+    ));
+    program
+}
+
+// same as program_with_code, with four writable words at DATA_BASE_ADDRESS
+pub fn program_with_data(code: &[u32]) -> Program {
+    let mut program = program_with_code(code);
+    program.add_section(Section::new(
+        "data",
+        DATA_BASE_ADDRESS,
+        16,
+        false,
+        true,
+        false,
+    ));
+    program
+}
@@ -19,7 +19,7 @@ fn audit_tests() {
                 let (result, _) =
                     verify_file(&format!("{}", path), fname.starts_with("audit_14_")).unwrap();
                 match result {
-                    ExecutionResult::Halt(0, _) => {
+                    ExecutionResult::Halt(0, _, _) => {
                         info!("File {} executed successfully", path);
                         count += 1;
                     }
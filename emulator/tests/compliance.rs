@@ -19,7 +19,7 @@ fn list_files() {
 
                 let (result, _) = verify_file(&format!("{}", path), false).unwrap();
                 match result {
-                    ExecutionResult::Halt(exit_code, _) => {
+                    ExecutionResult::Halt(exit_code, _, _) => {
                         assert!(exit_code == 0, "Error executing file {}", path);
                         info!("File {} executed successfully", path);
                         count += 1;
@@ -0,0 +1,120 @@
+use bitvmx_cpu_definitions::trace::TraceRead;
+use emulator::{
+    executor::{fast::execute_fast, fetcher::execute_program, utils::FailConfiguration},
+    loader::program::{load_elf, Program, AUX_REGISTER_2},
+    ExecutionResult,
+};
+
+fn execute_full(fname: &str) -> (ExecutionResult, Program) {
+    let mut program = load_elf(fname, false).unwrap();
+    let (result, _) = execute_program(
+        &mut program,
+        Vec::new(),
+        ".bss",
+        false,
+        &None,
+        None,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        None,
+        None,
+        FailConfiguration::default(),
+        false,
+    );
+    (result, program)
+}
+
+fn execute_lean(fname: &str) -> (ExecutionResult, Program) {
+    let mut program = load_elf(fname, false).unwrap();
+    let result = execute_fast(&mut program, Vec::new(), ".bss", false, None);
+    (result, program)
+}
+
+// the fast execution doesn't keep the last steps, so only the values of the output are compared
+fn output_values(result: ExecutionResult) -> ExecutionResult {
+    match result {
+        ExecutionResult::Halt(exit_code, step, output) => ExecutionResult::Halt(
+            exit_code,
+            step,
+            output
+                .into_iter()
+                .map(|read| TraceRead::new(read.address, read.value, 0))
+                .collect(),
+        ),
+        result => result,
+    }
+}
+
+fn assert_same_execution(fname: &str) {
+    let (full_result, full) = execute_full(fname);
+    let (fast_result, fast) = execute_lean(fname);
+
+    assert_eq!(
+        output_values(full_result),
+        output_values(fast_result),
+        "Result of {}",
+        fname
+    );
+    assert_eq!(full.step, fast.step, "Steps of {}", fname);
+    assert_eq!(
+        (full.pc.get_address(), full.pc.get_micro()),
+        (fast.pc.get_address(), fast.pc.get_micro()),
+        "PC of {}",
+        fname
+    );
+    assert_eq!(full.halt, fast.halt, "Halt of {}", fname);
+    assert_eq!(
+        full.captured_output, fast.captured_output,
+        "Output of {}",
+        fname
+    );
+    for idx in 0..=AUX_REGISTER_2 {
+        assert_eq!(
+            full.registers.get(idx),
+            fast.registers.get(idx),
+            "Register {} of {}",
+            idx,
+            fname
+        );
+    }
+    for (full_section, fast_section) in full.sections.iter().zip(fast.sections.iter()) {
+        assert_eq!(
+            full_section.to_words(),
+            fast_section.to_words(),
+            "Section {} of {}",
+            full_section.name,
+            fname
+        );
+    }
+}
+
+fn elf_files(path: &str) -> Vec<String> {
+    std::fs::read_dir(path)
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .filter(|fname| fname.ends_with(".elf") && !fname.contains("fence_i"))
+        .collect()
+}
+
+#[test]
+fn fast_execution_matches_compliance() {
+    let files = elf_files("../docker-riscv32/compliance/build");
+    assert!(!files.is_empty());
+    for fname in files {
+        assert_same_execution(&fname);
+    }
+}
+
+#[test]
+fn fast_execution_matches_exceptions() {
+    let files = elf_files("../docker-riscv32/riscv32/build/exceptions");
+    assert!(!files.is_empty());
+    for fname in files {
+        assert_same_execution(&fname);
+    }
+}
@@ -0,0 +1,75 @@
+use bitcoin_script_riscv::riscv::bitmanip::BitmanipInstruction;
+use emulator::{
+    executor::{fetcher::*, utils::FailConfiguration, verifier::verify_script},
+    loader::program::{Program, Section},
+};
+use rstest::rstest;
+mod utils;
+use utils::common::get_new_program;
+
+const CODE_BASE_ADDRESS: u32 = 0x1000;
+const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000;
+
+#[rstest]
+#[case(0x20c5_2533, 0x1234_5678, 0x10, 0x2468_acf0 + 0x10)] // sh1add a0, a0, a2
+#[case(0x20c5_6533, 0x1234_5678, 0x10, 0x91a2_b3c0 + 0x10)] // sh3add a0, a0, a2
+#[case(0x40c5_7533, 0xff00_ff00, 0x0f0f_0f0f, 0xf000_f000)] // andn a0, a0, a2
+#[case(0x40c5_4533, 0xff00_ff00, 0x0f0f_0f0f, 0x0ff0_0ff0)] // xnor a0, a0, a2
+#[case(0x0ac5_4533, 0xffff_fff0, 0x10, 0xffff_fff0)] // min a0, a0, a2
+#[case(0x0ac5_7533, 0xffff_fff0, 0x10, 0xffff_fff0)] // maxu a0, a0, a2
+#[case(0x60c5_1533, 0x8000_0001, 0x24, 0x0000_0018)] // rol a0, a0, a2
+#[case(0x60c5_5533, 0x8000_0001, 0x01, 0xc000_0000)] // ror a0, a0, a2
+#[case(0x6075_5513, 0x0000_0080, 0, 0x0000_0001)] // rori a0, a0, 7
+#[case(0x6005_1513, 0x0000_ffff, 0, 16)] // clz a0, a0
+#[case(0x6015_1513, 0x0001_0000, 0, 16)] // ctz a0, a0
+#[case(0x6025_1513, 0xf0f0_0001, 0, 9)] // cpop a0, a0
+#[case(0x6045_1513, 0x0000_0080, 0, 0xffff_ff80)] // sext.b a0, a0
+#[case(0x0805_4533, 0xffff_8000, 0, 0x0000_8000)] // zext.h a0, a0
+#[case(0x2875_5513, 0x0100_2000, 0, 0xff00_ff00)] // orc.b a0, a0
+#[case(0x6985_5513, 0x1234_5678, 0, 0x7856_3412)] // rev8 a0, a0
+fn test_bitmanip_instructions(
+    #[case] opcode: u32,
+    #[case] value_1: u32,
+    #[case] value_2: u32,
+    #[case] expected: u32,
+) {
+    let mut program = get_new_program();
+    program.registers.set(10, value_1, 0);
+    program.registers.set(12, value_2, 0);
+
+    let instruction = BitmanipInstruction::decode(opcode).unwrap();
+    let _ = op_bitmanip(&instruction, &mut program);
+
+    assert_eq!(program.registers.get(10), expected);
+    assert_eq!(program.pc.get_address(), 4);
+}
+
+#[rstest]
+fn test_bitmanip_execution() {
+    // 0x1000: sh2add a0, a1, a2
+    // 0x1004: clz a3, a0
+    // 0x1008: rev8 a4, a0
+    let words: Vec<u32> = vec![0x20c5_c533, 0x6005_1693, 0x6985_5713];
+
+    let mut program = Program::new(CODE_BASE_ADDRESS, REGISTERS_BASE_ADDRESS, 0xE000_0000);
+    program.add_section(Section::new_with_data(
+        "code",
+        words.iter().map(|word| word.to_be()).collect(),
+        CODE_BASE_ADDRESS,
+        words.len() as u32 * 4,
+        true,
+        false,
+        true,
+    ));
+    program.registers.set(11, 0x0010_0000, 0);
+    program.registers.set(12, 0x0000_0123, 0);
+
+    let expected = [(10, 0x0040_0123), (13, 9), (14, 0x2301_4000)];
+
+    for (register, value) in expected {
+        let trace = execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+
+        assert_eq!(program.registers.get(register), value);
+        assert!(verify_script(&trace, REGISTERS_BASE_ADDRESS, &None).is_ok());
+    }
+}
@@ -0,0 +1,85 @@
+use emulator::{
+    executor::{fetcher::*, utils::FailConfiguration, verifier::verify_script},
+    loader::program::{Program, Section},
+    ExecutionResult,
+};
+use rstest::rstest;
+mod utils;
+use utils::common::{create_jtype_from, get_new_program};
+
+const CODE_BASE_ADDRESS: u32 = 0x1000;
+const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000;
+
+fn get_compressed_program() -> Program {
+    // 0x1000: c.li x10, 5
+    // 0x1002: addi x11, x10, 1 (spans two words)
+    // 0x1006: c.mv x12, x11
+    program_with_words(&[0x0593_4515, 0x862e_0015])
+}
+
+fn program_with_words(words: &[u32]) -> Program {
+    let mut program = Program::new(CODE_BASE_ADDRESS, REGISTERS_BASE_ADDRESS, 0xE000_0000);
+    program.add_section(Section::new_with_data(
+        "code",
+        words.iter().map(|word| word.to_be()).collect(),
+        CODE_BASE_ADDRESS,
+        words.len() as u32 * 4,
+        true,
+        false,
+        true,
+    ));
+    program
+}
+
+#[rstest]
+fn test_compressed_execution() {
+    let mut program = get_compressed_program();
+
+    let expected = [
+        (0x0593_4515, 0x1002, 10, 5),
+        (0x0015_0593, 0x1006, 11, 6),
+        (0x0000_862e, 0x1008, 12, 6),
+    ];
+
+    for (opcode, next_pc, register, value) in expected {
+        let trace = execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+
+        assert_eq!(trace.read_pc.opcode, opcode);
+        assert_eq!(trace.trace_step.write_pc.get_address(), next_pc);
+        assert_eq!(program.registers.get(register), value);
+        assert!(verify_script(&trace, REGISTERS_BASE_ADDRESS, &None).is_ok());
+    }
+}
+
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn test_reserved_compressed_instruction(#[case] decode_cache: bool) {
+    // 0x1000: c.lwsp x0, 0(x2) is reserved
+    let mut program = program_with_words(&[0x0001_4002]);
+    if decode_cache {
+        program.build_decode_cache();
+    }
+
+    let result = execute_step(&mut program, false, false, FailConfiguration::default());
+    assert!(matches!(
+        result,
+        Err(ExecutionResult::InstructionNotImplemented(0x0001_4002, _))
+    ));
+}
+
+#[rstest]
+fn test_compressed_jal_links_next_instruction() {
+    let imm = 1022;
+    let rd = 1;
+
+    let mut program = get_new_program();
+    program.instruction_size = 2;
+
+    let x = create_jtype_from(imm, rd as u8);
+
+    let _ = op_jal(&x, &mut program);
+
+    assert_eq!(program.pc.get_address(), imm);
+    assert_eq!(program.registers.get(rd), 2);
+}
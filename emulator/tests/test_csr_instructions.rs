@@ -0,0 +1,101 @@
+use bitvmx_cpu_definitions::{
+    memory::{MemoryAccessType, MemoryWitness},
+    trace::TraceRead,
+};
+use emulator::{
+    executor::{fetcher::*, utils::FailConfiguration, verifier::verify_script},
+    loader::program::{Program, Section},
+    ExecutionResult,
+};
+use rstest::rstest;
+
+const CODE_BASE_ADDRESS: u32 = 0x1000;
+const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000;
+
+fn get_csr_program(words: Vec<u32>) -> Program {
+    let mut program = Program::new(CODE_BASE_ADDRESS, REGISTERS_BASE_ADDRESS, 0xE000_0000);
+    program.add_section(Section::new_with_data(
+        "code",
+        words.iter().map(|word| word.to_be()).collect(),
+        CODE_BASE_ADDRESS,
+        words.len() as u32 * 4,
+        true,
+        false,
+        true,
+    ));
+    program
+}
+
+#[rstest]
+fn test_csr_counters() {
+    // rdcycle a0
+    // rdinstret a1
+    // rdtimeh a2
+    // rdtime zero
+    let mut program = get_csr_program(vec![0xc000_2573, 0xc020_25f3, 0xc810_2673, 0xc010_2073]);
+
+    let expected = [(10, 0), (11, 1), (12, 0)];
+
+    for (register, value) in expected {
+        let trace = execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+
+        // the counters are not read, the script computes them from the step
+        assert_eq!(trace.read_2.address, 0);
+        assert_eq!(trace.mem_witness.read_2(), MemoryAccessType::Unused);
+        assert_eq!(trace.trace_step.write_1.value, value);
+        assert_eq!(program.registers.get(register), value);
+        assert!(verify_script(&trace, REGISTERS_BASE_ADDRESS, &None).is_ok());
+    }
+
+    let trace = execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+    assert_eq!(trace.trace_step.write_pc.get_address(), 0x1010);
+    assert!(verify_script(&trace, REGISTERS_BASE_ADDRESS, &None).is_ok());
+}
+
+#[rstest]
+#[case(0xc000_2573, 0x1_0000_0005)] // rdcycle a0
+#[case(0xc800_2573, 0x1_0000_0005)] // rdcycleh a0
+#[case(0xc820_2573, 0x3_0000_0000)] // rdinstreth a0
+fn test_csr_dishonest_counter(#[case] opcode: u32, #[case] step: u64) {
+    let mut program = get_csr_program(vec![opcode]);
+    program.step = step;
+
+    let trace = execute_step(&mut program, false, false, FailConfiguration::default()).unwrap();
+    assert!(verify_script(&trace, REGISTERS_BASE_ADDRESS, &None).is_ok());
+
+    // a value that doesn't match the step
+    let mut dishonest = trace.clone();
+    dishonest.trace_step.write_1.value = trace.trace_step.write_1.value.wrapping_add(1);
+    assert!(verify_script(&dishonest, REGISTERS_BASE_ADDRESS, &None).is_err());
+
+    // the honest value verified at a different step
+    let mut dishonest = trace.clone();
+    dishonest.step_number = step + 0x1_0000_0001;
+    assert!(verify_script(&dishonest, REGISTERS_BASE_ADDRESS, &None).is_err());
+
+    // the value can't be justified by a read of a register
+    let mut dishonest = trace.clone();
+    dishonest.read_2 = TraceRead::new(0xF000_0088, trace.trace_step.write_1.value, 0);
+    dishonest.mem_witness = MemoryWitness::new(
+        MemoryAccessType::Unused,
+        MemoryAccessType::Register,
+        MemoryAccessType::Register,
+    );
+    assert!(verify_script(&dishonest, REGISTERS_BASE_ADDRESS, &None).is_err());
+}
+
+#[rstest]
+#[case(0xc005_9573)] // csrrw a0, cycle, a1
+#[case(0xc002_e573)] // csrrsi a0, cycle, 5
+#[case(0xc005_b573)] // csrrc a0, cycle, a1
+#[case(0x3000_2573)] // csrr a0, mstatus
+fn test_csr_invalid_access(#[case] opcode: u32) {
+    let mut program = get_csr_program(vec![opcode]);
+
+    let result = execute_step(&mut program, false, false, FailConfiguration::default());
+
+    assert_eq!(
+        result.unwrap_err(),
+        ExecutionResult::InvalidCsrAccess(opcode >> 20)
+    );
+}
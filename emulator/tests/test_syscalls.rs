@@ -0,0 +1,159 @@
+use bitvmx_cpu_definitions::syscall::*;
+use emulator::{
+    executor::{fetcher::*, utils::FailConfiguration, verifier::verify_script},
+    loader::program::{vec_u8_to_vec_u32, Program, Section},
+};
+use rstest::rstest;
+
+const CODE_BASE_ADDRESS: u32 = 0x1000;
+const REGISTERS_BASE_ADDRESS: u32 = 0xF000_0000;
+const DATA_BASE_ADDRESS: u32 = 0xA000_0000;
+const INPUT_BASE_ADDRESS: u32 = 0xB000_0000;
+const HEAP_BASE_ADDRESS: u32 = 0xC000_0000;
+const ECALL: u32 = 0x0000_0073;
+
+fn get_syscall_program(ecalls: u32, data: &[u8]) -> Program {
+    let mut program = Program::new(CODE_BASE_ADDRESS, REGISTERS_BASE_ADDRESS, 0xE000_0000);
+    program.add_section(Section::new_with_data(
+        "code",
+        vec![ECALL.to_be(); ecalls as usize],
+        CODE_BASE_ADDRESS,
+        ecalls * 4,
+        true,
+        false,
+        true,
+    ));
+    program.add_section(Section::new_with_data(
+        "data",
+        vec_u8_to_vec_u32(data, false),
+        DATA_BASE_ADDRESS,
+        data.len() as u32,
+        false,
+        true,
+        true,
+    ));
+    program.add_section(Section::new(
+        ".input",
+        INPUT_BASE_ADDRESS,
+        0x10,
+        false,
+        true,
+        false,
+    ));
+    program.add_section(Section::new(
+        "heap",
+        HEAP_BASE_ADDRESS,
+        0x100,
+        false,
+        true,
+        false,
+    ));
+    program
+}
+
+fn set_arguments(program: &mut Program, syscall: u32, arguments: &[u32]) {
+    program.registers.set(REGISTER_SYSCALL, syscall, 0);
+    for (i, argument) in arguments.iter().enumerate() {
+        program
+            .registers
+            .set(REGISTER_ARG_0 + i as u32, *argument, 0);
+    }
+}
+
+// executes the micro instructions of the ecall and verifies every step
+fn execute_ecall(program: &mut Program) -> u32 {
+    let address = program.pc.get_address();
+    let mut steps = 0;
+    while program.pc.get_address() == address && !program.halt {
+        let trace = execute_step(program, false, false, FailConfiguration::default()).unwrap();
+        assert!(verify_script(&trace, REGISTERS_BASE_ADDRESS, &None).is_ok());
+        steps += 1;
+    }
+    steps
+}
+
+#[rstest]
+#[case(DATA_BASE_ADDRESS + 1, 5, b"hel".to_vec())]
+#[case(DATA_BASE_ADDRESS + 4, 2, b"lo".to_vec())]
+#[case(DATA_BASE_ADDRESS + 3, 0, vec![])]
+fn test_write(#[case] buf: u32, #[case] len: u32, #[case] expected: Vec<u8>) {
+    let mut program = get_syscall_program(1, b"xhello!!");
+    set_arguments(&mut program, SYSCALL_WRITE, &[FD_STDOUT, buf, len]);
+
+    execute_ecall(&mut program);
+
+    assert_eq!(program.captured_output, expected);
+    assert_eq!(program.registers.get(REGISTER_ARG_0), expected.len() as u32);
+}
+
+#[rstest]
+fn test_read() {
+    let mut program = get_syscall_program(3, &[1, 2, 3, 4, 5, 6, 7, 8]);
+    program.load_input(b"ab".to_vec(), ".input", false).unwrap();
+
+    // reads one byte at a time until the end of the input
+    for (buf, expected) in [
+        (DATA_BASE_ADDRESS + 2, 1),
+        (DATA_BASE_ADDRESS + 7, 1),
+        (DATA_BASE_ADDRESS, 0),
+    ] {
+        set_arguments(&mut program, SYSCALL_READ, &[FD_STDIN, buf, 4]);
+        execute_ecall(&mut program);
+        assert_eq!(program.registers.get(REGISTER_ARG_0), expected);
+    }
+
+    assert_eq!(
+        program.read_mem(DATA_BASE_ADDRESS, false).unwrap(),
+        0x0461_0201
+    );
+    assert_eq!(
+        program.read_mem(DATA_BASE_ADDRESS + 4, false).unwrap(),
+        0x6207_0605
+    );
+}
+
+#[rstest]
+#[case(0, HEAP_BASE_ADDRESS)]
+#[case(HEAP_BASE_ADDRESS + 0x80, HEAP_BASE_ADDRESS + 0x80)]
+#[case(0xD000_0000, HEAP_BASE_ADDRESS + 0x100)]
+fn test_brk(#[case] requested: u32, #[case] expected: u32) {
+    let mut program = get_syscall_program(1, &[0; 4]);
+    program.declare_heap("heap").unwrap();
+    set_arguments(&mut program, SYSCALL_BRK, &[requested]);
+
+    assert_eq!(execute_ecall(&mut program), 3);
+    assert_eq!(program.registers.get(REGISTER_ARG_0), expected);
+}
+
+#[rstest]
+fn test_brk_without_heap() {
+    let mut program = get_syscall_program(1, &[0; 4]);
+    set_arguments(&mut program, SYSCALL_BRK, &[HEAP_BASE_ADDRESS]);
+
+    execute_ecall(&mut program);
+
+    assert_eq!(program.registers.get(REGISTER_ARG_0), 0);
+}
+
+#[rstest]
+#[case(57, 3, RETURN_ENOSYS)] // close
+#[case(SYSCALL_WRITE, FD_STDIN, RETURN_EBADF)]
+#[case(SYSCALL_READ, FD_STDOUT, RETURN_EBADF)]
+fn test_syscall_error(#[case] syscall: u32, #[case] arg_0: u32, #[case] expected: u32) {
+    let mut program = get_syscall_program(1, &[0; 4]);
+    set_arguments(&mut program, syscall, &[arg_0]);
+
+    assert_eq!(execute_ecall(&mut program), 1);
+    assert_eq!(program.registers.get(REGISTER_ARG_0), expected);
+}
+
+#[rstest]
+fn test_exit() {
+    let mut program = get_syscall_program(1, &[0; 4]);
+    set_arguments(&mut program, SYSCALL_EXIT, &[0]);
+
+    execute_ecall(&mut program);
+
+    assert!(program.halt);
+    assert_eq!(program.pc.get_address(), CODE_BASE_ADDRESS);
+}
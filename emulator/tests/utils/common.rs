@@ -5,7 +5,10 @@ use emulator::{
         fetcher::{execute_program, FullTrace},
         utils::FailConfiguration,
     },
-    loader::program::{load_elf, Program, Registers, Section},
+    loader::{
+        program::{load_elf, Program, Registers, Section},
+        symbols::Symbolizer,
+    },
     EmulatorError, ExecutionResult,
 };
 use rand::Rng;
@@ -66,6 +69,13 @@ pub fn get_new_program() -> Program {
         read_only_sections: SectionDefinition::default(),
         register_sections: SectionDefinition::default(),
         code_sections: SectionDefinition::default(),
+        instruction_size: 4,
+        captured_output: vec![],
+        symbolizer: Symbolizer::default(),
+        input_digest: String::new(),
+        output_section: None,
+        elf_sections: vec![],
+        decode_cache: None,
     }
 }
 
@@ -80,6 +90,7 @@ pub fn get_new_section() -> Section {
         is_write: true,
         initialized: true,
         registers: false,
+        pages: None,
     }
 }
 